@@ -6,6 +6,7 @@ use std::net::Ipv4Addr;
 use std::{collections::BTreeMap, net::IpAddr, path::Path};
 
 use fdo_store::StoreConfig;
+use fdo_util::private_key::PrivateKeyLocation;
 use fdo_util::servers::configuration::{
     owner_onboarding_server::DEFAULT_REGISTRATION_PERIOD,
     owner_onboarding_server::DEFAULT_RE_REGISTRATION_WINDOW,
@@ -69,6 +70,10 @@ pub(super) struct Configuration {
     pub serviceinfo_api_auth_token: String,
     #[clap(skip)]
     pub serviceinfo_api_admin_token: String,
+    #[clap(skip)]
+    pub owner_onboarding_admin_token: String,
+    #[clap(skip)]
+    pub rendezvous_admin_token: String,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -104,6 +109,8 @@ impl Default for Configuration {
             contact_addresses: vec![],
             serviceinfo_api_auth_token: String::from(""),
             serviceinfo_api_admin_token: String::from(""),
+            owner_onboarding_admin_token: String::from(""),
+            rendezvous_admin_token: String::from(""),
         }
     }
 }
@@ -174,8 +181,11 @@ impl Configuration {
         Ok(ServiceInfoSettings {
             initial_user: None,
             files: None,
+            downloads: None,
+            uploads: None,
             commands: None,
             diskencryption_clevis: None,
+            sys: None,
             additional_serviceinfo: None,
             after_onboarding_reboot: Some(false),
         })
@@ -225,8 +235,14 @@ fn generate_configs(aio_dir: &Path, config_args: &Configuration) -> Result<(), E
             ),
 
             max_wait_seconds: None,
+            owner_policies: None,
 
             bind: get_bind(config_args.listen_port_rendezvous_server)?,
+            tls: None,
+            coap_bind: None,
+            metrics_bind: None,
+
+            admin_auth_token: Some(config_args.rendezvous_admin_token.clone()),
         };
     write_config(aio_dir, "rendezvous_server.yml", &rendezvous_config)
         .context("Error writing rendezvous server configuration file")?;
@@ -239,6 +255,8 @@ fn generate_configs(aio_dir: &Path, config_args: &Configuration) -> Result<(), E
                 .context("Error generating serviceinfo settings")?,
 
             bind: get_bind(config_args.listen_port_serviceinfo_api_server)?,
+            tls: None,
+            metrics_bind: None,
 
             service_info_auth_token: Some(config_args.serviceinfo_api_auth_token.clone()),
             admin_auth_token: Some(config_args.serviceinfo_api_admin_token.clone()),
@@ -246,6 +264,17 @@ fn generate_configs(aio_dir: &Path, config_args: &Configuration) -> Result<(), E
             device_specific_store_driver: StoreConfig::Directory {
                 path: aio_dir.join("stores").join("serviceinfo_api_devices"),
             },
+            device_results_store_driver: Some(StoreConfig::Directory {
+                path: aio_dir
+                    .join("stores")
+                    .join("serviceinfo_api_device_results"),
+            }),
+            device_uploads_store_driver: Some(StoreConfig::Directory {
+                path: aio_dir
+                    .join("stores")
+                    .join("serviceinfo_api_device_uploads"),
+            }),
+            device_uploads_path: Some(aio_dir.join("device_uploads")),
         };
     write_config(
         aio_dir,
@@ -270,6 +299,9 @@ fn generate_configs(aio_dir: &Path, config_args: &Configuration) -> Result<(), E
             },
 
             bind: get_bind(config_args.listen_port_manufacturing_server)?,
+            tls: None,
+            coap_bind: None,
+            metrics_bind: None,
 
             ownership_voucher_store_driver: StoreConfig::Directory {
                 path: aio_dir.join("stores").join(if config_args.separate_manufacturing_and_owner_voucher_store {
@@ -305,8 +337,8 @@ fn generate_configs(aio_dir: &Path, config_args: &Configuration) -> Result<(), E
                 .context("Error generating rendezvous info")?,
             manufacturing: fdo_util::servers::configuration::manufacturing_server::ManufacturingSettings {
                 manufacturer_cert_path: AbsolutePathBuf::new(aio_dir.join("keys").join("manufacturer_cert.pem")).unwrap(),
-                manufacturer_private_key: Some(AbsolutePathBuf::new(aio_dir.join("keys").join("manufacturer_key.der")).unwrap()),
-                device_cert_ca_private_key: AbsolutePathBuf::new(aio_dir.join("keys").join("device_ca_key.der")).unwrap(),
+                manufacturer_private_key: Some(PrivateKeyLocation::File(aio_dir.join("keys").join("manufacturer_key.der"))),
+                device_cert_ca_private_key: PrivateKeyLocation::File(aio_dir.join("keys").join("device_ca_key.der")),
                 device_cert_ca_chain: AbsolutePathBuf::new(aio_dir.join("keys").join("device_ca_cert.pem")).unwrap(),
                 owner_cert_path: Some(AbsolutePathBuf::new(aio_dir.join("keys").join("owner_cert.pem")).unwrap()),
             }
@@ -326,6 +358,9 @@ fn generate_configs(aio_dir: &Path, config_args: &Configuration) -> Result<(), E
             },
 
             bind: get_bind(config_args.listen_port_owner_onboarding_server)?,
+            tls: None,
+            coap_bind: None,
+            metrics_bind: None,
 
             ownership_voucher_store_driver: StoreConfig::Directory {
                 path: aio_dir.join("stores").join("owner_vouchers"),
@@ -334,15 +369,15 @@ fn generate_configs(aio_dir: &Path, config_args: &Configuration) -> Result<(), E
                 AbsolutePathBuf::new(aio_dir.join("keys").join("device_ca_cert.pem"))
                     .expect("Failed to build absolute path"),
             ),
-            owner_private_key_path: AbsolutePathBuf::new(
+            owner_private_key_path: PrivateKeyLocation::File(
                 aio_dir.join("keys").join("owner_key.der"),
-            )
-            .unwrap(),
+            ),
 
             owner_public_key_path: AbsolutePathBuf::new(
                 aio_dir.join("keys").join("owner_cert.pem"),
             )
             .unwrap(),
+            additional_owner_keys: Vec::new(),
             service_info_api_url: format!(
                 "http://localhost:{}/device_info", //DevSkim: ignore DS137138
                 config_args.listen_port_serviceinfo_api_server
@@ -355,9 +390,16 @@ fn generate_configs(aio_dir: &Path, config_args: &Configuration) -> Result<(), E
                 .generate_owner_addresses()
                 .context("Error generating owner addresses")?,
             report_to_rendezvous_endpoint_enabled: true,
+            admin_auth_token: Some(config_args.owner_onboarding_admin_token.clone()),
 
             ov_registration_period: config_args.ov_registration_period,
             ov_re_registration_window: config_args.ov_re_registration_window,
+            max_service_info_loops: None,
+
+            owner2_private_key_path: None,
+            owner2_public_key_path: None,
+            replacement_rendezvous_info: None,
+            replacement_ownership_voucher_store_driver: None,
         };
     write_config(
         aio_dir,
@@ -429,6 +471,8 @@ pub(super) fn generate_configs_and_keys(
         "rendezvous_registered",
         "owner_vouchers",
         "serviceinfo_api_per_device",
+        "serviceinfo_api_device_results",
+        "serviceinfo_api_device_uploads",
         "manufacturer_keys",
     ] {
         std::fs::create_dir(aio_dir.join("stores").join(store_dir))
@@ -462,6 +506,12 @@ pub(super) fn generate_configs_and_keys(
         generate_secret_token().context("Error generating auth token")?;
     config_args.serviceinfo_api_admin_token =
         generate_secret_token().context("Error generating admin token")?;
+    log::debug!("Generating Owner Onboarding admin secret");
+    config_args.owner_onboarding_admin_token =
+        generate_secret_token().context("Error generating owner admin token")?;
+    log::debug!("Generating Rendezvous admin secret");
+    config_args.rendezvous_admin_token =
+        generate_secret_token().context("Error generating rendezvous admin token")?;
 
     log::debug!("Determining contact addresses");
     match config_args.contact_hostname {
@@ -1,4 +1,4 @@
-use std::{borrow::Borrow, env, fs, path::PathBuf, process::Command, thread, time};
+use std::{borrow::Borrow, env, fs, path::PathBuf, process::Command, str::FromStr, thread, time};
 
 use anyhow::{anyhow, bail, Context, Result};
 use rand::Rng;
@@ -13,14 +13,17 @@ use fdo_data_formats::{
     enhanced_types::{RendezvousInterpretedDirective, RendezvousInterpreterSide},
     messages,
     ownershipvoucher::{OwnershipVoucher, OwnershipVoucherHeader},
+    publickey::PublicKey,
     types::{
         new_eat, COSEHeaderMap, COSESign, CipherSuite, EATokenPayload, HMac, KexSuite,
         KeyDeriveSide, KeyExchange, Nonce, PayloadCreating, SigInfo, TO1DataPayload,
-        TO2AddressEntry, TO2ProveDevicePayload, TO2ProveOVHdrPayload, UnverifiedValue,
+        TO2AddressEntry, TO2ProveDevicePayload, TO2ProveOVHdrPayload, TO2SetupDevicePayload,
+        UnverifiedValue, DEFAULT_MAX_SERVICE_INFO_LOOPS, DEFAULT_SERVICE_INFO_MTU,
+        MIN_SERVICE_INFO_MTU,
     },
     DeviceCredential, ProtocolVersion, Serializable,
 };
-use fdo_http_wrapper::client::{RequestResult, ServiceClient};
+use fdo_http_wrapper::client::{RequestResult, ServiceClient, TlsPin};
 use fdo_util::device_credential_locations;
 use fdo_util::device_credential_locations::UsableDeviceCredentialLocation;
 
@@ -100,6 +103,7 @@ fn get_to2_urls(entries: &[TO2AddressEntry]) -> Vec<String> {
         let prot_text = match addr_entry.protocol() {
             TransportProtocol::Http => "http",
             TransportProtocol::Https => "https",
+            TransportProtocol::CoAP => "coap",
             _ => continue,
         };
         if let Some(dns_name) = addr_entry.dns() {
@@ -123,7 +127,10 @@ fn get_to2_urls(entries: &[TO2AddressEntry]) -> Vec<String> {
     urls
 }
 
-async fn get_client_list(rv_entry: &RendezvousInterpretedDirective) -> Result<Vec<ServiceClient>> {
+async fn get_client_list(
+    protocol_version: ProtocolVersion,
+    rv_entry: &RendezvousInterpretedDirective,
+) -> Result<Vec<ServiceClient>> {
     log::trace!("Getting client list from rv_entry {:?}", rv_entry);
     let mut service_client_list = Vec::new();
 
@@ -132,7 +139,7 @@ async fn get_client_list(rv_entry: &RendezvousInterpretedDirective) -> Result<Ve
         log::trace!("No URLs found");
     }
     if rv_entry.bypass {
-        bail!("Rendezvous Bypass is not yet implemented");
+        bail!("Rendezvous Bypass directives have no rendezvous server");
     }
     if rv_entry.wifi_ssid.is_some() {
         bail!("Rendezvous WiFi configuration is not yet implemented");
@@ -142,25 +149,48 @@ async fn get_client_list(rv_entry: &RendezvousInterpretedDirective) -> Result<Ve
     }
     if rv_entry.protocol != RendezvousProtocolValue::Http
         && rv_entry.protocol != RendezvousProtocolValue::Https
+        && rv_entry.protocol != RendezvousProtocolValue::CoAPUDP
     {
-        bail!("Non-HTTP(S) protocol is not implemented");
+        bail!("Only the HTTP(S) and CoAP protocols are implemented");
+    }
+    let tls_pin = TlsPin {
+        server_certificate_hash: rv_entry.server_certificate_hash.clone(),
+        ca_certificate_hash: rv_entry.ca_certificate_hash.clone(),
+    };
+    if !tls_pin.is_empty() && rv_entry.protocol != RendezvousProtocolValue::Https {
+        bail!("Rendezvous certificate hashes are only supported over HTTPS");
     }
     for url in &urls {
-        service_client_list.push(fdo_http_wrapper::client::ServiceClient::new(
-            ProtocolVersion::Version1_1,
-            url,
-        ));
+        service_client_list.push(
+            ServiceClient::new_with_tls_pin(protocol_version, url, tls_pin.clone())
+                .with_context(|| format!("Error creating client for {url}"))?,
+        );
     }
     log::trace!("Client list: {:?}", service_client_list);
     Ok(service_client_list)
 }
 
+fn get_device_sig_type(devcred: &dyn DeviceCredential) -> Result<DeviceSigType> {
+    devcred
+        .get_signer()
+        .context("Error getting device signer")?
+        .device_sig_type()
+        .context("Error determining device signature type")
+}
+
 /// TO1: Sends HelloRV, Receives HelloRVAck
 async fn perform_hellorv(
     devcred: &dyn DeviceCredential,
     client: &mut ServiceClient,
 ) -> Result<COSESign, ClientError> {
-    let sig_type = DeviceSigType::StSECP384R1;
+    let sig_type = get_device_sig_type(devcred).map_err(|e| {
+        ClientError::Request(ErrorResult::new(
+            ErrorCode::InternalServerError,
+            "Error determining device signature type",
+            MessageType::TO1HelloRV,
+            e,
+        ))
+    })?;
 
     let hello_rv = messages::v11::to1::HelloRV::new(
         devcred.device_guid().clone(),
@@ -223,7 +253,8 @@ async fn perform_hellorv(
                 e,
             ))
         })?;
-    let token = COSESign::from_eat(eat, None, signer.as_ref())
+    let token = signer
+        .sign_eat(eat, None)
         .context("Error signing new token")
         .map_err(|e| {
             ClientError::Response(ErrorResult::new(
@@ -552,8 +583,8 @@ async fn get_and_verify_ov_header_signature(
     prove_ov_hdr: &COSESign,
     prove_ov_hdr_payload: &UnverifiedValue<TO2ProveOVHdrPayload>,
     header_hmac: HMac,
-    to1d: &COSESign,
-) -> Result<TO2ProveOVHdrPayload, ClientError> {
+    to1d: Option<&COSESign>,
+) -> Result<(TO2ProveOVHdrPayload, PublicKey), ClientError> {
     // Get the other OV entries
     let ov_entries = get_ov_entries(
         client,
@@ -573,7 +604,7 @@ async fn get_and_verify_ov_header_signature(
     // At this moment, we have validated all we can, we'll check the signature later (After we get the final bits of the OV)
     let ownership_voucher = {
         let header = prove_ov_hdr_payload.get_unverified_value().ov_header();
-        OwnershipVoucher::from_parts(ProtocolVersion::Version1_1, header, header_hmac, ov_entries)
+        OwnershipVoucher::from_parts(client.protocol_version(), header, header_hmac, ov_entries)
     }
     .context("Error reconstructing Ownership Voucher")
     .map_err(|e| {
@@ -589,42 +620,27 @@ async fn get_and_verify_ov_header_signature(
         ownership_voucher
     );
 
-    // Get the last entry of the ownership voucher, this automatically validates everything (yay abstraction!)
-    let ov_owner_entry = ownership_voucher
-        .iter_entries()
-        .context("Error initializing iterator")
-        .map_err(|e| {
-            ClientError::Response(ErrorResult::new(
-                ErrorCode::InternalServerError,
-                "Error initializing iterator",
-                MessageType::TO2OVNextEntry,
-                e,
-            ))
-        })?
-        .last()
-        .context("Error validating ownership voucher")
-        .map_err(|e| {
-            ClientError::Response(ErrorResult::new(
+    // Verify all the entries, to get the current owner key
+    let report = ownership_voucher.verify(None, None, None, None);
+    let owner_pubkey = match report.owner_public_key() {
+        Some(owner_pubkey) if report.is_valid() => owner_pubkey.clone(),
+        _ => {
+            let failures: Vec<String> = report.failures().map(|f| f.to_string()).collect();
+            return Err(ClientError::Response(ErrorResult::new(
                 ErrorCode::InvalidMessageError,
                 "Error validating ownership voucher",
                 MessageType::TO2OVNextEntry,
-                e,
-            ))
-        })?
-        .context("Last entry on ownership voucher was wrong")
-        .map_err(|e| {
-            ClientError::Response(ErrorResult::new(
-                ErrorCode::InvalidMessageError,
-                "Last entry on ownership voucher was wrong",
-                MessageType::TO2OVNextEntry,
-                e,
-            ))
-        })?;
-    log::trace!("Got owner entry: {:?}", ov_owner_entry);
+                anyhow!(
+                    "Ownership voucher verification failed: {}",
+                    failures.join(", ")
+                ),
+            )));
+        }
+    };
 
     // Now, we can finally verify the OV Header signature we got at the top!
     let prove_ov_hdr_payload: TO2ProveOVHdrPayload = prove_ov_hdr
-        .get_payload(ov_owner_entry.public_key().pkey())
+        .get_payload(owner_pubkey.pkey())
         .context("Error validating ProveOVHdr signature")
         .map_err(|e| {
             ClientError::Response(ErrorResult::new(
@@ -634,23 +650,22 @@ async fn get_and_verify_ov_header_signature(
                 e,
             ))
         })?;
-    log::trace!(
-        "ProveOVHdr validated with public key: {:?}",
-        ov_owner_entry.public_key()
-    );
+    log::trace!("ProveOVHdr validated with public key: {:?}", owner_pubkey);
 
-    // Verify that to1d was signed by the current owner
-    to1d.verify(ov_owner_entry.public_key().pkey())
-        .context("Error validating to1d after receiving full ownership voucher")
-        .map_err(|e| {
-            ClientError::Response(ErrorResult::new(
-                ErrorCode::InvalidMessageError,
-                "Error validating to1d after receiving full ownership voucher",
-                MessageType::TO2OVNextEntry,
-                e,
-            ))
-        })?;
-    Ok(prove_ov_hdr_payload)
+    // Verify that to1d was signed by the current owner, there is none with Rendezvous Bypass
+    if let Some(to1d) = to1d {
+        to1d.verify(owner_pubkey.pkey())
+            .context("Error validating to1d after receiving full ownership voucher")
+            .map_err(|e| {
+                ClientError::Response(ErrorResult::new(
+                    ErrorCode::InvalidMessageError,
+                    "Error validating to1d after receiving full ownership voucher",
+                    MessageType::TO2OVNextEntry,
+                    e,
+                ))
+            })?;
+    }
+    Ok((prove_ov_hdr_payload, owner_pubkey))
 }
 
 async fn perform_key_derivation(
@@ -671,7 +686,7 @@ async fn perform_key_derivation(
 
     // Perform the key derivation
     let a_key_exchange = prove_ov_hdr_payload.a_key_exchange();
-    let b_key_exchange = KeyExchange::new(kexsuite)
+    let b_key_exchange = KeyExchange::new_for_device(kexsuite, a_key_exchange)
         .context("Error creating device side of key exchange")
         .map_err(|e| {
             ClientError::Response(ErrorResult::new(
@@ -711,7 +726,7 @@ async fn perform_provedevice(
     nonce6: &Nonce,
     nonce7: &Nonce,
     new_keys: fdo_http_wrapper::EncryptionKeys,
-) -> Result<(), ClientError> {
+) -> Result<TO2SetupDevicePayload, ClientError> {
     let prove_device_payload = TO2ProveDevicePayload::new(
         b_key_exchange
             .get_public()
@@ -762,20 +777,17 @@ async fn perform_provedevice(
                 e,
             ))
         })?;
-    let prove_device_token = COSESign::from_eat(
-        prove_device_eat,
-        Some(prove_device_eat_unprotected),
-        signer.as_ref(),
-    )
-    .context("Error signing ProveDevice EAT")
-    .map_err(|e| {
-        ClientError::Response(ErrorResult::new(
-            ErrorCode::InternalServerError,
-            "Error signing ProveDevice EAT",
-            MessageType::TO2OVNextEntry,
-            e,
-        ))
-    })?;
+    let prove_device_token = signer
+        .sign_eat(prove_device_eat, Some(prove_device_eat_unprotected))
+        .context("Error signing ProveDevice EAT")
+        .map_err(|e| {
+            ClientError::Response(ErrorResult::new(
+                ErrorCode::InternalServerError,
+                "Error signing ProveDevice EAT",
+                MessageType::TO2OVNextEntry,
+                e,
+            ))
+        })?;
 
     log::trace!("Prepared prove_device_token: {:?}", prove_device_token);
     let prove_device_msg = messages::v11::to2::ProveDevice::new(prove_device_token);
@@ -791,14 +803,147 @@ async fn perform_provedevice(
     })?;
     log::trace!("Got setup_device response: {:?}", setup_device);
 
-    Ok(())
+    // The SetupDevice payload is signed by the Owner2Key it contains
+    let setup_device = setup_device.into_token();
+    let setup_device_payload: UnverifiedValue<TO2SetupDevicePayload> = setup_device
+        .get_payload_unverified()
+        .context("Error parsing unverified SetupDevice payload")
+        .map_err(|e| {
+            ClientError::Response(ErrorResult::new(
+                ErrorCode::MessageBodyError,
+                "Error parsing unverified SetupDevice payload",
+                MessageType::TO2SetupDevice,
+                e,
+            ))
+        })?;
+    let owner2_key = setup_device_payload
+        .get_unverified_value()
+        .owner2_key()
+        .clone();
+    let setup_device_payload: TO2SetupDevicePayload = setup_device
+        .get_payload(owner2_key.pkey())
+        .context("Error validating SetupDevice signature")
+        .map_err(|e| {
+            ClientError::Response(ErrorResult::new(
+                ErrorCode::InvalidMessageError,
+                "Error validating SetupDevice signature",
+                MessageType::TO2SetupDevice,
+                e,
+            ))
+        })?;
+    if nonce7 != setup_device_payload.nonce7() {
+        return Err(ClientError::Response(ErrorResult::new(
+            ErrorCode::InvalidMessageError,
+            "Nonce7 did not match in SetupDevice",
+            MessageType::TO2SetupDevice,
+            anyhow!("Nonce7 did not match in SetupDevice"),
+        )));
+    }
+
+    Ok(setup_device_payload)
+}
+
+/// What happens to the device credential after a successful TO2
+enum CredentialOutcome {
+    /// Credential Reuse: the credential is kept, so the device can onboard again
+    Reuse,
+    /// Credential Resale: the credential is rewritten for the replacement
+    /// Ownership Voucher header, whose HMAC is sent to the Owner
+    Replace(Box<OwnershipVoucherHeader>, HMac),
+    /// The credential can't be used again, so it's deactivated
+    Deactivate,
+}
+
+/// Determines whether the Owner requests Credential Reuse or Resale, and for
+/// Resale builds the replacement Ownership Voucher header and computes its HMAC.
+fn get_credential_outcome(
+    devcred: &dyn DeviceCredential,
+    can_replace: bool,
+    ov_header: &[u8],
+    ov_header_hmac: &HMac,
+    owner_pubkey: &PublicKey,
+    setup_device_payload: &TO2SetupDevicePayload,
+) -> Result<CredentialOutcome, ClientError> {
+    let map_err = |e: anyhow::Error| {
+        ClientError::Response(ErrorResult::new(
+            ErrorCode::InternalServerError,
+            "Error computing the credential replacement",
+            MessageType::TO2SetupDevice,
+            e,
+        ))
+    };
+
+    let same_rendezvous_info = setup_device_payload
+        .rendezvous_info()
+        .serialize_data()
+        .context("Error serializing replacement rendezvous info")
+        .map_err(map_err)?
+        == devcred
+            .rendezvous_info()
+            .serialize_data()
+            .context("Error serializing rendezvous info")
+            .map_err(map_err)?;
+    let same_owner_key = setup_device_payload
+        .owner2_key()
+        .matches_pkey(owner_pubkey.pkey())
+        .context("Error comparing Owner2Key")
+        .map_err(map_err)?;
+    if setup_device_payload.guid() == devcred.device_guid()
+        && same_rendezvous_info
+        && same_owner_key
+    {
+        log::info!("Owner requested Credential Reuse, keeping the device credential");
+        return Ok(CredentialOutcome::Reuse);
+    }
+    if setup_device_payload.rendezvous_info().values().is_empty() {
+        // Nobody could onboard a credential without rendezvous info
+        log::info!("Owner provided no replacement rendezvous info, not replacing credential");
+        return Ok(CredentialOutcome::Deactivate);
+    }
+    if !can_replace {
+        // Without a replacement HMAC, the Owner does not keep a replacement voucher
+        log::warn!("Device credential can not be rewritten, not replacing credential");
+        return Ok(CredentialOutcome::Deactivate);
+    }
+
+    let ov_header = OwnershipVoucherHeader::deserialize_data(ov_header)
+        .context("Error deserializing OV Header")
+        .map_err(map_err)?;
+    let replacement_header = ov_header
+        .replacement(
+            setup_device_payload.guid().clone(),
+            setup_device_payload.rendezvous_info().clone(),
+            setup_device_payload.owner2_key().clone(),
+        )
+        .context("Error building replacement OV Header")
+        .map_err(map_err)?;
+    let replacement_hmac = devcred
+        .perform_hmac(
+            &replacement_header
+                .serialize_data()
+                .context("Error serializing replacement OV Header")
+                .map_err(map_err)?,
+            ov_header_hmac.get_type(),
+        )
+        .context("Error computing replacement HMAC")
+        .map_err(map_err)?;
+
+    Ok(CredentialOutcome::Replace(
+        Box::new(replacement_header),
+        replacement_hmac,
+    ))
 }
 
-/// TO2: Sends DeviceServiceInfoReady, Receives OwnerService
-async fn perform_deviceserviceinfoready(client: &mut ServiceClient) -> Result<(), ClientError> {
+/// TO2: Sends DeviceServiceInfoReady, Receives OwnerServiceInfoReady
+///
+/// Returns the maximum ServiceInfo size the Owner is willing to receive.
+async fn perform_deviceserviceinfoready(
+    client: &mut ServiceClient,
+    replacement_hmac: Option<HMac>,
+) -> Result<Option<u64>, ClientError> {
     let owner_service_info_ready: RequestResult<messages::v11::to2::OwnerServiceInfoReady> = client
         .send_request(
-            messages::v11::to2::DeviceServiceInfoReady::new(None, None),
+            messages::v11::to2::DeviceServiceInfoReady::new(replacement_hmac, None),
             None,
         )
         .await;
@@ -816,7 +961,7 @@ async fn perform_deviceserviceinfoready(client: &mut ServiceClient) -> Result<()
         "Received OwnerServiceInfoReady: {:?}",
         owner_service_info_ready
     );
-    Ok(())
+    Ok(owner_service_info_ready.max_device_service_info_size())
 }
 
 /// TO2: Sends Done, Receives Done2
@@ -851,11 +996,11 @@ async fn perform_to2(
     devcredloc: &dyn UsableDeviceCredentialLocation,
     devcred: &dyn DeviceCredential,
     url: &str,
-    to1d: &COSESign,
+    to1d: Option<&COSESign>,
 ) -> Result<bool> {
     log::info!("Performing TO2 protocol, URL: {:?}", url);
 
-    let mut client = fdo_http_wrapper::client::ServiceClient::new(ProtocolVersion::Version1_1, url);
+    let mut client = fdo_http_wrapper::client::ServiceClient::new(devcred.protocol_version(), url);
 
     let nonce5 = match get_nonce(MessageType::TO1RVRedirect).await {
         Ok(nonce5) => nonce5,
@@ -871,9 +1016,15 @@ async fn perform_to2(
         },
     };
 
-    let sigtype = DeviceSigType::StSECP384R1;
-    let kexsuite = KexSuite::Ecdh384;
-    let ciphersuite = CipherSuite::A256Gcm;
+    let sigtype = get_device_sig_type(devcred)?;
+    let kexsuite = match env::var("KEX_SUITE") {
+        Ok(kexsuite) => KexSuite::from_str(&kexsuite).context("Invalid KEX_SUITE")?,
+        Err(_) => KexSuite::Ecdh384,
+    };
+    let ciphersuite = match env::var("CIPHER_SUITE") {
+        Ok(ciphersuite) => CipherSuite::from_str(&ciphersuite).context("Invalid CIPHER_SUITE")?,
+        Err(_) => CipherSuite::A256Gcm,
+    };
 
     // Send: HelloDevice, Receive: ProveOVHdr
     let (prove_ov_hdr, prove_ov_hdr_payload, header_hmac) =
@@ -907,7 +1058,7 @@ async fn perform_to2(
         },
     };
     // Get OV and verify its signature
-    let prove_ov_hdr_payload = match get_and_verify_ov_header_signature(
+    let (prove_ov_hdr_payload, owner_pubkey) = match get_and_verify_ov_header_signature(
         &mut client,
         &prove_ov_hdr,
         &prove_ov_hdr_payload,
@@ -929,6 +1080,10 @@ async fn perform_to2(
         },
     };
 
+    let ov_header = prove_ov_hdr_payload.ov_header().to_vec();
+    let ov_header_hmac = prove_ov_hdr_payload.hmac().clone();
+    let max_owner_message_size = prove_ov_hdr_payload.max_owner_message_size();
+
     // Key derivation
     let (b_key_exchange, new_keys) = match perform_key_derivation(
         &mut client,
@@ -967,7 +1122,7 @@ async fn perform_to2(
     };
 
     // Send: ProveDevice, Receive: SetupDevice
-    match perform_provedevice(
+    let setup_device_payload = match perform_provedevice(
         devcred,
         &mut client,
         b_key_exchange,
@@ -977,7 +1132,7 @@ async fn perform_to2(
     )
     .await
     {
-        Ok(_) => (),
+        Ok(payload) => payload,
         Err(e) => match e {
             ClientError::Request(e) => {
                 send_client_error(&mut client, &e).await;
@@ -990,9 +1145,16 @@ async fn perform_to2(
         },
     };
 
-    // Send: DeviceServiceInfoReady, Receive: OwnerServiceInfoReady
-    match perform_deviceserviceinfoready(&mut client).await {
-        Ok(_) => (),
+    // Determine whether the Owner performs Credential Reuse or Resale
+    let credential_outcome = match get_credential_outcome(
+        devcred,
+        devcredloc.can_replace(),
+        &ov_header,
+        &ov_header_hmac,
+        &owner_pubkey,
+        &setup_device_payload,
+    ) {
+        Ok(credential_outcome) => credential_outcome,
         Err(e) => match e {
             ClientError::Request(e) => {
                 send_client_error(&mut client, &e).await;
@@ -1004,9 +1166,47 @@ async fn perform_to2(
             }
         },
     };
+    let replacement_hmac = match &credential_outcome {
+        CredentialOutcome::Replace(_, replacement_hmac) => Some(replacement_hmac.clone()),
+        CredentialOutcome::Reuse | CredentialOutcome::Deactivate => None,
+    };
+
+    // Send: DeviceServiceInfoReady, Receive: OwnerServiceInfoReady
+    let max_device_service_info_size =
+        match perform_deviceserviceinfoready(&mut client, replacement_hmac).await {
+            Ok(size) => size,
+            Err(e) => match e {
+                ClientError::Request(e) => {
+                    send_client_error(&mut client, &e).await;
+                    bail!(e.error);
+                }
+                ClientError::Response(e) => {
+                    send_client_error(&mut client, &e).await;
+                    bail!(e.error);
+                }
+            },
+        };
+
+    // Determine how large the ServiceInfo we send to the owner may be
+    let mut service_info_mtu = max_device_service_info_size.unwrap_or(DEFAULT_SERVICE_INFO_MTU);
+    // Older implementations announced a bogus message size, so ignore anything unusably small
+    if max_owner_message_size as u64 >= MIN_SERVICE_INFO_MTU {
+        service_info_mtu = std::cmp::min(service_info_mtu, max_owner_message_size as u64);
+    }
+
+    let max_service_info_loops = match env::var("MAX_SERVICE_INFO_LOOPS") {
+        Ok(max) => max.parse().context("Invalid MAX_SERVICE_INFO_LOOPS")?,
+        Err(_) => DEFAULT_MAX_SERVICE_INFO_LOOPS,
+    };
 
     // Now, the magic: performing the roundtrip! We delegated that.
-    let reboot_required = match serviceinfo::perform_to2_serviceinfos(&mut client).await {
+    let reboot_required = match serviceinfo::perform_to2_serviceinfos(
+        &mut client,
+        service_info_mtu,
+        max_service_info_loops,
+    )
+    .await
+    {
         Err(serviceinfo_err) => {
             log::error!("ServiceInfo failed, error: {:?}", serviceinfo_err);
             let e_result = ErrorResult::new(
@@ -1033,20 +1233,9 @@ async fn perform_to2(
         bail!(e_result.error);
     }
 
-    if devcredloc.deactivate().is_err() {
-        let e_result = ErrorResult::new(
-            ErrorCode::InternalServerError,
-            "Error deactivating device credential",
-            MessageType::TO2OwnerServiceInfo,
-            anyhow!("Error deactivating device credential"),
-        );
-        send_client_error(&mut client, &e_result).await;
-        bail!(e_result.error);
-    }
-
     // Send: Done, Receive: Done2
     match perform_done(nonce7, nonce6, &mut client).await {
-        Ok(_) => Ok(reboot_required),
+        Ok(_) => (),
         Err(e) => match e {
             ClientError::Request(e) => {
                 send_client_error(&mut client, &e).await;
@@ -1058,6 +1247,27 @@ async fn perform_to2(
             }
         },
     }
+
+    // The Owner has confirmed the onboarding, update the credential
+    match credential_outcome {
+        CredentialOutcome::Reuse => {
+            log::info!("Device credential reused, it can be onboarded again")
+        }
+        CredentialOutcome::Replace(replacement_header, _) => {
+            devcredloc
+                .replace(&replacement_header)
+                .context("Error storing the replacement device credential")?;
+            log::info!(
+                "Device credential replaced, new device GUID: {}",
+                replacement_header.guid()
+            );
+        }
+        CredentialOutcome::Deactivate => devcredloc
+            .deactivate()
+            .context("Error deactivating device credential")?,
+    }
+
+    Ok(reboot_required)
 }
 
 fn get_delay_between_retries(rv_entry_delay: u32) -> u64 {
@@ -1127,12 +1337,6 @@ async fn main() -> Result<()> {
         log::info!("Device credential deactivated, skipping Device Onboarding");
         return Ok(());
     }
-    if dc.protocol_version() != ProtocolVersion::Version1_1 {
-        bail!(
-            "Device credential protocol version {} not supported",
-            dc.protocol_version()
-        );
-    }
 
     // Get rv entries
     let rv_info = get_rv_info(dc.as_ref())?;
@@ -1145,50 +1349,61 @@ async fn main() -> Result<()> {
         for rv_entry in rv_info.iter() {
             rv_entry_delay = rv_entry.delay;
 
-            let client_list = match get_client_list(rv_entry).await {
-                Ok(client_list) => client_list,
-                Err(e) => {
-                    log::error!(
-                        "Error {:?} getting usable rendezvous client list from rv_entry {:?}",
-                        e,
-                        rv_entry
-                    );
-                    continue;
-                }
-            };
+            let (to2_addresses, to1d) = if rv_entry.bypass {
+                // The directive points to the owner server itself, skip TO1
+                let to2_addresses = rv_entry.get_urls();
+                log::info!(
+                    "Rendezvous Bypass, using TO2 addresses: {:?}",
+                    to2_addresses
+                );
+                (to2_addresses, None)
+            } else {
+                let client_list = match get_client_list(dc.protocol_version(), rv_entry).await {
+                    Ok(client_list) => client_list,
+                    Err(e) => {
+                        log::error!(
+                            "Error {:?} getting usable rendezvous client list from rv_entry {:?}",
+                            e,
+                            rv_entry
+                        );
+                        continue;
+                    }
+                };
 
-            // Get owner info
-            let to1d = get_to1d(dc.as_ref(), client_list).await;
-            let to1d = match to1d {
-                Ok(to1d) => to1d,
-                Err(e) => {
-                    log::error!(
-                        "Error {:?} getting usable To1d from rv_entry {:?}",
-                        e,
-                        rv_entry
-                    );
-                    continue;
-                }
-            };
+                // Get owner info
+                let to1d = get_to1d(dc.as_ref(), client_list).await;
+                let to1d = match to1d {
+                    Ok(to1d) => to1d,
+                    Err(e) => {
+                        log::error!(
+                            "Error {:?} getting usable To1d from rv_entry {:?}",
+                            e,
+                            rv_entry
+                        );
+                        continue;
+                    }
+                };
+
+                let to1d_payload: UnverifiedValue<TO1DataPayload> =
+                    match to1d.get_payload_unverified() {
+                        Ok(to1d_payload) => to1d_payload,
+                        Err(e) => {
+                            log::trace!(
+                                "Error getting TO1 payload unverified {:?} with rv_entry {:?}",
+                                e,
+                                rv_entry
+                            );
+                            continue;
+                        }
+                    };
 
-            let to1d_payload: UnverifiedValue<TO1DataPayload> = match to1d.get_payload_unverified()
-            {
-                Ok(to1d_payload) => to1d_payload,
-                Err(e) => {
-                    log::trace!(
-                        "Error getting TO1 payload unverified {:?} with rv_entry {:?}",
-                        e,
-                        rv_entry
-                    );
-                    continue;
-                }
+                // Contact owner and perform ownership transfer
+                let to2_addresses = to1d_payload.get_unverified_value().to2_addresses();
+                let to2_addresses = get_to2_urls(to2_addresses);
+                log::info!("Got TO2 addresses: {:?}", to2_addresses);
+                (to2_addresses, Some(to1d))
             };
 
-            // Contact owner and perform ownership transfer
-            let to2_addresses = to1d_payload.get_unverified_value().to2_addresses();
-            let to2_addresses = get_to2_urls(to2_addresses);
-            log::info!("Got TO2 addresses: {:?}", to2_addresses);
-
             if to2_addresses.is_empty() {
                 log::trace!(
                     "No valid TO2 addresses received with rv_entry {:?}",
@@ -1198,9 +1413,14 @@ async fn main() -> Result<()> {
             }
 
             for to2_address in to2_addresses {
-                match perform_to2(devcred_location.borrow(), dc.as_ref(), &to2_address, &to1d)
-                    .await
-                    .context("Error performing TO2 ownership protocol")
+                match perform_to2(
+                    devcred_location.borrow(),
+                    dc.as_ref(),
+                    &to2_address,
+                    to1d.as_ref(),
+                )
+                .await
+                .context("Error performing TO2 ownership protocol")
                 {
                     Ok(maybe_reboot) => {
                         onboarding_performed = true;
@@ -1,15 +1,26 @@
-use std::process::{Command, Stdio};
+use std::process::{Child, Command, Stdio};
+use std::time::{Duration, Instant};
 use std::{
-    collections::HashSet,
+    collections::{HashSet, VecDeque},
     fs::{File, Permissions},
-    io::Write,
+    io::{Read, Write},
     path::Path,
     str,
 };
 use std::{env, fs};
-use std::{os::unix::fs::PermissionsExt, path::PathBuf};
+use std::{
+    os::unix::{
+        fs::{OpenOptionsExt, PermissionsExt},
+        process::ExitStatusExt,
+    },
+    path::PathBuf,
+};
 
 use anyhow::{anyhow, bail, Context, Result};
+use openssl::{
+    hash::{Hasher, MessageDigest},
+    memcmp,
+};
 
 use fdo_data_formats::{
     constants::{
@@ -17,17 +28,18 @@ use fdo_data_formats::{
         StandardServiceInfoModule,
     },
     messages::v11::to2::{DeviceServiceInfo, OwnerServiceInfo},
-    types::{CborSimpleTypeExt, Hash, ServiceInfo},
+    types::{CborSimpleType, CborSimpleTypeExt, Hash, ServiceInfo},
 };
 use fdo_http_wrapper::client::{RequestResult, ServiceClient};
 use fdo_util::passwd_shadow;
 
-const MAX_SERVICE_INFO_LOOPS: u32 = 1000;
-
 fn find_available_modules() -> Result<Vec<ServiceInfoModule>> {
     let mut module_list = vec![
         // These modules are always here
         StandardServiceInfoModule::DevMod.into(),
+        StandardServiceInfoModule::Sys.into(),
+        StandardServiceInfoModule::Download.into(),
+        StandardServiceInfoModule::Upload.into(),
         FedoraIotServiceInfoModule::SSHKey.into(),
         FedoraIotServiceInfoModule::BinaryFile.into(),
         FedoraIotServiceInfoModule::Command.into(),
@@ -289,6 +301,275 @@ impl<'a> BinaryFileInProgress<'a> {
     }
 }
 
+/// State of an fdo.download transfer, which is kept across ServiceInfo
+/// rounds so that the data can be streamed to disk as it arrives.
+///
+/// The name, length and sha-384 may arrive in any order, the transfer starts
+/// with the first data chunk.
+struct DownloadInProgress {
+    prefix: Option<String>,
+    name: Option<String>,
+    length: Option<u64>,
+    digest: Option<Vec<u8>>,
+    transfer: Option<DownloadTransfer>,
+}
+
+struct DownloadTransfer {
+    path: PathBuf,
+    temp_path: PathBuf,
+    file: File,
+    hasher: Hasher,
+    received: u64,
+}
+
+impl DownloadInProgress {
+    fn new(prefix: Option<String>) -> Self {
+        DownloadInProgress {
+            prefix,
+            name: None,
+            length: None,
+            digest: None,
+            transfer: None,
+        }
+    }
+
+    fn process(
+        &mut self,
+        key: &str,
+        value: &CborSimpleType,
+        si_out: &mut ServiceInfo,
+    ) -> Result<()> {
+        match key {
+            "active" => {}
+            "name" | "length" | "sha-384" if self.transfer.is_some() => {
+                bail!("Got fdo.download {} during a transfer", key);
+            }
+            "name" => {
+                let name = value.as_str().context("Error parsing download name")?;
+                self.name = Some(name.to_string());
+                self.finish_if_empty(si_out)?;
+            }
+            "length" => {
+                self.length = Some(value.as_u64().context("Error parsing download length")?);
+                self.finish_if_empty(si_out)?;
+            }
+            "sha-384" => {
+                self.digest = Some(
+                    value
+                        .as_bytes()
+                        .context("Error parsing download sha-384")?
+                        .to_vec(),
+                );
+            }
+            "data" => {
+                let data = value.as_bytes().context("Error parsing download data")?;
+                if self.transfer.is_none() {
+                    self.start()?;
+                }
+                let transfer = self.transfer.as_mut().unwrap();
+                let length = self.length.unwrap();
+                transfer.received += data.len() as u64;
+                if transfer.received > length {
+                    bail!(
+                        "Got more fdo.download data ({} bytes) than announced ({} bytes)",
+                        transfer.received,
+                        length
+                    );
+                }
+                transfer
+                    .file
+                    .write_all(data)
+                    .with_context(|| format!("Error writing to {:?}", transfer.temp_path))?;
+                transfer.hasher.update(data)?;
+                if transfer.received == length {
+                    self.finish(si_out)?;
+                }
+            }
+            _ => log::warn!("Unsupported fdo.download key {}", key),
+        }
+        Ok(())
+    }
+
+    /// An empty file has no data chunks, so it is written as soon as both
+    /// its name and length are known
+    fn finish_if_empty(&mut self, si_out: &mut ServiceInfo) -> Result<()> {
+        if self.name.is_some() && self.length == Some(0) {
+            self.start()?;
+            self.finish(si_out)?;
+        }
+        Ok(())
+    }
+
+    fn start(&mut self) -> Result<()> {
+        let length = self.length.context("Got fdo.download data before length")?;
+        let name = self
+            .name
+            .take()
+            .context("Got fdo.download data before name")?;
+
+        // Relative names are relative to the devmod:dir we announced
+        let path = Path::new("/").join(&name);
+        let path = BinaryFileInProgress::destination_path(
+            path.to_str().context("Invalid download path")?,
+            self.prefix.as_deref(),
+        )?;
+        let file_name = path.file_name().context("Download path has no file name")?;
+        let temp_path =
+            path.with_file_name(format!(".{}.fdo-download", file_name.to_string_lossy()));
+
+        log::info!("Downloading {} bytes to {:?}", length, path);
+        fs::create_dir_all(path.parent().unwrap()).context("Error creating file's directory")?;
+        let file = fs::OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .mode(0o600)
+            .open(&temp_path)
+            .with_context(|| format!("Error creating {temp_path:?}"))?;
+
+        self.transfer = Some(DownloadTransfer {
+            path,
+            temp_path,
+            file,
+            hasher: Hasher::new(MessageDigest::sha384())?,
+            received: 0,
+        });
+        Ok(())
+    }
+
+    fn finish(&mut self, si_out: &mut ServiceInfo) -> Result<()> {
+        let mut transfer = self.transfer.take().unwrap();
+        let length = self.length.take().unwrap();
+        let expected_digest = self.digest.take();
+
+        transfer
+            .file
+            .sync_all()
+            .with_context(|| format!("Error syncing {:?}", transfer.temp_path))?;
+        let digest = transfer.hasher.finish()?;
+
+        if let Some(expected_digest) = expected_digest {
+            if expected_digest.len() != digest.len() || !memcmp::eq(&expected_digest, &digest) {
+                log::error!("Download of {:?} has an invalid digest", transfer.path);
+                fs::remove_file(&transfer.temp_path)
+                    .with_context(|| format!("Error removing {:?}", transfer.temp_path))?;
+                si_out.add(StandardServiceInfoModule::Download, "done", &-1)?;
+                return Ok(());
+            }
+        }
+
+        fs::rename(&transfer.temp_path, &transfer.path)
+            .with_context(|| format!("Error moving download to {:?}", transfer.path))?;
+        log::info!("Download of {:?} finished", transfer.path);
+        si_out.add(StandardServiceInfoModule::Download, "done", &length)?;
+        Ok(())
+    }
+}
+
+// Size of the individual fdo.upload data entries
+const UPLOAD_CHUNK_SIZE: usize = 1024;
+
+/// An fdo.upload transfer requested by the owner, which is read from disk one
+/// message at a time so that large files are never held in memory at once.
+struct UploadInProgress {
+    name: String,
+    need_sha: bool,
+    transfer: Option<UploadTransfer>,
+}
+
+struct UploadTransfer {
+    file: File,
+    remaining: u64,
+    hasher: Option<Hasher>,
+}
+
+impl UploadInProgress {
+    fn new(name: &str, need_sha: bool) -> Self {
+        UploadInProgress {
+            name: name.to_string(),
+            need_sha,
+            transfer: None,
+        }
+    }
+
+    /// Adds up to `max_bytes` of data (rounded up to a whole chunk) to the
+    /// ServiceInfo, and returns whether the upload is complete
+    fn send_data(&mut self, si_out: &mut ServiceInfo, max_bytes: u64) -> Result<bool> {
+        if self.transfer.is_none() && !self.start(si_out)? {
+            return Ok(true);
+        }
+        let transfer = self.transfer.as_mut().unwrap();
+
+        let mut sent = 0;
+        let mut buf = vec![0; UPLOAD_CHUNK_SIZE];
+        while transfer.remaining > 0 && sent < max_bytes {
+            let len = (transfer.remaining as usize).min(UPLOAD_CHUNK_SIZE);
+            if let Err(e) = transfer.file.read_exact(&mut buf[..len]) {
+                log::warn!("Error reading {} for upload: {}", self.name, e);
+                si_out.add(StandardServiceInfoModule::Upload, "error", &e.to_string())?;
+                return Ok(true);
+            }
+            if let Some(hasher) = transfer.hasher.as_mut() {
+                hasher.update(&buf[..len])?;
+            }
+            si_out.add(
+                StandardServiceInfoModule::Upload,
+                "data",
+                &serde_bytes::Bytes::new(&buf[..len]),
+            )?;
+            transfer.remaining -= len as u64;
+            sent += len as u64;
+        }
+        if transfer.remaining > 0 {
+            return Ok(false);
+        }
+
+        if let Some(mut hasher) = transfer.hasher.take() {
+            si_out.add(
+                StandardServiceInfoModule::Upload,
+                "sha-384",
+                &serde_bytes::Bytes::new(&hasher.finish()?),
+            )?;
+        }
+        log::info!("Upload of {} finished", self.name);
+        Ok(true)
+    }
+
+    /// Opens the file and announces the upload, returns false if the file
+    /// can't be read, in which case the error is reported to the owner
+    fn start(&mut self, si_out: &mut ServiceInfo) -> Result<bool> {
+        // Relative names are relative to the devmod:dir we announced
+        let path = Path::new("/").join(&self.name);
+        log::info!("Uploading {:?}", path);
+
+        si_out.add(StandardServiceInfoModule::Upload, "name", &self.name)?;
+        let opened = File::open(&path).and_then(|file| {
+            let length = file.metadata()?.len();
+            Ok((file, length))
+        });
+        let (file, length) = match opened {
+            Ok(opened) => opened,
+            Err(e) => {
+                log::warn!("Error reading {:?} for upload: {}", path, e);
+                si_out.add(StandardServiceInfoModule::Upload, "error", &e.to_string())?;
+                return Ok(false);
+            }
+        };
+
+        si_out.add(StandardServiceInfoModule::Upload, "length", &length)?;
+        self.transfer = Some(UploadTransfer {
+            file,
+            remaining: length,
+            hasher: if self.need_sha {
+                Some(Hasher::new(MessageDigest::sha384())?)
+            } else {
+                None
+            },
+        });
+        Ok(true)
+    }
+}
+
 #[derive(Debug)]
 struct DiskEncryptionInProgress {
     disk_label: Option<String>,
@@ -382,6 +663,127 @@ impl DiskEncryptionInProgress {
     }
 }
 
+// How long we wait for an fdo.sys:exec_cb command before reporting it is still running
+const SYS_EXEC_CB_STATUS_TIMEOUT: u64 = 5;
+// The longest we are willing to wait when the owner asks us to check back later
+const SYS_EXEC_CB_MAX_WAIT: u64 = 60;
+
+/// State of the fdo.sys module, which is kept across ServiceInfo rounds
+/// so that commands started with exec_cb can be reported on later.
+#[derive(Debug, Default)]
+struct SysModuleState {
+    file: Option<(PathBuf, File)>,
+    running_command: Option<Child>,
+}
+
+impl SysModuleState {
+    fn filedesc(&mut self, name: &str) -> Result<()> {
+        // Relative names are relative to the devmod:dir we announced
+        let path = Path::new("/").join(name);
+        log::trace!("Opening {:?} for fdo.sys writes", path);
+        let file = fs::OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .mode(0o700)
+            .open(&path)
+            .with_context(|| format!("Error opening file {path:?}"))?;
+        self.file = Some((path, file));
+        Ok(())
+    }
+
+    fn write(&mut self, data: &[u8]) -> Result<()> {
+        match &mut self.file {
+            None => bail!("Got fdo.sys:write before filedesc"),
+            Some((path, file)) => file
+                .write_all(data)
+                .with_context(|| format!("Error writing to file {path:?}")),
+        }
+    }
+
+    fn exec(&mut self, args: &[String]) -> Result<()> {
+        let (command, args) = args.split_first().context("Empty fdo.sys:exec command")?;
+        log::info!("Executing fdo.sys command {} {:?}", command, args);
+        let status = Command::new(command)
+            .args(args)
+            .status()
+            .with_context(|| format!("Error running command {command}"))?;
+        if !status.success() {
+            bail!("Command {} {:?} failed: {:?}", command, args, status);
+        }
+        Ok(())
+    }
+
+    async fn exec_cb(&mut self, args: &[String], si_out: &mut ServiceInfo) -> Result<()> {
+        if self.running_command.is_some() {
+            bail!("Got fdo.sys:exec_cb while another command is still running");
+        }
+        let (command, args) = args
+            .split_first()
+            .context("Empty fdo.sys:exec_cb command")?;
+        log::info!("Starting fdo.sys command {} {:?}", command, args);
+        self.running_command = Some(
+            Command::new(command)
+                .args(args)
+                .spawn()
+                .with_context(|| format!("Error starting command {command}"))?,
+        );
+        self.report_status(si_out, SYS_EXEC_CB_STATUS_TIMEOUT).await
+    }
+
+    async fn status_cb(
+        &mut self,
+        completed: bool,
+        timeout: u64,
+        si_out: &mut ServiceInfo,
+    ) -> Result<()> {
+        if completed {
+            // The owner requests the command to be terminated
+            if let Some(child) = &mut self.running_command {
+                log::info!("Owner requested termination of fdo.sys command");
+                child.kill().context("Error terminating command")?;
+            }
+        }
+        self.report_status(si_out, std::cmp::min(timeout, SYS_EXEC_CB_MAX_WAIT))
+            .await
+    }
+
+    /// Waits up to `timeout` seconds for the running command, and reports its
+    /// exit code if it finished or the time waited if it is still running
+    async fn report_status(&mut self, si_out: &mut ServiceInfo, timeout: u64) -> Result<()> {
+        let child = match &mut self.running_command {
+            None => bail!("Got fdo.sys:status_cb without a running command"),
+            Some(child) => child,
+        };
+        let deadline = Instant::now() + Duration::from_secs(timeout);
+
+        loop {
+            if let Some(status) = child.try_wait().context("Error checking command status")? {
+                log::info!("fdo.sys command finished: {:?}", status);
+                self.running_command = None;
+                // Commands killed by a signal get the usual shell exit code
+                let code = status
+                    .code()
+                    .or_else(|| status.signal().map(|signal| 128 + signal))
+                    .unwrap_or(-1);
+                return Ok(si_out.add(
+                    StandardServiceInfoModule::Sys,
+                    "status_cb",
+                    &(true, code, 0),
+                )?);
+            }
+            if Instant::now() >= deadline {
+                return Ok(si_out.add(
+                    StandardServiceInfoModule::Sys,
+                    "status_cb",
+                    &(false, 0, timeout),
+                )?);
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+    }
+}
+
 #[derive(Debug)]
 struct CommandInProgress {
     command: Option<String>,
@@ -402,7 +804,9 @@ impl CommandInProgress {
         }
     }
 
-    fn execute(self, si_out: &mut ServiceInfo) -> Result<()> {
+    /// Runs the command, and adds its results to `si_out`. The output can be
+    /// large, so it is split over as many entries as needed for the MTU.
+    fn execute(self, si_out: &mut ServiceInfo, service_info_mtu: u64) -> Result<()> {
         si_out.add(
             FedoraIotServiceInfoModule::Command,
             "command",
@@ -416,17 +820,19 @@ impl CommandInProgress {
         let output = cmd.output().context("Error running command")?;
 
         if self.return_stdout {
-            si_out.add(
+            si_out.add_chunked(
                 FedoraIotServiceInfoModule::Command,
                 "stdout",
-                &serde_bytes::Bytes::new(&output.stdout),
+                &output.stdout,
+                service_info_mtu,
             )?;
         }
         if self.return_stderr {
-            si_out.add(
+            si_out.add_chunked(
                 FedoraIotServiceInfoModule::Command,
                 "stderr",
-                &serde_bytes::Bytes::new(&output.stderr),
+                &output.stderr,
+                service_info_mtu,
             )?;
         }
         si_out.add(
@@ -448,7 +854,113 @@ impl CommandInProgress {
     }
 }
 
-async fn process_serviceinfo_in(si_in: &ServiceInfo, si_out: &mut ServiceInfo) -> Result<bool> {
+// Locations to get the device model and serial number from, in order of preference
+const DEVICE_MODEL_PATHS: &[&str] = &[
+    "/sys/class/dmi/id/product_name",
+    "/sys/firmware/devicetree/base/model",
+];
+const DEVICE_SERIAL_PATHS: &[&str] = &[
+    "/sys/class/dmi/id/product_serial",
+    "/sys/firmware/devicetree/base/serial-number",
+];
+
+fn read_first_sysfs_value(paths: &[&str]) -> Option<String> {
+    paths.iter().find_map(|path| {
+        let value = fs::read(path).ok()?;
+        // Device tree values are NUL-terminated
+        let value = String::from_utf8_lossy(&value)
+            .trim_matches(|c: char| c == '\0' || c.is_whitespace())
+            .to_string();
+        if value.is_empty() {
+            None
+        } else {
+            Some(value)
+        }
+    })
+}
+
+fn devmod_bin() -> &'static str {
+    match std::env::consts::ARCH {
+        "x86_64" => "x86:X86_64",
+        "x86" => "x86:X86",
+        "aarch64" => "arm:ARM64",
+        "arm" => "arm:ARM",
+        arch => arch,
+    }
+}
+
+fn devmod_progenv() -> String {
+    let mut progenv = vec!["bin"];
+    for (env, path) in [
+        ("sh", "/bin/sh"),
+        ("py3", "/usr/bin/python3"),
+        ("py2", "/usr/bin/python2"),
+    ] {
+        if Path::new(path).exists() {
+            progenv.push(env);
+        }
+    }
+    progenv.join(":")
+}
+
+fn add_devmod(out_si: &mut ServiceInfo, modules: &[ServiceInfoModule]) -> Result<()> {
+    let sysinfo =
+        sys_info::linux_os_release().context("Error getting operating system information")?;
+    let version = sysinfo
+        .pretty_name
+        .context("Operating system did not provide a version")?;
+    let device = read_first_sysfs_value(DEVICE_MODEL_PATHS).unwrap_or_else(|| {
+        log::warn!("Unable to determine device model");
+        "unknown".to_string()
+    });
+    let tmp = env::temp_dir();
+    let tmp = tmp
+        .to_str()
+        .context("Temporary directory path is not valid UTF-8")?;
+
+    out_si.add(StandardServiceInfoModule::DevMod, "active", &true)?;
+    out_si.add(
+        StandardServiceInfoModule::DevMod,
+        "os",
+        &std::env::consts::OS,
+    )?;
+    out_si.add(
+        StandardServiceInfoModule::DevMod,
+        "arch",
+        &std::env::consts::ARCH,
+    )?;
+    out_si.add(StandardServiceInfoModule::DevMod, "version", &version)?;
+    out_si.add(StandardServiceInfoModule::DevMod, "device", &device)?;
+    if let Some(serial) = read_first_sysfs_value(DEVICE_SERIAL_PATHS) {
+        out_si.add(
+            StandardServiceInfoModule::DevMod,
+            "sn",
+            &serde_bytes::Bytes::new(serial.as_bytes()),
+        )?;
+    }
+    out_si.add(StandardServiceInfoModule::DevMod, "pathsep", &"/")?;
+    out_si.add(StandardServiceInfoModule::DevMod, "sep", &":")?;
+    out_si.add(StandardServiceInfoModule::DevMod, "nl", &"\n")?;
+    out_si.add(StandardServiceInfoModule::DevMod, "tmp", &tmp)?;
+    out_si.add(StandardServiceInfoModule::DevMod, "dir", &"/")?;
+    out_si.add(
+        StandardServiceInfoModule::DevMod,
+        "progenv",
+        &devmod_progenv(),
+    )?;
+    out_si.add(StandardServiceInfoModule::DevMod, "bin", &devmod_bin())?;
+    out_si.add_modules(modules)?;
+
+    Ok(())
+}
+
+async fn process_serviceinfo_in(
+    si_in: &ServiceInfo,
+    si_out: &mut ServiceInfo,
+    sys_state: &mut SysModuleState,
+    uploads: &mut VecDeque<UploadInProgress>,
+    service_info_mtu: u64,
+) -> Result<bool> {
     let mut active_modules: HashSet<ServiceInfoModule> = HashSet::new();
 
     let mut sshkey_user: Option<String> = None;
@@ -465,6 +977,8 @@ async fn process_serviceinfo_in(si_in: &ServiceInfo, si_out: &mut ServiceInfo) -
     let mut command_in_progress = CommandInProgress::new();
     let mut disk_encryption_in_progress = DiskEncryptionInProgress::new();
 
+    let mut upload_need_sha = false;
+
     let mut reboot_requested = false;
 
     for (module, key, value) in si_in.iter() {
@@ -484,7 +998,44 @@ async fn process_serviceinfo_in(si_in: &ServiceInfo, si_out: &mut ServiceInfo) -
             log::trace!("Skipping non-activated module {}", module);
             bail!("Non-activated module {} got request", module);
         }
-        if module == FedoraIotServiceInfoModule::SSHKey.into() {
+        if module == StandardServiceInfoModule::Sys.into() {
+            if key == "filedesc" {
+                sys_state.filedesc(value.as_str().context("Error parsing fdo.sys filedesc")?)?;
+            } else if key == "write" {
+                sys_state.write(value.as_bytes().context("Error parsing fdo.sys write")?)?;
+            } else if key == "exec" {
+                sys_state
+                    .exec(&value.as_str_array().context("Error parsing fdo.sys exec")?)
+                    .context("Error executing fdo.sys command")?;
+            } else if key == "exec_cb" {
+                sys_state
+                    .exec_cb(
+                        &value
+                            .as_str_array()
+                            .context("Error parsing fdo.sys exec_cb")?,
+                        si_out,
+                    )
+                    .await
+                    .context("Error starting fdo.sys command")?;
+            } else if key == "status_cb" {
+                let (completed, timeout) = match &value {
+                    CborSimpleType::Array(status) if status.len() == 3 => {
+                        (status[0].as_bool(), status[2].as_u64())
+                    }
+                    _ => (None, None),
+                };
+                let completed = completed.context("Error parsing fdo.sys status_cb completed")?;
+                let timeout = timeout.context("Error parsing fdo.sys status_cb timeout")?;
+                sys_state.status_cb(completed, timeout, si_out).await?;
+            }
+        } else if module == StandardServiceInfoModule::Upload.into() {
+            if key == "need_sha" {
+                upload_need_sha = value.as_bool().context("Error parsing upload need_sha")?;
+            } else if key == "name" {
+                let name = value.as_str().context("Error parsing upload name")?;
+                uploads.push_back(UploadInProgress::new(name, upload_need_sha));
+            }
+        } else if module == FedoraIotServiceInfoModule::SSHKey.into() {
             if key == "username" {
                 let value = value.as_str().context("Error parsing username value")?;
                 sshkey_user = Some(value.to_string());
@@ -645,7 +1196,7 @@ async fn process_serviceinfo_in(si_in: &ServiceInfo, si_out: &mut ServiceInfo) -
                     .context("Error parsing command return_stderr")?;
             } else if key == "execute" {
                 command_in_progress
-                    .execute(si_out)
+                    .execute(si_out, service_info_mtu)
                     .context("Error executing command")?;
                 command_in_progress = CommandInProgress::new();
             }
@@ -759,46 +1310,52 @@ async fn process_serviceinfo_in(si_in: &ServiceInfo, si_out: &mut ServiceInfo) -
     Ok(reboot_requested)
 }
 
-pub(crate) async fn perform_to2_serviceinfos(client: &mut ServiceClient) -> Result<bool> {
+pub(crate) async fn perform_to2_serviceinfos(
+    client: &mut ServiceClient,
+    service_info_mtu: u64,
+    max_service_info_loops: u32,
+) -> Result<bool> {
     let mut loop_num = 0;
     let mut out_si = ServiceInfo::new();
+    let mut out_si_chunks: VecDeque<ServiceInfo> = VecDeque::new();
+    let mut in_si = ServiceInfo::new();
+    let mut owner_has_more = false;
+    let mut sys_state = SysModuleState::default();
+    let mut download_in_progress = DownloadInProgress::new(env::var("BINARYFILE_PATH_PREFIX").ok());
+    let mut uploads: VecDeque<UploadInProgress> = VecDeque::new();
     let mut reboot_required = false;
 
-    while loop_num < MAX_SERVICE_INFO_LOOPS {
+    while loop_num < max_service_info_loops {
         if loop_num == 0 {
             let modules = find_available_modules().context("Error getting list of modules")?;
-            let sysinfo = sys_info::linux_os_release()
-                .context("Error getting operating system information")?;
 
             // We just blindly send the devmod module
-            out_si.add(StandardServiceInfoModule::DevMod, "active", &true)?;
-            out_si.add(
-                StandardServiceInfoModule::DevMod,
-                "os",
-                &std::env::consts::OS,
-            )?;
-            out_si.add(
-                StandardServiceInfoModule::DevMod,
-                "arch",
-                &std::env::consts::ARCH,
-            )?;
-            out_si.add(
-                StandardServiceInfoModule::DevMod,
-                "version",
-                &sysinfo.pretty_name.unwrap(),
-            )?;
-            out_si.add(StandardServiceInfoModule::DevMod, "device", &"unused")?;
-            out_si.add(StandardServiceInfoModule::DevMod, "sep", &":")?;
-            out_si.add(
-                StandardServiceInfoModule::DevMod,
-                "bin",
-                &std::env::consts::ARCH,
-            )?;
-            out_si.add_modules(&modules)?;
+            add_devmod(&mut out_si, &modules).context("Error adding devmod ServiceInfo")?;
         }
 
-        let send_si = DeviceServiceInfo::new(false, out_si);
-        out_si = ServiceInfo::new();
+        let send_si = if owner_has_more {
+            // While the owner has more for us, we are only allowed to send empty messages
+            DeviceServiceInfo::new(false, ServiceInfo::new())
+        } else {
+            if out_si_chunks.is_empty() {
+                // Uploads are read from disk about one message at a time
+                if let Some(upload) = uploads.front_mut() {
+                    if upload
+                        .send_data(&mut out_si, service_info_mtu)
+                        .with_context(|| format!("Error uploading {}", upload.name))?
+                    {
+                        uploads.pop_front();
+                    }
+                }
+                out_si_chunks.extend(
+                    std::mem::take(&mut out_si)
+                        .split_to_mtu(service_info_mtu)
+                        .context("Error splitting ServiceInfo")?,
+                );
+            }
+            let chunk = out_si_chunks.pop_front().unwrap_or_default();
+            DeviceServiceInfo::new(!out_si_chunks.is_empty() || !uploads.is_empty(), chunk)
+        };
         log::trace!("Sending ServiceInfo loop {}: {:?}", loop_num, send_si);
 
         let return_si: RequestResult<OwnerServiceInfo> = client.send_request(send_si, None).await;
@@ -807,33 +1364,57 @@ pub(crate) async fn perform_to2_serviceinfos(client: &mut ServiceClient) -> Resu
         log::trace!("Got ServiceInfo loop {}: {:?}", loop_num, return_si);
 
         if return_si.is_done() {
+            if download_in_progress.transfer.is_some() {
+                log::warn!("Owner finished ServiceInfo during an fdo.download transfer");
+            }
+            if sys_state.running_command.is_some() {
+                log::warn!("Owner finished ServiceInfo while an fdo.sys command is still running");
+            }
+            if !uploads.is_empty() {
+                log::warn!("Owner finished ServiceInfo during an fdo.upload transfer");
+            }
             log::trace!("ServiceInfo loops done, number taken: {}", loop_num);
             return Ok(reboot_required);
         }
-        if return_si.is_more_service_info() {
-            // TODO
-            bail!("OwnerServiceInfo indicated it has more for us.. we don't support that yet");
-        }
 
-        // Process
-        let reboot_si = process_serviceinfo_in(return_si.service_info(), &mut out_si)
+        // Downloads are streamed to disk as they arrive, everything else is
+        // collected until the owner indicates it has no more for us
+        for (module, key, value) in return_si.service_info().iter() {
+            if module == StandardServiceInfoModule::Download.into() {
+                download_in_progress
+                    .process(&key, &value, &mut out_si)
+                    .context("Error processing fdo.download")?;
+            } else {
+                in_si.add(module, &key, &value)?;
+            }
+        }
+        owner_has_more = return_si.is_more_service_info();
+        if !owner_has_more && !in_si.is_empty() {
+            let reboot_si = process_serviceinfo_in(
+                &std::mem::take(&mut in_si),
+                &mut out_si,
+                &mut sys_state,
+                &mut uploads,
+                service_info_mtu,
+            )
             .await
             .context("Error processing returned serviceinfo")?;
-        if !reboot_required {
-            reboot_required = reboot_si;
+            if !reboot_required {
+                reboot_required = reboot_si;
+            }
         }
 
         loop_num += 1;
     }
     Err(anyhow!(
         "Maximum number of ServiceInfo loops ({}) exceeded",
-        MAX_SERVICE_INFO_LOOPS
+        max_service_info_loops
     ))
 }
 
 #[cfg(test)]
 mod test {
-    use std::path::PathBuf;
+    use std::path::{Path, PathBuf};
 
     use super::BinaryFileInProgress;
 
@@ -906,4 +1487,292 @@ mod test {
         let empty_password = "";
         assert!(create_user_with_password(test_user, empty_password).is_ok());
     }
+
+    #[test]
+    fn test_read_first_sysfs_value() {
+        let path = std::env::temp_dir().join(format!("fdo-test-model-{}", std::process::id()));
+        std::fs::write(&path, b"Test Model\n\0").unwrap();
+        let path_str = path.to_str().unwrap();
+
+        assert_eq!(
+            read_first_sysfs_value(&["/nonexistent/fdo/model", path_str]),
+            Some("Test Model".to_string())
+        );
+        assert_eq!(read_first_sysfs_value(&["/nonexistent/fdo/model"]), None);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    fn download_test_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("fdo-test-download-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn run_download(dir: &Path, messages: &[(&str, CborSimpleType)]) -> Result<Vec<i64>> {
+        let mut download = DownloadInProgress::new(Some(dir.to_str().unwrap().to_string()));
+        let mut si_out = ServiceInfo::new();
+        for (key, value) in messages {
+            download.process(key, value, &mut si_out)?;
+        }
+        Ok(si_out
+            .iter()
+            .filter(|(_, key, _)| key == "done")
+            .map(|(_, _, value)| value.as_i64().unwrap())
+            .collect())
+    }
+
+    #[test]
+    fn test_download_any_order() {
+        let contents = b"FDO download test contents\n".repeat(100);
+        let digest = Hash::from_data(HashType::Sha384, &contents).unwrap();
+
+        let length = CborSimpleType::Integer(contents.len() as i128);
+        let name = CborSimpleType::Text("/var/lib/fdo/test.bin".to_string());
+        let sha = CborSimpleType::Bytes(digest.value_bytes().to_vec());
+        let data: Vec<(&str, CborSimpleType)> = contents
+            .chunks(1000)
+            .map(|chunk| ("data", CborSimpleType::Bytes(chunk.to_vec())))
+            .collect();
+
+        let orders = [
+            [("length", &length), ("sha-384", &sha), ("name", &name)],
+            [("name", &name), ("sha-384", &sha), ("length", &length)],
+            [("sha-384", &sha), ("length", &length), ("name", &name)],
+        ];
+        for (num, order) in orders.iter().enumerate() {
+            let dir = download_test_dir(&format!("order{num}"));
+            let mut messages: Vec<(&str, CborSimpleType)> = order
+                .iter()
+                .map(|(key, value)| (*key, (*value).clone()))
+                .collect();
+            messages.extend(data.iter().cloned());
+
+            let done = run_download(&dir, &messages).unwrap();
+            assert_eq!(done, vec![contents.len() as i64]);
+            assert_eq!(
+                std::fs::read(dir.join("var/lib/fdo/test.bin")).unwrap(),
+                contents
+            );
+            assert!(!dir.join("var/lib/fdo/.test.bin.fdo-download").exists());
+            std::fs::remove_dir_all(&dir).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_download_empty_file() {
+        let dir = download_test_dir("empty");
+        let digest = Hash::from_data(HashType::Sha384, b"").unwrap();
+
+        let done = run_download(
+            &dir,
+            &[
+                (
+                    "sha-384",
+                    CborSimpleType::Bytes(digest.value_bytes().to_vec()),
+                ),
+                ("name", CborSimpleType::Text("/empty".to_string())),
+                ("length", CborSimpleType::Integer(0)),
+            ],
+        )
+        .unwrap();
+        assert_eq!(done, vec![0]);
+        assert!(std::fs::read(dir.join("empty")).unwrap().is_empty());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_download_invalid_digest() {
+        let dir = download_test_dir("digest");
+        let digest = Hash::from_data(HashType::Sha384, b"other contents").unwrap();
+
+        let done = run_download(
+            &dir,
+            &[
+                ("name", CborSimpleType::Text("/test.bin".to_string())),
+                ("length", CborSimpleType::Integer(8)),
+                (
+                    "sha-384",
+                    CborSimpleType::Bytes(digest.value_bytes().to_vec()),
+                ),
+                ("data", CborSimpleType::Bytes(b"contents".to_vec())),
+            ],
+        )
+        .unwrap();
+        assert_eq!(done, vec![-1]);
+        assert!(!dir.join("test.bin").exists());
+        assert!(!dir.join(".test.bin.fdo-download").exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_download_incomplete_header() {
+        let dir = download_test_dir("header");
+        let data = ("data", CborSimpleType::Bytes(b"contents".to_vec()));
+
+        assert!(run_download(&dir, std::slice::from_ref(&data)).is_err());
+        assert!(run_download(
+            &dir,
+            &[
+                ("name", CborSimpleType::Text("/test.bin".to_string())),
+                data.clone()
+            ]
+        )
+        .is_err());
+        assert!(run_download(
+            &dir,
+            &[
+                ("name", CborSimpleType::Text("/test.bin".to_string())),
+                ("length", CborSimpleType::Integer(4)),
+                data
+            ]
+        )
+        .is_err());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    fn run_upload(name: &str, need_sha: bool, max_bytes: u64) -> Vec<ServiceInfo> {
+        let mut upload = UploadInProgress::new(name, need_sha);
+        let mut messages = Vec::new();
+        loop {
+            let mut si_out = ServiceInfo::new();
+            let done = upload.send_data(&mut si_out, max_bytes).unwrap();
+            messages.push(si_out);
+            if done {
+                return messages;
+            }
+        }
+    }
+
+    #[test]
+    fn test_upload_in_chunks() {
+        let contents = b"FDO upload test contents\n".repeat(200);
+        let dir = download_test_dir("upload");
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("test.bin");
+        std::fs::write(&path, &contents).unwrap();
+
+        let messages = run_upload(path.to_str().unwrap(), true, 2048);
+        // 5000 bytes are sent as 2048 bytes per message, rounded up to whole chunks
+        assert_eq!(messages.len(), 3);
+
+        let mut received = Vec::new();
+        let mut digest = None;
+        for (num, message) in messages.iter().enumerate() {
+            let mut message_bytes = 0;
+            for (_, key, value) in message.iter() {
+                match key.as_str() {
+                    "name" => assert_eq!(value.as_str().unwrap(), path.to_str().unwrap()),
+                    "length" => assert_eq!(value.as_u64().unwrap(), contents.len() as u64),
+                    "data" => {
+                        let data = value.as_bytes().unwrap();
+                        assert!(data.len() <= UPLOAD_CHUNK_SIZE);
+                        message_bytes += data.len();
+                        received.extend_from_slice(data);
+                    }
+                    "sha-384" => digest = Some(value.as_bytes().unwrap().to_vec()),
+                    _ => panic!("Unexpected fdo.upload:{}", key),
+                }
+            }
+            if num < messages.len() - 1 {
+                assert_eq!(message_bytes, 2048);
+            }
+        }
+        assert_eq!(received, contents);
+        assert_eq!(
+            digest.unwrap(),
+            Hash::from_data(HashType::Sha384, &contents)
+                .unwrap()
+                .value()
+        );
+
+        // Without need_sha, no digest is sent
+        let messages = run_upload(path.to_str().unwrap(), false, 2048);
+        assert!(messages
+            .iter()
+            .all(|message| message.iter().all(|(_, key, _)| key != "sha-384")));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_upload_empty_and_missing_file() {
+        let dir = download_test_dir("upload-empty");
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("empty.bin");
+        std::fs::write(&path, b"").unwrap();
+
+        let messages = run_upload(path.to_str().unwrap(), true, 2048);
+        assert_eq!(messages.len(), 1);
+        let keys: Vec<String> = messages[0].iter().map(|(_, key, _)| key).collect();
+        assert_eq!(keys, vec!["name", "length", "sha-384"]);
+
+        let messages = run_upload(dir.join("missing.bin").to_str().unwrap(), true, 2048);
+        assert_eq!(messages.len(), 1);
+        let keys: Vec<String> = messages[0].iter().map(|(_, key, _)| key).collect();
+        assert_eq!(keys, vec!["name", "error"]);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    fn sys_status(si: &ServiceInfo) -> Vec<CborSimpleType> {
+        si.iter()
+            .filter(|(_, key, _)| key == "status_cb")
+            .map(|(_, _, value)| value)
+            .collect()
+    }
+
+    fn status_value(completed: bool, code: i128, timeout: i128) -> CborSimpleType {
+        CborSimpleType::Array(vec![
+            CborSimpleType::Bool(completed),
+            CborSimpleType::Integer(code),
+            CborSimpleType::Integer(timeout),
+        ])
+    }
+
+    #[tokio::test]
+    async fn test_sys_exec_cb_exit_code() {
+        let mut state = SysModuleState::default();
+        let mut si_out = ServiceInfo::new();
+        state
+            .exec_cb(&["true".to_string()], &mut si_out)
+            .await
+            .unwrap();
+        state
+            .exec_cb(
+                &["sh".to_string(), "-c".to_string(), "exit 3".to_string()],
+                &mut si_out,
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            sys_status(&si_out),
+            vec![status_value(true, 0, 0), status_value(true, 3, 0)]
+        );
+        assert!(state.running_command.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_sys_status_cb_timeout() {
+        let mut state = SysModuleState {
+            running_command: Some(Command::new("sleep").arg("30").spawn().unwrap()),
+            ..Default::default()
+        };
+
+        // Still running, we report how long we waited
+        let mut si_out = ServiceInfo::new();
+        state.status_cb(false, 1, &mut si_out).await.unwrap();
+        assert_eq!(sys_status(&si_out), vec![status_value(false, 0, 1)]);
+        assert!(state.running_command.is_some());
+
+        // The owner terminates it
+        let mut si_out = ServiceInfo::new();
+        state.status_cb(true, 1, &mut si_out).await.unwrap();
+        assert_eq!(sys_status(&si_out), vec![status_value(true, 128 + 9, 0)]);
+        assert!(state.running_command.is_none());
+
+        let mut si_out = ServiceInfo::new();
+        assert!(state.status_cb(false, 1, &mut si_out).await.is_err());
+    }
 }
@@ -414,6 +414,30 @@ impl ParsedArray<ParsedArraySizeDynamic> {
         self.contents.push(item.serialize_data()?);
         Ok(())
     }
+
+    /// Converts into a sized array, if it has the expected number of elements
+    pub(crate) fn into_sized<N>(self) -> Result<ParsedArray<N>, Error>
+    where
+        N: ParsedArraySizeStatic,
+    {
+        let expected_len = N::SIZE.unwrap_or_default();
+        if self.contents.len() as u64 != expected_len {
+            return Err(ArrayParseError::InvalidNumberOfElements(
+                self.contents.len() as u64,
+                expected_len,
+            )
+            .into());
+        }
+
+        Ok(ParsedArray {
+            tag: self.tag,
+
+            header: None,
+            contents: self.contents,
+
+            _marker: std::marker::PhantomData,
+        })
+    }
 }
 
 #[derive(Clone, Default)]
@@ -445,6 +469,12 @@ where
         Ok(())
     }
 
+    /// Sets an element to already encoded CBOR data, which is kept as-is
+    pub(crate) fn set_raw(&mut self, n: usize, value: Vec<u8>) {
+        check_bounds!(n);
+        self.contents[n] = Some(value);
+    }
+
     /// This method turns the ParsedArrayBuilder into a ParsedArray.
     ///
     /// Safety:
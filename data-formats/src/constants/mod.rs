@@ -120,7 +120,67 @@ pub enum DeviceSigType {
     StEPID20 = 92,
 }
 
-#[derive(Debug, Clone, Copy, Serialize_repr, Deserialize_repr)]
+const PS256: i16 = -37;
+const PS384: i16 = -38;
+
+// RSA signature algorithms are not provided by the COSE library, so we handle
+// those ourselves.
+#[derive(Debug, Clone, Copy, Serialize_repr, Deserialize_repr, Eq, PartialEq, FromPrimitive)]
+#[repr(i16)]
+#[non_exhaustive]
+pub enum RsaSignatureAlgorithm {
+    Rs256 = RS256,
+    Rs384 = RS384,
+    Ps256 = PS256,
+    Ps384 = PS384,
+}
+
+impl RsaSignatureAlgorithm {
+    pub fn for_key(key_bits: u32, use_pss: bool) -> Result<Self> {
+        match (key_bits, use_pss) {
+            (2048, false) => Ok(RsaSignatureAlgorithm::Rs256),
+            (3072, false) => Ok(RsaSignatureAlgorithm::Rs384),
+            (2048, true) => Ok(RsaSignatureAlgorithm::Ps256),
+            (3072, true) => Ok(RsaSignatureAlgorithm::Ps384),
+            _ => Err(Error::UnsupportedAlgorithm),
+        }
+    }
+
+    pub fn from_cose_alg(alg: i128) -> Option<Self> {
+        RsaSignatureAlgorithm::from_i128(alg)
+    }
+
+    pub fn get_md(&self) -> MessageDigest {
+        match self {
+            RsaSignatureAlgorithm::Rs256 | RsaSignatureAlgorithm::Ps256 => MessageDigest::sha256(),
+            RsaSignatureAlgorithm::Rs384 | RsaSignatureAlgorithm::Ps384 => MessageDigest::sha384(),
+        }
+    }
+
+    pub fn uses_pss(&self) -> bool {
+        matches!(
+            self,
+            RsaSignatureAlgorithm::Ps256 | RsaSignatureAlgorithm::Ps384
+        )
+    }
+
+    pub fn padding(&self) -> openssl::rsa::Padding {
+        if self.uses_pss() {
+            openssl::rsa::Padding::PKCS1_PSS
+        } else {
+            openssl::rsa::Padding::PKCS1
+        }
+    }
+
+    pub fn device_sig_type(&self) -> DeviceSigType {
+        match self {
+            RsaSignatureAlgorithm::Rs256 | RsaSignatureAlgorithm::Ps256 => DeviceSigType::StRSA2048,
+            RsaSignatureAlgorithm::Rs384 | RsaSignatureAlgorithm::Ps384 => DeviceSigType::StRSA3072,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize_repr, Deserialize_repr, FromPrimitive)]
 #[repr(i16)]
 #[non_exhaustive]
 pub enum PublicKeyType {
@@ -131,6 +191,18 @@ pub enum PublicKeyType {
     SECP384R1 = 11,
 }
 
+impl PublicKeyType {
+    /// Also accepts the COSE algorithm identifiers that some FDO 1.0
+    /// implementations use as the type of EC keys
+    pub(crate) fn from_i16_compat(value: i16) -> Option<Self> {
+        match value {
+            -7 => Some(PublicKeyType::SECP256R1),
+            -35 => Some(PublicKeyType::SECP384R1),
+            value => PublicKeyType::from_i16(value),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy, Serialize_repr, Deserialize_repr)]
 #[repr(u8)]
 #[non_exhaustive]
@@ -207,22 +279,22 @@ pub enum RendezvousVariable {
 }
 
 impl RendezvousVariable {
-    fn name(&self) -> &'static str {
+    pub fn name(&self) -> &'static str {
         match self {
-            RendezvousVariable::DeviceOnly => "device-only",
-            RendezvousVariable::OwnerOnly => "owner-only",
-            RendezvousVariable::IPAddress => "ip-address",
-            RendezvousVariable::DevicePort => "device-port",
-            RendezvousVariable::OwnerPort => "owner-port",
+            RendezvousVariable::DeviceOnly => "device_only",
+            RendezvousVariable::OwnerOnly => "owner_only",
+            RendezvousVariable::IPAddress => "ip_address",
+            RendezvousVariable::DevicePort => "device_port",
+            RendezvousVariable::OwnerPort => "owner_port",
             RendezvousVariable::Dns => "dns",
-            RendezvousVariable::ServerCertHash => "server-cert-hash",
-            RendezvousVariable::CaCertHash => "ca-cert-hash",
-            RendezvousVariable::UserInput => "user-input",
-            RendezvousVariable::WifiSsid => "wifi-ssid",
-            RendezvousVariable::WifiPw => "wifi-pw",
+            RendezvousVariable::ServerCertHash => "server_cert_hash",
+            RendezvousVariable::CaCertHash => "ca_cert_hash",
+            RendezvousVariable::UserInput => "user_input",
+            RendezvousVariable::WifiSsid => "wifi_ssid",
+            RendezvousVariable::WifiPw => "wifi_pw",
             RendezvousVariable::Medium => "medium",
             RendezvousVariable::Protocol => "protocol",
-            RendezvousVariable::Delaysec => "delay-sec",
+            RendezvousVariable::Delaysec => "delay_sec",
             RendezvousVariable::Bypass => "bypass",
         }
     }
@@ -271,14 +343,76 @@ impl RendezvousVariable {
                 _ => return Err(Error::InconsistentValue(self.name())),
             },
 
+            // These are hashes, in "<algorithm>:<hex digest>" notation
+            RendezvousVariable::ServerCertHash | RendezvousVariable::CaCertHash => match val {
+                serde_cbor::Value::Text(v) => {
+                    serde_cbor::value::to_value(crate::types::Hash::from_str(&v)?)?
+                }
+                _ => return Err(Error::InconsistentValue(self.name())),
+            },
+
             // TODO
-            RendezvousVariable::ServerCertHash => {
-                return Err(Error::NotImplemented("ServerCertHash"))
-            }
-            RendezvousVariable::CaCertHash => return Err(Error::NotImplemented("CaCertHash")),
             RendezvousVariable::Medium => return Err(Error::NotImplemented("Medium")),
         })
     }
+
+    pub fn value_from_machine_to_human(&self, val: serde_cbor::Value) -> Result<serde_cbor::Value> {
+        Ok(match self {
+            // These are just markers: their existence means they're true
+            RendezvousVariable::DeviceOnly
+            | RendezvousVariable::OwnerOnly
+            | RendezvousVariable::UserInput
+            | RendezvousVariable::Bypass => serde_cbor::Value::Bool(true),
+
+            // These are integers
+            RendezvousVariable::DevicePort
+            | RendezvousVariable::OwnerPort
+            | RendezvousVariable::Delaysec => match val {
+                serde_cbor::Value::Integer(i) => serde_cbor::Value::Integer(i),
+                _ => return Err(Error::InconsistentValue(self.name())),
+            },
+
+            // These are strings
+            RendezvousVariable::Dns | RendezvousVariable::WifiSsid | RendezvousVariable::WifiPw => {
+                match val {
+                    serde_cbor::Value::Text(t) => serde_cbor::Value::Text(t),
+                    _ => return Err(Error::InconsistentValue(self.name())),
+                }
+            }
+
+            // Slightly more complicated values
+            RendezvousVariable::Protocol => {
+                let protocol: RendezvousProtocolValue = serde_cbor::value::from_value(val)?;
+                serde_cbor::Value::Text(protocol.name().to_string())
+            }
+
+            RendezvousVariable::IPAddress => match val {
+                serde_cbor::Value::Bytes(v) => {
+                    let addr = match v.len() {
+                        4 => std::net::IpAddr::from(<[u8; 4]>::try_from(&v[..]).unwrap()),
+                        16 => std::net::IpAddr::from(<[u8; 16]>::try_from(&v[..]).unwrap()),
+                        _ => return Err(Error::InconsistentValue(self.name())),
+                    };
+                    serde_cbor::Value::Text(addr.to_string())
+                }
+                _ => return Err(Error::InconsistentValue(self.name())),
+            },
+
+            // These are hashes, in "<algorithm>:<hex digest>" notation
+            RendezvousVariable::ServerCertHash | RendezvousVariable::CaCertHash => {
+                let hash: crate::types::Hash = serde_cbor::value::from_value(val)?;
+                let alg = match hash.get_type() {
+                    HashType::Sha256 => "sha256",
+                    HashType::Sha384 => "sha384",
+                    _ => return Err(Error::InconsistentValue(self.name())),
+                };
+                serde_cbor::Value::Text(format!("{}:{}", alg, hex::encode(hash.value())))
+            }
+
+            // No human notation is defined for this yet
+            RendezvousVariable::Medium => val,
+        })
+    }
 }
 
 impl FromStr for RendezvousVariable {
@@ -338,10 +472,23 @@ impl FromStr for RendezvousProtocolValue {
 }
 
 impl RendezvousProtocolValue {
+    fn name(&self) -> &'static str {
+        match self {
+            RendezvousProtocolValue::Rest => "rest",
+            RendezvousProtocolValue::Http => "http",
+            RendezvousProtocolValue::Https => "https",
+            RendezvousProtocolValue::Tcp => "tcp",
+            RendezvousProtocolValue::Tls => "tls",
+            RendezvousProtocolValue::CoAPTCP => "coaptcp",
+            RendezvousProtocolValue::CoAPUDP => "coapudp",
+        }
+    }
+
     pub(crate) fn default_port(&self) -> Option<u32> {
         match self {
             RendezvousProtocolValue::Http => Some(80),
             RendezvousProtocolValue::Https => Some(443),
+            RendezvousProtocolValue::CoAPUDP => Some(5683),
             _ => None,
         }
     }
@@ -459,3 +606,67 @@ impl FromStr for KeyStorageType {
         })
     }
 }
+
+#[cfg(test)]
+mod test_rendezvous_variable {
+    use std::str::FromStr;
+
+    use super::RendezvousVariable;
+
+    fn roundtrip(name: &str, human: serde_cbor::Value) {
+        let variable = RendezvousVariable::from_str(name).unwrap();
+        let machine = variable.value_from_human_to_machine(human.clone()).unwrap();
+        let back = variable.value_from_machine_to_human(machine).unwrap();
+        assert_eq!(back, human);
+    }
+
+    #[test]
+    fn test_rendezvous_value_roundtrip() {
+        roundtrip(
+            "ip_address",
+            serde_cbor::Value::Text("192.0.2.1".to_string()),
+        );
+        roundtrip("ip", serde_cbor::Value::Text("2001:db8::1".to_string()));
+        roundtrip("device_port", serde_cbor::Value::Integer(8082));
+        roundtrip(
+            "dns",
+            serde_cbor::Value::Text("fdo.example.com".to_string()),
+        );
+        roundtrip("protocol", serde_cbor::Value::Text("https".to_string()));
+        roundtrip("bypass", serde_cbor::Value::Bool(true));
+        roundtrip(
+            "server_cert_hash",
+            serde_cbor::Value::Text(
+                "sha256:8a2235cbccf8f70f55d5f610053685eefc153983eb9867f556976115fb9a1692"
+                    .to_string(),
+            ),
+        );
+    }
+
+    #[test]
+    fn test_rendezvous_variable_name_roundtrip() {
+        for variable in [
+            RendezvousVariable::DeviceOnly,
+            RendezvousVariable::OwnerOnly,
+            RendezvousVariable::IPAddress,
+            RendezvousVariable::DevicePort,
+            RendezvousVariable::OwnerPort,
+            RendezvousVariable::Dns,
+            RendezvousVariable::ServerCertHash,
+            RendezvousVariable::CaCertHash,
+            RendezvousVariable::UserInput,
+            RendezvousVariable::WifiSsid,
+            RendezvousVariable::WifiPw,
+            RendezvousVariable::Medium,
+            RendezvousVariable::Protocol,
+            RendezvousVariable::Delaysec,
+            RendezvousVariable::Bypass,
+        ] {
+            assert_eq!(
+                RendezvousVariable::from_str(variable.name()).unwrap() as u8,
+                variable as u8
+            );
+        }
+        assert!(RendezvousVariable::from_str("device-port").is_err());
+    }
+}
@@ -60,6 +60,9 @@ impl FromStr for ServiceInfoModule {
             }
 
             "devmod" => StandardServiceInfoModule::DevMod.into(),
+            "fdo.sys" => StandardServiceInfoModule::Sys.into(),
+            "fdo.download" => StandardServiceInfoModule::Download.into(),
+            "fdo.upload" => StandardServiceInfoModule::Upload.into(),
 
             other => ServiceInfoModule::Unsupported(other.to_string()),
         })
@@ -105,6 +108,9 @@ impl Display for ServiceInfoModule {
 #[non_exhaustive]
 pub enum StandardServiceInfoModule {
     DevMod,
+    Sys,
+    Download,
+    Upload,
 }
 
 impl Display for StandardServiceInfoModule {
@@ -114,6 +120,9 @@ impl Display for StandardServiceInfoModule {
             "{}",
             match self {
                 StandardServiceInfoModule::DevMod => "devmod",
+                StandardServiceInfoModule::Sys => "fdo.sys",
+                StandardServiceInfoModule::Download => "fdo.download",
+                StandardServiceInfoModule::Upload => "fdo.upload",
             }
         )
     }
@@ -1,18 +1,24 @@
 use std::{
     cell::RefCell,
     convert::{TryFrom, TryInto},
+    ffi::CString,
+    ptr::null_mut,
 };
 
 use crate::{
-    constants::HashType,
+    constants::{HashType, RsaSignatureAlgorithm},
+    devicecredential::DeviceSigner,
     errors::Error,
     types::HMac,
-    types::{Guid, Hash, RendezvousInfo},
+    types::{Guid, Hash, RendezvousInfo, RsaSigningKey},
     DeviceCredential, ProtocolVersion,
 };
 
 use aws_nitro_enclaves_cose::{error::CoseError, sign::SignatureAlgorithm};
-use openssl::{pkey::PKey, sign::Signer};
+use openssl::{
+    pkey::{Id, PKey},
+    sign::Signer,
+};
 use serde::{Deserialize, Serialize};
 use serde_tuple::Serialize_tuple;
 use tss_esapi::{
@@ -33,11 +39,14 @@ pub enum KeyStorage {
     },
 }
 
+fn tcti_conf() -> tss_esapi::tcti_ldr::TctiNameConf {
+    tss_esapi::tcti_ldr::TctiNameConf::from_environment_variable()
+        .unwrap_or_else(|_| tss_esapi::tcti_ldr::TctiNameConf::Tabrmd(Default::default()))
+}
+
 fn get_semi_tpm_ctx_and_primary(
 ) -> Result<(tss_esapi::Context, tss_esapi::handles::KeyHandle), Error> {
-    let tcti_conf = tss_esapi::tcti_ldr::TctiNameConf::from_environment_variable()
-        .unwrap_or_else(|_| tss_esapi::tcti_ldr::TctiNameConf::Tabrmd(Default::default()));
-    let mut tss_context = tss_esapi::Context::new(tcti_conf)?;
+    let mut tss_context = tss_esapi::Context::new(tcti_conf())?;
 
     let primary_template = semi_tpm_primary_key_template()?;
     let primary_handle = tss_context
@@ -202,13 +211,22 @@ impl DeviceCredential for FileDeviceCredential {
         &self.pubkey_hash
     }
 
-    fn get_signer(
-        &self,
-    ) -> Result<Box<dyn aws_nitro_enclaves_cose::crypto::SigningPrivateKey>, Error> {
+    fn perform_hmac(&self, data: &[u8], hmac_type: HashType) -> Result<HMac, Error> {
+        self.key_storage.perform_hmac(data, hmac_type)
+    }
+
+    fn get_signer(&self) -> Result<DeviceSigner, Error> {
         match self.key_storage {
             KeyStorage::Plain {
                 ref private_key, ..
-            } => Ok(Box::new(PKey::private_key_from_der(private_key)?)),
+            } => {
+                let private_key = PKey::private_key_from_der(private_key)?;
+                if private_key.id() == Id::RSA {
+                    Ok(DeviceSigner::Rsa(Box::new(private_key)))
+                } else {
+                    Ok(DeviceSigner::Cose(Box::new(private_key)))
+                }
+            }
             KeyStorage::Tpm {
                 ref signing_public,
                 ref signing_private,
@@ -225,12 +243,157 @@ impl DeviceCredential for FileDeviceCredential {
                     )
                 })?;
 
-                Ok(Box::new(TpmCoseSigner {
+                let signer = TpmCoseSigner {
                     tss_context: RefCell::new(tss_context),
                     _primary_handle: primary_handle,
                     signing_handle,
                     signing_public,
-                }))
+                };
+                if matches!(
+                    signer.signing_public,
+                    tss_esapi::structures::Public::Rsa { .. }
+                ) {
+                    Ok(DeviceSigner::Rsa(Box::new(signer)))
+                } else {
+                    Ok(DeviceSigner::Cose(Box::new(signer)))
+                }
+            }
+        }
+    }
+}
+
+/// ESYS context for the TPM commands that tss-esapi does not wrap
+///
+/// This opens a second connection to the TPM, so it needs a TCTI that allows
+/// concurrent connections, like tabrmd or the kernel resource manager
+/// (`/dev/tpmrm0`).
+struct RawEsysContext {
+    tcti: *mut tss_esapi::tss2_esys::TSS2_TCTI_CONTEXT,
+    esys: *mut tss_esapi::tss2_esys::ESYS_CONTEXT,
+}
+
+fn check_tss_rc(command: &'static str, rc: tss_esapi::tss2_esys::TSS2_RC) -> Result<(), Error> {
+    if rc == tss_esapi::constants::tss::TPM2_RC_SUCCESS {
+        Ok(())
+    } else {
+        Err(Error::TpmCommandFailed(command, rc))
+    }
+}
+
+impl RawEsysContext {
+    fn new() -> Result<Self, Error> {
+        let tcti_conf = CString::try_from(tcti_conf())?;
+        let mut context = RawEsysContext {
+            tcti: null_mut(),
+            esys: null_mut(),
+        };
+        check_tss_rc("Tss2_TctiLdr_Initialize", unsafe {
+            tss_esapi::tss2_esys::Tss2_TctiLdr_Initialize(tcti_conf.as_ptr(), &mut context.tcti)
+        })?;
+        check_tss_rc("Esys_Initialize", unsafe {
+            tss_esapi::tss2_esys::Esys_Initialize(&mut context.esys, context.tcti, null_mut())
+        })?;
+        Ok(context)
+    }
+
+    /// Hashes data longer than fits in a single TPM2_Hash with a hash sequence,
+    /// returning the digest with the ticket that restricted keys need to sign it
+    fn hash_sequence(
+        &mut self,
+        hash_alg: tss_esapi::interface_types::algorithm::HashingAlgorithm,
+        data: &[u8],
+    ) -> Result<
+        (
+            tss_esapi::structures::Digest,
+            tss_esapi::structures::HashcheckTicket,
+        ),
+        Error,
+    > {
+        use tss_esapi::tss2_esys::{ESYS_TR_NONE, ESYS_TR_PASSWORD};
+
+        let mut sequence = ESYS_TR_NONE;
+        check_tss_rc("TPM2_HashSequenceStart", unsafe {
+            tss_esapi::tss2_esys::Esys_HashSequenceStart(
+                self.esys,
+                ESYS_TR_NONE,
+                ESYS_TR_NONE,
+                ESYS_TR_NONE,
+                &Default::default(),
+                hash_alg.into(),
+                &mut sequence,
+            )
+        })?;
+
+        let mut chunks = data
+            .chunks(tss_esapi::structures::MaxBuffer::MAX_SIZE)
+            .map(|chunk| {
+                tss_esapi::structures::MaxBuffer::try_from(chunk)
+                    .map(tss_esapi::tss2_esys::TPM2B_MAX_BUFFER::from)
+            })
+            .peekable();
+        let mut digest = null_mut();
+        let mut validation = null_mut();
+        let result = loop {
+            let chunk = match chunks.next() {
+                Some(Ok(chunk)) => chunk,
+                Some(Err(e)) => break Err(e.into()),
+                None => Default::default(),
+            };
+            if chunks.peek().is_some() {
+                let rc = unsafe {
+                    tss_esapi::tss2_esys::Esys_SequenceUpdate(
+                        self.esys,
+                        sequence,
+                        ESYS_TR_PASSWORD,
+                        ESYS_TR_NONE,
+                        ESYS_TR_NONE,
+                        &chunk,
+                    )
+                };
+                if let Err(e) = check_tss_rc("TPM2_SequenceUpdate", rc) {
+                    break Err(e);
+                }
+                continue;
+            }
+            // Completing the sequence also flushes it
+            let rc = unsafe {
+                tss_esapi::tss2_esys::Esys_SequenceComplete(
+                    self.esys,
+                    sequence,
+                    ESYS_TR_PASSWORD,
+                    ESYS_TR_NONE,
+                    ESYS_TR_NONE,
+                    &chunk,
+                    tss_esapi::constants::tss::TPM2_RH_OWNER,
+                    &mut digest,
+                    &mut validation,
+                )
+            };
+            sequence = ESYS_TR_NONE;
+            break check_tss_rc("TPM2_SequenceComplete", rc);
+        };
+        if sequence != ESYS_TR_NONE {
+            unsafe { tss_esapi::tss2_esys::Esys_FlushContext(self.esys, sequence) };
+        }
+        result?;
+
+        let (digest_value, validation_value) = unsafe { (*digest, *validation) };
+        unsafe {
+            tss_esapi::tss2_esys::Esys_Free(digest as *mut _);
+            tss_esapi::tss2_esys::Esys_Free(validation as *mut _);
+        }
+        Ok((digest_value.try_into()?, validation_value.try_into()?))
+    }
+}
+
+impl Drop for RawEsysContext {
+    fn drop(&mut self) {
+        unsafe {
+            if !self.esys.is_null() {
+                tss_esapi::tss2_esys::Esys_Finalize(&mut self.esys);
+            }
+            if !self.tcti.is_null() {
+                tss_esapi::tss2_esys::Tss2_TctiLdr_Finalize(&mut self.tcti);
             }
         }
     }
@@ -256,7 +419,9 @@ impl TpmCoseSigner {
         aws_nitro_enclaves_cose::error::CoseError,
     > {
         match public {
-            tss_esapi::structures::Public::Rsa { .. } => unimplemented!(),
+            tss_esapi::structures::Public::Rsa { .. } => Err(CoseError::UnsupportedError(
+                "RSA keys are not supported by the COSE library".into(),
+            )),
             tss_esapi::structures::Public::Ecc { parameters, .. } => {
                 let hash_alg = match parameters.ecc_scheme() {
                     tss_esapi::structures::EccScheme::EcDsa(sig_alg) => sig_alg.hashing_algorithm(),
@@ -307,8 +472,72 @@ impl TpmCoseSigner {
                 }
                 Ok(((sig_alg, param_hash_alg), hash_alg, key_length))
             }
-            _ => unimplemented!(),
+            _ => Err(CoseError::UnsupportedError(
+                "Unsupported signing key type".into(),
+            )),
+        }
+    }
+
+    fn public_to_rsa_algorithm(
+        public: &tss_esapi::structures::Public,
+    ) -> Result<RsaSignatureAlgorithm, Error> {
+        let parameters = match public {
+            tss_esapi::structures::Public::Rsa { parameters, .. } => parameters,
+            _ => return Err(Error::UnsupportedAlgorithm),
+        };
+        let (use_pss, hash_alg) = match parameters.rsa_scheme() {
+            tss_esapi::structures::RsaScheme::RsaSsa(scheme) => (false, scheme.hashing_algorithm()),
+            tss_esapi::structures::RsaScheme::RsaPss(scheme) => (true, scheme.hashing_algorithm()),
+            _ => return Err(Error::UnsupportedAlgorithm),
+        };
+        let (key_bits, correct_hash_alg) = match parameters.key_bits() {
+            tss_esapi::interface_types::key_bits::RsaKeyBits::Rsa2048 => (
+                2048,
+                tss_esapi::interface_types::algorithm::HashingAlgorithm::Sha256,
+            ),
+            tss_esapi::interface_types::key_bits::RsaKeyBits::Rsa3072 => (
+                3072,
+                tss_esapi::interface_types::algorithm::HashingAlgorithm::Sha384,
+            ),
+            _ => return Err(Error::UnsupportedAlgorithm),
+        };
+        if hash_alg != correct_hash_alg {
+            return Err(Error::InconsistentValue("Invalid hash algorithm"));
         }
+        RsaSignatureAlgorithm::for_key(key_bits, use_pss)
+    }
+
+    fn tpm_sign(&self, digest: &[u8]) -> Result<tss_esapi::structures::Signature, CoseError> {
+        let validation = tss_esapi::tss2_esys::TPMT_TK_HASHCHECK {
+            tag: tss_esapi::constants::tss::TPM2_ST_HASHCHECK,
+            hierarchy: tss_esapi::constants::tss::TPM2_RH_NULL,
+            digest: Default::default(),
+        }
+        .try_into()
+        .map_err(|_| {
+            CoseError::UnsupportedError("Error converting TPMT_TK_HASHCHECK".to_string())
+        })?;
+        let data = tss_esapi::structures::Digest::try_from(digest).map_err(|_| {
+            CoseError::UnsupportedError("Invalid data signing attempted".to_string())
+        })?;
+        self.tpm_sign_digest(data, validation)
+    }
+
+    fn tpm_sign_digest(
+        &self,
+        digest: tss_esapi::structures::Digest,
+        validation: tss_esapi::structures::HashcheckTicket,
+    ) -> Result<tss_esapi::structures::Signature, CoseError> {
+        let mut ctx = self.tss_context.borrow_mut();
+        ctx.execute_with_nullauth_session(|ctx| {
+            ctx.sign(
+                self.signing_handle,
+                digest,
+                tss_esapi::structures::SignatureScheme::Null,
+                validation,
+            )
+        })
+        .map_err(|e| CoseError::UnsupportedError(format!("Error signing: {e}")))
     }
 }
 
@@ -353,33 +582,7 @@ fn merge_ec_signature(bytes_r: &[u8], bytes_s: &[u8], key_length: usize) -> Vec<
 impl aws_nitro_enclaves_cose::crypto::SigningPrivateKey for TpmCoseSigner {
     fn sign(&self, digest: &[u8]) -> Result<Vec<u8>, CoseError> {
         let key_length = Self::public_to_parameters(&self.signing_public)?.2;
-        let validation = tss_esapi::tss2_esys::TPMT_TK_HASHCHECK {
-            tag: tss_esapi::constants::tss::TPM2_ST_HASHCHECK,
-            hierarchy: tss_esapi::constants::tss::TPM2_RH_NULL,
-            digest: Default::default(),
-        }
-        .try_into()
-        .map_err(|_| {
-            CoseError::UnsupportedError("Error converting TPMT_TK_HASHCHECK".to_string())
-        })?;
-        let data = tss_esapi::structures::Digest::try_from(digest).map_err(|_| {
-            CoseError::UnsupportedError("Invalid data signing attempted".to_string())
-        })?;
-
-        // Special scope to not leak the context
-        let signature = {
-            let mut ctx = self.tss_context.borrow_mut();
-            ctx.execute_with_nullauth_session(|ctx| {
-                ctx.sign(
-                    self.signing_handle,
-                    data,
-                    tss_esapi::structures::SignatureScheme::Null,
-                    validation,
-                )
-            })
-            .map_err(|e| CoseError::UnsupportedError(format!("Error signing: {e}")))?
-        };
-        match signature {
+        match self.tpm_sign(digest)? {
             tss_esapi::structures::Signature::EcDsa(signature) => Ok(merge_ec_signature(
                 signature.signature_r().value(),
                 signature.signature_s().value(),
@@ -391,3 +594,51 @@ impl aws_nitro_enclaves_cose::crypto::SigningPrivateKey for TpmCoseSigner {
         }
     }
 }
+
+impl RsaSigningKey for TpmCoseSigner {
+    fn rsa_algorithm(&self) -> Result<RsaSignatureAlgorithm, Error> {
+        TpmCoseSigner::public_to_rsa_algorithm(&self.signing_public)
+    }
+
+    fn rsa_sign(&self, data: &[u8]) -> Result<Vec<u8>, Error> {
+        let sig_alg = self.rsa_algorithm()?;
+        let signature = if self.signing_public.object_attributes().restricted() {
+            // Restricted keys only sign digests computed by the TPM itself, so have the
+            // TPM hash the data to get a ticket proving that
+            let hash_alg = match sig_alg {
+                RsaSignatureAlgorithm::Rs256 | RsaSignatureAlgorithm::Ps256 => {
+                    tss_esapi::interface_types::algorithm::HashingAlgorithm::Sha256
+                }
+                RsaSignatureAlgorithm::Rs384 | RsaSignatureAlgorithm::Ps384 => {
+                    tss_esapi::interface_types::algorithm::HashingAlgorithm::Sha384
+                }
+            };
+            let (digest, validation) = match tss_esapi::structures::MaxBuffer::try_from(data) {
+                Ok(data) => self
+                    .tss_context
+                    .borrow_mut()
+                    .execute_without_session(|ctx| {
+                        ctx.hash(
+                            data,
+                            hash_alg,
+                            tss_esapi::interface_types::resource_handles::Hierarchy::Owner,
+                        )
+                    })?,
+                // TPM2_Hash takes at most MaxBuffer::MAX_SIZE bytes, and tss-esapi
+                // does not wrap the hash sequence commands for longer data
+                Err(_) => RawEsysContext::new()?.hash_sequence(hash_alg, data)?,
+            };
+            self.tpm_sign_digest(digest, validation)?
+        } else {
+            let digest = openssl::hash::hash(sig_alg.get_md(), data)?;
+            self.tpm_sign(&digest)?
+        };
+        match signature {
+            tss_esapi::structures::Signature::RsaSsa(signature)
+            | tss_esapi::structures::Signature::RsaPss(signature) => {
+                Ok(signature.signature().value().to_vec())
+            }
+            _ => Err(Error::InconsistentValue("Invalid signature type")),
+        }
+    }
+}
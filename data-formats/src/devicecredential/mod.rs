@@ -1,6 +1,12 @@
+use aws_nitro_enclaves_cose::crypto::SigningPrivateKey;
+
 use crate::{
+    constants::{DeviceSigType, HashType},
     errors::Error,
-    types::{Guid, HMac, Hash, RendezvousInfo},
+    types::{
+        COSEHeaderMap, COSESign, EATokenPayload, Guid, HMac, Hash, PayloadState, RendezvousInfo,
+        RsaSigningKey,
+    },
     ProtocolVersion,
 };
 
@@ -13,9 +19,55 @@ pub trait DeviceCredential: std::fmt::Debug {
     fn rendezvous_info(&self) -> &RendezvousInfo;
     fn manufacturer_pubkey_hash(&self) -> &Hash;
 
-    fn get_signer(
+    fn perform_hmac(&self, data: &[u8], hmac_type: HashType) -> Result<HMac, Error>;
+
+    fn get_signer(&self) -> Result<DeviceSigner, Error>;
+}
+
+pub enum DeviceSigner {
+    Cose(Box<dyn SigningPrivateKey>),
+    // RSA signatures are not supported by the COSE library
+    Rsa(Box<dyn RsaSigningKey>),
+}
+
+impl DeviceSigner {
+    pub fn device_sig_type(&self) -> Result<DeviceSigType, Error> {
+        match self {
+            DeviceSigner::Cose(signer) => match signer.get_parameters()?.0 {
+                aws_nitro_enclaves_cose::sign::SignatureAlgorithm::ES256 => {
+                    Ok(DeviceSigType::StSECP256R1)
+                }
+                aws_nitro_enclaves_cose::sign::SignatureAlgorithm::ES384 => {
+                    Ok(DeviceSigType::StSECP384R1)
+                }
+                _ => Err(Error::UnsupportedAlgorithm),
+            },
+            DeviceSigner::Rsa(signer) => Ok(signer.rsa_algorithm()?.device_sig_type()),
+        }
+    }
+
+    pub fn sign_eat<ES>(
         &self,
-    ) -> Result<Box<dyn aws_nitro_enclaves_cose::crypto::SigningPrivateKey>, Error>;
+        eat: EATokenPayload<ES>,
+        unprotected: Option<COSEHeaderMap>,
+    ) -> Result<COSESign, Error>
+    where
+        ES: PayloadState,
+    {
+        match self {
+            DeviceSigner::Cose(signer) => COSESign::from_eat(eat, unprotected, signer.as_ref()),
+            DeviceSigner::Rsa(signer) => COSESign::from_eat_rsa(eat, unprotected, signer.as_ref()),
+        }
+    }
+}
+
+impl std::fmt::Debug for DeviceSigner {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DeviceSigner::Cose(_) => f.write_str("DeviceSigner::Cose"),
+            DeviceSigner::Rsa(_) => f.write_str("DeviceSigner::Rsa"),
+        }
+    }
 }
 
 pub mod file;
@@ -43,6 +43,7 @@ impl RendezvousInterpretedDirective {
         let protocol_text = match self.protocol {
             RendezvousProtocolValue::Http => "http",
             RendezvousProtocolValue::Https => "https",
+            RendezvousProtocolValue::CoAPUDP => "coap",
             _ => return Vec::new(),
         };
 
@@ -202,6 +203,19 @@ impl RendezvousInfo {
             })
             .collect()
     }
+
+    /// Whether every directive has Bypass set
+    ///
+    /// In that case the device goes straight to TO2 at the addresses in the
+    /// directives, so the owner has no rendezvous server to perform TO0 with.
+    pub fn is_bypass_only(&self) -> bool {
+        !self.values().is_empty()
+            && self.values().iter().all(|directive| {
+                directive
+                    .iter()
+                    .any(|(variable, _)| matches!(variable, RendezvousVariable::Bypass))
+            })
+    }
 }
 
 #[derive(Debug)]
@@ -275,3 +289,116 @@ impl X5Bag {
         self.certs.drain().map(|(_, v)| v).collect()
     }
 }
+
+#[cfg(test)]
+mod test_rendezvous_interpreted {
+    use std::str::FromStr;
+
+    use super::RendezvousInterpreterSide;
+    use crate::{
+        constants::RendezvousVariable,
+        types::{CborSimpleType, Hash, RendezvousInfo},
+    };
+
+    #[test]
+    fn test_cert_hashes() {
+        let server_hash = "sha256:a0ad3bd36eb5a8b3d9c4eb5b2bdd44dd24b69d2c8b1c08fd3a4a1c3e9fc4bb4b";
+        let ca_hash = "sha384:86255fa2c36e4b30969eae17dc34c772cbebdfc58b58403900be87614eb1a34b8780263f255eb5e65ca9bbb8641cccfe";
+
+        let directive = vec![
+            (
+                RendezvousVariable::Dns,
+                serde_cbor::Value::Text("localhost".to_string()),
+            ),
+            (
+                RendezvousVariable::Protocol,
+                serde_cbor::Value::Text("https".to_string()),
+            ),
+            (
+                RendezvousVariable::ServerCertHash,
+                serde_cbor::Value::Text(server_hash.to_string()),
+            ),
+            (
+                RendezvousVariable::CaCertHash,
+                serde_cbor::Value::Text(ca_hash.to_string()),
+            ),
+        ]
+        .into_iter()
+        .map(|(key, val)| (key, key.value_from_human_to_machine(val).unwrap()))
+        .collect();
+
+        let info = RendezvousInfo::new(vec![directive]).unwrap();
+
+        let interpreted = info
+            .to_interpreted(RendezvousInterpreterSide::Device)
+            .unwrap();
+        assert_eq!(interpreted.len(), 1);
+        assert_eq!(
+            interpreted[0].server_certificate_hash,
+            Some(Hash::from_str(server_hash).unwrap())
+        );
+        assert_eq!(
+            interpreted[0].ca_certificate_hash,
+            Some(Hash::from_str(ca_hash).unwrap())
+        );
+
+        // The owner side has no use for the certificate hashes
+        let interpreted = info
+            .to_interpreted(RendezvousInterpreterSide::Owner)
+            .unwrap();
+        assert_eq!(interpreted[0].server_certificate_hash, None);
+    }
+
+    #[test]
+    fn test_bypass() {
+        let directive = |bypass: bool| -> Vec<(RendezvousVariable, CborSimpleType)> {
+            let mut directive = vec![
+                (
+                    RendezvousVariable::IPAddress,
+                    serde_cbor::Value::Text("192.0.2.1".to_string()),
+                ),
+                (
+                    RendezvousVariable::Protocol,
+                    serde_cbor::Value::Text("http".to_string()),
+                ),
+                (
+                    RendezvousVariable::DevicePort,
+                    serde_cbor::Value::Integer(8081),
+                ),
+            ];
+            if bypass {
+                directive.push((RendezvousVariable::Bypass, serde_cbor::Value::Null));
+            }
+            directive
+                .into_iter()
+                .map(|(key, val)| (key, key.value_from_human_to_machine(val).unwrap()))
+                .collect()
+        };
+
+        let info = RendezvousInfo::new(vec![directive(true)]).unwrap();
+        assert!(info.is_bypass_only());
+        let interpreted = info
+            .to_interpreted(RendezvousInterpreterSide::Device)
+            .unwrap();
+        assert!(interpreted[0].bypass);
+        assert_eq!(interpreted[0].get_urls(), vec!["http://192.0.2.1:8081"]);
+        // The owner has no rendezvous server to contact
+        assert!(info
+            .to_interpreted(RendezvousInterpreterSide::Owner)
+            .unwrap()
+            .is_empty());
+
+        let info = RendezvousInfo::new(vec![directive(true), directive(false)]).unwrap();
+        assert!(!info.is_bypass_only());
+    }
+
+    #[test]
+    fn test_invalid_cert_hash() {
+        assert!(RendezvousVariable::ServerCertHash
+            .value_from_human_to_machine(serde_cbor::Value::Text("sha256:abcd".to_string()))
+            .is_err());
+        assert!(RendezvousVariable::CaCertHash
+            .value_from_human_to_machine(serde_cbor::Value::Integer(42))
+            .is_err());
+    }
+}
@@ -71,6 +71,10 @@ pub enum Error {
     UnsupportedVersion(Option<crate::constants::ProtocolVersion>),
     #[error("TPM/TSS error: {0:?}")]
     TssError(#[from] tss_esapi::Error),
+    #[error("TPM command {0} failed with response code {1:#x}")]
+    TpmCommandFailed(&'static str, u32),
     #[error("Empty data")]
     EmptyData,
+    #[error("ServiceInfo entry {0} of {1} bytes does not fit in the MTU budget of {2} bytes")]
+    ServiceInfoEntryTooLarge(String, usize, usize),
 }
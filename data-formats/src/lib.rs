@@ -19,6 +19,9 @@ pub mod messages;
 
 pub mod cborparser;
 
+#[cfg(any(test, feature = "test-utils"))]
+pub mod test_utils;
+
 mod serializable;
 pub use serializable::DeserializableMany;
 pub use serializable::Serializable;
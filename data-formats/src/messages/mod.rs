@@ -5,11 +5,21 @@ use crate::{
     ProtocolVersion, Serializable,
 };
 
+pub mod v10;
 pub mod v11;
 
 pub trait ClientMessage: Message {}
 pub trait ServerMessage: Message {}
 
+/// Links a message to its definition in FDO 1.0, so that it can be exchanged
+/// with 1.0 implementations while being handled as its 1.1 definition.
+pub trait VersionedMessage: Message {
+    type V10: Message;
+
+    fn to_v10(self) -> Result<Self::V10, crate::Error>;
+    fn from_v10(message: Self::V10) -> Result<Self, crate::Error>;
+}
+
 #[derive(Debug, Error)]
 pub enum ParseError {
     #[error("Overall error: {0}")]
@@ -4,9 +4,13 @@ use serde_tuple::Serialize_tuple;
 use crate::simple_message_serializable;
 use crate::{
     constants::MessageType,
-    messages::{ClientMessage, EncryptionRequirement, Message, ServerMessage},
+    messages::{
+        v11::di as v11, ClientMessage, EncryptionRequirement, Message, ServerMessage,
+        VersionedMessage,
+    },
     ownershipvoucher::OwnershipVoucherHeader,
     types::{CborSimpleType, HMac},
+    Error,
 };
 
 #[derive(Debug, Serialize_tuple, Deserialize)]
@@ -159,3 +163,18 @@ impl Serialize for Done {
         seq.end()
     }
 }
+
+same_encoding!(v11, SetCredentials, SetHMAC, Done);
+
+// In 1.0, mfg_info is embedded as-is instead of wrapped in a bstr
+impl VersionedMessage for v11::AppStart {
+    type V10 = AppStart;
+
+    fn to_v10(self) -> Result<AppStart, Error> {
+        Ok(AppStart::new(self.mfg_info()?))
+    }
+
+    fn from_v10(message: AppStart) -> Result<Self, Error> {
+        v11::AppStart::new(message.mfg_info)
+    }
+}
@@ -3,7 +3,7 @@ use serde_tuple::Serialize_tuple;
 
 use crate::{
     constants::{KeyStorageType, MessageType, MfgStringType, PublicKeyType},
-    messages::{ClientMessage, EncryptionRequirement, Message, ServerMessage},
+    messages::{v11::diun as v11, ClientMessage, EncryptionRequirement, Message, ServerMessage},
     simple_message_serializable,
     types::{COSESign, CipherSuite, KexSuite, Nonce},
 };
@@ -54,7 +54,7 @@ impl Message for Connect {
     }
 
     fn is_valid_previous_message(message_type: Option<MessageType>) -> bool {
-        matches!(message_type, None)
+        message_type.is_none()
     }
 
     fn encryption_requirement() -> Option<EncryptionRequirement> {
@@ -277,3 +277,13 @@ impl Message for Done {
 }
 
 impl ServerMessage for Done {}
+
+same_encoding!(
+    v11,
+    Connect,
+    Accept,
+    RequestKeyParameters,
+    ProvideKeyParameters,
+    ProvideKey,
+    Done,
+);
@@ -2,7 +2,7 @@ use serde_tuple::{Deserialize_tuple, Serialize_tuple};
 
 use crate::{
     constants::{ErrorCode, MessageType},
-    messages::{ClientMessage, EncryptionRequirement, Message, ServerMessage},
+    messages::{v11, ClientMessage, EncryptionRequirement, Message, ServerMessage},
 };
 
 #[derive(Debug, Serialize_tuple, Deserialize_tuple)]
@@ -76,6 +76,8 @@ impl Message for ErrorMessage {
 impl ClientMessage for ErrorMessage {}
 impl ServerMessage for ErrorMessage {}
 
+same_encoding!(v11, ErrorMessage);
+
 #[cfg(test)]
 mod test {
     use std::u128;
@@ -1,3 +1,27 @@
+/// Implements `VersionedMessage` for the 1.1 definitions of the messages that
+/// are encoded the same way in 1.0
+macro_rules! same_encoding {
+    ($v11:ident, $($name:ident),+ $(,)?) => {
+        $(
+            impl crate::messages::VersionedMessage for $v11::$name {
+                type V10 = $name;
+
+                fn to_v10(self) -> Result<$name, crate::Error> {
+                    <$name as crate::Serializable>::deserialize_data(
+                        &crate::Serializable::serialize_data(&self)?,
+                    )
+                }
+
+                fn from_v10(message: $name) -> Result<Self, crate::Error> {
+                    <Self as crate::Serializable>::deserialize_data(
+                        &crate::Serializable::serialize_data(&message)?,
+                    )
+                }
+            }
+        )+
+    };
+}
+
 mod error;
 pub use error::ErrorMessage;
 
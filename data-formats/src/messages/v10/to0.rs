@@ -1,10 +1,14 @@
 use serde::{Deserialize, Serialize};
+use serde_bytes::ByteBuf;
 use serde_tuple::Serialize_tuple;
 
 use crate::{
-    cborparser::ParsedArray,
+    cborparser::{ParsedArray, ParsedArrayBuilder},
     constants::{HashType, MessageType},
-    messages::{ClientMessage, EncryptionRequirement, Message, ServerMessage},
+    messages::{
+        v11::to0 as v11, ClientMessage, EncryptionRequirement, Message, ServerMessage,
+        VersionedMessage,
+    },
     types::{COSESign, Hash, Nonce, TO0Data},
     Error, Serializable,
 };
@@ -25,7 +29,7 @@ impl Message for Hello {
     }
 
     fn is_valid_previous_message(message_type: Option<MessageType>) -> bool {
-        matches!(message_type, None)
+        message_type.is_none()
     }
 
     fn encryption_requirement() -> Option<EncryptionRequirement> {
@@ -121,9 +125,10 @@ impl Serializable for OwnerSign {
 
 impl OwnerSign {
     pub fn new(to0d: TO0Data, to1d: COSESign) -> Result<Self, Error> {
-        let mut contents = unsafe { ParsedArray::new() };
+        let mut contents = ParsedArrayBuilder::new();
         contents.set(0, &to0d)?;
         contents.set(1, &to1d)?;
+        let contents = contents.build();
 
         Ok(OwnerSign {
             contents,
@@ -200,3 +205,32 @@ impl Message for AcceptOwner {
 }
 
 impl ServerMessage for AcceptOwner {}
+
+same_encoding!(v11, Hello, HelloAck, AcceptOwner);
+
+// In 1.0, to0d is embedded as-is instead of wrapped in a bstr. The encoded
+// to0d is kept so that it still matches the to0d hash in to1d.
+impl VersionedMessage for v11::OwnerSign {
+    type V10 = OwnerSign;
+
+    fn to_v10(self) -> Result<OwnerSign, Error> {
+        let mut contents = ParsedArrayBuilder::new();
+        contents.set_raw(0, self.to0d_raw().to_vec());
+        contents.set(1, self.to1d())?;
+        let contents = contents.build();
+
+        Ok(OwnerSign {
+            contents,
+
+            cached_to0d: self.to0d()?,
+            cached_to1d: self.to1d().clone(),
+        })
+    }
+
+    fn from_v10(message: OwnerSign) -> Result<Self, Error> {
+        v11::OwnerSign::new(
+            ByteBuf::from(message.contents.get_raw(0).to_vec()),
+            message.cached_to1d,
+        )
+    }
+}
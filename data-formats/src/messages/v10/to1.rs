@@ -4,7 +4,7 @@ use serde_tuple::Serialize_tuple;
 use crate::simple_message_serializable;
 use crate::{
     constants::MessageType,
-    messages::{ClientMessage, EncryptionRequirement, Message, ServerMessage},
+    messages::{v11::to1 as v11, ClientMessage, EncryptionRequirement, Message, ServerMessage},
     types::{COSESign, Guid, Nonce, SigInfo},
 };
 
@@ -37,7 +37,7 @@ impl Message for HelloRV {
     }
 
     fn is_valid_previous_message(message_type: Option<crate::constants::MessageType>) -> bool {
-        matches!(message_type, None)
+        message_type.is_none()
     }
 
     fn encryption_requirement() -> Option<EncryptionRequirement> {
@@ -167,3 +167,5 @@ impl Message for RVRedirect {
 }
 
 impl ServerMessage for RVRedirect {}
+
+same_encoding!(v11, HelloRV, HelloRVAck, ProveToRV, RVRedirect);
@@ -2,10 +2,13 @@ use serde::Deserialize;
 use serde_tuple::Serialize_tuple;
 
 use crate::{
-    cborparser::ParsedArray,
-    messages::{ClientMessage, EncryptionRequirement, Message, ServerMessage},
+    cborparser::{ParsedArray, ParsedArrayBuilder},
+    messages::{
+        v11::to2 as v11, ClientMessage, EncryptionRequirement, Message, ServerMessage,
+        VersionedMessage,
+    },
     ownershipvoucher::OwnershipVoucherEntry,
-    simple_message_serializable, Serializable,
+    simple_message_serializable, Error, Serializable,
 };
 
 use crate::{
@@ -66,7 +69,7 @@ impl Message for HelloDevice {
     }
 
     fn is_valid_previous_message(message_type: Option<MessageType>) -> bool {
-        matches!(message_type, None)
+        message_type.is_none()
     }
 
     fn encryption_requirement() -> Option<EncryptionRequirement> {
@@ -176,10 +179,11 @@ impl Serializable for OVNextEntry {
     where
         W: std::io::Write,
     {
-        let mut contents: ParsedArray<crate::cborparser::ParsedArraySize2> =
-            unsafe { ParsedArray::new() };
+        let mut contents: ParsedArrayBuilder<crate::cborparser::ParsedArraySize2> =
+            ParsedArrayBuilder::new();
         contents.set(0, &self.entry_num)?;
         contents.set(1, &self.entry)?;
+        let contents = contents.build();
 
         contents.serialize_to_writer(writer)
     }
@@ -533,3 +537,43 @@ impl Message for Done2 {
 }
 
 impl ServerMessage for Done2 {}
+
+same_encoding!(
+    v11,
+    ProveOVHdr,
+    GetOVNextEntry,
+    OVNextEntry,
+    ProveDevice,
+    SetupDevice,
+    DeviceServiceInfoReady,
+    OwnerServiceInfoReady,
+    DeviceServiceInfo,
+    OwnerServiceInfo,
+    Done,
+    Done2,
+);
+
+// The max device message size is not known in 1.0, so there is no limit
+impl VersionedMessage for v11::HelloDevice {
+    type V10 = HelloDevice;
+
+    fn to_v10(self) -> Result<HelloDevice, Error> {
+        Ok(HelloDevice::new(
+            self.guid().clone(),
+            self.nonce5().clone(),
+            self.kex_suite(),
+            self.cipher_suite(),
+            self.a_signature_info().clone(),
+        ))
+    }
+
+    fn from_v10(message: HelloDevice) -> Result<Self, Error> {
+        Ok(v11::HelloDevice::new(
+            message.guid,
+            message.nonce5,
+            message.kex_suite,
+            message.cipher_suite,
+            message.a_signature_info,
+        ))
+    }
+}
@@ -146,6 +146,11 @@ impl OwnerSign {
     pub fn to0d_hash(&self, hash_type: HashType) -> Result<Hash, Error> {
         Hash::from_data(hash_type, &self.cached_to0d)
     }
+
+    /// The encoded to0d, as covered by the to0d hash
+    pub(crate) fn to0d_raw(&self) -> &[u8] {
+        &self.cached_to0d
+    }
 }
 
 impl Message for OwnerSign {
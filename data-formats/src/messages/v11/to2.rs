@@ -13,7 +13,7 @@ use crate::{
     types::{COSESign, CipherSuite, Guid, HMac, KexSuite, Nonce, ServiceInfo, SigInfo},
 };
 
-pub(crate) const MAX_MESSAGE_SIZE: u16 = (2 ^ 16) - 1;
+pub(crate) const MAX_MESSAGE_SIZE: u16 = u16::MAX;
 
 #[derive(Debug, Serialize_tuple, Deserialize)]
 pub struct HelloDevice {
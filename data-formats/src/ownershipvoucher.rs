@@ -1,16 +1,21 @@
 use std::collections::HashMap;
+use std::convert::TryFrom;
 use std::ops::Range;
 
-use openssl::pkey::{PKeyRef, Private};
-use serde::Deserialize;
+use openssl::{
+    pkey::{PKey, PKeyRef, Private},
+    sign::Signer,
+};
+use serde::{Deserialize, Serialize};
 use serde_bytes::ByteBuf;
-use serde_tuple::Serialize_tuple;
 
 use crate::{
     cborparser::{
-        ParsedArray, ParsedArrayBuilder, ParsedArraySize5, ParsedArraySize6, ParsedArraySizeDynamic,
+        ParsedArray, ParsedArrayBuilder, ParsedArraySize4, ParsedArraySize5, ParsedArraySize6,
+        ParsedArraySizeDynamic,
     },
     constants::HashType,
+    enhanced_types::X5Bag,
     errors::Result,
     publickey::{PublicKey, X5Chain},
     serializable::MaybeSerializable,
@@ -33,6 +38,15 @@ enum OwnershipVoucherIndex {
     Entries = 4,
 }
 
+// FDO 1.0 vouchers have no protocol version, and embed the header as-is
+#[derive(Debug)]
+enum OwnershipVoucherV10Index {
+    Header = 0,
+    HeaderHmac = 1,
+    DeviceCertificateChain = 2,
+    Entries = 3,
+}
+
 #[derive(Debug, Clone)]
 pub struct OwnershipVoucher {
     contents: ParsedArray<ParsedArraySize5>,
@@ -66,6 +80,72 @@ impl OwnershipVoucher {
             cached_entries,
         })
     }
+
+    /// FDO 1.0 vouchers are kept in the 1.1 layout, and converted back when serialized
+    fn from_v10_parsed_array(contents: ParsedArray<ParsedArraySize4>) -> Result<Self> {
+        let header = contents.get_raw(OwnershipVoucherV10Index::Header as usize);
+        let protocol_version = OwnershipVoucherHeader::deserialize_data(header)?.protocol_version();
+        if protocol_version != ProtocolVersion::Version1_0 {
+            return Err(Error::InvalidProtocolVersion(protocol_version));
+        }
+
+        let mut converted = ParsedArrayBuilder::new();
+        converted.set(
+            OwnershipVoucherIndex::ProtocolVersion as usize,
+            &protocol_version,
+        )?;
+        converted.set(
+            OwnershipVoucherIndex::Header as usize,
+            &ByteBuf::from(header),
+        )?;
+        converted.set_raw(
+            OwnershipVoucherIndex::HeaderHmac as usize,
+            contents
+                .get_raw(OwnershipVoucherV10Index::HeaderHmac as usize)
+                .to_vec(),
+        );
+        converted.set_raw(
+            OwnershipVoucherIndex::DeviceCertificateChain as usize,
+            contents
+                .get_raw(OwnershipVoucherV10Index::DeviceCertificateChain as usize)
+                .to_vec(),
+        );
+        converted.set_raw(
+            OwnershipVoucherIndex::Entries as usize,
+            contents
+                .get_raw(OwnershipVoucherV10Index::Entries as usize)
+                .to_vec(),
+        );
+
+        Self::from_parsed_array(converted.build())
+    }
+
+    fn to_v10_parsed_array(&self) -> Result<ParsedArray<ParsedArraySize4>> {
+        let header: ByteBuf = self.contents.get(OwnershipVoucherIndex::Header as usize)?;
+
+        let mut contents = ParsedArrayBuilder::new();
+        contents.set_raw(OwnershipVoucherV10Index::Header as usize, header.into_vec());
+        contents.set_raw(
+            OwnershipVoucherV10Index::HeaderHmac as usize,
+            self.contents
+                .get_raw(OwnershipVoucherIndex::HeaderHmac as usize)
+                .to_vec(),
+        );
+        contents.set_raw(
+            OwnershipVoucherV10Index::DeviceCertificateChain as usize,
+            self.contents
+                .get_raw(OwnershipVoucherIndex::DeviceCertificateChain as usize)
+                .to_vec(),
+        );
+        contents.set_raw(
+            OwnershipVoucherV10Index::Entries as usize,
+            self.contents
+                .get_raw(OwnershipVoucherIndex::Entries as usize)
+                .to_vec(),
+        );
+
+        Ok(contents.build())
+    }
 }
 
 impl Serializable for OwnershipVoucher {
@@ -73,22 +153,24 @@ impl Serializable for OwnershipVoucher {
     where
         R: std::io::Read,
     {
-        let contents = ParsedArray::deserialize_from_reader(reader);
-        if let Err(Error::ArrayParseError(
-            crate::cborparser::ArrayParseError::InvalidNumberOfElements(4, 5),
-        )) = contents
-        {
-            return Err(Error::UnsupportedVersion(Some(ProtocolVersion::Version1_0)));
-        };
-        let contents = contents?;
-        Self::from_parsed_array(contents)
+        let contents: ParsedArray<ParsedArraySizeDynamic> =
+            ParsedArray::deserialize_from_reader(reader)?;
+        if contents.len() == 4 {
+            Self::from_v10_parsed_array(contents.into_sized()?)
+        } else {
+            Self::from_parsed_array(contents.into_sized()?)
+        }
     }
 
     fn serialize_to_writer<W>(&self, writer: W) -> Result<()>
     where
         W: std::io::Write,
     {
-        self.contents.serialize_to_writer(writer)
+        if self.cached_protocol_version == ProtocolVersion::Version1_0 {
+            self.to_v10_parsed_array()?.serialize_to_writer(writer)
+        } else {
+            self.contents.serialize_to_writer(writer)
+        }
     }
 }
 
@@ -273,32 +355,22 @@ impl OwnershipVoucher {
         }
 
         let hdrinfo_hash = self.header().get_hdr_info_hash(self.hash_type())?;
-        let (last_hash, current_owner_pubkey) = if self.cached_entries.is_empty() {
-            (
-                self.hdr_hash(self.hash_type())?,
-                self.header().manufacturer_public_key().clone(),
-            )
+        let last_hash = if self.cached_entries.is_empty() {
+            self.hdr_hash(self.hash_type())?
         } else {
-            let last_idx = self.cached_entries.len() - 1;
-
-            let last_hash = self.cached_entries.get_hash(last_idx, self.hash_type())?;
-            let lastentry: OwnershipVoucherEntry = self.cached_entries.get(last_idx)?;
-            let lastentry: UnverifiedValue<OwnershipVoucherEntryPayload> =
-                lastentry.get_payload_unverified()?;
-
-            (
-                last_hash,
-                lastentry.get_unverified_value().public_key.clone(),
-            )
+            self.cached_entries
+                .get_hash(self.cached_entries.len() - 1, self.hash_type())?
         };
+        let current_owner_pubkey = self.owner_public_key_unverified()?;
 
         if !current_owner_pubkey.matches_pkey(owner_private_key)? {
             return Err(Error::NonOwnerKey);
         }
 
         // Create new entry
-        let new_entry =
+        let mut new_entry =
             OwnershipVoucherEntryPayload::new(last_hash, hdrinfo_hash, extra, next_party.clone())?;
+        new_entry.protocol_version = self.cached_protocol_version;
 
         // Sign with private key
         let signed_new_entry = COSESign::new(&new_entry, None, owner_private_key)?;
@@ -319,6 +391,21 @@ impl OwnershipVoucher {
         &self.cached_header
     }
 
+    /// Returns the public key of the current owner: the one in the last entry,
+    /// or the manufacturer key if the voucher has no entries yet.
+    ///
+    /// Note that this does not verify the entries, use `iter_entries` for that.
+    pub fn owner_public_key_unverified(&self) -> Result<PublicKey> {
+        if self.cached_entries.is_empty() {
+            return Ok(self.header().manufacturer_public_key().clone());
+        }
+        let lastentry: OwnershipVoucherEntry =
+            self.cached_entries.get(self.cached_entries.len() - 1)?;
+        let lastentry: UnverifiedValue<OwnershipVoucherEntryPayload> =
+            lastentry.get_payload_unverified()?;
+        Ok(lastentry.get_unverified_value().public_key.clone())
+    }
+
     pub fn header_raw(&self) -> ByteBuf {
         self.contents
             .get(OwnershipVoucherIndex::Header as usize)
@@ -426,6 +513,382 @@ impl<'a> EntryIter<'a> {
     }
 }
 
+/// A check performed by `OwnershipVoucher::verify`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OwnershipVoucherCheck {
+    /// The manufacturer key is one of the trusted manufacturer keys
+    ManufacturerKey,
+    /// The device certificate chain hash in the header matches the chain
+    DeviceCertificateChainHash,
+    /// The device certificate chain is well-formed
+    DeviceCertificateChain,
+    /// The device certificate chain is issued by a trusted device CA
+    DeviceCertificateChainTrusted,
+    /// The header HMAC matches the device HMAC secret
+    HeaderHmac,
+    /// The entry is signed by the previous owner
+    EntrySignature(usize),
+    /// The entry contains the hash of the previous entry, or of the header for the first entry
+    EntryPreviousHash(usize),
+    /// The entry contains the hash of the header info
+    EntryHeaderInfoHash(usize),
+    /// The voucher is extended to the expected owner key
+    OwnerKey,
+}
+
+impl OwnershipVoucherCheck {
+    /// The entry the check applies to, if any
+    pub fn entry(&self) -> Option<usize> {
+        match self {
+            OwnershipVoucherCheck::EntrySignature(entry)
+            | OwnershipVoucherCheck::EntryPreviousHash(entry)
+            | OwnershipVoucherCheck::EntryHeaderInfoHash(entry) => Some(*entry),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for OwnershipVoucherCheck {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OwnershipVoucherCheck::ManufacturerKey => write!(f, "Manufacturer key trusted"),
+            OwnershipVoucherCheck::DeviceCertificateChainHash => {
+                write!(f, "Device certificate chain hash")
+            }
+            OwnershipVoucherCheck::DeviceCertificateChain => write!(f, "Device certificate chain"),
+            OwnershipVoucherCheck::DeviceCertificateChainTrusted => {
+                write!(f, "Device certificate chain trusted")
+            }
+            OwnershipVoucherCheck::HeaderHmac => write!(f, "Header HMAC"),
+            OwnershipVoucherCheck::EntrySignature(entry) => write!(f, "Entry {entry} signature"),
+            OwnershipVoucherCheck::EntryPreviousHash(entry) => {
+                write!(f, "Entry {entry} previous entry hash")
+            }
+            OwnershipVoucherCheck::EntryHeaderInfoHash(entry) => {
+                write!(f, "Entry {entry} header info hash")
+            }
+            OwnershipVoucherCheck::OwnerKey => write!(f, "Owner key"),
+        }
+    }
+}
+
+/// The outcome of a check performed by `OwnershipVoucher::verify`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "result", content = "reason", rename_all = "snake_case")]
+pub enum VerificationOutcome {
+    Passed,
+    Failed(String),
+    /// The check was not performed, because what it needs was not provided
+    Skipped(String),
+}
+
+impl VerificationOutcome {
+    fn from_result(result: Result<()>) -> Self {
+        match result {
+            Ok(()) => VerificationOutcome::Passed,
+            Err(e) => VerificationOutcome::Failed(e.to_string()),
+        }
+    }
+
+    pub fn is_failed(&self) -> bool {
+        matches!(self, VerificationOutcome::Failed(_))
+    }
+}
+
+impl std::fmt::Display for VerificationOutcome {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            VerificationOutcome::Passed => write!(f, "passed"),
+            VerificationOutcome::Failed(reason) => write!(f, "FAILED ({reason})"),
+            VerificationOutcome::Skipped(reason) => write!(f, "skipped ({reason})"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct VerificationCheckResult {
+    pub check: OwnershipVoucherCheck,
+    pub outcome: VerificationOutcome,
+}
+
+impl std::fmt::Display for VerificationCheckResult {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.check, self.outcome)
+    }
+}
+
+/// The result of every check performed by `OwnershipVoucher::verify`
+#[derive(Debug, Clone, Serialize)]
+pub struct VerificationReport {
+    valid: bool,
+    checks: Vec<VerificationCheckResult>,
+
+    #[serde(skip)]
+    owner_public_key: Option<PublicKey>,
+    #[serde(skip)]
+    device_public_key: Option<PublicKey>,
+}
+
+impl VerificationReport {
+    fn push(&mut self, check: OwnershipVoucherCheck, outcome: VerificationOutcome) {
+        if outcome.is_failed() {
+            self.valid = false;
+        }
+        self.checks.push(VerificationCheckResult { check, outcome });
+    }
+
+    /// Whether none of the checks failed
+    pub fn is_valid(&self) -> bool {
+        self.valid
+    }
+
+    pub fn checks(&self) -> &[VerificationCheckResult] {
+        &self.checks
+    }
+
+    pub fn failures(&self) -> impl Iterator<Item = &VerificationCheckResult> {
+        self.checks
+            .iter()
+            .filter(|result| result.outcome.is_failed())
+    }
+
+    /// The key of the current owner, if all entries were verified
+    pub fn owner_public_key(&self) -> Option<&PublicKey> {
+        self.owner_public_key.as_ref()
+    }
+
+    /// The public key of the device, if its certificate chain was verified
+    pub fn device_public_key(&self) -> Option<&PublicKey> {
+        self.device_public_key.as_ref()
+    }
+}
+
+impl OwnershipVoucher {
+    /// Verifies the whole voucher, and reports the outcome of each check.
+    ///
+    /// Checks that need any of the optional arguments are skipped when it is not provided.
+    pub fn verify(
+        &self,
+        trusted_manufacturer_keys: Option<&X5Bag>,
+        device_ca_bag: Option<&X5Bag>,
+        expected_owner_key: Option<&PublicKey>,
+        device_hmac_secret: Option<&[u8]>,
+    ) -> VerificationReport {
+        let mut report = VerificationReport {
+            valid: true,
+            checks: Vec::new(),
+
+            owner_public_key: None,
+            device_public_key: None,
+        };
+        let header = self.header();
+
+        report.push(
+            OwnershipVoucherCheck::ManufacturerKey,
+            match trusted_manufacturer_keys {
+                None => VerificationOutcome::Skipped("No trusted manufacturer keys".to_string()),
+                Some(keys) if keys.contains_publickey(header.manufacturer_public_key()) => {
+                    VerificationOutcome::Passed
+                }
+                Some(_) => VerificationOutcome::Failed("Manufacturer key not trusted".to_string()),
+            },
+        );
+
+        self.verify_device_certificate_chain(&mut report, device_ca_bag);
+
+        report.push(
+            OwnershipVoucherCheck::HeaderHmac,
+            match device_hmac_secret {
+                None => VerificationOutcome::Skipped("No device HMAC secret".to_string()),
+                Some(secret) => VerificationOutcome::from_result(self.verify_header_hmac(secret)),
+            },
+        );
+
+        let owner_key = self.verify_entries(&mut report);
+
+        report.push(
+            OwnershipVoucherCheck::OwnerKey,
+            match (expected_owner_key, &owner_key) {
+                (None, _) => VerificationOutcome::Skipped("No expected owner key".to_string()),
+                (Some(_), None) => {
+                    VerificationOutcome::Failed("Owner key could not be verified".to_string())
+                }
+                (Some(expected), Some(owner_key)) => {
+                    match expected.matches_pkey(owner_key.pkey()) {
+                        Ok(true) => VerificationOutcome::Passed,
+                        Ok(false) => VerificationOutcome::Failed(
+                            "Voucher is extended to a different owner key".to_string(),
+                        ),
+                        Err(e) => VerificationOutcome::Failed(e.to_string()),
+                    }
+                }
+            },
+        );
+        report.owner_public_key = owner_key;
+
+        report
+    }
+
+    fn verify_device_certificate_chain(
+        &self,
+        report: &mut VerificationReport,
+        device_ca_bag: Option<&X5Bag>,
+    ) {
+        let chain = match self.device_certificate_chain() {
+            Some(chain) => chain,
+            None => {
+                for check in [
+                    OwnershipVoucherCheck::DeviceCertificateChainHash,
+                    OwnershipVoucherCheck::DeviceCertificateChain,
+                    OwnershipVoucherCheck::DeviceCertificateChainTrusted,
+                ] {
+                    report.push(
+                        check,
+                        VerificationOutcome::Skipped("No device certificate chain".to_string()),
+                    );
+                }
+                return;
+            }
+        };
+
+        report.push(
+            OwnershipVoucherCheck::DeviceCertificateChainHash,
+            match self.header().device_certificate_chain_hash() {
+                None => VerificationOutcome::Failed(
+                    "No device certificate chain hash in header".to_string(),
+                ),
+                // The spec hashes the CBOR encoded chain, while our DI hashes the
+                // concatenated DER certificates: accept both.
+                Some(hash) => VerificationOutcome::from_result(
+                    hash.compare_data(
+                        self.contents
+                            .get_raw(OwnershipVoucherIndex::DeviceCertificateChain as usize),
+                    )
+                    .or_else(|_| {
+                        let chain_der =
+                            chain
+                                .chain()
+                                .iter()
+                                .try_fold(Vec::new(), |mut bytes, cert| {
+                                    bytes.extend(cert.to_der()?);
+                                    Ok::<_, Error>(bytes)
+                                })?;
+                        hash.compare_data(&chain_der)
+                    }),
+                ),
+            },
+        );
+
+        let device_cert = chain.insecure_verify_without_root_verification();
+        let outcome = match device_cert.and_then(|cert| PublicKey::try_from(cert.clone())) {
+            Ok(device_public_key) => {
+                report.device_public_key = Some(device_public_key);
+                VerificationOutcome::Passed
+            }
+            Err(e) => VerificationOutcome::Failed(e.to_string()),
+        };
+        report.push(OwnershipVoucherCheck::DeviceCertificateChain, outcome);
+
+        report.push(
+            OwnershipVoucherCheck::DeviceCertificateChainTrusted,
+            match device_ca_bag {
+                None => VerificationOutcome::Skipped("No device CA certificates".to_string()),
+                Some(bag) => {
+                    VerificationOutcome::from_result(chain.verify_from_x5bag(bag).map(|_| ()))
+                }
+            },
+        );
+    }
+
+    fn verify_header_hmac(&self, secret: &[u8]) -> Result<()> {
+        let hmac_type = self.header_hmac().get_type();
+        let hmac_key = PKey::hmac(secret)?;
+        let mut hmac_signer = Signer::new(hmac_type.get_md(), &hmac_key)?;
+        hmac_signer.update(&self.header_raw())?;
+        let hmac = HMac::from_digest(hmac_type, hmac_signer.sign_to_vec()?)?;
+        self.header_hmac().compare(&hmac)
+    }
+
+    /// Checks every entry, and returns the owner key if they are all valid
+    fn verify_entries(&self, report: &mut VerificationReport) -> Option<PublicKey> {
+        let mut owner_key = self.header().manufacturer_public_key().clone();
+        let mut valid = true;
+
+        for index in 0..self.cached_entries.len() {
+            let entry: OwnershipVoucherEntry = match self.cached_entries.get(index) {
+                Ok(entry) => entry,
+                Err(e) => {
+                    report.push(
+                        OwnershipVoucherCheck::EntrySignature(index),
+                        VerificationOutcome::Failed(e.to_string()),
+                    );
+                    return None;
+                }
+            };
+            let payload: UnverifiedValue<OwnershipVoucherEntryPayload> =
+                match entry.get_payload_unverified() {
+                    Ok(payload) => payload,
+                    Err(e) => {
+                        report.push(
+                            OwnershipVoucherCheck::EntrySignature(index),
+                            VerificationOutcome::Failed(e.to_string()),
+                        );
+                        return None;
+                    }
+                };
+            // The other checks are still performed if the signature is invalid,
+            // so that the report is complete
+            let payload = payload.get_unverified_value();
+
+            let outcomes = [
+                (
+                    OwnershipVoucherCheck::EntrySignature(index),
+                    VerificationOutcome::from_result(entry.verify(owner_key.pkey())),
+                ),
+                (
+                    OwnershipVoucherCheck::EntryPreviousHash(index),
+                    VerificationOutcome::from_result(self.verify_previous_hash(index, payload)),
+                ),
+                (
+                    OwnershipVoucherCheck::EntryHeaderInfoHash(index),
+                    VerificationOutcome::from_result(
+                        self.header()
+                            .get_hdr_info_hash(payload.hash_header_info.get_type())
+                            .and_then(|hash| payload.hash_header_info.compare(&hash)),
+                    ),
+                ),
+            ];
+            for (check, outcome) in outcomes {
+                valid &= !outcome.is_failed();
+                report.push(check, outcome);
+            }
+
+            owner_key = payload.public_key.clone();
+        }
+
+        if valid {
+            Some(owner_key)
+        } else {
+            None
+        }
+    }
+
+    fn verify_previous_hash(
+        &self,
+        index: usize,
+        payload: &OwnershipVoucherEntryPayload,
+    ) -> Result<()> {
+        let hash_type = payload.hash_previous_entry.get_type();
+        let previous_hash = if index == 0 {
+            self.hdr_hash(hash_type)?
+        } else {
+            self.cached_entries.get_hash(index - 1, hash_type)?
+        };
+        payload.hash_previous_entry.compare(&previous_hash)
+    }
+}
+
 #[derive(Debug)]
 #[repr(u8)]
 enum OwnershipVoucherHeaderIndex {
@@ -465,10 +928,17 @@ impl OwnershipVoucherHeader {
             &protocol_version,
         )?;
         contents.set(OwnershipVoucherHeaderIndex::Guid as usize, &guid)?;
-        contents.set(
-            OwnershipVoucherHeaderIndex::RendezvousInfo as usize,
-            &rendezvous_info,
-        )?;
+        if protocol_version == ProtocolVersion::Version1_0 {
+            contents.set_raw(
+                OwnershipVoucherHeaderIndex::RendezvousInfo as usize,
+                rendezvous_info.to_v10_data()?,
+            );
+        } else {
+            contents.set(
+                OwnershipVoucherHeaderIndex::RendezvousInfo as usize,
+                &rendezvous_info,
+            )?;
+        }
         contents.set(
             OwnershipVoucherHeaderIndex::DeviceInfo as usize,
             &device_info,
@@ -526,6 +996,24 @@ impl OwnershipVoucherHeader {
         self.cached_device_certificate_chain_hash.as_ref()
     }
 
+    /// Builds the header of the replacement Ownership Voucher created during
+    /// TO2 (Credential Resale), with the Owner2Key as new manufacturer key.
+    pub fn replacement(
+        &self,
+        guid: Guid,
+        rendezvous_info: RendezvousInfo,
+        owner2_public_key: PublicKey,
+    ) -> Result<Self> {
+        Self::new(
+            self.cached_protocol_version,
+            guid,
+            rendezvous_info,
+            self.cached_device_info.clone(),
+            owner2_public_key,
+            self.cached_device_certificate_chain_hash.clone(),
+        )
+    }
+
     fn get_hdr_info_hash(&self, hash_type: HashType) -> Result<Hash> {
         // TODO: Check with FIDO Alliance whether this is correct.
         // For the HashPrevEntry, we compute with the actual CBOR type prefix,
@@ -580,8 +1068,13 @@ impl Serializable for OwnershipVoucherHeader {
         let cached_protocol_version =
             contents.get(OwnershipVoucherHeaderIndex::ProtocolVersion as usize)?;
         let cached_guid = contents.get(OwnershipVoucherHeaderIndex::Guid as usize)?;
-        let cached_rendezvous_info =
-            contents.get(OwnershipVoucherHeaderIndex::RendezvousInfo as usize)?;
+        let cached_rendezvous_info = if cached_protocol_version == ProtocolVersion::Version1_0 {
+            RendezvousInfo::from_v10_data(
+                contents.get_raw(OwnershipVoucherHeaderIndex::RendezvousInfo as usize),
+            )?
+        } else {
+            contents.get(OwnershipVoucherHeaderIndex::RendezvousInfo as usize)?
+        };
         let cached_device_info: String =
             contents.get(OwnershipVoucherHeaderIndex::DeviceInfo as usize)?;
         check_device_info(&cached_device_info)?;
@@ -644,12 +1137,97 @@ impl std::ops::Deref for OwnershipVoucherEntry {
     }
 }
 
-#[derive(Debug, Deserialize, Serialize_tuple, Clone)]
+#[derive(Debug, Clone)]
 pub struct OwnershipVoucherEntryPayload {
     hash_previous_entry: Hash,
     hash_header_info: Hash,
     extra: ExtraType,
     public_key: PublicKey,
+
+    // FDO 1.0 entries have no extra field
+    protocol_version: ProtocolVersion,
+}
+
+impl Serialize for OwnershipVoucherEntryPayload {
+    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        use serde::ser::SerializeTuple;
+
+        if self.protocol_version == ProtocolVersion::Version1_0 {
+            let mut tuple = serializer.serialize_tuple(3)?;
+            tuple.serialize_element(&self.hash_previous_entry)?;
+            tuple.serialize_element(&self.hash_header_info)?;
+            tuple.serialize_element(&self.public_key)?;
+            tuple.end()
+        } else {
+            let mut tuple = serializer.serialize_tuple(4)?;
+            tuple.serialize_element(&self.hash_previous_entry)?;
+            tuple.serialize_element(&self.hash_header_info)?;
+            tuple.serialize_element(&self.extra)?;
+            tuple.serialize_element(&self.public_key)?;
+            tuple.end()
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for OwnershipVoucherEntryPayload {
+    fn deserialize<D>(deserializer: D) -> std::result::Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        struct EntryPayloadVisitor;
+
+        impl<'de> serde::de::Visitor<'de> for EntryPayloadVisitor {
+            type Value = OwnershipVoucherEntryPayload;
+
+            fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+                formatter.write_str("an ownership voucher entry payload")
+            }
+
+            fn visit_seq<A>(self, mut seq: A) -> std::result::Result<Self::Value, A::Error>
+            where
+                A: serde::de::SeqAccess<'de>,
+            {
+                let hash_previous_entry = seq
+                    .next_element()?
+                    .ok_or_else(|| serde::de::Error::invalid_length(0, &self))?;
+                let hash_header_info = seq
+                    .next_element()?
+                    .ok_or_else(|| serde::de::Error::invalid_length(1, &self))?;
+
+                // The FDO 1.0 entries are the ones without the extra field. The array
+                // length is not known up front for indefinite-length arrays, so the
+                // version is determined by whether a fourth element follows.
+                let third: serde_cbor::Value = seq
+                    .next_element()?
+                    .ok_or_else(|| serde::de::Error::invalid_length(2, &self))?;
+                let fourth: Option<serde_cbor::Value> = seq.next_element()?;
+                let (protocol_version, extra, public_key) = match fourth {
+                    None => (ProtocolVersion::Version1_0, None, third),
+                    Some(public_key) => (
+                        ProtocolVersion::Version1_1,
+                        serde_cbor::value::from_value(third).map_err(serde::de::Error::custom)?,
+                        public_key,
+                    ),
+                };
+                let public_key =
+                    serde_cbor::value::from_value(public_key).map_err(serde::de::Error::custom)?;
+
+                Ok(OwnershipVoucherEntryPayload {
+                    hash_previous_entry,
+                    hash_header_info,
+                    extra,
+                    public_key,
+
+                    protocol_version,
+                })
+            }
+        }
+
+        deserializer.deserialize_seq(EntryPayloadVisitor)
+    }
 }
 
 impl OwnershipVoucherEntryPayload {
@@ -664,6 +1242,8 @@ impl OwnershipVoucherEntryPayload {
             hash_header_info,
             extra,
             public_key,
+
+            protocol_version: ProtocolVersion::Version1_1,
         })
     }
 
@@ -683,3 +1263,263 @@ impl OwnershipVoucherEntryPayload {
         &self.public_key
     }
 }
+
+#[cfg(test)]
+mod test_verify {
+    use std::convert::TryFrom;
+
+    use openssl::{
+        pkey::{PKey, Private},
+        x509::X509,
+    };
+
+    use super::{
+        OwnershipVoucher, OwnershipVoucherCheck, OwnershipVoucherEntry,
+        OwnershipVoucherEntryPayload, OwnershipVoucherIndex, VerificationReport,
+    };
+    use crate::{
+        constants::HashType,
+        enhanced_types::X5Bag,
+        publickey::PublicKey,
+        test_utils::{generate_cert, generate_public_key},
+        types::{COSESign, Hash},
+        ProtocolVersion,
+    };
+
+    struct TestVoucher {
+        voucher: OwnershipVoucher,
+        manufacturer_cert: X509,
+        manufacturer_key: PKey<Private>,
+        owner_public_key: PublicKey,
+    }
+
+    /// Builds a voucher with a device certificate chain, without entries
+    fn build_voucher(device_certificate_chain_hash: Option<Hash>) -> TestVoucher {
+        let (manufacturer_key, manufacturer_cert) = generate_cert("Manufacturer", None);
+        let (_, owner_public_key) = generate_public_key("Owner");
+        let voucher = crate::test_utils::build_voucher(
+            &PublicKey::try_from(manufacturer_cert.clone()).unwrap(),
+            device_certificate_chain_hash,
+        );
+
+        TestVoucher {
+            voucher,
+            manufacturer_cert,
+            manufacturer_key,
+            owner_public_key,
+        }
+    }
+
+    fn build_extended_voucher() -> TestVoucher {
+        let mut test_voucher = build_voucher(None);
+        test_voucher
+            .voucher
+            .extend(
+                &test_voucher.manufacturer_key,
+                None,
+                &test_voucher.owner_public_key,
+            )
+            .unwrap();
+        test_voucher
+    }
+
+    /// Appends an entry without the checks that `extend` performs
+    fn push_raw_entry(
+        voucher: &mut OwnershipVoucher,
+        hash_previous_entry: Hash,
+        sign_key: &PKey<Private>,
+        next_party: &PublicKey,
+    ) {
+        let hash_header_info = voucher
+            .header()
+            .get_hdr_info_hash(HashType::Sha256)
+            .unwrap();
+        let payload = OwnershipVoucherEntryPayload::new(
+            hash_previous_entry,
+            hash_header_info,
+            None,
+            next_party.clone(),
+        )
+        .unwrap();
+        let entry = OwnershipVoucherEntry::new(COSESign::new(&payload, None, sign_key).unwrap());
+
+        voucher.cached_entries.push(&entry).unwrap();
+        voucher
+            .contents
+            .set(
+                OwnershipVoucherIndex::Entries as usize,
+                &voucher.cached_entries,
+            )
+            .unwrap();
+    }
+
+    fn failed_checks(report: &VerificationReport) -> Vec<OwnershipVoucherCheck> {
+        report.failures().map(|result| result.check).collect()
+    }
+
+    #[test]
+    fn test_verify_valid() {
+        let test_voucher = build_extended_voucher();
+        let trusted_manufacturer_keys =
+            X5Bag::with_certs(vec![test_voucher.manufacturer_cert.clone()]).unwrap();
+
+        let report = test_voucher.voucher.verify(
+            Some(&trusted_manufacturer_keys),
+            None,
+            Some(&test_voucher.owner_public_key),
+            None,
+        );
+        assert!(report.is_valid(), "{:?}", report);
+        assert!(report
+            .owner_public_key()
+            .unwrap()
+            .matches_pkey(test_voucher.owner_public_key.pkey())
+            .unwrap());
+        assert!(report.device_public_key().is_some());
+    }
+
+    #[test]
+    fn test_verify_tampered_entry_signature() {
+        let mut test_voucher = build_voucher(None);
+        let (stranger_key, _) = generate_cert("Stranger", None);
+        let hash_previous_entry = test_voucher.voucher.hdr_hash(HashType::Sha256).unwrap();
+        push_raw_entry(
+            &mut test_voucher.voucher,
+            hash_previous_entry,
+            &stranger_key,
+            &test_voucher.owner_public_key,
+        );
+
+        let report = test_voucher.voucher.verify(None, None, None, None);
+        assert!(!report.is_valid());
+        assert_eq!(
+            failed_checks(&report),
+            vec![OwnershipVoucherCheck::EntrySignature(0)]
+        );
+        assert!(report.owner_public_key().is_none());
+    }
+
+    #[test]
+    fn test_verify_tampered_entry_previous_hash() {
+        let mut test_voucher = build_voucher(None);
+        let wrong_hash = Hash::from_data(HashType::Sha256, b"not the header").unwrap();
+        let manufacturer_key = test_voucher.manufacturer_key.clone();
+        push_raw_entry(
+            &mut test_voucher.voucher,
+            wrong_hash,
+            &manufacturer_key,
+            &test_voucher.owner_public_key,
+        );
+
+        let report = test_voucher.voucher.verify(None, None, None, None);
+        assert_eq!(
+            failed_checks(&report),
+            vec![OwnershipVoucherCheck::EntryPreviousHash(0)]
+        );
+        assert!(report.owner_public_key().is_none());
+    }
+
+    #[test]
+    fn test_verify_untrusted_manufacturer() {
+        let test_voucher = build_extended_voucher();
+        let (_, other_cert) = generate_cert("Other manufacturer", None);
+        let trusted_manufacturer_keys = X5Bag::with_certs(vec![other_cert]).unwrap();
+
+        let report =
+            test_voucher
+                .voucher
+                .verify(Some(&trusted_manufacturer_keys), None, None, None);
+        assert_eq!(
+            failed_checks(&report),
+            vec![OwnershipVoucherCheck::ManufacturerKey]
+        );
+    }
+
+    #[test]
+    fn test_verify_wrong_owner_key() {
+        let test_voucher = build_extended_voucher();
+        let (_, other_owner_public_key) = generate_public_key("Other owner");
+
+        let report = test_voucher
+            .voucher
+            .verify(None, None, Some(&other_owner_public_key), None);
+        assert_eq!(
+            failed_checks(&report),
+            vec![OwnershipVoucherCheck::OwnerKey]
+        );
+    }
+
+    #[test]
+    fn test_verify_mismatched_chain_hash() {
+        let wrong_hash = Hash::from_data(HashType::Sha256, b"not the chain").unwrap();
+        let test_voucher = build_voucher(Some(wrong_hash));
+
+        let report = test_voucher.voucher.verify(None, None, None, None);
+        assert_eq!(
+            failed_checks(&report),
+            vec![OwnershipVoucherCheck::DeviceCertificateChainHash]
+        );
+    }
+
+    fn entry_payload(protocol_version: ProtocolVersion) -> OwnershipVoucherEntryPayload {
+        let (_, owner_public_key) = generate_public_key("Owner");
+        let mut payload = OwnershipVoucherEntryPayload::new(
+            Hash::from_data(HashType::Sha256, b"previous entry").unwrap(),
+            Hash::from_data(HashType::Sha256, b"header info").unwrap(),
+            None,
+            owner_public_key,
+        )
+        .unwrap();
+        payload.protocol_version = protocol_version;
+        payload
+    }
+
+    /// Re-encodes a definite-length CBOR array as an indefinite-length one
+    fn to_indefinite_array(payload: &OwnershipVoucherEntryPayload) -> Vec<u8> {
+        let elements: Vec<serde_cbor::Value> =
+            serde_cbor::value::from_value(serde_cbor::value::to_value(payload).unwrap()).unwrap();
+        let mut encoded = vec![0x9f];
+        for element in elements {
+            encoded.extend(serde_cbor::to_vec(&element).unwrap());
+        }
+        encoded.push(0xff);
+        encoded
+    }
+
+    #[test]
+    fn test_entry_payload_version_by_element_count() {
+        for protocol_version in [ProtocolVersion::Version1_0, ProtocolVersion::Version1_1] {
+            let payload = entry_payload(protocol_version);
+            let definite = serde_cbor::to_vec(&payload).unwrap();
+            let indefinite = to_indefinite_array(&payload);
+            assert_ne!(definite, indefinite);
+
+            for encoded in [definite, indefinite] {
+                let decoded: OwnershipVoucherEntryPayload =
+                    serde_cbor::from_slice(&encoded).unwrap();
+                assert_eq!(decoded.protocol_version, protocol_version);
+                assert_eq!(decoded.hash_previous_entry, payload.hash_previous_entry);
+                assert_eq!(decoded.hash_header_info, payload.hash_header_info);
+                assert!(decoded.extra.is_none());
+                assert!(decoded
+                    .public_key
+                    .matches_pkey(payload.public_key.pkey())
+                    .unwrap());
+                // Re-encoding keeps the version's layout
+                assert_eq!(
+                    serde_cbor::to_vec(&decoded).unwrap(),
+                    serde_cbor::to_vec(&payload).unwrap()
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_entry_payload_invalid_length() {
+        let elements = vec![serde_cbor::Value::Null, serde_cbor::Value::Null];
+        assert!(serde_cbor::from_slice::<OwnershipVoucherEntryPayload>(
+            &serde_cbor::to_vec(&elements).unwrap()
+        )
+        .is_err());
+    }
+}
@@ -54,9 +54,12 @@ impl<'de> Deserialize<'de> for PublicKey {
             where
                 A: serde::de::SeqAccess<'de>,
             {
-                let key_type: PublicKeyType = seq
+                let key_type: i16 = seq
                     .next_element()?
                     .ok_or_else(|| serde::de::Error::invalid_length(0, &self))?;
+                let key_type = PublicKeyType::from_i16_compat(key_type).ok_or_else(|| {
+                    serde::de::Error::custom(format!("Invalid public key type {key_type}"))
+                })?;
                 let encoding: PublicKeyEncoding = seq
                     .next_element()?
                     .ok_or_else(|| serde::de::Error::invalid_length(1, &self))?;
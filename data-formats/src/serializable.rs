@@ -1,4 +1,7 @@
-use crate::cborparser::{ParsedArray, ParsedArrayBuilder, ParsedArraySize2};
+use crate::cborparser::{
+    ParsedArray, ParsedArrayBuilder, ParsedArraySize5, ParsedArraySizeDynamic,
+};
+use crate::constants::ProtocolVersion;
 use crate::publickey::PublicKey;
 use crate::types::COSESign;
 use crate::Error;
@@ -93,6 +96,13 @@ where
 pub struct StoredItem {
     pub public_key: PublicKey,
     pub to1d: COSESign,
+    // The manufacturer and owner keys of the registered ownership voucher,
+    // not available for items stored before they were added
+    pub manufacturer_public_key: Option<PublicKey>,
+    pub owner_public_key: Option<PublicKey>,
+    // The protocol version of the registered ownership voucher, not
+    // available for items stored before it was added
+    pub protocol_version: Option<ProtocolVersion>,
 }
 
 impl Serializable for StoredItem {
@@ -100,21 +110,37 @@ impl Serializable for StoredItem {
     where
         R: std::io::Read,
     {
-        let contents: ParsedArray<ParsedArraySize2> = ParsedArray::deserialize_from_reader(reader)?;
+        let contents: ParsedArray<ParsedArraySizeDynamic> =
+            ParsedArray::deserialize_from_reader(reader)?;
 
         let public_key = contents.get(0)?;
         let to1d = contents.get(1)?;
-
-        Ok(StoredItem { public_key, to1d })
+        let (manufacturer_public_key, owner_public_key, protocol_version) = match contents.len() {
+            2 => (None, None, None),
+            4 => (contents.get(2)?, contents.get(3)?, None),
+            5 => (contents.get(2)?, contents.get(3)?, contents.get(4)?),
+            _ => return Err(Error::InconsistentValue("Invalid StoredItem length")),
+        };
+
+        Ok(StoredItem {
+            public_key,
+            to1d,
+            manufacturer_public_key,
+            owner_public_key,
+            protocol_version,
+        })
     }
 
     fn serialize_to_writer<W>(&self, writer: W) -> Result<(), Error>
     where
         W: std::io::Write,
     {
-        let mut contents: ParsedArrayBuilder<ParsedArraySize2> = ParsedArrayBuilder::new();
+        let mut contents: ParsedArrayBuilder<ParsedArraySize5> = ParsedArrayBuilder::new();
         contents.set(0, &self.public_key)?;
         contents.set(1, &self.to1d)?;
+        contents.set(2, &self.manufacturer_public_key)?;
+        contents.set(3, &self.owner_public_key)?;
+        contents.set(4, &self.protocol_version)?;
         let contents = contents.build();
 
         contents.serialize_to_writer(writer)
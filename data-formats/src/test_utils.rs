@@ -0,0 +1,110 @@
+//! Helpers to build certificates and vouchers in tests, shared by the FDO
+//! crates through the `test-utils` feature
+
+use std::convert::TryFrom;
+
+use openssl::{
+    asn1::Asn1Time,
+    bn::BigNum,
+    ec::{EcGroup, EcKey},
+    hash::MessageDigest,
+    nid::Nid,
+    pkey::{PKey, Private},
+    x509::{
+        extension::{BasicConstraints, SubjectAlternativeName},
+        X509Builder, X509NameBuilder, X509,
+    },
+};
+
+use crate::{
+    constants::HashType,
+    ownershipvoucher::{OwnershipVoucher, OwnershipVoucherHeader},
+    publickey::{PublicKey, X5Chain},
+    types::{Guid, HMac, Hash, RendezvousInfo},
+    ProtocolVersion,
+};
+
+/// Generates a P-256 key and a certificate for it, valid for a day
+///
+/// Without `issuer`, the certificate is a self-signed CA certificate.
+/// Otherwise it is signed by the issuer and valid for `common_name` as DNS name.
+pub fn generate_cert(
+    common_name: &str,
+    issuer: Option<&(PKey<Private>, X509)>,
+) -> (PKey<Private>, X509) {
+    let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1).unwrap();
+    let key = PKey::from_ec_key(EcKey::generate(&group).unwrap()).unwrap();
+
+    let mut name = X509NameBuilder::new().unwrap();
+    name.append_entry_by_nid(Nid::COMMONNAME, common_name)
+        .unwrap();
+    let name = name.build();
+
+    let mut builder = X509Builder::new().unwrap();
+    builder.set_version(2).unwrap();
+    builder
+        .set_serial_number(&BigNum::from_u32(1).unwrap().to_asn1_integer().unwrap())
+        .unwrap();
+    builder.set_subject_name(&name).unwrap();
+    builder.set_pubkey(&key).unwrap();
+    builder
+        .set_not_before(&Asn1Time::days_from_now(0).unwrap())
+        .unwrap();
+    builder
+        .set_not_after(&Asn1Time::days_from_now(1).unwrap())
+        .unwrap();
+    match issuer {
+        Some((issuer_key, issuer_cert)) => {
+            let san = SubjectAlternativeName::new()
+                .dns(common_name)
+                .build(&builder.x509v3_context(Some(issuer_cert), None))
+                .unwrap();
+            builder.append_extension(san).unwrap();
+            builder.set_issuer_name(issuer_cert.subject_name()).unwrap();
+            builder.sign(issuer_key, MessageDigest::sha256()).unwrap();
+        }
+        None => {
+            builder
+                .append_extension(BasicConstraints::new().critical().ca().build().unwrap())
+                .unwrap();
+            builder.set_issuer_name(&name).unwrap();
+            builder.sign(&key, MessageDigest::sha256()).unwrap();
+        }
+    }
+
+    (key, builder.build())
+}
+
+/// Generates a P-256 key with a self-signed certificate, as FDO public key
+pub fn generate_public_key(common_name: &str) -> (PKey<Private>, PublicKey) {
+    let (key, cert) = generate_cert(common_name, None);
+    (key, PublicKey::try_from(cert).unwrap())
+}
+
+/// Builds a voucher without entries for a new device certificate
+///
+/// The header references the device certificate chain with
+/// `device_certificate_chain_hash`, or with the SHA256 of the device
+/// certificate if not set.
+pub fn build_voucher(
+    manufacturer_public_key: &PublicKey,
+    device_certificate_chain_hash: Option<Hash>,
+) -> OwnershipVoucher {
+    let (_, device_cert) = generate_cert("Device", None);
+    let device_certificate_chain_hash = device_certificate_chain_hash.unwrap_or_else(|| {
+        Hash::from_data(HashType::Sha256, &device_cert.to_der().unwrap()).unwrap()
+    });
+    let chain = X5Chain::new(vec![device_cert]).unwrap();
+
+    let header = OwnershipVoucherHeader::new(
+        ProtocolVersion::Version1_1,
+        Guid::new().unwrap(),
+        RendezvousInfo::new(Vec::new()).unwrap(),
+        "testdevice".to_string(),
+        manufacturer_public_key.clone(),
+        Some(device_certificate_chain_hash),
+    )
+    .unwrap();
+    let header_hmac = HMac::from_digest(HashType::HmacSha256, vec![0; 32]).unwrap();
+    OwnershipVoucher::new(header, header_hmac, Some(chain)).unwrap()
+}
@@ -16,8 +16,8 @@ use serde_tuple::Serialize_tuple;
 use crate::{
     cborparser::{ParsedArray, ParsedArrayBuilder},
     constants::{
-        DeviceSigType, HashType, HeaderKeys, RendezvousVariable, ServiceInfoModule,
-        StandardServiceInfoModule, TransportProtocol,
+        DeviceSigType, HashType, HeaderKeys, ProtocolVersion, RendezvousVariable,
+        RsaSignatureAlgorithm, ServiceInfoModule, StandardServiceInfoModule, TransportProtocol,
     },
     errors::Error,
     ownershipvoucher::OwnershipVoucher,
@@ -29,10 +29,13 @@ use openssl::{
     bn::{BigNum, BigNumContext},
     dh::Dh,
     ec::{EcGroup, EcKey, EcPoint},
+    encrypt::{Decrypter, Encrypter},
     hash::{hash, MessageDigest},
     nid::Nid,
-    pkey::Params,
+    pkey::{Id, PKey, PKeyRef, Params, Private, Public},
     rand::rand_bytes,
+    rsa::{Padding, Rsa},
+    sign::{RsaPssSaltlen, Signer, Verifier},
     symm::Cipher,
 };
 use openssl_kdf::{perform_kdf, KdfArgument, KdfKbMode, KdfMacType, KdfType};
@@ -450,6 +453,27 @@ impl RendezvousInfo {
         Ok(RendezvousInfo(out))
     }
 
+    /// Parses the FDO 1.0 encoding, in which the values are not wrapped in a bstr
+    pub(crate) fn from_v10_data(data: &[u8]) -> Result<RendezvousInfo, Error> {
+        let directives: Vec<Vec<(RendezvousVariable, CborSimpleType)>> =
+            serde_cbor::from_slice(data)?;
+        Self::new(directives)
+    }
+
+    pub(crate) fn to_v10_data(&self) -> Result<Vec<u8>, Error> {
+        let directives = self
+            .0
+            .iter()
+            .map(|directive| {
+                directive
+                    .iter()
+                    .map(|(variable, value)| Ok((*variable, serde_cbor::from_slice(value)?)))
+                    .collect::<Result<Vec<(RendezvousVariable, CborSimpleType)>, Error>>()
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+        Ok(serde_cbor::to_vec(&directives)?)
+    }
+
     pub fn values(&self) -> &[RendezvousDirective] {
         &self.0
     }
@@ -732,7 +756,24 @@ impl TO2ProveDevicePayload {
     }
 }
 
-#[derive(Default, Debug, Serialize, Deserialize)]
+/// The ServiceInfo MTU to use when the peer did not indicate one
+pub const DEFAULT_SERVICE_INFO_MTU: u64 = 1300;
+/// The smallest ServiceInfo MTU we will honor, smaller values are treated as the minimum
+pub const MIN_SERVICE_INFO_MTU: u64 = 256;
+/// The number of ServiceInfo rounds allowed when no other limit is configured
+///
+/// Each round carries at most one MTU of ServiceInfo, so together with
+/// `DEFAULT_SERVICE_INFO_MTU` this caps the ServiceInfo exchanged during TO2
+/// at about 1.2 MB, unless the limit or the MTU are raised.
+pub const DEFAULT_MAX_SERVICE_INFO_LOOPS: u32 = 1000;
+
+// Space reserved for the CBOR array headers of the ServiceInfo and the
+// DeviceServiceInfo/OwnerServiceInfo message wrapping it.
+const SERVICE_INFO_MESSAGE_OVERHEAD: usize = 8;
+// How much the CBOR headers of a binary value can grow with its length
+const BYTES_VALUE_HEADER_GROWTH: usize = 8;
+
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
 #[serde(transparent)]
 pub struct ServiceInfo(Vec<(String, ByteBuf)>);
 
@@ -794,6 +835,205 @@ impl ServiceInfo {
             })
             .collect()
     }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn extend(&mut self, other: ServiceInfo) {
+        self.0.extend(other.0)
+    }
+
+    /// The space available for ServiceInfo entries in a message of at most
+    /// `mtu` bytes.
+    pub fn mtu_budget(mtu: u64) -> usize {
+        std::cmp::max(mtu, MIN_SERVICE_INFO_MTU) as usize - SERVICE_INFO_MESSAGE_OVERHEAD
+    }
+
+    /// The encoded size of the entries, as counted against the MTU budget.
+    pub fn encoded_size(&self) -> Result<usize, Error> {
+        self.0
+            .iter()
+            .map(|entry| Ok(serde_cbor::to_vec(entry)?.len()))
+            .sum()
+    }
+
+    /// The largest binary value that fits in an entry for `module` and `key`
+    /// of at most `budget` bytes.
+    pub fn max_bytes_value_len<M>(module: M, key: &str, budget: usize) -> Result<usize, Error>
+    where
+        M: Into<ServiceInfoModule>,
+    {
+        let mut entry = ServiceInfo::new();
+        entry.add(module, key, &serde_bytes::Bytes::new(&[]))?;
+        Ok(budget.saturating_sub(entry.encoded_size()? + BYTES_VALUE_HEADER_GROWTH))
+    }
+
+    /// Adds binary data as consecutive entries with the same key, each small
+    /// enough to fit in a message of the provided MTU.
+    pub fn add_chunked<M>(
+        &mut self,
+        module: M,
+        key: &str,
+        data: &[u8],
+        mtu: u64,
+    ) -> Result<(), Error>
+    where
+        M: Into<ServiceInfoModule>,
+    {
+        let module: ServiceInfoModule = module.into();
+        let chunk_len = Self::max_bytes_value_len(module.clone(), key, Self::mtu_budget(mtu))?;
+        if chunk_len == 0 {
+            return Err(Error::ServiceInfoEntryTooLarge(
+                format!("{module}:{key}"),
+                data.len(),
+                Self::mtu_budget(mtu),
+            ));
+        }
+        if data.is_empty() {
+            return self.add(module, key, &serde_bytes::Bytes::new(data));
+        }
+        for chunk in data.chunks(chunk_len) {
+            self.add(module.clone(), key, &serde_bytes::Bytes::new(chunk))?;
+        }
+        Ok(())
+    }
+
+    /// Splits the entries over as many ServiceInfo messages as needed to
+    /// have each of them fit in the provided MTU.
+    ///
+    /// Entries are never split, an entry that is larger than the MTU by itself
+    /// is an error. Large binary values should be added with `add_chunked`.
+    pub fn split_to_mtu(self, mtu: u64) -> Result<Vec<ServiceInfo>, Error> {
+        let budget = Self::mtu_budget(mtu);
+
+        let mut chunks = Vec::new();
+        let mut current = ServiceInfo::new();
+        let mut current_size = 0;
+
+        for entry in self.0 {
+            let entry_size = serde_cbor::to_vec(&entry)?.len();
+            if entry_size > budget {
+                return Err(Error::ServiceInfoEntryTooLarge(entry.0, entry_size, budget));
+            }
+            if !current.is_empty() && current_size + entry_size > budget {
+                chunks.push(std::mem::take(&mut current));
+                current_size = 0;
+            }
+            current_size += entry_size;
+            current.0.push(entry);
+        }
+        if !current.is_empty() {
+            chunks.push(current);
+        }
+
+        Ok(chunks)
+    }
+}
+
+#[cfg(test)]
+mod test_service_info {
+    use super::{
+        CborSimpleTypeExt, Error, ServiceInfo, StandardServiceInfoModule, MIN_SERVICE_INFO_MTU,
+    };
+
+    #[test]
+    fn test_split_to_mtu() {
+        let mut si = ServiceInfo::new();
+        for i in 0..20 {
+            si.add(
+                StandardServiceInfoModule::DevMod,
+                &format!("key{i}"),
+                &vec![0u8; 50],
+            )
+            .unwrap();
+        }
+        si.add(StandardServiceInfoModule::DevMod, "last", &true)
+            .unwrap();
+
+        let chunks = si.split_to_mtu(MIN_SERVICE_INFO_MTU).unwrap();
+        assert!(chunks.len() > 2);
+        assert_eq!(chunks.iter().map(|c| c.len()).sum::<usize>(), 21);
+        for chunk in &chunks {
+            let size = serde_cbor::to_vec(chunk).unwrap().len();
+            assert!(size <= MIN_SERVICE_INFO_MTU as usize);
+        }
+        let values = chunks.last().unwrap().values().unwrap();
+        assert_eq!(values.last().unwrap().1, ":last");
+    }
+
+    #[test]
+    fn test_split_too_large() {
+        let mut si = ServiceInfo::new();
+        si.add(StandardServiceInfoModule::DevMod, "large", &vec![0u8; 1000])
+            .unwrap();
+        assert!(matches!(
+            si.split_to_mtu(MIN_SERVICE_INFO_MTU),
+            Err(Error::ServiceInfoEntryTooLarge(..))
+        ));
+    }
+
+    #[test]
+    fn test_add_chunked() {
+        let data: Vec<u8> = (0..1000).map(|i| i as u8).collect();
+        let mut si = ServiceInfo::new();
+        si.add_chunked(
+            StandardServiceInfoModule::DevMod,
+            "data",
+            &data,
+            MIN_SERVICE_INFO_MTU,
+        )
+        .unwrap();
+        assert!(si.len() > 1000 / MIN_SERVICE_INFO_MTU as usize);
+
+        let mut received: Vec<u8> = Vec::new();
+        for (_, key, value) in si.iter() {
+            assert_eq!(key, "data");
+            received.extend(value.as_bytes().unwrap());
+        }
+        assert_eq!(received, data);
+
+        // Every entry fits in a message by itself
+        let entries = si.len();
+        let chunks = si.split_to_mtu(MIN_SERVICE_INFO_MTU).unwrap();
+        assert_eq!(chunks.iter().map(|c| c.len()).sum::<usize>(), entries);
+
+        // Empty data is still sent, as one empty entry
+        let mut si = ServiceInfo::new();
+        si.add_chunked(StandardServiceInfoModule::DevMod, "data", &[], 1300)
+            .unwrap();
+        assert_eq!(si.len(), 1);
+    }
+
+    #[test]
+    fn test_split_empty() {
+        assert!(ServiceInfo::new().split_to_mtu(1300).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_encoded_size() {
+        let mut si = ServiceInfo::new();
+        assert_eq!(si.encoded_size().unwrap(), 0);
+        si.add(StandardServiceInfoModule::DevMod, "key", &vec![0u8; 50])
+            .unwrap();
+        si.add(StandardServiceInfoModule::DevMod, "other", &true)
+            .unwrap();
+        // Only the array header of the message is not counted
+        assert_eq!(
+            si.encoded_size().unwrap() + 1,
+            serde_cbor::to_vec(&si).unwrap().len()
+        );
+
+        assert!(ServiceInfo::mtu_budget(1300) < 1300);
+        assert_eq!(
+            ServiceInfo::mtu_budget(0),
+            ServiceInfo::mtu_budget(MIN_SERVICE_INFO_MTU)
+        );
+    }
 }
 
 #[derive(Debug)]
@@ -836,7 +1076,8 @@ impl Iterator for ServiceInfoIter<'_> {
 
 #[derive(Debug)]
 pub struct TO2ProveOVHdrPayload {
-    contents: ParsedArray<crate::cborparser::ParsedArraySize8>,
+    // FDO 1.0 payloads do not have the max owner message size
+    contents: ParsedArray<crate::cborparser::ParsedArraySizeDynamic>,
 
     cached_ov_header: ByteBuf,
     cached_num_ov_entries: u16,
@@ -853,8 +1094,19 @@ impl Serializable for TO2ProveOVHdrPayload {
     where
         R: std::io::Read,
     {
-        let contents = ParsedArray::deserialize_from_reader(reader)?;
-
+        let contents: ParsedArray<crate::cborparser::ParsedArraySizeDynamic> =
+            ParsedArray::deserialize_from_reader(reader)?;
+
+        let cached_max_owner_message_size = match contents.len() {
+            7 => crate::messages::v11::to2::MAX_MESSAGE_SIZE,
+            8 => contents.get(7)?,
+            len => {
+                return Err(crate::cborparser::ArrayParseError::InvalidNumberOfElements(
+                    len as u64, 8,
+                )
+                .into())
+            }
+        };
         let cached_ov_header = contents.get(0)?;
         let cached_num_ov_entries = contents.get(1)?;
         let cached_hmac = contents.get(2)?;
@@ -862,7 +1114,6 @@ impl Serializable for TO2ProveOVHdrPayload {
         let cached_b_signature_info = contents.get(4)?;
         let cached_a_key_exchange = contents.get(5)?;
         let cached_hello_device_hash = contents.get(6)?;
-        let cached_max_owner_message_size = contents.get(7)?;
 
         Ok(TO2ProveOVHdrPayload {
             contents,
@@ -887,7 +1138,9 @@ impl Serializable for TO2ProveOVHdrPayload {
 }
 
 impl TO2ProveOVHdrPayload {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
+        protocol_version: ProtocolVersion,
         ov_header: ByteBuf,
         num_ov_entries: u16,
         hmac: HMac,
@@ -898,16 +1151,17 @@ impl TO2ProveOVHdrPayload {
     ) -> Result<Self, Error> {
         let a_key_exchange = ByteBuf::from(a_key_exchange);
 
-        let mut contents = ParsedArrayBuilder::new();
-        contents.set(0, &ov_header)?;
-        contents.set(1, &num_ov_entries)?;
-        contents.set(2, &hmac)?;
-        contents.set(3, &nonce5)?;
-        contents.set(4, &b_signature_info)?;
-        contents.set(5, &a_key_exchange)?;
-        contents.set(6, &hello_device_hash)?;
-        contents.set(7, &crate::messages::v11::to2::MAX_MESSAGE_SIZE)?;
-        let contents = contents.build();
+        let mut contents = ParsedArray::new_empty();
+        contents.push(&ov_header)?;
+        contents.push(&num_ov_entries)?;
+        contents.push(&hmac)?;
+        contents.push(&nonce5)?;
+        contents.push(&b_signature_info)?;
+        contents.push(&a_key_exchange)?;
+        contents.push(&hello_device_hash)?;
+        if protocol_version != ProtocolVersion::Version1_0 {
+            contents.push(&crate::messages::v11::to2::MAX_MESSAGE_SIZE)?;
+        }
 
         Ok(TO2ProveOVHdrPayload {
             contents,
@@ -996,6 +1250,10 @@ pub enum KeyDeriveSide {
 pub enum KeyExchange {
     Ecdh(KexSuite, Vec<u8>, Vec<u8>),
     Dhkex(KexSuite, Vec<u8>),
+    // Owner side: RSA private key (DER)
+    AsymkexOwner(KexSuite, Vec<u8>),
+    // Device side: random, random encrypted to the Owner key
+    AsymkexDevice(KexSuite, Vec<u8>, Vec<u8>),
 }
 
 const KEY_DERIVE_LABEL: &[u8] = b"FIDO-KDF";
@@ -1019,6 +1277,43 @@ impl KeyExchange {
 
                 Ok(KeyExchange::Ecdh(suite, key, our_random))
             }
+            KexSuite::Asymkex2048 | KexSuite::Asymkex3072 => {
+                let key = Rsa::generate(suite.get_asymkex_rsa_bits())?;
+                let key = PKey::from_rsa(key)?;
+                Ok(KeyExchange::AsymkexOwner(suite, key.private_key_to_der()?))
+            }
+        }
+    }
+
+    /// Creates the Device side of the key exchange.
+    ///
+    /// For the symmetric suites this is the same as `new`, but the asymmetric
+    /// suites need the xAKeyExchange of the Owner to build the xBKeyExchange.
+    pub fn new_for_device(suite: KexSuite, a_key_exchange: &[u8]) -> Result<Self, Error> {
+        match suite {
+            KexSuite::Asymkex2048 | KexSuite::Asymkex3072 => {
+                let owner_key = PKey::public_key_from_der(a_key_exchange)?;
+                if owner_key.id() != Id::RSA {
+                    return Err(Error::KeyExchangeError("Owner key is not an RSA key"));
+                }
+                if owner_key.bits() != suite.get_asymkex_rsa_bits() {
+                    return Err(Error::KeyExchangeError("Owner key is invalid size"));
+                }
+
+                let mut our_random = vec![0; suite.get_asymkex_random_size()];
+                rand_bytes(&mut our_random)?;
+
+                let mut encrypter = Encrypter::new(&owner_key)?;
+                encrypter.set_rsa_padding(Padding::PKCS1_OAEP)?;
+                encrypter.set_rsa_oaep_md(MessageDigest::sha256())?;
+                encrypter.set_rsa_mgf1_md(MessageDigest::sha256())?;
+                let mut encrypted = vec![0; encrypter.encrypt_len(&our_random)?];
+                let encrypted_len = encrypter.encrypt(&our_random, &mut encrypted)?;
+                encrypted.truncate(encrypted_len);
+
+                Ok(KeyExchange::AsymkexDevice(suite, our_random, encrypted))
+            }
+            _ => Self::new(suite),
         }
     }
 
@@ -1050,6 +1345,11 @@ impl KeyExchange {
 
                 Ok(self.encode_ecdh_bstr(&public_x, &public_y, our_random))
             }
+            KeyExchange::AsymkexOwner(_, key) => {
+                let key = PKey::private_key_from_der(key)?;
+                Ok(key.public_key_to_der()?)
+            }
+            KeyExchange::AsymkexDevice(_, _, encrypted) => Ok(encrypted.clone()),
         }
     }
 
@@ -1171,6 +1471,41 @@ impl KeyExchange {
         }
     }
 
+    fn derive_key_asymkex(
+        &self,
+        our_side: KeyDeriveSide,
+        other: &[u8],
+    ) -> Result<(Vec<u8>, Vec<u8>), Error> {
+        match (self, our_side) {
+            (KeyExchange::AsymkexOwner(suite, key), KeyDeriveSide::OwnerService) => {
+                let key = PKey::private_key_from_der(key)?;
+
+                let mut decrypter = Decrypter::new(&key)?;
+                decrypter.set_rsa_padding(Padding::PKCS1_OAEP)?;
+                decrypter.set_rsa_oaep_md(MessageDigest::sha256())?;
+                decrypter.set_rsa_mgf1_md(MessageDigest::sha256())?;
+                let mut other_random = vec![0; decrypter.decrypt_len(other)?];
+                let other_random_len = decrypter.decrypt(other, &mut other_random)?;
+                other_random.truncate(other_random_len);
+
+                if other_random.len() != suite.get_asymkex_random_size() {
+                    return Err(Error::KeyExchangeError("Other random is invalid size"));
+                }
+
+                Ok((other_random, vec![]))
+            }
+            (KeyExchange::AsymkexDevice(_, our_random, _), KeyDeriveSide::Device) => {
+                // The Owner key was already used when creating the xBKeyExchange
+                Ok((our_random.clone(), vec![]))
+            }
+            (KeyExchange::AsymkexOwner(..), _) | (KeyExchange::AsymkexDevice(..), _) => Err(
+                Error::KeyExchangeError("Asymmetric key exchange used on wrong side"),
+            ),
+            // Only ASYMKEX suites call into here
+            _ => unreachable!(),
+        }
+    }
+
     pub fn derive_key(
         &self,
         our_side: KeyDeriveSide,
@@ -1181,6 +1516,9 @@ impl KeyExchange {
         let (shared_secret, context_rand) = match self {
             KeyExchange::Dhkex(..) => self.derive_key_dh(other)?,
             KeyExchange::Ecdh(..) => self.derive_key_ecdh(our_side, other)?,
+            KeyExchange::AsymkexOwner(..) | KeyExchange::AsymkexDevice(..) => {
+                self.derive_key_asymkex(our_side, other)?
+            }
         };
 
         let mut salt = Vec::with_capacity(KEY_DERIVE_CONTEXT_PREFIX.len() + context_rand.len() + 2);
@@ -1239,6 +1577,80 @@ impl std::fmt::Debug for KeyExchange {
     }
 }
 
+#[cfg(test)]
+mod test_key_exchange {
+    use super::{CipherSuite, DerivedKeys, KexSuite, KeyDeriveSide, KeyExchange};
+
+    fn test_roundtrip(suite: KexSuite) {
+        let owner = KeyExchange::new(suite).unwrap();
+        let device = KeyExchange::new_for_device(suite, &owner.get_public().unwrap()).unwrap();
+
+        let owner_keys = owner
+            .derive_key(
+                KeyDeriveSide::OwnerService,
+                CipherSuite::A128Gcm,
+                &device.get_public().unwrap(),
+                false,
+            )
+            .unwrap();
+        let device_keys = device
+            .derive_key(
+                KeyDeriveSide::Device,
+                CipherSuite::A128Gcm,
+                &owner.get_public().unwrap(),
+                false,
+            )
+            .unwrap();
+
+        match (owner_keys, device_keys) {
+            (DerivedKeys::Combined { sevk: owner }, DerivedKeys::Combined { sevk: device }) => {
+                assert_eq!(owner, device)
+            }
+            _ => panic!("Unexpected key types derived"),
+        }
+    }
+
+    #[test]
+    fn test_asymkex2048_roundtrip() {
+        test_roundtrip(KexSuite::Asymkex2048);
+    }
+
+    #[test]
+    fn test_asymkex3072_roundtrip() {
+        test_roundtrip(KexSuite::Asymkex3072);
+    }
+
+    #[test]
+    fn test_split_key_derivation() {
+        let owner = KeyExchange::new(KexSuite::Ecdh384).unwrap();
+        let device = KeyExchange::new(KexSuite::Ecdh384).unwrap();
+
+        let keys = owner
+            .derive_key(
+                KeyDeriveSide::OwnerService,
+                CipherSuite::Aes256CtrHmacSha384,
+                &device.get_public().unwrap(),
+                false,
+            )
+            .unwrap();
+        match keys {
+            DerivedKeys::Split { sek, svk } => {
+                assert_eq!(sek.len(), 32);
+                assert_eq!(svk.len(), 64);
+            }
+            _ => panic!("Unexpected key type derived"),
+        }
+    }
+
+    #[test]
+    fn test_asymkex_wrong_side() {
+        let owner = KeyExchange::new(KexSuite::Asymkex2048).unwrap();
+        assert!(owner
+            .derive_key(KeyDeriveSide::Device, CipherSuite::A128Gcm, &[], false)
+            .is_err());
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct MessageProtocolInfo {
     token: Option<Vec<u8>>,
@@ -1261,6 +1673,9 @@ pub enum KexSuite {
     // Diffie-Hellmann Key Exchange Protocol
     DhkexId14,
     DhkexId15,
+    // Asymmetric Key Exchange Protocol
+    Asymkex2048,
+    Asymkex3072,
 }
 
 impl FromStr for KexSuite {
@@ -1272,6 +1687,8 @@ impl FromStr for KexSuite {
             "ECDH384" => Ok(KexSuite::Ecdh384),
             "DHKEXid14" => Ok(KexSuite::DhkexId14),
             "DHKEXid15" => Ok(KexSuite::DhkexId15),
+            "ASYMKEX2048" => Ok(KexSuite::Asymkex2048),
+            "ASYMKEX3072" => Ok(KexSuite::Asymkex3072),
             other => Err(Error::InvalidSuiteName(other.to_string())),
         }
     }
@@ -1284,6 +1701,8 @@ impl Display for KexSuite {
             KexSuite::Ecdh384 => write!(f, "ECDH384"),
             KexSuite::DhkexId14 => write!(f, "DHKEXid14"),
             KexSuite::DhkexId15 => write!(f, "DHKEXid15"),
+            KexSuite::Asymkex2048 => write!(f, "ASYMKEX2048"),
+            KexSuite::Asymkex3072 => write!(f, "ASYMKEX3072"),
         }
     }
 }
@@ -1348,6 +1767,24 @@ impl KexSuite {
         Ok(EcGroup::from_curve_name(curve_name)?)
     }
 
+    fn get_asymkex_rsa_bits(&self) -> u32 {
+        match self {
+            KexSuite::Asymkex2048 => 2048,
+            KexSuite::Asymkex3072 => 3072,
+            // Only ASYMKEX suites call into here
+            _ => unreachable!(),
+        }
+    }
+
+    fn get_asymkex_random_size(&self) -> usize {
+        match self {
+            KexSuite::Asymkex2048 => 32,
+            KexSuite::Asymkex3072 => 96,
+            // Only ASYMKEX suites call into here
+            _ => unreachable!(),
+        }
+    }
+
     fn get_dh_params(&self) -> Result<Dh<Params>, Error> {
         match self {
             KexSuite::DhkexId14 => {
@@ -1405,21 +1842,33 @@ pub enum CipherSuite {
     // Combined ciphers
     A128Gcm = 1,
     A256Gcm = 3,
+    // Split ciphers, using Encrypt-then-MAC
+    Aes128CbcHmacSha256 = -17760703,
+    Aes128CtrHmacSha256 = -17760704,
+    Aes256CbcHmacSha384 = -17760705,
+    Aes256CtrHmacSha384 = -17760706,
 }
 
 impl CipherSuite {
-    fn uses_combined_key(&self) -> bool {
+    pub fn uses_combined_key(&self) -> bool {
         match self {
             CipherSuite::A128Gcm | CipherSuite::A256Gcm => true,
+            CipherSuite::Aes128CbcHmacSha256
+            | CipherSuite::Aes128CtrHmacSha256
+            | CipherSuite::Aes256CbcHmacSha384
+            | CipherSuite::Aes256CtrHmacSha384 => false,
         }
     }
 
+    // The SVK comes first in the KDF output, so this is the SVK length
     fn split_key_split_pos(&self) -> usize {
         match self {
             CipherSuite::A128Gcm | CipherSuite::A256Gcm => {
                 // Gcm ciphers should never call into the split_key
                 unreachable!()
             }
+            CipherSuite::Aes128CbcHmacSha256 | CipherSuite::Aes128CtrHmacSha256 => 32,
+            CipherSuite::Aes256CbcHmacSha384 | CipherSuite::Aes256CtrHmacSha384 => 64,
         }
     }
 
@@ -1427,12 +1876,33 @@ impl CipherSuite {
         match self {
             CipherSuite::A128Gcm => 16,
             CipherSuite::A256Gcm => 32,
+            CipherSuite::Aes128CbcHmacSha256 | CipherSuite::Aes128CtrHmacSha256 => 16 + 32,
+            CipherSuite::Aes256CbcHmacSha384 | CipherSuite::Aes256CtrHmacSha384 => 32 + 64,
         }
     }
 
     fn kdf_digest(&self) -> MessageDigest {
         match self {
             CipherSuite::A128Gcm | CipherSuite::A256Gcm => MessageDigest::sha256(),
+            CipherSuite::Aes128CbcHmacSha256 | CipherSuite::Aes128CtrHmacSha256 => {
+                MessageDigest::sha256()
+            }
+            CipherSuite::Aes256CbcHmacSha384 | CipherSuite::Aes256CtrHmacSha384 => {
+                MessageDigest::sha384()
+            }
+        }
+    }
+
+    /// The MAC used in the COSE_Mac0 wrapping of split ciphers
+    pub fn etm_mac_type(&self) -> Option<HashType> {
+        match self {
+            CipherSuite::A128Gcm | CipherSuite::A256Gcm => None,
+            CipherSuite::Aes128CbcHmacSha256 | CipherSuite::Aes128CtrHmacSha256 => {
+                Some(HashType::HmacSha256)
+            }
+            CipherSuite::Aes256CbcHmacSha384 | CipherSuite::Aes256CtrHmacSha384 => {
+                Some(HashType::HmacSha384)
+            }
         }
     }
 
@@ -1440,6 +1910,10 @@ impl CipherSuite {
         match self {
             CipherSuite::A128Gcm => Cipher::aes_128_gcm(),
             CipherSuite::A256Gcm => Cipher::aes_256_gcm(),
+            CipherSuite::Aes128CbcHmacSha256 => Cipher::aes_128_cbc(),
+            CipherSuite::Aes128CtrHmacSha256 => Cipher::aes_128_ctr(),
+            CipherSuite::Aes256CbcHmacSha384 => Cipher::aes_256_cbc(),
+            CipherSuite::Aes256CtrHmacSha384 => Cipher::aes_256_ctr(),
         }
     }
 }
@@ -1451,6 +1925,10 @@ impl FromStr for CipherSuite {
         match s {
             "A128GCM" => Ok(CipherSuite::A128Gcm),
             "A256GCM" => Ok(CipherSuite::A256Gcm),
+            "AES128/CBC/HMAC-SHA256" => Ok(CipherSuite::Aes128CbcHmacSha256),
+            "AES128/CTR/HMAC-SHA256" => Ok(CipherSuite::Aes128CtrHmacSha256),
+            "AES256/CBC/HMAC-SHA384" => Ok(CipherSuite::Aes256CbcHmacSha384),
+            "AES256/CTR/HMAC-SHA384" => Ok(CipherSuite::Aes256CtrHmacSha384),
             other => Err(Error::InvalidSuiteName(other.to_string())),
         }
     }
@@ -1461,6 +1939,10 @@ impl Display for CipherSuite {
         match self {
             CipherSuite::A128Gcm => write!(f, "A128GCM"),
             CipherSuite::A256Gcm => write!(f, "A256GCM"),
+            CipherSuite::Aes128CbcHmacSha256 => write!(f, "AES128/CBC/HMAC-SHA256"),
+            CipherSuite::Aes128CtrHmacSha256 => write!(f, "AES128/CTR/HMAC-SHA256"),
+            CipherSuite::Aes256CbcHmacSha384 => write!(f, "AES256/CBC/HMAC-SHA384"),
+            CipherSuite::Aes256CtrHmacSha384 => write!(f, "AES256/CTR/HMAC-SHA384"),
         }
     }
 }
@@ -1730,6 +2212,42 @@ impl COSEHeaderMap {
 
 const COSESIGN_TAG: u64 = 18;
 
+/// A key that can create the RSA signatures the COSE library does not support
+pub trait RsaSigningKey {
+    fn rsa_algorithm(&self) -> Result<RsaSignatureAlgorithm, Error>;
+    /// Signs the data, hashing it with the digest of the RSA algorithm
+    fn rsa_sign(&self, data: &[u8]) -> Result<Vec<u8>, Error>;
+}
+
+impl RsaSigningKey for PKey<Private> {
+    fn rsa_algorithm(&self) -> Result<RsaSignatureAlgorithm, Error> {
+        if self.id() != Id::RSA {
+            return Err(Error::UnsupportedAlgorithm);
+        }
+        RsaSignatureAlgorithm::for_key(self.bits(), false)
+    }
+
+    fn rsa_sign(&self, data: &[u8]) -> Result<Vec<u8>, Error> {
+        let sig_alg = self.rsa_algorithm()?;
+        let mut signer = Signer::new(sig_alg.get_md(), self)?;
+        signer.set_rsa_padding(sig_alg.padding())?;
+        if sig_alg.uses_pss() {
+            signer.set_rsa_pss_saltlen(RsaPssSaltlen::DIGEST_LENGTH)?;
+        }
+        signer.update(data)?;
+        Ok(signer.sign_to_vec()?)
+    }
+}
+
+fn cose_sign1_sig_structure(protected: &[u8], payload: &[u8]) -> Result<Vec<u8>, Error> {
+    Ok(serde_cbor::to_vec(&(
+        "Signature1",
+        ByteBuf::from(protected),
+        ByteBuf::new(),
+        ByteBuf::from(payload),
+    ))?)
+}
+
 #[derive(Debug, Clone)]
 pub struct COSESign {
     contents: ParsedArray<crate::cborparser::ParsedArraySize4>,
@@ -1836,6 +2354,75 @@ impl COSESign {
         Self::new_from_inner(inner)
     }
 
+    pub fn new_rsa<T>(
+        payload: &T,
+        unprotected: Option<COSEHeaderMap>,
+        sign_key: &dyn RsaSigningKey,
+    ) -> Result<Self, Error>
+    where
+        T: Serializable,
+    {
+        let unprotected = match unprotected {
+            Some(v) => v,
+            None => COSEHeaderMap::new(),
+        };
+        let payload = payload.serialize_data()?;
+
+        let sig_alg = sign_key.rsa_algorithm()?;
+        let mut protected = std::collections::BTreeMap::new();
+        protected.insert(
+            serde_cbor::Value::Integer(1),
+            serde_cbor::Value::Integer(sig_alg as i128),
+        );
+        let protected = serde_cbor::to_vec(&protected)?;
+
+        let signature = sign_key.rsa_sign(&cose_sign1_sig_structure(&protected, &payload)?)?;
+
+        let mut contents = ParsedArrayBuilder::new();
+        contents.set(0, &ByteBuf::from(protected))?;
+        contents.set(1, &unprotected.0)?;
+        contents.set(2, &ByteBuf::from(payload))?;
+        contents.set(3, &ByteBuf::from(signature))?;
+        let mut contents = contents.build();
+        contents.set_tag(Some(COSESIGN_TAG));
+
+        let inner = COSESignInner::from_bytes(&contents.serialize_data()?)?;
+
+        Ok(COSESign {
+            contents,
+
+            cached_inner: inner,
+        })
+    }
+
+    fn verify_rsa(&self, key: &PKeyRef<Public>) -> Result<(), Error> {
+        let protected: ByteBuf = self.contents.get(0)?;
+        let payload: ByteBuf = self.contents.get(2)?;
+        let signature: ByteBuf = self.contents.get(3)?;
+
+        let protected_map: std::collections::BTreeMap<serde_cbor::Value, serde_cbor::Value> =
+            serde_cbor::from_slice(&protected)?;
+        let sig_alg = match protected_map.get(&serde_cbor::Value::Integer(1)) {
+            Some(serde_cbor::Value::Integer(alg)) => RsaSignatureAlgorithm::from_cose_alg(*alg),
+            _ => None,
+        }
+        .ok_or(Error::UnsupportedAlgorithm)?;
+
+        let mut verifier = Verifier::new(sig_alg.get_md(), key)?;
+        verifier.set_rsa_padding(sig_alg.padding())?;
+        if sig_alg.uses_pss() {
+            // TPMs may use either the digest length or the maximum length as salt
+            // length, so let OpenSSL detect it (RSA_PSS_SALTLEN_AUTO)
+            verifier.set_rsa_pss_saltlen(RsaPssSaltlen::custom(-2))?;
+        }
+        verifier.update(&cose_sign1_sig_structure(&protected, &payload)?)?;
+        if verifier.verify(&signature)? {
+            Ok(())
+        } else {
+            Err(Error::InconsistentValue("Signature verification failed"))
+        }
+    }
+
     pub fn verify(&self, sign_key: &dyn SigningPublicKey) -> Result<(), Error> {
         if self.cached_inner.verify_signature(sign_key)? {
             Ok(())
@@ -1844,6 +2431,18 @@ impl COSESign {
         }
     }
 
+    pub fn from_eat_rsa<ES>(
+        eat: EATokenPayload<ES>,
+        unprotected: Option<COSEHeaderMap>,
+        sign_key: &dyn RsaSigningKey,
+    ) -> Result<Self, Error>
+    where
+        ES: PayloadState,
+    {
+        let claims = eat.to_map();
+        Self::new_rsa(&claims.0, unprotected, sign_key)
+    }
+
     pub fn from_eat<ES>(
         eat: EATokenPayload<ES>,
         unprotected: Option<COSEHeaderMap>,
@@ -1872,6 +2471,20 @@ impl COSESign {
         T::deserialize_data(&payload)
     }
 
+    /// Like `get_payload`, but also supports RSA keys
+    pub fn get_payload_with_pkey<T>(&self, key: &PKeyRef<Public>) -> Result<T, Error>
+    where
+        T: Serializable,
+    {
+        if key.id() == Id::RSA {
+            self.verify_rsa(key)?;
+            let payload = self.cached_inner.get_payload(None)?;
+            T::deserialize_data(&payload)
+        } else {
+            self.get_payload(key)
+        }
+    }
+
     pub fn get_eat_unverified(&self) -> Result<EATokenPayload<PayloadUnverified>, Error> {
         let claims: COSEHeaderMapType = unsafe { self.get_payload_unverified()?.into_unverified() };
         let claims = COSEHeaderMap(claims);
@@ -1889,6 +2502,17 @@ impl COSESign {
         eat_from_map(claims)
     }
 
+    /// Like `get_eat`, but also supports RSA keys
+    pub fn get_eat_with_pkey(
+        &self,
+        key: &PKeyRef<Public>,
+    ) -> Result<EATokenPayload<PayloadVerified>, Error> {
+        let claims: COSEHeaderMapType = self.get_payload_with_pkey(key)?;
+        let claims = COSEHeaderMap(claims);
+
+        eat_from_map(claims)
+    }
+
     pub fn get_protected_value_unverified<T>(
         &self,
         header_key: HeaderKeys,
@@ -1931,6 +2555,35 @@ impl COSESign {
     }
 }
 
+#[cfg(test)]
+mod test_cose_rsa {
+    use openssl::{pkey::PKey, rsa::Rsa};
+
+    use super::COSESign;
+    use crate::Serializable;
+
+    #[test]
+    fn test_rsa_sign_verify() {
+        for bits in [2048, 3072] {
+            let key = PKey::from_rsa(Rsa::generate(bits).unwrap()).unwrap();
+            let pubkey = PKey::public_key_from_der(&key.public_key_to_der().unwrap()).unwrap();
+
+            let signed = COSESign::new_rsa(&"testpayload".to_string(), None, &key).unwrap();
+            let signed = COSESign::deserialize_data(&signed.serialize_data().unwrap()).unwrap();
+
+            let payload: String = signed.get_payload_with_pkey(&pubkey).unwrap();
+            assert_eq!(payload, "testpayload");
+
+            let other_key = PKey::from_rsa(Rsa::generate(bits).unwrap()).unwrap();
+            let other_pubkey =
+                PKey::public_key_from_der(&other_key.public_key_to_der().unwrap()).unwrap();
+            assert!(signed
+                .get_payload_with_pkey::<String>(&other_pubkey)
+                .is_err());
+        }
+    }
+}
+
 #[derive(Debug)]
 pub enum RemoteTransport {
     Tcp,
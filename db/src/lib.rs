@@ -14,6 +14,7 @@ use fdo_data_formats::StoredItem;
 use models::ManufacturerOV;
 use models::OwnerOV;
 use models::RendezvousOV;
+use models::StoredSession;
 
 pub trait DBStoreManufacturer<T>
 where
@@ -28,6 +29,9 @@ where
     /// Gets an OV
     fn get_ov(guid: &str, conn: &mut T) -> Result<ManufacturerOV>;
 
+    /// Returns the number of OVs in the DB
+    fn count_ovs(conn: &mut T) -> Result<i64>;
+
     /// Returns all the OVs in the DB
     fn get_all_ovs(conn: &mut T) -> Result<Vec<ManufacturerOV>>;
 
@@ -56,6 +60,12 @@ where
     /// Gets an OV
     fn get_ov(guid: &str, conn: &mut T) -> Result<OwnerOV>;
 
+    /// Returns all the OVs in the DB
+    fn get_all_ovs(conn: &mut T) -> Result<Vec<OwnerOV>>;
+
+    /// Returns the number of OVs in the DB
+    fn count_ovs(conn: &mut T) -> Result<i64>;
+
     /// Deletes an OV
     fn delete_ov(guid: &str, conn: &mut T) -> Result<()>;
 
@@ -98,9 +108,28 @@ where
     /// Inserts an OV
     fn insert_ov(ov: &StoredItem, guid: &str, ttl: Option<i64>, conn: &mut T) -> Result<()>;
 
+    /// Inserts or replaces an OV tagged with `owner_group`, unless
+    /// `max_entries` other OVs of that group without a ttl or with a ttl
+    /// after `now` are stored already. The check and the insert run in a
+    /// single transaction. Returns whether the OV was stored.
+    fn insert_ov_in_group(
+        ov: &StoredItem,
+        guid: &str,
+        owner_group: &str,
+        max_entries: Option<u64>,
+        now: i64,
+        conn: &mut T,
+    ) -> Result<bool>;
+
     /// Gets an OV
     fn get_ov(guid: &str, conn: &mut T) -> Result<RendezvousOV>;
 
+    /// Returns all the OVs in the DB
+    fn get_all_ovs(conn: &mut T) -> Result<Vec<RendezvousOV>>;
+
+    /// Returns the number of OVs in the DB
+    fn count_ovs(conn: &mut T) -> Result<i64>;
+
     /// Deletes an OV
     fn delete_ov(guid: &str, conn: &mut T) -> Result<()>;
 
@@ -112,3 +141,32 @@ where
     /// database if 'None' is passed as the ttl.
     fn update_ov_ttl(guid: &str, ttl: Option<i64>, conn: &mut T) -> Result<()>;
 }
+
+pub trait DBStoreSession<T>
+where
+    T: diesel::r2d2::R2D2Connection + 'static,
+{
+    /// Gets a connection pool
+    fn get_conn_pool(url: String) -> Pool<ConnectionManager<T>>;
+
+    /// Inserts a session, replacing the contents of an existing session with
+    /// the same id. The ttl of an existing session is kept.
+    fn insert_session(id: &str, contents: Vec<u8>, conn: &mut T) -> Result<()>;
+
+    /// Gets a session
+    fn get_session(id: &str, conn: &mut T) -> Result<StoredSession>;
+
+    /// Returns the number of sessions in the DB
+    fn count_sessions(conn: &mut T) -> Result<i64>;
+
+    /// Deletes a session
+    fn delete_session(id: &str, conn: &mut T) -> Result<()>;
+
+    /// Deletes all sessions whose ttl is less or equal to the given ttl
+    fn delete_session_ttl_le(ttl: i64, conn: &mut T) -> Result<()>;
+
+    /// Updates the ttl of an existing session.
+    /// Option<i64> is set as the ttl type so that we can set NULL in the
+    /// database if 'None' is passed as the ttl.
+    fn update_session_ttl(id: &str, ttl: Option<i64>, conn: &mut T) -> Result<()>;
+}
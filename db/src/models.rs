@@ -9,6 +9,7 @@ pub struct RendezvousOV {
     pub guid: String,
     pub contents: Vec<u8>,
     pub ttl: Option<i64>,
+    pub owner_group: Option<String>,
 }
 
 #[derive(Insertable)]
@@ -17,6 +18,7 @@ pub struct NewRendezvousOV {
     pub guid: String,
     pub contents: Vec<u8>,
     pub ttl: Option<i64>,
+    pub owner_group: Option<String>,
 }
 
 #[derive(Queryable, Selectable, Identifiable, AsChangeset)]
@@ -57,12 +59,30 @@ pub struct NewManufacturerOV {
     pub ttl: Option<i64>,
 }
 
+#[derive(Queryable, Selectable, Identifiable)]
+#[diesel(table_name = crate::schema::sessions)]
+#[diesel(treat_none_as_null = true)]
+#[diesel(primary_key(id))]
+pub struct StoredSession {
+    pub id: String,
+    pub contents: Vec<u8>,
+    pub ttl: Option<i64>,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = crate::schema::sessions)]
+pub struct NewStoredSession {
+    pub id: String,
+    pub contents: Vec<u8>,
+    pub ttl: Option<i64>,
+}
+
 impl fmt::Display for RendezvousOV {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(
             f,
-            "GUID: {}, ttl: {:?}, contents: {:?}",
-            self.guid, self.ttl, self.contents
+            "GUID: {}, ttl: {:?}, owner group: {:?}, contents: {:?}",
+            self.guid, self.ttl, self.owner_group, self.contents
         )
     }
 }
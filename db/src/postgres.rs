@@ -1,8 +1,9 @@
-use super::{DBStoreManufacturer, DBStoreOwner, DBStoreRendezvous};
+use super::{DBStoreManufacturer, DBStoreOwner, DBStoreRendezvous, DBStoreSession};
 use crate::models::NewManufacturerOV;
 use crate::schema::manufacturer_vouchers;
 use crate::schema::owner_vouchers;
 use crate::schema::rendezvous_vouchers;
+use crate::schema::sessions;
 use fdo_data_formats::StoredItem;
 
 use diesel::prelude::*;
@@ -12,7 +13,10 @@ use diesel::PgConnection;
 
 use anyhow::Result;
 
-use super::models::{ManufacturerOV, NewOwnerOV, NewRendezvousOV, OwnerOV, RendezvousOV};
+use super::models::{
+    ManufacturerOV, NewOwnerOV, NewRendezvousOV, NewStoredSession, OwnerOV, RendezvousOV,
+    StoredSession,
+};
 
 use fdo_data_formats::ownershipvoucher::OwnershipVoucher as OV;
 use fdo_data_formats::Serializable;
@@ -47,6 +51,13 @@ impl DBStoreManufacturer<PgConnection> for PostgresManufacturerDB {
         Ok(result)
     }
 
+    fn count_ovs(conn: &mut PgConnection) -> Result<i64> {
+        let result = super::schema::manufacturer_vouchers::dsl::manufacturer_vouchers
+            .count()
+            .get_result(conn)?;
+        Ok(result)
+    }
+
     fn get_all_ovs(conn: &mut PgConnection) -> Result<Vec<ManufacturerOV>> {
         let result = super::schema::manufacturer_vouchers::dsl::manufacturer_vouchers
             .select(ManufacturerOV::as_select())
@@ -113,6 +124,20 @@ impl DBStoreOwner<PgConnection> for PostgresOwnerDB {
         Ok(result)
     }
 
+    fn get_all_ovs(conn: &mut PgConnection) -> Result<Vec<OwnerOV>> {
+        let result = super::schema::owner_vouchers::dsl::owner_vouchers
+            .select(OwnerOV::as_select())
+            .load(conn)?;
+        Ok(result)
+    }
+
+    fn count_ovs(conn: &mut PgConnection) -> Result<i64> {
+        let result = super::schema::owner_vouchers::dsl::owner_vouchers
+            .count()
+            .get_result(conn)?;
+        Ok(result)
+    }
+
     fn delete_ov(guid: &str, conn: &mut PgConnection) -> Result<()> {
         diesel::delete(owner_vouchers::dsl::owner_vouchers)
             .filter(super::schema::owner_vouchers::guid.eq(guid))
@@ -208,6 +233,7 @@ impl DBStoreRendezvous<PgConnection> for PostgresRendezvousDB {
             guid: guid.to_string(),
             contents: ov.serialize_data()?,
             ttl,
+            owner_group: None,
         };
         diesel::insert_into(super::schema::rendezvous_vouchers::table)
             .values(&new_ov_rendezvous)
@@ -215,6 +241,49 @@ impl DBStoreRendezvous<PgConnection> for PostgresRendezvousDB {
         Ok(())
     }
 
+    fn insert_ov_in_group(
+        ov: &StoredItem,
+        guid: &str,
+        owner_group: &str,
+        max_entries: Option<u64>,
+        now: i64,
+        conn: &mut PgConnection,
+    ) -> Result<bool> {
+        let new_ov_rendezvous = NewRendezvousOV {
+            guid: guid.to_string(),
+            contents: ov.serialize_data()?,
+            ttl: None,
+            owner_group: Some(owner_group.to_string()),
+        };
+        conn.transaction::<_, anyhow::Error, _>(|conn| {
+            // Keep concurrent registrations from both passing the check
+            diesel::sql_query("LOCK TABLE rendezvous_vouchers IN SHARE ROW EXCLUSIVE MODE")
+                .execute(conn)?;
+            if let Some(max_entries) = max_entries {
+                let count: i64 = super::schema::rendezvous_vouchers::dsl::rendezvous_vouchers
+                    .filter(super::schema::rendezvous_vouchers::owner_group.eq(owner_group))
+                    .filter(super::schema::rendezvous_vouchers::guid.ne(guid))
+                    .filter(
+                        super::schema::rendezvous_vouchers::ttl
+                            .is_null()
+                            .or(super::schema::rendezvous_vouchers::ttl.gt(now)),
+                    )
+                    .count()
+                    .get_result(conn)?;
+                if count as u64 >= max_entries {
+                    return Ok(false);
+                }
+            }
+            diesel::delete(rendezvous_vouchers::dsl::rendezvous_vouchers)
+                .filter(super::schema::rendezvous_vouchers::guid.eq(guid))
+                .execute(conn)?;
+            diesel::insert_into(super::schema::rendezvous_vouchers::table)
+                .values(&new_ov_rendezvous)
+                .execute(conn)?;
+            Ok(true)
+        })
+    }
+
     fn get_ov(guid: &str, conn: &mut PgConnection) -> Result<RendezvousOV> {
         let result = super::schema::rendezvous_vouchers::dsl::rendezvous_vouchers
             .filter(super::schema::rendezvous_vouchers::guid.eq(guid))
@@ -222,6 +291,20 @@ impl DBStoreRendezvous<PgConnection> for PostgresRendezvousDB {
         Ok(result)
     }
 
+    fn get_all_ovs(conn: &mut PgConnection) -> Result<Vec<RendezvousOV>> {
+        let result = super::schema::rendezvous_vouchers::dsl::rendezvous_vouchers
+            .select(RendezvousOV::as_select())
+            .load(conn)?;
+        Ok(result)
+    }
+
+    fn count_ovs(conn: &mut PgConnection) -> Result<i64> {
+        let result = super::schema::rendezvous_vouchers::dsl::rendezvous_vouchers
+            .count()
+            .get_result(conn)?;
+        Ok(result)
+    }
+
     fn delete_ov(guid: &str, conn: &mut PgConnection) -> Result<()> {
         diesel::delete(rendezvous_vouchers::dsl::rendezvous_vouchers)
             .filter(super::schema::rendezvous_vouchers::guid.eq(guid))
@@ -244,3 +327,66 @@ impl DBStoreRendezvous<PgConnection> for PostgresRendezvousDB {
         Ok(())
     }
 }
+
+pub struct PostgresSessionDB {}
+
+impl DBStoreSession<PgConnection> for PostgresSessionDB {
+    fn get_conn_pool(database_url: String) -> Pool<ConnectionManager<PgConnection>> {
+        let manager = ConnectionManager::<PgConnection>::new(database_url);
+        Pool::builder()
+            .test_on_check_out(true)
+            .build(manager)
+            .expect("Couldn't build db connection pool")
+    }
+
+    fn insert_session(id: &str, contents: Vec<u8>, conn: &mut PgConnection) -> Result<()> {
+        let new_session = NewStoredSession {
+            id: id.to_string(),
+            contents: contents.clone(),
+            ttl: None,
+        };
+        diesel::insert_into(super::schema::sessions::table)
+            .values(&new_session)
+            .on_conflict(super::schema::sessions::id)
+            .do_update()
+            .set(super::schema::sessions::contents.eq(contents))
+            .execute(conn)?;
+        Ok(())
+    }
+
+    fn get_session(id: &str, conn: &mut PgConnection) -> Result<StoredSession> {
+        let result = super::schema::sessions::dsl::sessions
+            .filter(super::schema::sessions::id.eq(id))
+            .first(conn)?;
+        Ok(result)
+    }
+
+    fn count_sessions(conn: &mut PgConnection) -> Result<i64> {
+        let result = super::schema::sessions::dsl::sessions
+            .count()
+            .get_result(conn)?;
+        Ok(result)
+    }
+
+    fn delete_session(id: &str, conn: &mut PgConnection) -> Result<()> {
+        diesel::delete(sessions::dsl::sessions)
+            .filter(super::schema::sessions::id.eq(id))
+            .execute(conn)?;
+        Ok(())
+    }
+
+    fn delete_session_ttl_le(ttl: i64, conn: &mut PgConnection) -> Result<()> {
+        diesel::delete(sessions::dsl::sessions)
+            .filter(super::schema::sessions::ttl.le(ttl))
+            .execute(conn)?;
+        Ok(())
+    }
+
+    fn update_session_ttl(id: &str, ttl: Option<i64>, conn: &mut PgConnection) -> Result<()> {
+        diesel::update(sessions::dsl::sessions)
+            .filter(super::schema::sessions::id.eq(id))
+            .set(super::schema::sessions::ttl.eq(ttl))
+            .execute(conn)?;
+        Ok(())
+    }
+}
@@ -20,5 +20,14 @@ diesel::table! {
         guid -> Text,
         contents -> Binary,
         ttl -> Nullable<BigInt>,
+        owner_group -> Nullable<Text>,
+    }
+}
+
+diesel::table! {
+    sessions (id) {
+        id -> Text,
+        contents -> Binary,
+        ttl -> Nullable<BigInt>,
     }
 }
@@ -1,4 +1,4 @@
-use super::{DBStoreManufacturer, DBStoreOwner, DBStoreRendezvous};
+use super::{DBStoreManufacturer, DBStoreOwner, DBStoreRendezvous, DBStoreSession};
 
 use diesel::prelude::*;
 use diesel::r2d2::ConnectionManager;
@@ -10,10 +10,13 @@ use crate::models::NewManufacturerOV;
 use crate::schema::manufacturer_vouchers;
 use crate::schema::owner_vouchers;
 use crate::schema::rendezvous_vouchers;
+use crate::schema::sessions;
 
 use anyhow::Result;
 
-use super::models::{NewOwnerOV, NewRendezvousOV, OwnerOV, RendezvousOV};
+use super::models::{
+    NewOwnerOV, NewRendezvousOV, NewStoredSession, OwnerOV, RendezvousOV, StoredSession,
+};
 
 use fdo_data_formats::ownershipvoucher::OwnershipVoucher as OV;
 use fdo_data_formats::Serializable;
@@ -49,6 +52,13 @@ impl DBStoreManufacturer<SqliteConnection> for SqliteManufacturerDB {
         Ok(result)
     }
 
+    fn count_ovs(conn: &mut SqliteConnection) -> Result<i64> {
+        let result = super::schema::manufacturer_vouchers::dsl::manufacturer_vouchers
+            .count()
+            .get_result(conn)?;
+        Ok(result)
+    }
+
     fn get_all_ovs(conn: &mut SqliteConnection) -> Result<Vec<ManufacturerOV>> {
         let result = super::schema::manufacturer_vouchers::dsl::manufacturer_vouchers
             .select(ManufacturerOV::as_select())
@@ -115,6 +125,20 @@ impl DBStoreOwner<SqliteConnection> for SqliteOwnerDB {
         Ok(result)
     }
 
+    fn get_all_ovs(conn: &mut SqliteConnection) -> Result<Vec<OwnerOV>> {
+        let result = super::schema::owner_vouchers::dsl::owner_vouchers
+            .select(OwnerOV::as_select())
+            .load(conn)?;
+        Ok(result)
+    }
+
+    fn count_ovs(conn: &mut SqliteConnection) -> Result<i64> {
+        let result = super::schema::owner_vouchers::dsl::owner_vouchers
+            .count()
+            .get_result(conn)?;
+        Ok(result)
+    }
+
     fn delete_ov(guid: &str, conn: &mut SqliteConnection) -> Result<()> {
         diesel::delete(owner_vouchers::dsl::owner_vouchers)
             .filter(super::schema::owner_vouchers::guid.eq(guid))
@@ -210,6 +234,7 @@ impl DBStoreRendezvous<SqliteConnection> for SqliteRendezvousDB {
             guid: guid.to_string(),
             contents: ov.serialize_data()?,
             ttl,
+            owner_group: None,
         };
         diesel::insert_into(super::schema::rendezvous_vouchers::table)
             .values(&new_ov_rendezvous)
@@ -217,6 +242,46 @@ impl DBStoreRendezvous<SqliteConnection> for SqliteRendezvousDB {
         Ok(())
     }
 
+    fn insert_ov_in_group(
+        ov: &StoredItem,
+        guid: &str,
+        owner_group: &str,
+        max_entries: Option<u64>,
+        now: i64,
+        conn: &mut SqliteConnection,
+    ) -> Result<bool> {
+        let new_ov_rendezvous = NewRendezvousOV {
+            guid: guid.to_string(),
+            contents: ov.serialize_data()?,
+            ttl: None,
+            owner_group: Some(owner_group.to_string()),
+        };
+        conn.immediate_transaction::<_, anyhow::Error, _>(|conn| {
+            if let Some(max_entries) = max_entries {
+                let count: i64 = super::schema::rendezvous_vouchers::dsl::rendezvous_vouchers
+                    .filter(super::schema::rendezvous_vouchers::owner_group.eq(owner_group))
+                    .filter(super::schema::rendezvous_vouchers::guid.ne(guid))
+                    .filter(
+                        super::schema::rendezvous_vouchers::ttl
+                            .is_null()
+                            .or(super::schema::rendezvous_vouchers::ttl.gt(now)),
+                    )
+                    .count()
+                    .get_result(conn)?;
+                if count as u64 >= max_entries {
+                    return Ok(false);
+                }
+            }
+            diesel::delete(rendezvous_vouchers::dsl::rendezvous_vouchers)
+                .filter(super::schema::rendezvous_vouchers::guid.eq(guid))
+                .execute(conn)?;
+            diesel::insert_into(super::schema::rendezvous_vouchers::table)
+                .values(&new_ov_rendezvous)
+                .execute(conn)?;
+            Ok(true)
+        })
+    }
+
     fn get_ov(guid: &str, conn: &mut SqliteConnection) -> Result<RendezvousOV> {
         let result = super::schema::rendezvous_vouchers::dsl::rendezvous_vouchers
             .filter(super::schema::rendezvous_vouchers::guid.eq(guid))
@@ -224,6 +289,20 @@ impl DBStoreRendezvous<SqliteConnection> for SqliteRendezvousDB {
         Ok(result)
     }
 
+    fn get_all_ovs(conn: &mut SqliteConnection) -> Result<Vec<RendezvousOV>> {
+        let result = super::schema::rendezvous_vouchers::dsl::rendezvous_vouchers
+            .select(RendezvousOV::as_select())
+            .load(conn)?;
+        Ok(result)
+    }
+
+    fn count_ovs(conn: &mut SqliteConnection) -> Result<i64> {
+        let result = super::schema::rendezvous_vouchers::dsl::rendezvous_vouchers
+            .count()
+            .get_result(conn)?;
+        Ok(result)
+    }
+
     fn delete_ov(guid: &str, conn: &mut SqliteConnection) -> Result<()> {
         diesel::delete(rendezvous_vouchers::dsl::rendezvous_vouchers)
             .filter(super::schema::rendezvous_vouchers::guid.eq(guid))
@@ -247,10 +326,73 @@ impl DBStoreRendezvous<SqliteConnection> for SqliteRendezvousDB {
     }
 }
 
+pub struct SqliteSessionDB {}
+
+impl DBStoreSession<SqliteConnection> for SqliteSessionDB {
+    fn get_conn_pool(database_url: String) -> Pool<ConnectionManager<SqliteConnection>> {
+        let manager = ConnectionManager::<SqliteConnection>::new(database_url);
+        Pool::builder()
+            .test_on_check_out(true)
+            .build(manager)
+            .expect("Couldn't build db connection pool")
+    }
+
+    fn insert_session(id: &str, contents: Vec<u8>, conn: &mut SqliteConnection) -> Result<()> {
+        let new_session = NewStoredSession {
+            id: id.to_string(),
+            contents: contents.clone(),
+            ttl: None,
+        };
+        diesel::insert_into(super::schema::sessions::table)
+            .values(&new_session)
+            .on_conflict(super::schema::sessions::id)
+            .do_update()
+            .set(super::schema::sessions::contents.eq(contents))
+            .execute(conn)?;
+        Ok(())
+    }
+
+    fn get_session(id: &str, conn: &mut SqliteConnection) -> Result<StoredSession> {
+        let result = super::schema::sessions::dsl::sessions
+            .filter(super::schema::sessions::id.eq(id))
+            .first(conn)?;
+        Ok(result)
+    }
+
+    fn count_sessions(conn: &mut SqliteConnection) -> Result<i64> {
+        let result = super::schema::sessions::dsl::sessions
+            .count()
+            .get_result(conn)?;
+        Ok(result)
+    }
+
+    fn delete_session(id: &str, conn: &mut SqliteConnection) -> Result<()> {
+        diesel::delete(sessions::dsl::sessions)
+            .filter(super::schema::sessions::id.eq(id))
+            .execute(conn)?;
+        Ok(())
+    }
+
+    fn delete_session_ttl_le(ttl: i64, conn: &mut SqliteConnection) -> Result<()> {
+        diesel::delete(sessions::dsl::sessions)
+            .filter(super::schema::sessions::ttl.le(ttl))
+            .execute(conn)?;
+        Ok(())
+    }
+
+    fn update_session_ttl(id: &str, ttl: Option<i64>, conn: &mut SqliteConnection) -> Result<()> {
+        diesel::update(sessions::dsl::sessions)
+            .filter(super::schema::sessions::id.eq(id))
+            .set(super::schema::sessions::ttl.eq(ttl))
+            .execute(conn)?;
+        Ok(())
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use super::{SqliteManufacturerDB, SqliteOwnerDB, SqliteRendezvousDB};
-    use crate::{schema::*, DBStoreManufacturer, DBStoreOwner, DBStoreRendezvous};
+    use super::{SqliteManufacturerDB, SqliteOwnerDB, SqliteRendezvousDB, SqliteSessionDB};
+    use crate::{schema::*, DBStoreManufacturer, DBStoreOwner, DBStoreRendezvous, DBStoreSession};
     use anyhow::Result;
     use diesel::connection::SimpleConnection;
     use diesel::prelude::*;
@@ -385,6 +527,10 @@ mod tests {
         let ov_db = SqliteOwnerDB::get_ov(&last_guid, conn)?;
         assert_eq!(ov_db.guid, last_guid);
 
+        // get all the owner ovs, we should have 3
+        let result = SqliteOwnerDB::get_all_ovs(conn)?;
+        assert_eq!(result.len(), 3);
+
         // select the owner ovs with to2 performed = true, we should have 2
         let result = SqliteOwnerDB::select_ov_to2_performed(true, conn)?;
         assert_eq!(result.len(), 2);
@@ -452,6 +598,9 @@ mod tests {
             let tmp = StoredItem {
                 public_key: pubkey,
                 to1d: cose,
+                manufacturer_public_key: None,
+                owner_public_key: None,
+                protocol_version: None,
             };
             ov_map.insert(ov.header().guid().to_string(), tmp);
         }
@@ -472,6 +621,7 @@ mod tests {
             .get_result(conn)
             .unwrap();
         assert_eq!(count, 3);
+        assert_eq!(SqliteRendezvousDB::get_all_ovs(conn)?.len(), 3);
 
         // get an ov by guid
         let ov_db = SqliteRendezvousDB::get_ov(&last_guid, conn)?;
@@ -505,6 +655,100 @@ mod tests {
             .get_result(conn)
             .unwrap();
         assert_eq!(count, 0);
+
+        // insert ovs in an owner group limited to 2 entries, the third one
+        // is rejected until one of the others expires
+        let guids: Vec<&String> = ov_map.keys().collect();
+        for guid in &guids[..2] {
+            assert!(SqliteRendezvousDB::insert_ov_in_group(
+                &ov_map[*guid],
+                guid,
+                "group",
+                Some(2),
+                1000,
+                conn
+            )?);
+        }
+        assert!(!SqliteRendezvousDB::insert_ov_in_group(
+            &ov_map[guids[2]],
+            guids[2],
+            "group",
+            Some(2),
+            1000,
+            conn
+        )?);
+        // replacing an ov of the group does not count it twice
+        assert!(SqliteRendezvousDB::insert_ov_in_group(
+            &ov_map[guids[0]],
+            guids[0],
+            "group",
+            Some(2),
+            1000,
+            conn
+        )?);
+        // other groups and unlimited inserts are not affected
+        assert!(SqliteRendezvousDB::insert_ov_in_group(
+            &ov_map[guids[2]],
+            guids[2],
+            "other",
+            Some(1),
+            1000,
+            conn
+        )?);
+        SqliteRendezvousDB::update_ov_ttl(guids[1], Some(500_i64), conn)?;
+        assert!(SqliteRendezvousDB::insert_ov_in_group(
+            &ov_map[guids[2]],
+            guids[2],
+            "group",
+            Some(2),
+            1000,
+            conn
+        )?);
+        assert_eq!(
+            SqliteRendezvousDB::get_ov(guids[2], conn)?.owner_group,
+            Some("group".to_string())
+        );
+        let count: i64 = rendezvous_vouchers::dsl::rendezvous_vouchers
+            .count()
+            .get_result(conn)
+            .unwrap();
+        assert_eq!(count, 3);
+        for guid in guids {
+            SqliteRendezvousDB::delete_ov(guid, conn)?;
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_session_database() -> Result<()> {
+        let database_url = env::var("SQLITE_SESSION_DATABASE_URL")
+            .expect("SQLITE_SESSION_DATABASE_URL must be set");
+        let pool = SqliteSessionDB::get_conn_pool(database_url);
+        let conn = &mut pool.get().unwrap();
+
+        for id in ["session1", "session2", "session3"] {
+            SqliteSessionDB::insert_session(id, id.as_bytes().to_vec(), conn)?;
+        }
+        SqliteSessionDB::update_session_ttl("session1", Some(1000_i64), conn)?;
+        SqliteSessionDB::update_session_ttl("session2", Some(5000_i64), conn)?;
+
+        // storing an existing session replaces its contents and keeps its ttl
+        SqliteSessionDB::insert_session("session1", b"updated".to_vec(), conn)?;
+        let session_db = SqliteSessionDB::get_session("session1", conn)?;
+        assert_eq!(session_db.contents, b"updated");
+        assert_eq!(session_db.ttl, Some(1000));
+
+        // delete sessions with ttl <= 4000, only session1 should be deleted
+        SqliteSessionDB::delete_session_ttl_le(4000_i64, conn)?;
+        let count: i64 = sessions::dsl::sessions.count().get_result(conn).unwrap();
+        assert_eq!(count, 2);
+        assert!(SqliteSessionDB::get_session("session1", conn).is_err());
+
+        // delete a session by id, we should have 1 at the end
+        SqliteSessionDB::delete_session("session3", conn)?;
+        let count: i64 = sessions::dsl::sessions.count().get_result(conn).unwrap();
+        assert_eq!(count, 1);
+
         Ok(())
     }
 }
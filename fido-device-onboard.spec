@@ -81,7 +81,11 @@ install -D -m 0644 -t %{buildroot}%{_docdir}/fdo/migrations/migrations_manufactu
 install -D -m 0644 -t %{buildroot}%{_docdir}/fdo/migrations/migrations_owner_onboarding_server_postgres  migrations/migrations_owner_onboarding_server_postgres/2023-10-03-152801_create_db/*
 install -D -m 0644 -t %{buildroot}%{_docdir}/fdo/migrations/migrations_owner_onboarding_server_sqlite  migrations/migrations_owner_onboarding_server_sqlite/2023-10-03-152801_create_db/*
 install -D -m 0644 -t %{buildroot}%{_docdir}/fdo/migrations/migrations_rendezvous_server_postgres  migrations/migrations_rendezvous_server_postgres/2023-10-03-152801_create_db/*
+install -D -m 0644 -t %{buildroot}%{_docdir}/fdo/migrations/migrations_rendezvous_server_postgres/2026-10-18-130000_add_owner_group  migrations/migrations_rendezvous_server_postgres/2026-10-18-130000_add_owner_group/*
 install -D -m 0644 -t %{buildroot}%{_docdir}/fdo/migrations/migrations_rendezvous_server_sqlite  migrations/migrations_rendezvous_server_sqlite/2023-10-03-152801_create_db/*
+install -D -m 0644 -t %{buildroot}%{_docdir}/fdo/migrations/migrations_rendezvous_server_sqlite/2026-10-18-130000_add_owner_group  migrations/migrations_rendezvous_server_sqlite/2026-10-18-130000_add_owner_group/*
+install -D -m 0644 -t %{buildroot}%{_docdir}/fdo/migrations/migrations_session_store_postgres  migrations/migrations_session_store_postgres/2026-10-18-120000_create_db/*
+install -D -m 0644 -t %{buildroot}%{_docdir}/fdo/migrations/migrations_session_store_sqlite  migrations/migrations_session_store_sqlite/2026-10-18-120000_create_db/*
 # duplicates as needed by AIO command so link them
 mkdir -p %{buildroot}%{_bindir}
 ln -sr %{buildroot}%{_bindir}/fdo-owner-tool  %{buildroot}%{_libexecdir}/fdo/fdo-owner-tool
@@ -153,6 +157,8 @@ Requires: openssl-libs >= 3.0.1-12
 %{_docdir}/fdo/owner-onboarding-server.yml
 %{_docdir}/fdo/migrations/migrations_owner_onboarding_server_postgres/*
 %{_docdir}/fdo/migrations/migrations_owner_onboarding_server_sqlite/*
+%{_docdir}/fdo/migrations/migrations_session_store_postgres/*
+%{_docdir}/fdo/migrations/migrations_session_store_sqlite/*
 %{_unitdir}/fdo-serviceinfo-api-server.service
 %{_unitdir}/fdo-owner-onboarding-server.service
 
@@ -191,6 +197,8 @@ License: %combined_license
 %{_docdir}/fdo/rendezvous-*.yml
 %{_docdir}/fdo/migrations/migrations_rendezvous_server_postgres/*
 %{_docdir}/fdo/migrations/migrations_rendezvous_server_sqlite/*
+%{_docdir}/fdo/migrations/migrations_session_store_postgres/*
+%{_docdir}/fdo/migrations/migrations_session_store_sqlite/*
 %{_unitdir}/fdo-rendezvous-server.service
 
 %post -n fdo-rendezvous-server
@@ -227,6 +235,8 @@ Requires: openssl-libs >= 3.0.1-12
 %{_docdir}/fdo/manufacturing-server.yml
 %{_docdir}/fdo/migrations/migrations_manufacturing_server_postgres/*
 %{_docdir}/fdo/migrations/migrations_manufacturing_server_sqlite/*
+%{_docdir}/fdo/migrations/migrations_session_store_postgres/*
+%{_docdir}/fdo/migrations/migrations_session_store_sqlite/*
 %{_unitdir}/fdo-manufacturing-server.service
 
 %post -n fdo-manufacturing-server
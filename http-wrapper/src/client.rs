@@ -1,22 +1,39 @@
-use std::{convert::TryFrom, str::FromStr};
-
+use std::{convert::TryFrom, net::IpAddr, str::FromStr, sync::Arc, time::SystemTime};
+
+use openssl::{
+    stack::Stack,
+    x509::{
+        store::X509StoreBuilder,
+        verify::{X509VerifyFlags, X509VerifyParam},
+        X509StoreContext, X509,
+    },
+};
+use rustls::{
+    client::{ServerCertVerified, ServerCertVerifier},
+    Certificate, ServerName,
+};
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
-use aws_nitro_enclaves_cose::error::CoseError;
 use fdo_data_formats::{
     constants::MessageType,
-    messages::{v11::ErrorMessage, ClientMessage, EncryptionRequirement, Message, ServerMessage},
+    messages::{
+        v11::ErrorMessage, ClientMessage, EncryptionRequirement, Message, ServerMessage,
+        VersionedMessage,
+    },
+    types::Hash,
     ProtocolVersion, Serializable,
 };
 
 use crate::EncryptionKeys;
 
+mod coap;
+
 #[derive(Debug, Error)]
 #[non_exhaustive]
 pub enum Error {
     #[error("Cryptographic error encrypting/decrypting")]
-    Crypto(#[from] CoseError),
+    Crypto(#[from] crate::EncryptionError),
     #[error("Error parsing or generating request")]
     Parse(#[from] fdo_data_formats::messages::ParseError),
     #[error("Data format error: {0}")]
@@ -43,6 +60,12 @@ pub enum Error {
     UrlParseError(#[from] url::ParseError),
     #[error("The URL {0:?} is not valid: {1:?}")]
     InvalidUrl(String, &'static str),
+    #[error("TLS pin verification failed: {0}")]
+    TlsPinMismatch(String),
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("CoAP error: {0}")]
+    Coap(String),
 }
 
 pub type RequestResult<MT> = Result<MT, Error>;
@@ -98,24 +121,16 @@ impl JsonClient {
         })
     }
 
-    pub async fn send_get<'a, QT, OT>(&self, query: QT) -> RequestResult<OT>
+    fn build_request<'a, QT>(&self, method: reqwest::Method, query: QT) -> reqwest::RequestBuilder
     where
         QT: IntoIterator<Item = (&'a str, &'a str)>,
-        OT: serde::de::DeserializeOwned,
     {
         let mut url = self.base_url.clone();
+        url.query_pairs_mut().extend_pairs(query);
 
-        let query_str = query
-            .into_iter()
-            .map(|(k, v)| format!("{k}={v}"))
-            .collect::<Vec<_>>()
-            .join("&");
-
-        url.set_query(Some(&query_str));
-
-        let request_builder = self.client.request(reqwest::Method::GET, url);
+        let request_builder = self.client.request(method, url);
 
-        let request_builder = match &self.authentication {
+        match &self.authentication {
             JsonAuthentication::None => request_builder,
             JsonAuthentication::BearerToken { token } => {
                 request_builder.header("Authorization", format!("Bearer {token}"))
@@ -123,8 +138,13 @@ impl JsonClient {
             JsonAuthentication::ClientCertificate { .. } => {
                 unreachable!("Should not be possible to get here")
             }
-        };
+        }
+    }
 
+    async fn execute<OT>(&self, request_builder: reqwest::RequestBuilder) -> RequestResult<OT>
+    where
+        OT: serde::de::DeserializeOwned,
+    {
         let request = request_builder.build()?;
 
         log::trace!("Sending JSON API request: {:?}", request);
@@ -135,6 +155,170 @@ impl JsonClient {
 
         resp?.error_for_status()?.json().await.map_err(Error::from)
     }
+
+    pub async fn send_get<'a, QT, OT>(&self, query: QT) -> RequestResult<OT>
+    where
+        QT: IntoIterator<Item = (&'a str, &'a str)>,
+        OT: serde::de::DeserializeOwned,
+    {
+        self.execute(self.build_request(reqwest::Method::GET, query))
+            .await
+    }
+
+    pub async fn send_post<'a, QT, IT, OT>(&self, query: QT, body: &IT) -> RequestResult<OT>
+    where
+        QT: IntoIterator<Item = (&'a str, &'a str)>,
+        IT: serde::Serialize,
+        OT: serde::de::DeserializeOwned,
+    {
+        self.execute(self.build_request(reqwest::Method::POST, query).json(body))
+            .await
+    }
+}
+
+/// Pins the TLS identity of a server, as configured with the ServerCertHash
+/// and CaCertHash rendezvous variables.
+///
+/// When a pin is set, the server certificate is not validated against the
+/// system trust store, but against the pinned hashes instead. The pins are
+/// checked during the TLS handshake, so nothing is sent to a server that does
+/// not match them.
+#[derive(Debug, Clone, Default)]
+pub struct TlsPin {
+    pub server_certificate_hash: Option<Hash>,
+    pub ca_certificate_hash: Option<Hash>,
+}
+
+impl TlsPin {
+    pub fn is_empty(&self) -> bool {
+        self.server_certificate_hash.is_none() && self.ca_certificate_hash.is_none()
+    }
+
+    fn verify(&self, host: &str, chain: &[Vec<u8>]) -> RequestResult<()> {
+        let leaf = chain.first().ok_or_else(|| {
+            Error::TlsPinMismatch("Server did not present a certificate".to_string())
+        })?;
+
+        if let Some(server_hash) = &self.server_certificate_hash {
+            if server_hash.compare_data(leaf).is_err() {
+                return Err(Error::TlsPinMismatch(
+                    "Server certificate does not match the pinned hash".to_string(),
+                ));
+            }
+        }
+
+        if let Some(ca_hash) = &self.ca_certificate_hash {
+            let chain = chain
+                .iter()
+                .map(|cert| X509::from_der(cert))
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|e| Error::TlsPinMismatch(format!("Invalid server certificate: {e}")))?;
+            verify_chain_to_ca(ca_hash, host, &chain)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Checks the certificates presented in the TLS handshake against a `TlsPin`
+struct TlsPinVerifier(TlsPin);
+
+impl ServerCertVerifier for TlsPinVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &Certificate,
+        intermediates: &[Certificate],
+        server_name: &ServerName,
+        _scts: &mut dyn Iterator<Item = &[u8]>,
+        _ocsp_response: &[u8],
+        _now: SystemTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        let host = match server_name {
+            ServerName::DnsName(name) => name.as_ref().to_string(),
+            ServerName::IpAddress(ip) => ip.to_string(),
+            _ => {
+                return Err(rustls::Error::General(
+                    "Unsupported server name type".to_string(),
+                ))
+            }
+        };
+        let chain: Vec<Vec<u8>> = std::iter::once(end_entity)
+            .chain(intermediates)
+            .map(|cert| cert.0.clone())
+            .collect();
+
+        match self.0.verify(&host, &chain) {
+            Ok(()) => Ok(ServerCertVerified::assertion()),
+            Err(e) => Err(rustls::Error::General(e.to_string())),
+        }
+    }
+}
+
+fn verify_chain_to_ca(ca_hash: &Hash, host: &str, chain: &[X509]) -> RequestResult<()> {
+    let leaf = match chain.first() {
+        Some(leaf) => leaf,
+        None => {
+            return Err(Error::TlsPinMismatch(
+                "Server did not present a certificate".to_string(),
+            ))
+        }
+    };
+    let anchor = chain
+        .iter()
+        .skip(1)
+        .find(|cert| match cert.to_der() {
+            Ok(der) => ca_hash.compare_data(&der).is_ok(),
+            Err(_) => false,
+        })
+        .ok_or_else(|| {
+            Error::TlsPinMismatch("No certificate in the chain matches the CA hash".to_string())
+        })?;
+
+    let crypto_err =
+        |e: openssl::error::ErrorStack| Error::TlsPinMismatch(format!("Cryptographic error: {e}"));
+
+    let mut param = X509VerifyParam::new().map_err(crypto_err)?;
+    param
+        .set_flags(X509VerifyFlags::PARTIAL_CHAIN)
+        .map_err(crypto_err)?;
+    match IpAddr::from_str(host) {
+        Ok(ip) => param.set_ip(ip).map_err(crypto_err)?,
+        Err(_) => param.set_host(host).map_err(crypto_err)?,
+    }
+
+    let mut store = X509StoreBuilder::new().map_err(crypto_err)?;
+    store.add_cert(anchor.clone()).map_err(crypto_err)?;
+    store.set_param(&param).map_err(crypto_err)?;
+    let store = store.build();
+
+    let mut intermediates = Stack::new().map_err(crypto_err)?;
+    for cert in chain.iter().skip(1) {
+        intermediates.push(cert.clone()).map_err(crypto_err)?;
+    }
+
+    let mut context = X509StoreContext::new().map_err(crypto_err)?;
+    let (valid, error) = context
+        .init(&store, leaf, &intermediates, |c| {
+            let valid = c.verify_cert()?;
+            Ok((valid, c.error()))
+        })
+        .map_err(crypto_err)?;
+    if valid {
+        Ok(())
+    } else {
+        Err(Error::TlsPinMismatch(format!(
+            "Server certificate does not chain to the pinned CA: {error}"
+        )))
+    }
+}
+
+/// A response to an FDO message, independent of the transport
+struct TransportResponse {
+    success: bool,
+    message_type: Option<String>,
+    authorization: Option<String>,
+    non_interoperable_kdf: bool,
+    body: Vec<u8>,
 }
 
 #[derive(Debug)]
@@ -161,6 +345,102 @@ impl ServiceClient {
         }
     }
 
+    /// Creates a client that only accepts a server matching the TLS pin.
+    ///
+    /// The base URL must use https for pins to be applicable.
+    pub fn new_with_tls_pin(
+        protocol_version: ProtocolVersion,
+        base_url: &str,
+        tls_pin: TlsPin,
+    ) -> RequestResult<Self> {
+        if tls_pin.is_empty() {
+            return Ok(Self::new(protocol_version, base_url));
+        }
+        let url = reqwest::Url::parse(base_url)?;
+        if url.scheme() != "https" {
+            return Err(Error::InvalidUrl(
+                base_url.to_string(),
+                "TLS pinning requires an https URL",
+            ));
+        }
+
+        // The pins replace the validation against the system trust store
+        let tls_config = rustls::ClientConfig::builder()
+            .with_safe_defaults()
+            .with_custom_certificate_verifier(Arc::new(TlsPinVerifier(tls_pin)))
+            .with_no_client_auth();
+        let client = reqwest::Client::builder()
+            .use_preconfigured_tls(tls_config)
+            .build()?;
+
+        Ok(ServiceClient {
+            client,
+            ..Self::new(protocol_version, base_url)
+        })
+    }
+
+    fn is_coap(&self) -> RequestResult<bool> {
+        if self.base_url.starts_with("coaps:") {
+            return Err(Error::InvalidUrl(
+                self.base_url.clone(),
+                "CoAP over DTLS is not supported",
+            ));
+        }
+        Ok(self.base_url.starts_with("coap:"))
+    }
+
+    async fn send_http_request(
+        &mut self,
+        path: &str,
+        to_send: Vec<u8>,
+    ) -> RequestResult<TransportResponse> {
+        let url = format!("{}{}", &self.base_url, path);
+
+        let mut req = self
+            .client
+            .post(&url)
+            .header("Content-Type", "application/cbor")
+            .body(to_send);
+
+        if let Some(authorization_token) = &self.authorization_token {
+            req = req.header("Authorization", authorization_token);
+        }
+
+        if !fdo_data_formats::interoperable_kdf_available() {
+            req = req.header("X-Non-Interoperable-KDF", "true");
+        }
+
+        let resp = req.send().await?;
+
+        let message_type = resp
+            .headers()
+            .get("message-type")
+            .map(|v| v.to_str().map(str::to_string))
+            .transpose()
+            .map_err(|_| Error::InvalidMessageType("non-string".to_string()))?;
+        let authorization = resp
+            .headers()
+            .get("authorization")
+            .map(|v| v.to_str().unwrap().to_string());
+        let non_interoperable_kdf = resp
+            .headers()
+            .get("X-Non-Interoperable-KDF")
+            .map(|v| v.to_str().unwrap_or("").eq("true"))
+            .unwrap_or(false);
+
+        Ok(TransportResponse {
+            success: resp.status().is_success(),
+            message_type,
+            authorization,
+            non_interoperable_kdf,
+            body: resp.bytes().await?.to_vec(),
+        })
+    }
+
+    pub fn protocol_version(&self) -> ProtocolVersion {
+        self.protocol_version
+    }
+
     pub fn non_interoperable_kdf_required(&self) -> Option<bool> {
         self.non_interoperable_kdf_required
     }
@@ -171,8 +451,8 @@ impl ServiceClient {
         new_keys: Option<EncryptionKeys>,
     ) -> RequestResult<SM>
     where
-        OM: Message + ClientMessage,
-        SM: Message + ServerMessage,
+        OM: VersionedMessage + ClientMessage,
+        SM: VersionedMessage + ServerMessage,
     {
         if !OM::is_valid_previous_message(self.last_message_type) {
             return Err(Error::InvalidSequenceRequest);
@@ -208,44 +488,35 @@ impl ServiceClient {
             }
         }
 
-        let to_send = to_send.serialize_data()?;
+        // Messages are exchanged in their 1.0 definition with 1.0 services
+        let to_send = if self.protocol_version == ProtocolVersion::Version1_0 {
+            to_send.to_v10()?.serialize_data()?
+        } else {
+            to_send.serialize_data()?
+        };
         let to_send = self.encryption_keys.encrypt(&to_send)?;
         log::trace!("Sending message: {:?}", hex::encode(&to_send));
 
-        let url = format!(
-            "{}/fdo/{}/msg/{}",
-            &self.base_url,
-            self.protocol_version,
-            OM::message_type() as u8
-        );
-
-        let mut req = self
-            .client
-            .post(&url)
-            .header("Content-Type", "application/cbor")
-            .body(to_send);
-
-        if let Some(authorization_token) = &self.authorization_token {
-            req = req.header("Authorization", authorization_token);
-        }
-
-        if !fdo_data_formats::interoperable_kdf_available() {
-            req = req.header("X-Non-Interoperable-KDF", "true");
-        }
+        let path = crate::message_path(self.protocol_version, OM::message_type());
 
         if let Some(new_keys) = new_keys {
             self.encryption_keys = new_keys;
         }
 
-        let resp = req.send().await?;
+        let resp = if self.is_coap()? {
+            coap::send_request(
+                &self.base_url,
+                &path,
+                self.authorization_token.as_deref(),
+                &to_send,
+            )
+            .await?
+        } else {
+            self.send_http_request(&path, to_send).await?
+        };
 
         if self.non_interoperable_kdf_required.is_none() {
-            self.non_interoperable_kdf_required = Some(
-                resp.headers()
-                    .get("X-Non-Interoperable-KDF")
-                    .map(|v| v.to_str().unwrap_or("").eq("true"))
-                    .unwrap_or(false),
-            );
+            self.non_interoperable_kdf_required = Some(resp.non_interoperable_kdf);
             log::trace!(
                 "Determined whether non-interoperable KDF is required: {:?}",
                 self.non_interoperable_kdf_required
@@ -253,22 +524,19 @@ impl ServiceClient {
         }
 
         let msgtype = resp
-            .headers()
-            .get("message-type")
-            .map(reqwest::header::HeaderValue::to_str)
-            .transpose()
-            .map_err(|_| Error::InvalidMessageType("non-string".to_string()))?;
-        let msgtype = msgtype
-            .map(u8::from_str)
-            .transpose()
-            .map_err(|_| Error::InvalidMessageType(msgtype.unwrap().to_string()))?
-            .map(MessageType::try_from)
-            .transpose()
-            .map_err(|_| Error::InvalidMessageType(msgtype.unwrap().to_string()))?;
+            .message_type
+            .as_deref()
+            .map(|msgtype| {
+                u8::from_str(msgtype)
+                    .ok()
+                    .and_then(|msgtype| MessageType::try_from(msgtype).ok())
+                    .ok_or_else(|| Error::InvalidMessageType(msgtype.to_string()))
+            })
+            .transpose()?;
         let msgtype = match msgtype {
             Some(msgtype) => msgtype,
             None => {
-                if resp.status().is_success() {
+                if resp.success {
                     return Err(Error::MissingMessageType);
                 } else {
                     MessageType::Error
@@ -276,11 +544,11 @@ impl ServiceClient {
             }
         };
 
-        if let Some(val) = resp.headers().get("authorization") {
-            self.authorization_token = Some(val.to_str().unwrap().to_string());
+        if let Some(val) = resp.authorization {
+            self.authorization_token = Some(val);
         }
 
-        let is_success = if resp.status().is_success() {
+        let is_success = if resp.success {
             if msgtype != SM::message_type() {
                 return Err(Error::InvalidMessage(msgtype, SM::message_type()));
             }
@@ -292,14 +560,86 @@ impl ServiceClient {
             false
         };
 
-        let resp = resp.bytes().await?;
+        let resp = resp.body;
         log::trace!("Received: {:?}", hex::encode(&resp));
 
         if is_success {
             let resp = self.encryption_keys.decrypt(&resp)?;
-            Ok(SM::deserialize_data(&resp)?)
+            if self.protocol_version == ProtocolVersion::Version1_0 {
+                Ok(SM::from_v10(SM::V10::deserialize_data(&resp)?)?)
+            } else {
+                Ok(SM::deserialize_data(&resp)?)
+            }
         } else {
             Err(Error::Error(ErrorMessage::deserialize_data(&resp)?))
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use fdo_data_formats::{constants::HashType, test_utils::generate_cert, types::Hash};
+    use openssl::x509::X509;
+
+    use super::TlsPin;
+
+    fn hash(cert: &X509) -> Hash {
+        Hash::from_data(HashType::Sha256, &cert.to_der().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_server_certificate_pin() {
+        let ca = generate_cert("Test CA", None);
+        let (_, server_cert) = generate_cert("localhost", Some(&ca));
+        let (_, other_cert) = generate_cert("localhost", Some(&ca));
+        let chain = vec![server_cert.to_der().unwrap()];
+
+        let pin = TlsPin {
+            server_certificate_hash: Some(hash(&server_cert)),
+            ca_certificate_hash: None,
+        };
+        pin.verify("localhost", &chain).unwrap();
+
+        let pin = TlsPin {
+            server_certificate_hash: Some(hash(&other_cert)),
+            ca_certificate_hash: None,
+        };
+        assert!(pin.verify("localhost", &chain).is_err());
+        assert!(pin.verify("localhost", &[]).is_err());
+    }
+
+    #[test]
+    fn test_ca_certificate_pin() {
+        let ca = generate_cert("Test CA", None);
+        let other_ca = generate_cert("Other CA", None);
+        let (_, server_cert) = generate_cert("localhost", Some(&ca));
+        let chain = vec![server_cert.to_der().unwrap(), ca.1.to_der().unwrap()];
+
+        let pin = TlsPin {
+            server_certificate_hash: None,
+            ca_certificate_hash: Some(hash(&ca.1)),
+        };
+        pin.verify("localhost", &chain).unwrap();
+        // The certificate must also be valid for the host
+        assert!(pin.verify("example.com", &chain).is_err());
+        // The pinned CA must be presented by the server
+        assert!(pin
+            .verify("localhost", &[server_cert.to_der().unwrap()])
+            .is_err());
+
+        let pin = TlsPin {
+            server_certificate_hash: None,
+            ca_certificate_hash: Some(hash(&other_ca.1)),
+        };
+        assert!(pin.verify("localhost", &chain).is_err());
+
+        // A chain presenting the pinned CA, but not signed by it
+        let (_, forged_cert) = generate_cert("localhost", Some(&other_ca));
+        let forged_chain = vec![forged_cert.to_der().unwrap(), ca.1.to_der().unwrap()];
+        let pin = TlsPin {
+            server_certificate_hash: None,
+            ca_certificate_hash: Some(hash(&ca.1)),
+        };
+        assert!(pin.verify("localhost", &forged_chain).is_err());
+    }
+}
@@ -0,0 +1,274 @@
+//! CoAP transport for the service client
+//!
+//! Requests are confirmable POSTs to the message path, larger messages are
+//! sent and received block-wise (RFC 7959).
+
+use std::time::Duration;
+
+use coap_lite::{
+    CoapOption, ContentFormat, MessageClass, MessageType as CoapMessageType, Packet, RequestType,
+    ResponseType,
+};
+use openssl::rand::rand_bytes;
+use tokio::net::{lookup_host, UdpSocket};
+
+use super::{Error, RequestResult, TransportResponse};
+use crate::coap::{
+    decode_uint, encode_uint, get_option, AUTHORIZATION_OPTION, DEFAULT_PORT, MESSAGE_TYPE_OPTION,
+    NON_INTEROPERABLE_KDF_OPTION,
+};
+
+// Transmission parameters from RFC 7252
+const ACK_TIMEOUT: Duration = Duration::from_secs(2);
+const MAX_RETRANSMIT: u32 = 4;
+
+// 1024 byte blocks
+const BLOCK_SIZE_EXPONENT: u8 = 6;
+const BLOCK_SIZE: usize = 1 << (BLOCK_SIZE_EXPONENT + 4);
+
+const MAX_DATAGRAM_SIZE: usize = 65535;
+
+fn encode_block(num: u32, more: bool, size_exponent: u8) -> Vec<u8> {
+    encode_uint(num << 4 | (more as u32) << 3 | u32::from(size_exponent))
+}
+
+/// Returns the block number, whether more blocks follow and the size exponent
+fn decode_block(value: &[u8]) -> Option<(u32, bool, u8)> {
+    let value = decode_uint(value)?;
+    Some((value >> 4, value & 0x8 != 0, (value & 0x7) as u8))
+}
+
+fn coap_error(e: impl std::fmt::Debug) -> Error {
+    Error::Coap(format!("{e:?}"))
+}
+
+struct Connection {
+    socket: UdpSocket,
+    message_id: u16,
+    token: Vec<u8>,
+}
+
+impl Connection {
+    async fn connect(url: &url::Url) -> RequestResult<Self> {
+        if url.scheme() != "coap" {
+            return Err(Error::InvalidUrl(
+                url.to_string(),
+                "Only the coap scheme is supported",
+            ));
+        }
+        let host = url
+            .host_str()
+            .ok_or_else(|| Error::InvalidUrl(url.to_string(), "URL has no host"))?
+            .trim_start_matches('[')
+            .trim_end_matches(']');
+        let port = url.port().unwrap_or(DEFAULT_PORT);
+        let addr = lookup_host((host, port))
+            .await?
+            .next()
+            .ok_or_else(|| Error::InvalidUrl(url.to_string(), "Unable to resolve host"))?;
+
+        let socket = UdpSocket::bind(if addr.is_ipv4() {
+            "0.0.0.0:0"
+        } else {
+            "[::]:0"
+        })
+        .await?;
+        socket.connect(addr).await?;
+
+        let mut message_id = [0; 2];
+        let mut token = vec![0; 4];
+        rand_bytes(&mut message_id).map_err(coap_error)?;
+        rand_bytes(&mut token).map_err(coap_error)?;
+
+        Ok(Connection {
+            socket,
+            message_id: u16::from_be_bytes(message_id),
+            token,
+        })
+    }
+
+    /// Sends a confirmable request, and waits for its piggybacked or separate response
+    async fn exchange(&mut self, mut request: Packet) -> RequestResult<Packet> {
+        self.message_id = self.message_id.wrapping_add(1);
+        request.header.message_id = self.message_id;
+        request.set_token(self.token.clone());
+        let request = request.to_bytes().map_err(coap_error)?;
+
+        let mut buf = vec![0; MAX_DATAGRAM_SIZE];
+        let mut timeout = ACK_TIMEOUT;
+        // Set once the server acknowledged the request, with a separate response to follow
+        let mut acknowledged = false;
+        for _ in 0..=MAX_RETRANSMIT {
+            if !acknowledged {
+                self.socket.send(&request).await?;
+            }
+            let deadline = tokio::time::Instant::now() + timeout;
+            while let Ok(len) = tokio::time::timeout_at(deadline, self.socket.recv(&mut buf)).await
+            {
+                let packet = match Packet::from_bytes(&buf[..len?]) {
+                    Ok(packet) => packet,
+                    Err(_) => continue,
+                };
+                match packet.header.get_type() {
+                    CoapMessageType::Acknowledgement
+                        if packet.header.message_id == self.message_id =>
+                    {
+                        if matches!(packet.header.code, MessageClass::Empty) {
+                            acknowledged = true;
+                        } else {
+                            return Ok(packet);
+                        }
+                    }
+                    CoapMessageType::Confirmable | CoapMessageType::NonConfirmable
+                        if packet.get_token() == &self.token[..]
+                            && matches!(packet.header.code, MessageClass::Response(_)) =>
+                    {
+                        if matches!(packet.header.get_type(), CoapMessageType::Confirmable) {
+                            let mut ack = Packet::new();
+                            ack.header.set_version(1);
+                            ack.header.set_type(CoapMessageType::Acknowledgement);
+                            ack.header.code = MessageClass::Empty;
+                            ack.header.message_id = packet.header.message_id;
+                            self.socket
+                                .send(&ack.to_bytes().map_err(coap_error)?)
+                                .await?;
+                        }
+                        return Ok(packet);
+                    }
+                    _ => continue,
+                }
+            }
+            timeout *= 2;
+        }
+        Err(Error::Coap("No response received from server".to_string()))
+    }
+}
+
+fn build_request(
+    path: &str,
+    authorization: Option<&str>,
+    block: Option<(CoapOption, Vec<u8>)>,
+    payload: &[u8],
+) -> Packet {
+    let mut packet = Packet::new();
+    packet.header.set_version(1);
+    packet.header.set_type(CoapMessageType::Confirmable);
+    packet.header.code = MessageClass::Request(RequestType::Post);
+    for segment in path.split('/').filter(|segment| !segment.is_empty()) {
+        packet.add_option(CoapOption::UriPath, segment.as_bytes().to_vec());
+    }
+    packet.set_content_format(ContentFormat::ApplicationCBOR);
+    if let Some(authorization) = authorization {
+        packet.add_option(AUTHORIZATION_OPTION, authorization.as_bytes().to_vec());
+    }
+    if !fdo_data_formats::interoperable_kdf_available() {
+        packet.add_option(NON_INTEROPERABLE_KDF_OPTION, Vec::new());
+    }
+    if let Some((option, value)) = block {
+        packet.add_option(option, value);
+    }
+    packet.payload = payload.to_vec();
+    packet
+}
+
+pub(super) async fn send_request(
+    base_url: &str,
+    path: &str,
+    authorization: Option<&str>,
+    payload: &[u8],
+) -> RequestResult<TransportResponse> {
+    let url = url::Url::parse(base_url)?;
+    let mut connection = Connection::connect(&url).await?;
+    let path = format!("{}{}", url.path().trim_end_matches('/'), path);
+
+    // Send all but the last block, which gets the actual response
+    let mut blocks: Vec<&[u8]> = payload.chunks(BLOCK_SIZE).collect();
+    let last_block = blocks.pop().unwrap_or(&[]);
+    for (num, block) in blocks.iter().enumerate() {
+        let block1 = encode_block(num as u32, true, BLOCK_SIZE_EXPONENT);
+        let response = connection
+            .exchange(build_request(
+                &path,
+                authorization,
+                Some((CoapOption::Block1, block1)),
+                block,
+            ))
+            .await?;
+        if !matches!(
+            response.header.code,
+            MessageClass::Response(ResponseType::Continue)
+        ) {
+            return Err(Error::Coap(format!(
+                "Unexpected response to block {}: {}",
+                num, response.header.code
+            )));
+        }
+    }
+    let block1 = if blocks.is_empty() {
+        None
+    } else {
+        Some((
+            CoapOption::Block1,
+            encode_block(blocks.len() as u32, false, BLOCK_SIZE_EXPONENT),
+        ))
+    };
+    let response = connection
+        .exchange(build_request(&path, authorization, block1, last_block))
+        .await?;
+
+    let success = matches!(
+        response.header.code,
+        MessageClass::Response(ResponseType::Changed)
+            | MessageClass::Response(ResponseType::Content)
+    );
+    let message_type = get_option(&response, MESSAGE_TYPE_OPTION)
+        .map(|value| {
+            decode_uint(value)
+                .map(|value| value.to_string())
+                .ok_or_else(|| Error::InvalidMessageType("non-uint".to_string()))
+        })
+        .transpose()?;
+    let authorization = get_option(&response, AUTHORIZATION_OPTION)
+        .map(|value| String::from_utf8_lossy(value).to_string());
+    let non_interoperable_kdf = get_option(&response, NON_INTEROPERABLE_KDF_OPTION).is_some();
+
+    // Retrieve the remaining blocks of the response
+    let mut body = response.payload.clone();
+    let mut block2 = get_option(&response, CoapOption::Block2).and_then(decode_block);
+    while let Some((num, true, size_exponent)) = block2 {
+        let next = encode_block(num + 1, false, size_exponent);
+        let response = connection
+            .exchange(build_request(
+                &path,
+                authorization.as_deref(),
+                Some((CoapOption::Block2, next)),
+                &[],
+            ))
+            .await?;
+        body.extend_from_slice(&response.payload);
+        block2 = get_option(&response, CoapOption::Block2).and_then(decode_block);
+    }
+
+    Ok(TransportResponse {
+        success,
+        message_type,
+        authorization,
+        non_interoperable_kdf,
+        body,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::{decode_block, encode_block};
+
+    #[test]
+    fn test_block_options() {
+        assert_eq!(encode_block(0, true, 6), vec![0x0e]);
+        assert_eq!(decode_block(&encode_block(0, true, 6)), Some((0, true, 6)));
+        assert_eq!(
+            decode_block(&encode_block(300, false, 6)),
+            Some((300, false, 6))
+        );
+    }
+}
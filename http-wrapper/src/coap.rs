@@ -0,0 +1,55 @@
+//! Definitions shared by the CoAP (RFC 7252) client and server
+//!
+//! FDO messages are POSTed to the same path as over HTTP, see `message_path`.
+//! The HTTP headers the protocol relies on are carried in elective options
+//! from the experimental range.
+
+use coap_lite::{CoapOption, Packet};
+
+pub(crate) const DEFAULT_PORT: u16 = 5683;
+
+/// Message type of the response, as a uint
+pub(crate) const MESSAGE_TYPE_OPTION: CoapOption = CoapOption::Unknown(65000);
+/// Session token, in the same format as the HTTP Authorization header
+pub(crate) const AUTHORIZATION_OPTION: CoapOption = CoapOption::Unknown(65002);
+/// Present if the non-interoperable KDF is used, like the X-Non-Interoperable-KDF header
+pub(crate) const NON_INTEROPERABLE_KDF_OPTION: CoapOption = CoapOption::Unknown(65004);
+
+pub(crate) fn get_option(packet: &Packet, option: CoapOption) -> Option<&[u8]> {
+    packet
+        .get_option(option)
+        .and_then(|values| values.front())
+        .map(|value| value.as_slice())
+}
+
+pub(crate) fn encode_uint(value: u32) -> Vec<u8> {
+    let bytes = value.to_be_bytes();
+    let leading_zeros = bytes.iter().take_while(|b| **b == 0).count();
+    bytes[leading_zeros..].to_vec()
+}
+
+pub(crate) fn decode_uint(value: &[u8]) -> Option<u32> {
+    if value.len() > 4 {
+        return None;
+    }
+    Some(
+        value
+            .iter()
+            .fold(0, |acc, byte| (acc << 8) | u32::from(*byte)),
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::{decode_uint, encode_uint};
+
+    #[test]
+    fn test_uint_options() {
+        assert_eq!(encode_uint(0), Vec::<u8>::new());
+        assert_eq!(encode_uint(61), vec![61]);
+        assert_eq!(encode_uint(0x1234), vec![0x12, 0x34]);
+        assert_eq!(decode_uint(&encode_uint(0x123456)), Some(0x123456));
+        assert_eq!(decode_uint(&[]), Some(0));
+        assert_eq!(decode_uint(&[1, 2, 3, 4, 5]), None);
+    }
+}
@@ -0,0 +1,250 @@
+// Encrypt-then-MAC message wrapping for the split-key cipher suites.
+//
+// The plaintext is encrypted into a COSE_Encrypt0 structure (with the AES
+// mode as algorithm and the IV in the unprotected header), which is then used
+// as the payload of a COSE_Mac0 structure authenticated with the SVK.
+
+use std::collections::BTreeMap;
+
+use openssl::{memcmp, pkey::PKey, rand::rand_bytes, sign::Signer, symm};
+use serde_cbor::{tags::Tagged, Value};
+
+use fdo_data_formats::{constants::HashType, types::CipherSuite};
+
+use crate::EncryptionError;
+
+const COSE_ENCRYPT0_TAG: u64 = 16;
+const COSE_MAC0_TAG: u64 = 17;
+
+const HEADER_ALG: i128 = 1;
+const HEADER_IV: i128 = 5;
+
+const IV_SIZE: usize = 16;
+
+fn mac_type(cipher_suite: CipherSuite) -> Result<(HashType, i128), EncryptionError> {
+    match cipher_suite.etm_mac_type() {
+        Some(HashType::HmacSha256) => Ok((HashType::HmacSha256, 5)),
+        Some(HashType::HmacSha384) => Ok((HashType::HmacSha384, 6)),
+        _ => Err(EncryptionError::InvalidMessage(
+            "Cipher suite does not use Encrypt-then-MAC",
+        )),
+    }
+}
+
+fn protected_header(alg: i128) -> Result<Vec<u8>, EncryptionError> {
+    let mut header = BTreeMap::new();
+    header.insert(Value::Integer(HEADER_ALG), Value::Integer(alg));
+    Ok(serde_cbor::to_vec(&Value::Map(header))?)
+}
+
+fn check_protected_header(header: &[u8], alg: i128) -> Result<(), EncryptionError> {
+    let header: BTreeMap<Value, Value> = serde_cbor::from_slice(header)?;
+    match header.get(&Value::Integer(HEADER_ALG)) {
+        Some(Value::Integer(header_alg)) if *header_alg == alg => Ok(()),
+        _ => Err(EncryptionError::InvalidMessage(
+            "Unexpected algorithm in protected header",
+        )),
+    }
+}
+
+fn compute_mac(
+    mac_type: HashType,
+    svk: &[u8],
+    protected: &[u8],
+    payload: &[u8],
+) -> Result<Vec<u8>, EncryptionError> {
+    let mac_structure = serde_cbor::to_vec(&Value::Array(vec![
+        Value::Text("MAC0".to_string()),
+        Value::Bytes(protected.to_vec()),
+        Value::Bytes(vec![]),
+        Value::Bytes(payload.to_vec()),
+    ]))?;
+
+    let key = PKey::hmac(svk)?;
+    let mut signer = Signer::new(mac_type.get_md(), &key)?;
+    signer.update(&mac_structure)?;
+    Ok(signer.sign_to_vec()?)
+}
+
+fn parse_structure(
+    data: &[u8],
+    expected_tag: u64,
+    expected_len: usize,
+) -> Result<Vec<Value>, EncryptionError> {
+    let data: Tagged<Value> = serde_cbor::from_slice(data)?;
+    if let Some(tag) = data.tag {
+        if tag != expected_tag {
+            return Err(EncryptionError::InvalidMessage("Unexpected COSE tag"));
+        }
+    }
+    match data.value {
+        Value::Array(contents) if contents.len() == expected_len => Ok(contents),
+        _ => Err(EncryptionError::InvalidMessage("Invalid COSE structure")),
+    }
+}
+
+fn get_bytes(contents: &[Value], pos: usize) -> Result<&[u8], EncryptionError> {
+    match &contents[pos] {
+        Value::Bytes(value) => Ok(value.as_slice()),
+        _ => Err(EncryptionError::InvalidMessage("Invalid COSE structure")),
+    }
+}
+
+pub(crate) fn encrypt(
+    cipher_suite: CipherSuite,
+    sek: &[u8],
+    svk: &[u8],
+    plaintext: &[u8],
+) -> Result<Vec<u8>, EncryptionError> {
+    let (mac_type, mac_alg) = mac_type(cipher_suite)?;
+
+    let mut iv = vec![0; IV_SIZE];
+    rand_bytes(&mut iv)?;
+    let ciphertext = symm::encrypt(cipher_suite.openssl_cipher(), sek, Some(&iv[..]), plaintext)?;
+
+    let mut unprotected = BTreeMap::new();
+    unprotected.insert(Value::Integer(HEADER_IV), Value::Bytes(iv));
+    let inner = serde_cbor::to_vec(&Tagged::new(
+        Some(COSE_ENCRYPT0_TAG),
+        Value::Array(vec![
+            Value::Bytes(protected_header(cipher_suite as i128)?),
+            Value::Map(unprotected),
+            Value::Bytes(ciphertext),
+        ]),
+    ))?;
+
+    let protected = protected_header(mac_alg)?;
+    let mac = compute_mac(mac_type, svk, &protected, &inner)?;
+
+    Ok(serde_cbor::to_vec(&Tagged::new(
+        Some(COSE_MAC0_TAG),
+        Value::Array(vec![
+            Value::Bytes(protected),
+            Value::Map(BTreeMap::new()),
+            Value::Bytes(inner),
+            Value::Bytes(mac),
+        ]),
+    ))?)
+}
+
+pub(crate) fn decrypt(
+    cipher_suite: CipherSuite,
+    sek: &[u8],
+    svk: &[u8],
+    ciphertext: &[u8],
+) -> Result<Vec<u8>, EncryptionError> {
+    let (mac_type, mac_alg) = mac_type(cipher_suite)?;
+
+    let outer = parse_structure(ciphertext, COSE_MAC0_TAG, 4)?;
+    let protected = get_bytes(&outer, 0)?;
+    let inner = get_bytes(&outer, 2)?;
+    let mac = get_bytes(&outer, 3)?;
+
+    check_protected_header(protected, mac_alg)?;
+    let expected_mac = compute_mac(mac_type, svk, protected, inner)?;
+    if mac.len() != expected_mac.len() || !memcmp::eq(mac, &expected_mac) {
+        return Err(EncryptionError::InvalidMessage("MAC verification failed"));
+    }
+
+    let inner = parse_structure(inner, COSE_ENCRYPT0_TAG, 3)?;
+    check_protected_header(get_bytes(&inner, 0)?, cipher_suite as i128)?;
+    let iv = match &inner[1] {
+        Value::Map(unprotected) => match unprotected.get(&Value::Integer(HEADER_IV)) {
+            Some(Value::Bytes(iv)) if iv.len() == IV_SIZE => iv.as_slice(),
+            _ => return Err(EncryptionError::InvalidMessage("Missing or invalid IV")),
+        },
+        _ => return Err(EncryptionError::InvalidMessage("Invalid COSE structure")),
+    };
+
+    Ok(symm::decrypt(
+        cipher_suite.openssl_cipher(),
+        sek,
+        Some(iv),
+        get_bytes(&inner, 2)?,
+    )?)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // The SEK and SVK lengths as split from the key exchange KDF output
+    const SUITES: [(CipherSuite, usize, usize); 4] = [
+        (CipherSuite::Aes128CbcHmacSha256, 16, 32),
+        (CipherSuite::Aes128CtrHmacSha256, 16, 32),
+        (CipherSuite::Aes256CbcHmacSha384, 32, 64),
+        (CipherSuite::Aes256CtrHmacSha384, 32, 64),
+    ];
+
+    fn keys(sek_len: usize, svk_len: usize) -> (Vec<u8>, Vec<u8>) {
+        let mut sek = vec![0; sek_len];
+        rand_bytes(&mut sek).unwrap();
+        let mut svk = vec![0; svk_len];
+        rand_bytes(&mut svk).unwrap();
+        (sek, svk)
+    }
+
+    fn tamper(message: &[u8], tamper_fn: impl Fn(&mut Vec<Value>)) -> Vec<u8> {
+        let mut outer = parse_structure(message, COSE_MAC0_TAG, 4).unwrap();
+        tamper_fn(&mut outer);
+        serde_cbor::to_vec(&Tagged::new(Some(COSE_MAC0_TAG), Value::Array(outer))).unwrap()
+    }
+
+    fn flip_byte(value: &mut Value) {
+        match value {
+            Value::Bytes(bytes) => bytes[0] ^= 0x01,
+            _ => panic!("Expected bytes"),
+        }
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let plaintext = b"Hello, FDO!";
+        for (cipher_suite, sek_len, svk_len) in SUITES {
+            let (sek, svk) = keys(sek_len, svk_len);
+            let encrypted = encrypt(cipher_suite, &sek, &svk, plaintext).unwrap();
+            let decrypted = decrypt(cipher_suite, &sek, &svk, &encrypted).unwrap();
+            assert_eq!(decrypted, plaintext);
+        }
+    }
+
+    #[test]
+    fn test_combined_suite_rejected() {
+        let (sek, svk) = keys(16, 32);
+        assert!(encrypt(CipherSuite::A128Gcm, &sek, &svk, b"test").is_err());
+    }
+
+    #[test]
+    fn test_tampered_mac() {
+        for (cipher_suite, sek_len, svk_len) in SUITES {
+            let (sek, svk) = keys(sek_len, svk_len);
+            let encrypted = encrypt(cipher_suite, &sek, &svk, b"test").unwrap();
+            let tampered = tamper(&encrypted, |outer| flip_byte(&mut outer[3]));
+            assert!(matches!(
+                decrypt(cipher_suite, &sek, &svk, &tampered),
+                Err(EncryptionError::InvalidMessage("MAC verification failed"))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_tampered_ciphertext() {
+        for (cipher_suite, sek_len, svk_len) in SUITES {
+            let (sek, svk) = keys(sek_len, svk_len);
+            let encrypted = encrypt(cipher_suite, &sek, &svk, b"test").unwrap();
+            let tampered = tamper(&encrypted, |outer| {
+                let mut inner =
+                    parse_structure(get_bytes(outer, 2).unwrap(), COSE_ENCRYPT0_TAG, 3).unwrap();
+                flip_byte(&mut inner[2]);
+                outer[2] = Value::Bytes(
+                    serde_cbor::to_vec(&Tagged::new(Some(COSE_ENCRYPT0_TAG), Value::Array(inner)))
+                        .unwrap(),
+                );
+            });
+            assert!(matches!(
+                decrypt(cipher_suite, &sek, &svk, &tampered),
+                Err(EncryptionError::InvalidMessage("MAC verification failed"))
+            ));
+        }
+    }
+}
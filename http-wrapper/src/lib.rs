@@ -1,8 +1,18 @@
 use serde::{Deserialize, Serialize};
+use thiserror::Error;
 
 use aws_nitro_enclaves_cose::error::CoseError;
 use aws_nitro_enclaves_cose::{CipherConfiguration, CoseEncrypt0};
-use fdo_data_formats::types::{CipherSuite, DerivedKeys};
+use fdo_data_formats::{
+    constants::MessageType,
+    types::{CipherSuite, DerivedKeys},
+    ProtocolVersion,
+};
+
+mod etm;
+
+#[cfg(any(feature = "server", feature = "client"))]
+mod coap;
 
 #[cfg(feature = "server")]
 pub mod server;
@@ -10,6 +20,11 @@ pub mod server;
 #[cfg(feature = "client")]
 pub mod client;
 
+/// The path FDO messages of a type are sent to, for both HTTP and CoAP
+pub fn message_path(protocol_version: ProtocolVersion, message_type: MessageType) -> String {
+    format!("/fdo/{}/msg/{}", protocol_version, message_type as u8)
+}
+
 pub fn init_logging() {
     let filter = std::env::var("LOG_LEVEL").unwrap_or_else(|_| "info".to_string());
     pretty_env_logger::formatted_timed_builder()
@@ -18,6 +33,19 @@ pub fn init_logging() {
         .init();
 }
 
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum EncryptionError {
+    #[error("COSE error: {0:?}")]
+    Cose(#[from] CoseError),
+    #[error("Cryptographic stack error: {0}")]
+    CryptoStack(#[from] openssl::error::ErrorStack),
+    #[error("Serialization error: {0}")]
+    Serialization(#[from] serde_cbor::Error),
+    #[error("Invalid encrypted message: {0}")]
+    InvalidMessage(&'static str),
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct EncryptionKeys {
     cipher_suite: Option<CipherSuite>,
@@ -48,35 +76,40 @@ impl EncryptionKeys {
     }
 
     #[allow(clippy::panic)]
-    fn encrypt(&self, plaintext: &[u8]) -> Result<Vec<u8>, CoseError> {
-        if self.cipher_suite.is_none() {
-            Ok(plaintext.to_vec())
-        } else {
-            let k = match &self.keys {
-                Some(DerivedKeys::Combined { sevk: k }) => k,
-                _ => panic!(),
-            };
-            CoseEncrypt0::new(plaintext, CipherConfiguration::Gcm, &k[..])
-                .map(|c| c.as_bytes(true))?
+    fn encrypt(&self, plaintext: &[u8]) -> Result<Vec<u8>, EncryptionError> {
+        let cipher_suite = match self.cipher_suite {
+            None => return Ok(plaintext.to_vec()),
+            Some(cipher_suite) => cipher_suite,
+        };
+        match &self.keys {
+            Some(DerivedKeys::Combined { sevk: k }) => {
+                Ok(
+                    CoseEncrypt0::new(plaintext, CipherConfiguration::Gcm, &k[..])?
+                        .as_bytes(true)?,
+                )
+            }
+            Some(DerivedKeys::Split { sek, svk }) => {
+                etm::encrypt(cipher_suite, sek, svk, plaintext)
+            }
+            None => panic!(),
         }
     }
 
     #[allow(clippy::panic)]
-    fn decrypt(&self, ciphertext: &[u8]) -> Result<Vec<u8>, CoseError> {
-        if self.cipher_suite.is_none() {
-            Ok(ciphertext.to_vec())
-        } else {
-            let k = match &self.keys {
-                Some(DerivedKeys::Combined { sevk: k }) => k,
-                _ => panic!(),
-            };
-            match CoseEncrypt0::from_bytes(ciphertext) {
-                Ok(v) => match v.decrypt(k) {
-                    Ok((_, _, payload)) => Ok(payload),
-                    Err(e) => Err(e),
-                },
-                Err(e) => Err(e),
+    fn decrypt(&self, ciphertext: &[u8]) -> Result<Vec<u8>, EncryptionError> {
+        let cipher_suite = match self.cipher_suite {
+            None => return Ok(ciphertext.to_vec()),
+            Some(cipher_suite) => cipher_suite,
+        };
+        match &self.keys {
+            Some(DerivedKeys::Combined { sevk: k }) => {
+                let (_, _, payload) = CoseEncrypt0::from_bytes(ciphertext)?.decrypt(k)?;
+                Ok(payload)
+            }
+            Some(DerivedKeys::Split { sek, svk }) => {
+                etm::decrypt(cipher_suite, sek, svk, ciphertext)
             }
+            None => panic!(),
         }
     }
 }
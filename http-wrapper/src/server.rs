@@ -1,5 +1,10 @@
 use std::convert::Infallible;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::pin::Pin;
 use std::sync::Arc;
+use std::time::Instant;
 
 use super::EncryptionKeys;
 use fdo_data_formats::{
@@ -16,6 +21,9 @@ use thiserror::Error;
 use warp::{Filter, Rejection};
 pub use warp_sessions::Session;
 
+pub mod coap;
+pub mod metrics;
+
 pub struct RequestInformation {
     // Session stuff
     pub session: Session,
@@ -24,6 +32,8 @@ pub struct RequestInformation {
     // Other request metadata
     pub req_hash: Hash,
     pub headers: warp::http::header::HeaderMap,
+    // The protocol version of the endpoint the request was sent to
+    pub protocol_version: ProtocolVersion,
 }
 
 type SessionStoreT = Arc<SessionStore>;
@@ -46,7 +56,9 @@ impl SessionStore {
     pub fn new(
         store: Box<dyn Store<fdo_store::ReadWriteOpen, String, Session, SessionStoreMetadataKey>>,
     ) -> Arc<Self> {
-        Arc::new(SessionStore { store })
+        Arc::new(SessionStore {
+            store: metrics::timed_store("sessions", store),
+        })
     }
 }
 
@@ -81,7 +93,12 @@ impl SessionStore {
     }
 
     pub async fn perform_maintenance(&self) -> Result<(), SessionError> {
-        Ok(self.store.perform_maintenance().await?)
+        self.store.perform_maintenance().await?;
+        match self.store.count_data().await {
+            Ok(count) => metrics::set_store_entries("sessions", count),
+            Err(e) => log::debug!("Unable to count the active sessions: {:?}", e),
+        }
+        Ok(())
     }
 }
 
@@ -162,6 +179,8 @@ pub async fn handle_rejection(err: Rejection) -> Result<warp::reply::Response, I
         &local_err
     };
 
+    metrics::record_error(&err.0);
+
     Ok(to_response::<ErrorMessage>(err.0.to_response(), None))
 }
 
@@ -369,6 +388,102 @@ pub fn ping_handler() -> warp::filters::BoxedFilter<(warp::reply::Response,)> {
         .boxed()
 }
 
+/// TLS configuration for [`serve`]
+///
+/// If `client_ca_path` is set, clients are required to present a certificate
+/// signed by one of the CAs in that file (mutual TLS).
+#[derive(Debug, Clone)]
+pub struct TlsConfig {
+    pub cert_chain_path: PathBuf,
+    pub private_key_path: PathBuf,
+    pub client_ca_path: Option<PathBuf>,
+}
+
+type ServerFuture = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+/// Error types of the route filters that [`serve`] accepts
+///
+/// warp does not expose the bound it puts on the filter errors, so this is
+/// implemented for the two errors it accepts: [`Rejection`] for plain routes and
+/// [`Infallible`] for routes that recover their own rejections.
+pub trait ServeError: Sized {
+    fn serve<F, S>(
+        routes: F,
+        bind: SocketAddr,
+        tls: Option<TlsConfig>,
+        shutdown: S,
+    ) -> (SocketAddr, ServerFuture)
+    where
+        F: Filter<Error = Self> + Clone + Send + Sync + 'static,
+        F::Extract: warp::Reply,
+        S: Future<Output = ()> + Send + 'static;
+}
+
+macro_rules! impl_serve_error {
+    ($($error:ty),*) => {
+        $(
+            impl ServeError for $error {
+                fn serve<F, S>(
+                    routes: F,
+                    bind: SocketAddr,
+                    tls: Option<TlsConfig>,
+                    shutdown: S,
+                ) -> (SocketAddr, ServerFuture)
+                where
+                    F: Filter<Error = Self> + Clone + Send + Sync + 'static,
+                    F::Extract: warp::Reply,
+                    S: Future<Output = ()> + Send + 'static,
+                {
+                    match tls {
+                        Some(tls) => {
+                            log::info!("Using TLS with certificate {:?}", tls.cert_chain_path);
+                            let server = warp::serve(routes)
+                                .tls()
+                                .cert_path(tls.cert_chain_path)
+                                .key_path(tls.private_key_path);
+                            let server = match tls.client_ca_path {
+                                Some(client_ca_path) => {
+                                    server.client_auth_required_path(client_ca_path)
+                                }
+                                None => server,
+                            };
+                            let (addr, server) = server.bind_with_graceful_shutdown(bind, shutdown);
+                            (addr, Box::pin(server))
+                        }
+                        None => {
+                            let (addr, server) =
+                                warp::serve(routes).bind_with_graceful_shutdown(bind, shutdown);
+                            (addr, Box::pin(server))
+                        }
+                    }
+                }
+            }
+        )*
+    };
+}
+
+impl_serve_error!(Rejection, Infallible);
+
+/// Binds `routes` to `bind`, over TLS if `tls` is set and plain HTTP otherwise
+///
+/// Returns the bound address and the server future, which completes once
+/// `shutdown` has completed and the open connections are closed.
+pub fn serve<F, A, S>(
+    routes: F,
+    bind: A,
+    tls: Option<TlsConfig>,
+    shutdown: S,
+) -> (SocketAddr, ServerFuture)
+where
+    F: Filter + Clone + Send + Sync + 'static,
+    F::Error: ServeError,
+    F::Extract: warp::Reply,
+    A: Into<SocketAddr>,
+    S: Future<Output = ()> + Send + 'static,
+{
+    F::Error::serve(routes, bind.into(), tls, shutdown)
+}
+
 pub fn fdo_request_filter<UDT, IM, OM, F, FR>(
     protocol_version: ProtocolVersion,
     user_data: UDT,
@@ -416,12 +531,19 @@ where
         }
     }
 
-    warp::post()
-        // Construct expected HTTP path
-        .and(warp::path("fdo"))
-        .and(warp::path(IM::protocol_version().to_string()))
-        .and(warp::path("msg"))
-        .and(warp::path((IM::message_type() as u8).to_string()))
+    let filter = warp::post()
+        // Match the message path, shared with the CoAP transport
+        .and(warp::path::full())
+        .and_then(|path: warp::path::FullPath| async move {
+            if path.as_str() == crate::message_path(IM::protocol_version(), IM::message_type()) {
+                Ok(())
+            } else {
+                Err(warp::reject::not_found())
+            }
+        })
+        .untuple_one()
+        .map(|| metrics::record_request(IM::message_type()))
+        .untuple_one()
         // Parse the request
         .and(warp::body::content_length_limit(1024 * 16))
         .and(warp::body::bytes())
@@ -467,6 +589,7 @@ where
 
                         req_hash,
                         headers,
+                        protocol_version: IM::protocol_version(),
                     },
                 ))
             },
@@ -489,6 +612,127 @@ where
             },
         )
         .untuple_one()
-        .and_then(encrypt_and_generate_response::<IM, OM>)
+        .and_then(encrypt_and_generate_response::<IM, OM>);
+
+    // Record the processing time of the requests that made it through
+    warp::any()
+        .map(Instant::now)
+        .and(filter)
+        .map(|start, res| {
+            metrics::record_request_duration(IM::message_type(), start);
+            res
+        })
         .boxed()
 }
+
+/// Serves the FDO 1.0 definition of a message with a handler for its 1.1
+/// definition, converting the request and response between the two.
+pub fn fdo_request_filter_v10<UDT, IM, OM, F, FR>(
+    user_data: UDT,
+    session_store: SessionStoreT,
+    handler: F,
+) -> warp::filters::BoxedFilter<(warp::reply::Response,)>
+where
+    UDT: Clone + Send + Sync + 'static,
+    F: Fn(UDT, RequestInformation, IM) -> FR + Clone + Send + Sync + 'static,
+    FR: futures::Future<Output = Result<(OM, RequestInformation), warp::Rejection>> + Send,
+    IM: messages::VersionedMessage + ClientMessage + Send + 'static,
+    IM::V10: ClientMessage + Send + 'static,
+    OM: messages::VersionedMessage + ServerMessage + 'static,
+    OM::V10: ServerMessage + 'static,
+{
+    fdo_request_filter(
+        ProtocolVersion::Version1_0,
+        user_data,
+        session_store,
+        move |user_data: UDT, ses_with_store: RequestInformation, req: IM::V10| {
+            let handler = handler.clone();
+            async move {
+                let req = IM::from_v10(req).map_err(|e| {
+                    log::info!("Error converting 1.0 request: {:?}", e);
+                    warp::reject::custom(ParseError)
+                })?;
+                let (res, ses_with_store) = handler(user_data, ses_with_store, req).await?;
+                let res = res.to_v10().map_err(|e| {
+                    log::error!("Error converting response to 1.0: {:?}", e);
+                    Rejection::from(Error::new(
+                        ErrorCode::InternalServerError,
+                        IM::message_type(),
+                        "Internal error",
+                    ))
+                })?;
+                Ok((res, ses_with_store))
+            }
+        },
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::{Read, Write};
+    use std::net::TcpStream;
+
+    use fdo_data_formats::test_utils::generate_cert;
+    use openssl::ssl::{SslConnector, SslMethod, SslVerifyMode};
+    use warp::Filter;
+
+    use super::{serve, TlsConfig};
+
+    #[test]
+    fn test_serve_tls() {
+        let dir = std::env::temp_dir().join(format!("fdo-serve-tls-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let (key, cert) = generate_cert("localhost", None);
+
+        let tls = TlsConfig {
+            cert_chain_path: dir.join("cert.pem"),
+            private_key_path: dir.join("key.pem"),
+            client_ca_path: None,
+        };
+        std::fs::write(&tls.cert_chain_path, cert.to_pem().unwrap()).unwrap();
+        std::fs::write(
+            &tls.private_key_path,
+            key.private_key_to_pem_pkcs8().unwrap(),
+        )
+        .unwrap();
+
+        let routes = warp::path("hello").map(|| "world");
+        let response = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(async {
+                let (shutdown_sender, shutdown_receiver) = tokio::sync::oneshot::channel::<()>();
+                let (addr, server) = serve(routes, ([127, 0, 0, 1], 0), Some(tls), async move {
+                    let _ = shutdown_receiver.await;
+                });
+                let server = tokio::spawn(server);
+
+                let response = tokio::task::spawn_blocking(move || {
+                    let mut connector = SslConnector::builder(SslMethod::tls()).unwrap();
+                    connector.set_verify(SslVerifyMode::NONE);
+                    let stream = TcpStream::connect(addr).unwrap();
+                    let mut stream = connector.build().connect("localhost", stream).unwrap();
+                    stream
+                        .write_all(
+                            b"GET /hello HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
+                        )
+                        .unwrap();
+                    let mut response = String::new();
+                    let _ = stream.read_to_string(&mut response);
+                    response
+                })
+                .await
+                .unwrap();
+
+                shutdown_sender.send(()).unwrap();
+                server.await.unwrap();
+                response
+            });
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert!(response.starts_with("HTTP/1.1 200 OK"), "{}", response);
+        assert!(response.ends_with("world"), "{}", response);
+    }
+}
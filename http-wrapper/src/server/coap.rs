@@ -0,0 +1,681 @@
+//! CoAP transport for the FDO servers
+//!
+//! CoAP requests are translated to HTTP requests and passed to the same warp
+//! handlers that serve the FDO messages over HTTP, so the message handlers,
+//! sessions, encryption and metrics are shared between both transports.
+//! Messages that do not fit in a single datagram are transferred block-wise
+//! (RFC 7959).
+//!
+//! Duplicates of a request are answered from an exchange cache instead of being
+//! handled again (RFC 7252 section 4.5), and confirmable requests that are not
+//! handled within `SEPARATE_RESPONSE_DELAY` are acknowledged right away and get
+//! a separate response (RFC 7252 section 5.2.2).
+
+use std::{
+    collections::{HashMap, VecDeque},
+    convert::Infallible,
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicU16, Ordering},
+        Arc, Mutex as StdMutex,
+    },
+    time::{Duration, Instant},
+};
+
+use coap_lite::{
+    block_handler::{BlockHandler, BlockHandlerConfig},
+    CoapRequest, ContentFormat, MessageClass, MessageType as CoapMessageType, Packet, RequestType,
+    ResponseType,
+};
+use openssl::rand::rand_bytes;
+use tokio::{
+    net::UdpSocket,
+    sync::{oneshot, Mutex},
+};
+use warp::hyper::{self, service::Service, Body};
+
+use crate::coap::{
+    encode_uint, get_option, AUTHORIZATION_OPTION, MESSAGE_TYPE_OPTION,
+    NON_INTEROPERABLE_KDF_OPTION,
+};
+
+// Large enough for any datagram
+const MAX_DATAGRAM_SIZE: usize = 65535;
+
+// Transmission parameters from RFC 7252
+const ACK_TIMEOUT: Duration = Duration::from_secs(2);
+const MAX_RETRANSMIT: u32 = 4;
+const EXCHANGE_LIFETIME: Duration = Duration::from_secs(247);
+
+// Well below ACK_TIMEOUT, so that clients do not retransmit requests that are
+// still being handled
+const SEPARATE_RESPONSE_DELAY: Duration = Duration::from_secs(1);
+
+// Clients only have one outstanding request (NSTART is 1), so older exchanges
+// of a peer can be forgotten early. With replies of at most one block, the
+// cache stays within a few MB.
+const MAX_EXCHANGES_PER_PEER: usize = 32;
+const MAX_EXCHANGES: usize = 8192;
+
+type ExchangeKey = (SocketAddr, u16);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Exchange {
+    /// The request is being handled, `acknowledged` once an empty ACK was sent
+    InProgress { acknowledged: bool },
+    /// The request was handled, with the datagram sent in reply if any
+    Done(Option<Vec<u8>>),
+}
+
+/// Requests received in the last `EXCHANGE_LIFETIME`, by peer and message ID
+///
+/// At most `MAX_EXCHANGES_PER_PEER` exchanges are kept per peer and
+/// `MAX_EXCHANGES` in total, the oldest ones are forgotten first.
+#[derive(Debug, Default)]
+struct ExchangeCache {
+    // By key, with the sequence number of the exchange
+    exchanges: HashMap<ExchangeKey, (u64, Exchange)>,
+    // In the order they were received, including exchanges that were already
+    // forgotten for their peer
+    received: VecDeque<(Instant, u64, ExchangeKey)>,
+    // Message IDs of the exchanges in `exchanges`, oldest first
+    peers: HashMap<SocketAddr, VecDeque<u16>>,
+    next_sequence: u64,
+}
+
+impl ExchangeCache {
+    /// Registers a new exchange, returns the existing one if the request is a duplicate
+    fn begin(&mut self, key: ExchangeKey, now: Instant) -> Option<Exchange> {
+        while let Some((received, _, _)) = self.received.front() {
+            if now.duration_since(*received) < EXCHANGE_LIFETIME {
+                break;
+            }
+            self.forget_oldest();
+        }
+        if let Some((_, exchange)) = self.exchanges.get(&key) {
+            return Some(exchange.clone());
+        }
+
+        let sequence = self.next_sequence;
+        self.next_sequence += 1;
+        self.exchanges.insert(
+            key,
+            (
+                sequence,
+                Exchange::InProgress {
+                    acknowledged: false,
+                },
+            ),
+        );
+        self.received.push_back((now, sequence, key));
+        let peer = self.peers.entry(key.0).or_default();
+        peer.push_back(key.1);
+        if peer.len() > MAX_EXCHANGES_PER_PEER {
+            if let Some(message_id) = peer.pop_front() {
+                self.exchanges.remove(&(key.0, message_id));
+            }
+        }
+        if self.received.len() > MAX_EXCHANGES {
+            self.forget_oldest();
+        }
+        None
+    }
+
+    fn forget_oldest(&mut self) {
+        let (sequence, key) = match self.received.pop_front() {
+            Some((_, sequence, key)) => (sequence, key),
+            None => return,
+        };
+        // Skips exchanges that were already forgotten for their peer
+        if self.exchanges.get(&key).map(|(s, _)| *s) != Some(sequence) {
+            return;
+        }
+        self.exchanges.remove(&key);
+        // Being the oldest, it is also the oldest exchange of the peer
+        if let Some(peer) = self.peers.get_mut(&key.0) {
+            peer.pop_front();
+            if peer.is_empty() {
+                self.peers.remove(&key.0);
+            }
+        }
+    }
+
+    fn acknowledge(&mut self, key: &ExchangeKey) {
+        if let Some((_, exchange)) = self.exchanges.get_mut(key) {
+            *exchange = Exchange::InProgress { acknowledged: true };
+        }
+    }
+
+    fn complete(&mut self, key: &ExchangeKey, reply: Option<Vec<u8>>) {
+        if let Some((_, exchange)) = self.exchanges.get_mut(key) {
+            *exchange = Exchange::Done(reply);
+        }
+    }
+}
+
+fn empty_ack(message_id: u16) -> Packet {
+    let mut ack = Packet::new();
+    ack.header.set_version(1);
+    ack.header.set_type(CoapMessageType::Acknowledgement);
+    ack.header.code = MessageClass::Empty;
+    ack.header.message_id = message_id;
+    ack
+}
+
+fn encode(packet: &Packet) -> Option<Vec<u8>> {
+    match packet.to_bytes() {
+        Ok(bytes) => Some(bytes),
+        Err(e) => {
+            log::error!("Error encoding CoAP response: {:?}", e);
+            None
+        }
+    }
+}
+
+struct ServerState {
+    socket: UdpSocket,
+    block_handler: Mutex<BlockHandler<SocketAddr>>,
+    exchanges: StdMutex<ExchangeCache>,
+    // Separate responses waiting for their acknowledgement
+    pending_acks: StdMutex<HashMap<ExchangeKey, oneshot::Sender<()>>>,
+    next_message_id: AtomicU16,
+}
+
+impl ServerState {
+    async fn send(&self, datagram: &[u8], peer: SocketAddr) {
+        if let Err(e) = self.socket.send_to(datagram, peer).await {
+            log::warn!("Error sending CoAP response to {}: {:?}", peer, e);
+        }
+    }
+
+    async fn handle_exchange<S>(&self, packet: Packet, peer: SocketAddr, service: S)
+    where
+        S: Service<hyper::Request<Body>, Response = hyper::Response<Body>, Error = Infallible>,
+    {
+        let key = (peer, packet.header.message_id);
+        let confirmable = packet.header.get_type() == CoapMessageType::Confirmable;
+
+        let mut handling = Box::pin(handle_packet(packet, peer, service, &self.block_handler));
+        let reply = match tokio::time::timeout(SEPARATE_RESPONSE_DELAY, &mut handling).await {
+            Ok(response) => {
+                let reply = response.as_ref().and_then(encode);
+                if let Some(reply) = &reply {
+                    self.send(reply, peer).await;
+                }
+                reply
+            }
+            Err(_) if confirmable => {
+                let ack = encode(&empty_ack(key.1));
+                if let Some(ack) = &ack {
+                    self.send(ack, peer).await;
+                }
+                self.exchanges.lock().unwrap().acknowledge(&key);
+                if let Some(response) = handling.await {
+                    self.send_separate(response, peer).await;
+                }
+                // Duplicates of the request only need to be acknowledged again
+                ack
+            }
+            Err(_) => {
+                let reply = handling.await.as_ref().and_then(encode);
+                if let Some(reply) = &reply {
+                    self.send(reply, peer).await;
+                }
+                reply
+            }
+        };
+        self.exchanges.lock().unwrap().complete(&key, reply);
+    }
+
+    /// Sends a response as a confirmable message, until the client acknowledges it
+    async fn send_separate(&self, mut response: Packet, peer: SocketAddr) {
+        let message_id = self.next_message_id.fetch_add(1, Ordering::Relaxed);
+        response.header.set_type(CoapMessageType::Confirmable);
+        response.header.message_id = message_id;
+        let response = match encode(&response) {
+            Some(response) => response,
+            None => return,
+        };
+
+        let key = (peer, message_id);
+        let (acked_sender, mut acked) = oneshot::channel();
+        self.pending_acks.lock().unwrap().insert(key, acked_sender);
+        let mut timeout = ACK_TIMEOUT;
+        for _ in 0..=MAX_RETRANSMIT {
+            self.send(&response, peer).await;
+            if tokio::time::timeout(timeout, &mut acked).await.is_ok() {
+                return;
+            }
+            timeout *= 2;
+        }
+        self.pending_acks.lock().unwrap().remove(&key);
+        log::warn!("Separate CoAP response to {} was not acknowledged", peer);
+    }
+}
+
+pub struct CoapServer<S> {
+    state: Arc<ServerState>,
+    service: S,
+}
+
+impl<S> CoapServer<S>
+where
+    S: Service<hyper::Request<Body>, Response = hyper::Response<Body>, Error = Infallible>
+        + Clone
+        + Send
+        + 'static,
+    S::Future: Send,
+{
+    /// Binds the CoAP listener, `service` is usually `warp::service(routes)`
+    /// with only the FDO protocol routes
+    pub async fn bind(addr: SocketAddr, service: S) -> std::io::Result<Self> {
+        let mut message_id = [0; 2];
+        rand_bytes(&mut message_id).map_err(std::io::Error::other)?;
+
+        Ok(CoapServer {
+            state: Arc::new(ServerState {
+                socket: UdpSocket::bind(addr).await?,
+                block_handler: Mutex::new(BlockHandler::new(BlockHandlerConfig::default())),
+                exchanges: StdMutex::new(ExchangeCache::default()),
+                pending_acks: StdMutex::new(HashMap::new()),
+                next_message_id: AtomicU16::new(u16::from_be_bytes(message_id)),
+            }),
+            service,
+        })
+    }
+
+    pub fn local_addr(&self) -> std::io::Result<SocketAddr> {
+        self.state.socket.local_addr()
+    }
+
+    pub async fn run(self) {
+        let mut buf = vec![0; MAX_DATAGRAM_SIZE];
+        loop {
+            let (len, peer) = match self.state.socket.recv_from(&mut buf).await {
+                Ok(v) => v,
+                Err(e) => {
+                    log::warn!("Error receiving CoAP datagram: {:?}", e);
+                    continue;
+                }
+            };
+            let packet = match Packet::from_bytes(&buf[..len]) {
+                Ok(packet) => packet,
+                Err(e) => {
+                    log::debug!("Invalid CoAP datagram from {}: {:?}", peer, e);
+                    continue;
+                }
+            };
+
+            let key = (peer, packet.header.message_id);
+            match (packet.header.get_type(), &packet.header.code) {
+                (_, MessageClass::Request(_)) => {}
+                (CoapMessageType::Acknowledgement | CoapMessageType::Reset, _) => {
+                    let acked = self.state.pending_acks.lock().unwrap().remove(&key);
+                    if let Some(acked) = acked {
+                        let _ = acked.send(());
+                    }
+                    continue;
+                }
+                _ => continue,
+            }
+
+            let existing = self
+                .state
+                .exchanges
+                .lock()
+                .unwrap()
+                .begin(key, Instant::now());
+            match existing {
+                None => {}
+                // The response is sent shortly
+                Some(Exchange::InProgress {
+                    acknowledged: false,
+                }) => continue,
+                Some(Exchange::InProgress { acknowledged: true }) => {
+                    if let Some(ack) = encode(&empty_ack(key.1)) {
+                        self.state.send(&ack, peer).await;
+                    }
+                    continue;
+                }
+                Some(Exchange::Done(reply)) => {
+                    if let Some(reply) = reply {
+                        self.state.send(&reply, peer).await;
+                    }
+                    continue;
+                }
+            }
+
+            let state = self.state.clone();
+            let service = self.service.clone();
+            tokio::spawn(async move { state.handle_exchange(packet, peer, service).await });
+        }
+    }
+}
+
+fn to_http_request(
+    request: &CoapRequest<SocketAddr>,
+) -> Result<hyper::Request<Body>, ResponseType> {
+    if *request.get_method() != RequestType::Post {
+        return Err(ResponseType::MethodNotAllowed);
+    }
+
+    let mut builder = hyper::Request::builder()
+        .method(hyper::Method::POST)
+        .uri(format!("/{}", request.get_path()))
+        .header("Content-Type", "application/cbor");
+    if let Some(authorization) = get_option(&request.message, AUTHORIZATION_OPTION) {
+        let authorization =
+            std::str::from_utf8(authorization).map_err(|_| ResponseType::BadOption)?;
+        builder = builder.header("Authorization", authorization);
+    }
+    if get_option(&request.message, NON_INTEROPERABLE_KDF_OPTION).is_some() {
+        builder = builder.header("X-Non-Interoperable-KDF", "true");
+    }
+
+    builder
+        .body(Body::from(request.message.payload.clone()))
+        .map_err(|_| ResponseType::BadRequest)
+}
+
+async fn apply_http_response(
+    response: hyper::Response<Body>,
+    coap_response: &mut Packet,
+) -> Result<(), ResponseType> {
+    let status = response.status();
+    let header = |name| {
+        response
+            .headers()
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string)
+    };
+    let message_type = header("Message-Type");
+    let authorization = header("Authorization");
+    let non_interoperable_kdf = header("X-Non-Interoperable-KDF");
+
+    let body = hyper::body::to_bytes(response.into_body())
+        .await
+        .map_err(|_| ResponseType::InternalServerError)?;
+
+    coap_response.header.code = MessageClass::Response(if status.is_success() {
+        ResponseType::Changed
+    } else if status.is_client_error() {
+        ResponseType::BadRequest
+    } else {
+        ResponseType::InternalServerError
+    });
+    coap_response.set_content_format(ContentFormat::ApplicationCBOR);
+    if let Some(message_type) = message_type.and_then(|v| v.parse::<u32>().ok()) {
+        coap_response.add_option(MESSAGE_TYPE_OPTION, encode_uint(message_type));
+    }
+    if let Some(authorization) = authorization {
+        coap_response.add_option(AUTHORIZATION_OPTION, authorization.into_bytes());
+    }
+    if non_interoperable_kdf.as_deref() == Some("true") {
+        coap_response.add_option(NON_INTEROPERABLE_KDF_OPTION, Vec::new());
+    }
+    coap_response.payload = body.to_vec();
+
+    Ok(())
+}
+
+async fn handle_packet<S>(
+    packet: Packet,
+    peer: SocketAddr,
+    mut service: S,
+    block_handler: &Mutex<BlockHandler<SocketAddr>>,
+) -> Option<Packet>
+where
+    S: Service<hyper::Request<Body>, Response = hyper::Response<Body>, Error = Infallible>,
+{
+    let mut request = CoapRequest::from_packet(packet, peer);
+    // Not set for messages that do not expect a response
+    request.response.as_ref()?;
+
+    // Collects Block1 requests and serves the following Block2 responses
+    match block_handler.lock().await.intercept_request(&mut request) {
+        Ok(true) => return request.response.map(|response| response.message),
+        Ok(false) => {}
+        Err(e) => {
+            request.apply_from_error(e);
+            return request.response.map(|response| response.message);
+        }
+    }
+
+    let result = match to_http_request(&request) {
+        Ok(http_request) => {
+            log::trace!("CoAP request from {} for {}", peer, http_request.uri());
+            // warp services are always ready, no need to poll_ready
+            let http_response = match service.call(http_request).await {
+                Ok(response) => response,
+                Err(e) => match e {},
+            };
+            let response = &mut request.response.as_mut()?.message;
+            apply_http_response(http_response, response).await
+        }
+        Err(e) => Err(e),
+    };
+    if let Err(status) = result {
+        request.response.as_mut()?.set_status(status);
+        return request.response.map(|response| response.message);
+    }
+
+    // Splits large responses in blocks
+    if let Err(e) = block_handler.lock().await.intercept_response(&mut request) {
+        request.apply_from_error(e);
+    }
+    request.response.map(|response| response.message)
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        convert::Infallible,
+        net::SocketAddr,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        time::{Duration, Instant},
+    };
+
+    use coap_lite::{
+        CoapOption, MessageClass, MessageType as CoapMessageType, Packet, RequestType, ResponseType,
+    };
+    use tokio::net::UdpSocket;
+    use warp::Filter;
+
+    use super::{
+        empty_ack, CoapServer, Exchange, ExchangeCache, EXCHANGE_LIFETIME, MAX_EXCHANGES,
+        MAX_EXCHANGES_PER_PEER,
+    };
+
+    const TOKEN: &[u8] = &[1, 2, 3, 4];
+
+    fn run<F: std::future::Future>(future: F) -> F::Output {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(future)
+    }
+
+    fn request(message_id: u16) -> Vec<u8> {
+        let mut packet = Packet::new();
+        packet.header.set_version(1);
+        packet.header.set_type(CoapMessageType::Confirmable);
+        packet.header.code = MessageClass::Request(RequestType::Post);
+        packet.header.message_id = message_id;
+        packet.set_token(TOKEN.to_vec());
+        packet.add_option(CoapOption::UriPath, b"test".to_vec());
+        packet.to_bytes().unwrap()
+    }
+
+    async fn receive(socket: &UdpSocket) -> Packet {
+        let mut buf = vec![0; 1024];
+        let len = tokio::time::timeout(Duration::from_secs(5), socket.recv(&mut buf))
+            .await
+            .expect("No CoAP reply received")
+            .unwrap();
+        Packet::from_bytes(&buf[..len]).unwrap()
+    }
+
+    async fn connect(server_addr: SocketAddr) -> UdpSocket {
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        socket.connect(server_addr).await.unwrap();
+        socket
+    }
+
+    #[test]
+    fn test_exchange_cache() {
+        let peer: SocketAddr = "127.0.0.1:5683".parse().unwrap();
+        let now = Instant::now();
+        let mut cache = ExchangeCache::default();
+
+        assert_eq!(cache.begin((peer, 1), now), None);
+        assert_eq!(
+            cache.begin((peer, 1), now),
+            Some(Exchange::InProgress {
+                acknowledged: false
+            })
+        );
+        // Message IDs are per peer
+        assert_eq!(
+            cache.begin(("127.0.0.1:5684".parse().unwrap(), 1), now),
+            None
+        );
+
+        cache.acknowledge(&(peer, 1));
+        assert_eq!(
+            cache.begin((peer, 1), now),
+            Some(Exchange::InProgress { acknowledged: true })
+        );
+        cache.complete(&(peer, 1), Some(vec![42]));
+        assert_eq!(
+            cache.begin((peer, 1), now),
+            Some(Exchange::Done(Some(vec![42])))
+        );
+
+        // Forgotten after the exchange lifetime
+        assert_eq!(cache.begin((peer, 1), now + EXCHANGE_LIFETIME), None);
+        assert_eq!(cache.exchanges.len(), 1);
+        assert_eq!(cache.received.len(), 1);
+    }
+
+    #[test]
+    fn test_exchange_cache_limits() {
+        let peer: SocketAddr = "127.0.0.1:5683".parse().unwrap();
+        let now = Instant::now();
+        let mut cache = ExchangeCache::default();
+
+        // The oldest exchanges of a peer are forgotten first
+        for message_id in 0..=MAX_EXCHANGES_PER_PEER as u16 {
+            assert_eq!(cache.begin((peer, message_id), now), None);
+        }
+        assert_eq!(cache.exchanges.len(), MAX_EXCHANGES_PER_PEER);
+        assert!(cache.begin((peer, 1), now).is_some());
+        assert_eq!(cache.begin((peer, 0), now), None);
+        assert_eq!(cache.begin((peer, 1), now), None);
+
+        // And the oldest exchanges overall
+        for port in 0..MAX_EXCHANGES as u16 {
+            let other = SocketAddr::from(([127, 0, 0, 2], port));
+            assert_eq!(cache.begin((other, 0), now), None);
+        }
+        assert_eq!(cache.exchanges.len(), MAX_EXCHANGES);
+        assert_eq!(cache.received.len(), MAX_EXCHANGES);
+        assert_eq!(cache.peers.len(), MAX_EXCHANGES);
+        assert!(!cache.peers.contains_key(&peer));
+        assert!(cache
+            .begin((SocketAddr::from(([127, 0, 0, 2], 0)), 0), now)
+            .is_some());
+
+        // All expire together
+        assert_eq!(cache.begin((peer, 0), now + EXCHANGE_LIFETIME), None);
+        assert_eq!(cache.exchanges.len(), 1);
+        assert_eq!(cache.peers.len(), 1);
+    }
+
+    #[test]
+    fn test_duplicate_request() {
+        run(async {
+            let calls = Arc::new(AtomicUsize::new(0));
+            let handler_calls = calls.clone();
+            let routes = warp::any().map(move || {
+                handler_calls.fetch_add(1, Ordering::SeqCst);
+                "reply"
+            });
+            let server = CoapServer::bind("127.0.0.1:0".parse().unwrap(), warp::service(routes))
+                .await
+                .unwrap();
+            let socket = connect(server.local_addr().unwrap()).await;
+            tokio::spawn(server.run());
+
+            socket.send(&request(1)).await.unwrap();
+            let reply = receive(&socket).await;
+            assert_eq!(reply.header.get_type(), CoapMessageType::Acknowledgement);
+            assert_eq!(reply.header.message_id, 1);
+            assert_eq!(
+                reply.header.code,
+                MessageClass::Response(ResponseType::Changed)
+            );
+            assert_eq!(reply.payload, b"reply");
+
+            // A retransmission gets the same reply, without handling the request again
+            socket.send(&request(1)).await.unwrap();
+            let duplicate_reply = receive(&socket).await;
+            assert_eq!(
+                duplicate_reply.to_bytes().unwrap(),
+                reply.to_bytes().unwrap()
+            );
+            assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+            // A new message ID is a new request
+            socket.send(&request(2)).await.unwrap();
+            assert_eq!(receive(&socket).await.header.message_id, 2);
+            assert_eq!(calls.load(Ordering::SeqCst), 2);
+        })
+    }
+
+    #[test]
+    fn test_separate_response() {
+        run(async {
+            let routes = warp::any().and_then(|| async {
+                tokio::time::sleep(Duration::from_millis(1500)).await;
+                Ok::<_, Infallible>("slow reply")
+            });
+            let server = CoapServer::bind("127.0.0.1:0".parse().unwrap(), warp::service(routes))
+                .await
+                .unwrap();
+            let socket = connect(server.local_addr().unwrap()).await;
+            tokio::spawn(server.run());
+
+            socket.send(&request(7)).await.unwrap();
+            let ack = receive(&socket).await;
+            assert_eq!(ack.header.get_type(), CoapMessageType::Acknowledgement);
+            assert_eq!(ack.header.code, MessageClass::Empty);
+            assert_eq!(ack.header.message_id, 7);
+
+            // A retransmission is only acknowledged again
+            socket.send(&request(7)).await.unwrap();
+            assert_eq!(
+                receive(&socket).await.to_bytes().unwrap(),
+                ack.to_bytes().unwrap()
+            );
+
+            let response = receive(&socket).await;
+            assert_eq!(response.header.get_type(), CoapMessageType::Confirmable);
+            assert_ne!(response.header.message_id, 7);
+            assert_eq!(response.get_token(), TOKEN);
+            assert_eq!(
+                response.header.code,
+                MessageClass::Response(ResponseType::Changed)
+            );
+            assert_eq!(response.payload, b"slow reply");
+            socket
+                .send(&empty_ack(response.header.message_id).to_bytes().unwrap())
+                .await
+                .unwrap();
+        })
+    }
+}
@@ -0,0 +1,383 @@
+use std::{future::Future, net::SocketAddr, time::Instant};
+
+use async_trait::async_trait;
+use fdo_data_formats::{
+    constants::MessageType, messages::v11::ErrorMessage, ownershipvoucher::OwnershipVoucher,
+};
+use fdo_store::{
+    MetadataKey, MetadataLocalKey, MetadataValue, Readable, Store, StoreError, StoreOpenMode,
+    Writable,
+};
+use lazy_static::lazy_static;
+use prometheus::{
+    register_histogram_vec, register_int_counter_vec, register_int_gauge, register_int_gauge_vec,
+    Encoder, HistogramVec, IntCounterVec, IntGauge, IntGaugeVec, TextEncoder,
+};
+use warp::Filter;
+
+lazy_static! {
+    static ref REQUESTS: IntCounterVec = register_int_counter_vec!(
+        "fdo_requests_total",
+        "Number of FDO requests received, per message type",
+        &["message_type"]
+    )
+    .unwrap();
+    static ref ERRORS: IntCounterVec = register_int_counter_vec!(
+        "fdo_errors_total",
+        "Number of FDO error responses, per previous message type and error code",
+        &["message_type", "error_code"]
+    )
+    .unwrap();
+    static ref REQUEST_DURATION: HistogramVec = register_histogram_vec!(
+        "fdo_request_duration_seconds",
+        "Time spent processing successful FDO requests, per message type",
+        &["message_type"]
+    )
+    .unwrap();
+    static ref STORE_OPERATION_DURATION: HistogramVec = register_histogram_vec!(
+        "fdo_store_operation_duration_seconds",
+        "Time spent on store operations, per store and operation",
+        &["store", "operation"]
+    )
+    .unwrap();
+    static ref STORE_ENTRIES: IntGaugeVec = register_int_gauge_vec!(
+        "fdo_store_entries",
+        "Number of entries in a store, updated during maintenance",
+        &["store"]
+    )
+    .unwrap();
+    static ref REGISTRATIONS_OVER_QUOTA: IntCounterVec = register_int_counter_vec!(
+        "fdo_rendezvous_registrations_over_quota_total",
+        "Number of TO0 registrations rejected because the owner policy is at its quota, per policy",
+        &["policy"]
+    )
+    .unwrap();
+    static ref OWNER_VOUCHERS_PENDING_TO0: IntGauge = register_int_gauge!(
+        "fdo_owner_vouchers_pending_to0",
+        "Number of ownership vouchers pending TO0 at the last rendezvous report"
+    )
+    .unwrap();
+}
+
+pub(super) fn record_request(message_type: MessageType) {
+    REQUESTS
+        .with_label_values(&[&format!("{message_type:?}")])
+        .inc();
+}
+
+pub(super) fn record_request_duration(message_type: MessageType, start: Instant) {
+    REQUEST_DURATION
+        .with_label_values(&[&format!("{message_type:?}")])
+        .observe(start.elapsed().as_secs_f64());
+}
+
+pub(super) fn record_error(error: &ErrorMessage) {
+    ERRORS
+        .with_label_values(&[
+            &format!("{:?}", error.previous_message_type()),
+            &format!("{:?}", error.error_code()),
+        ])
+        .inc();
+}
+
+/// Runs a store operation, recording how long it took
+async fn time_store_operation<F, T>(store: &str, operation: &str, fut: F) -> T
+where
+    F: Future<Output = T>,
+{
+    let start = Instant::now();
+    let res = fut.await;
+    STORE_OPERATION_DURATION
+        .with_label_values(&[store, operation])
+        .observe(start.elapsed().as_secs_f64());
+    res
+}
+
+/// Sets the number of entries in a store, e.g. the active sessions or the
+/// registrations in the rendezvous server
+pub fn set_store_entries(store: &str, entries: u64) {
+    STORE_ENTRIES
+        .with_label_values(&[store])
+        .set(entries as i64);
+}
+
+/// Counts a TO0 registration rejected because the owner policy is at its quota
+pub fn record_registration_over_quota(policy: &str) {
+    REGISTRATIONS_OVER_QUOTA.with_label_values(&[policy]).inc();
+}
+
+pub fn set_owner_vouchers_pending_to0(vouchers: usize) {
+    OWNER_VOUCHERS_PENDING_TO0.set(vouchers as i64);
+}
+
+/// Wraps a store to record the duration of all its operations under `name`
+pub fn timed_store<OT, K, V, MKT>(
+    name: &'static str,
+    store: Box<dyn Store<OT, K, V, MKT>>,
+) -> Box<dyn Store<OT, K, V, MKT>>
+where
+    OT: StoreOpenMode + 'static,
+    K: Send + Sync + 'static,
+    V: Send + Sync + 'static,
+    MKT: MetadataLocalKey + 'static,
+{
+    Box::new(TimedStore { name, inner: store })
+}
+
+struct TimedStore<OT, K, V, MKT> {
+    name: &'static str,
+    inner: Box<dyn Store<OT, K, V, MKT>>,
+}
+
+#[async_trait]
+impl<OT, K, V, MKT> Store<OT, K, V, MKT> for TimedStore<OT, K, V, MKT>
+where
+    OT: StoreOpenMode + 'static,
+    K: Send + Sync + 'static,
+    V: Send + Sync + 'static,
+    MKT: MetadataLocalKey + 'static,
+{
+    async fn load_data(&self, key: &K) -> Result<Option<V>, StoreError>
+    where
+        OT: Readable,
+    {
+        time_store_operation(self.name, "load_data", self.inner.load_data(key)).await
+    }
+
+    async fn load_all_data(&self) -> Result<Vec<V>, StoreError>
+    where
+        OT: Readable,
+    {
+        time_store_operation(self.name, "load_all_data", self.inner.load_all_data()).await
+    }
+
+    async fn load_all_keys(&self) -> Result<Vec<K>, StoreError>
+    where
+        OT: Readable,
+    {
+        time_store_operation(self.name, "load_all_keys", self.inner.load_all_keys()).await
+    }
+
+    async fn count_data(&self) -> Result<u64, StoreError>
+    where
+        OT: Readable,
+    {
+        time_store_operation(self.name, "count_data", self.inner.count_data()).await
+    }
+
+    async fn load_metadata(
+        &self,
+        key: &K,
+        metadata_key: &MetadataKey<MKT>,
+    ) -> Result<Option<Vec<u8>>, StoreError>
+    where
+        OT: Readable,
+    {
+        time_store_operation(
+            self.name,
+            "load_metadata",
+            self.inner.load_metadata(key, metadata_key),
+        )
+        .await
+    }
+
+    async fn store_metadata(
+        &self,
+        key: &K,
+        metadata_key: &MetadataKey<MKT>,
+        metadata_value: &dyn MetadataValue,
+    ) -> Result<(), StoreError>
+    where
+        OT: Writable,
+    {
+        time_store_operation(
+            self.name,
+            "store_metadata",
+            self.inner.store_metadata(key, metadata_key, metadata_value),
+        )
+        .await
+    }
+
+    async fn destroy_metadata(
+        &self,
+        key: &K,
+        metadata_key: &MetadataKey<MKT>,
+    ) -> Result<(), StoreError>
+    where
+        OT: Writable,
+    {
+        time_store_operation(
+            self.name,
+            "destroy_metadata",
+            self.inner.destroy_metadata(key, metadata_key),
+        )
+        .await
+    }
+
+    async fn query_data(&self) -> Result<Box<dyn fdo_store::FilterType<V, MKT>>, StoreError>
+    where
+        OT: Writable,
+    {
+        time_store_operation(self.name, "query_data", self.inner.query_data()).await
+    }
+
+    async fn query_ovs_db(&self) -> Result<Vec<OwnershipVoucher>, StoreError> {
+        time_store_operation(self.name, "query_ovs_db", self.inner.query_ovs_db()).await
+    }
+
+    async fn query_ovs_db_to2_performed_to0_less_than(
+        &self,
+        to2: bool,
+        to0_max: i64,
+    ) -> Result<Vec<OwnershipVoucher>, StoreError> {
+        time_store_operation(
+            self.name,
+            "query_ovs_db_to2_performed_to0_less_than",
+            self.inner
+                .query_ovs_db_to2_performed_to0_less_than(to2, to0_max),
+        )
+        .await
+    }
+
+    async fn store_data(&self, key: K, value: V) -> Result<(), StoreError>
+    where
+        OT: Writable,
+    {
+        time_store_operation(self.name, "store_data", self.inner.store_data(key, value)).await
+    }
+
+    async fn store_data_in_group(
+        &self,
+        key: K,
+        value: V,
+        owner_group: &str,
+        max_entries: Option<u64>,
+    ) -> Result<bool, StoreError>
+    where
+        OT: Writable,
+    {
+        time_store_operation(
+            self.name,
+            "store_data_in_group",
+            self.inner
+                .store_data_in_group(key, value, owner_group, max_entries),
+        )
+        .await
+    }
+
+    async fn destroy_data(&self, key: &K) -> Result<(), StoreError>
+    where
+        OT: Writable,
+    {
+        time_store_operation(self.name, "destroy_data", self.inner.destroy_data(key)).await
+    }
+
+    async fn perform_maintenance(&self) -> Result<(), StoreError>
+    where
+        OT: Writable,
+    {
+        time_store_operation(
+            self.name,
+            "perform_maintenance",
+            self.inner.perform_maintenance(),
+        )
+        .await
+    }
+}
+
+/// Binds the listener serving the metrics, separate from the FDO and admin routes
+pub fn serve_metrics(
+    addr: SocketAddr,
+) -> Result<impl Future<Output = ()> + Send + 'static, warp::Error> {
+    let (addr, server) = warp::serve(metrics_handler()).try_bind_ephemeral(addr)?;
+    log::info!("Serving metrics on {}", addr);
+    Ok(server)
+}
+
+pub fn metrics_handler() -> warp::filters::BoxedFilter<(warp::reply::Response,)> {
+    warp::get()
+        .and(warp::path("metrics"))
+        .and(warp::path::end())
+        .map(|| {
+            let encoder = TextEncoder::new();
+            let mut buffer = Vec::new();
+            if let Err(e) = encoder.encode(&prometheus::gather(), &mut buffer) {
+                log::error!("Error encoding metrics: {:?}", e);
+            }
+            warp::http::response::Response::builder()
+                .header("Content-Type", encoder.format_type())
+                .body(buffer.into())
+                .unwrap()
+        })
+        .boxed()
+}
+
+#[cfg(test)]
+mod test {
+    use fdo_store::{ReadWriteOpen, Store, StoreConfig};
+
+    use super::{super::SessionStoreMetadataKey, metrics_handler, timed_store};
+
+    fn run<F: std::future::Future>(future: F) -> F::Output {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(future)
+    }
+
+    fn operation_count(store: &str, operation: &str) -> u64 {
+        super::STORE_OPERATION_DURATION
+            .with_label_values(&[store, operation])
+            .get_sample_count()
+    }
+
+    #[test]
+    fn test_timed_store() {
+        let path = std::env::temp_dir().join(format!("fdo-metrics-test-{}", std::process::id()));
+        let store: Box<dyn Store<ReadWriteOpen, String, String, SessionStoreMetadataKey>> =
+            StoreConfig::Directory { path: path.clone() }
+                .initialize()
+                .unwrap();
+        let store = timed_store("test_store", store);
+
+        run(async {
+            store
+                .store_data("key".to_string(), "value".to_string())
+                .await
+                .unwrap();
+            assert_eq!(
+                store.load_data(&"key".to_string()).await.unwrap(),
+                Some("value".to_string())
+            );
+            assert_eq!(store.load_data(&"other".to_string()).await.unwrap(), None);
+            store.destroy_data(&"key".to_string()).await.unwrap();
+        });
+        std::fs::remove_dir_all(&path).unwrap();
+
+        assert_eq!(operation_count("test_store", "store_data"), 1);
+        assert_eq!(operation_count("test_store", "load_data"), 2);
+        assert_eq!(operation_count("test_store", "destroy_data"), 1);
+        assert_eq!(operation_count("test_store", "count_data"), 0);
+    }
+
+    #[test]
+    fn test_metrics_handler() {
+        super::record_request(fdo_data_formats::constants::MessageType::DIAppStart);
+
+        let response = run(warp::test::request()
+            .method("GET")
+            .path("/metrics")
+            .reply(&metrics_handler()));
+        assert_eq!(response.status(), 200);
+        let body = String::from_utf8(response.body().to_vec()).unwrap();
+        assert!(body.contains("fdo_requests_total{message_type=\"DIAppStart\"}"));
+
+        // Only GET /metrics is served
+        let response = run(warp::test::request()
+            .method("GET")
+            .path("/ping")
+            .reply(&metrics_handler()));
+        assert_eq!(response.status(), 404);
+    }
+}
@@ -187,17 +187,43 @@ async fn get_valid_iface_and_mac() -> Result<(String, String)> {
 
 #[tokio::test]
 async fn test_device_credentials_with_tpm() -> Result<()> {
+    device_credentials_with_tpm_impl("SECP256R1").await
+}
+
+#[tokio::test]
+async fn test_device_credentials_with_tpm_rsa_pkcs() -> Result<()> {
+    device_credentials_with_tpm_impl("RSAPKCS").await
+}
+
+#[tokio::test]
+async fn test_device_credentials_with_tpm_rsa_pss() -> Result<()> {
+    device_credentials_with_tpm_impl("RSAPSS").await
+}
+
+#[tokio::test]
+async fn test_device_credentials_with_tpm_rsa_restricted() -> Result<()> {
+    device_credentials_with_tpm_impl("RSA2048RESTR").await
+}
+
+async fn device_credentials_with_tpm_impl(device_key_type: &str) -> Result<()> {
     let ci = env::var("FDO_PRIVILEGED").is_ok();
     if !ci {
         L.l("Skipped test_device_credentials with TPM\nTo run this tet set env variable FDO_PRIVILEGED and run test as superuser");
         return Ok(());
     }
-    if std::fs::File::open("/dev/tpm0").is_ok() {
-        env::set_var("TEST_TCTI", "device:/dev/tpm0");
-    } else {
-        L.l("Skipped test_device_credentials with TPM\nTo run this test we need /dev/tpm0");
-        return Ok(());
-    }
+    // A TPM simulator can be used by setting TEST_TCTI, e.g. to "swtpm:port=2321"
+    let tcti = match env::var("TEST_TCTI") {
+        Ok(tcti) => tcti,
+        Err(_) => {
+            if std::fs::File::open("/dev/tpm0").is_ok() {
+                env::set_var("TEST_TCTI", "device:/dev/tpm0");
+                "device:/dev/tpm0".to_string()
+            } else {
+                L.l("Skipped test_device_credentials with TPM\nTo run this test we need /dev/tpm0 or TEST_TCTI");
+                return Ok(());
+            }
+        }
+    };
     let mut ctx = TestContext::new().context("Error building test context")?;
     let mfg_server = ctx
         .start_test_server(
@@ -206,6 +232,7 @@ async fn test_device_credentials_with_tpm() -> Result<()> {
                 Ok(cfg.prepare_config_file(None, |cfg| {
                     cfg.insert("rendezvous_port", "1337");
                     cfg.insert("diun_key_type", "Tpm");
+                    cfg.insert("diun_device_key_type", device_key_type);
                     cfg.insert("device_identification_format", "SerialNumber");
                     Ok(())
                 })?)
@@ -223,7 +250,8 @@ async fn test_device_credentials_with_tpm() -> Result<()> {
             |cfg| {
                 cfg.env("DEVICE_CREDENTIAL_FILENAME", "devicecredential.dc")
                     .env("MANUFACTURING_INFO", "testdevice")
-                    .env("DIUN_PUB_KEY_INSECURE", "true");
+                    .env("DIUN_PUB_KEY_INSECURE", "true")
+                    .env("TCTI", &tcti);
                 Ok(())
             },
             Duration::from_secs(5),
@@ -250,6 +250,26 @@ where
         _ => bail!("Unknown DIUN key type: {}", diun_key_type),
     }
 
+    let owner_output = ctx
+        .run_owner_tool(
+            client_result.client_path(),
+            &[
+                "dump-device-credential",
+                "--output",
+                "yaml",
+                dc_path.to_str().unwrap(),
+            ],
+        )
+        .context("Error running dump-device-credential with YAML output")?;
+    owner_output
+        .expect_success()
+        .context("Dump-device-credential with YAML output failed")?;
+    owner_output.expect_stdout_line("active: true")?;
+    match diun_key_type {
+        "Tpm" => owner_output.expect_stdout_line("type: tpm")?,
+        _ => owner_output.expect_stdout_line("type: plain")?,
+    }
+
     let ov_dir = ctx.testpath().join("ownership_vouchers");
     let mut ov_files =
         std::fs::read_dir(ov_dir).context("Error reading ownership voucher directory")?;
@@ -286,6 +306,96 @@ where
     // It should have been extended to the "owner" time by the manufacturer
     owner_output.expect_stdout_line("Entry 0")?;
 
+    let owner_output = ctx
+        .run_owner_tool(
+            client_result.client_path(),
+            &[
+                "dump-ownership-voucher",
+                "--output",
+                "json",
+                ov_file.path().to_str().unwrap(),
+            ],
+        )
+        .context("Error running dump-ownership-voucher with JSON output")?;
+    owner_output
+        .expect_success()
+        .context("Dump-ownership-voucher with JSON output failed")?;
+    owner_output.expect_stdout_line("\"device_info\": \"testdevice\"")?;
+    owner_output.expect_stdout_line("\"subject\": \"CN=testdevice")?;
+    owner_output.expect_stdout_line("\"previous_entry_hash\"")?;
+
+    let key_path = ctx.keys_path();
+    let owner_output = ctx
+        .run_owner_tool(
+            client_result.client_path(),
+            &[
+                "verify-ownership-voucher",
+                "--output",
+                "json",
+                &format!(
+                    "--trusted-manufacturer-keys={}",
+                    key_path.join("manufacturer_cert.pem").to_str().unwrap()
+                ),
+                &format!(
+                    "--expected-owner-cert={}",
+                    key_path.join("owner_cert.pem").to_str().unwrap()
+                ),
+                ov_file.path().to_str().unwrap(),
+            ],
+        )
+        .context("Error running verify-ownership-voucher")?;
+    owner_output
+        .expect_success()
+        .context("Verify-ownership-voucher failed")?;
+    owner_output.expect_stdout_line("\"valid\": true")?;
+    owner_output.expect_not_stdout_line("\"result\": \"failed\"")?;
+
+    // A voucher extended to another owner must not verify for the original owner
+    let extended_ov_path = ctx.testpath().join("extended.ov");
+    fs::copy(ov_file.path(), &extended_ov_path).context("Error copying ownership voucher")?;
+    let owner_output = ctx
+        .run_owner_tool(
+            client_result.client_path(),
+            &[
+                "extend-ownership-voucher",
+                extended_ov_path.to_str().unwrap(),
+                &format!(
+                    "--current-owner-private-key={}",
+                    key_path.join("owner_key.der").to_str().unwrap()
+                ),
+                &format!(
+                    "--new-owner-cert={}",
+                    key_path.join("reseller_cert.pem").to_str().unwrap()
+                ),
+            ],
+        )
+        .context("Error running extend-ownership-voucher")?;
+    owner_output
+        .expect_success()
+        .context("Extend-ownership-voucher failed")?;
+
+    let owner_output = ctx
+        .run_owner_tool(
+            client_result.client_path(),
+            &[
+                "verify-ownership-voucher",
+                "--output",
+                "json",
+                &format!(
+                    "--expected-owner-cert={}",
+                    key_path.join("owner_cert.pem").to_str().unwrap()
+                ),
+                extended_ov_path.to_str().unwrap(),
+            ],
+        )
+        .context("Error running verify-ownership-voucher on the extended voucher")?;
+    owner_output
+        .expect_failure()
+        .context("Verify-ownership-voucher succeeded for the wrong owner")?;
+    owner_output.expect_stdout_line("\"valid\": false")?;
+    owner_output.expect_stdout_line("\"check\": \"owner_key\"")?;
+    owner_output.expect_stdout_line("\"result\": \"failed\"")?;
+
     L.l("Adding disk encryption tests");
     L.l("Creating empty disk image");
     if !Command::new("truncate")
@@ -664,3 +774,290 @@ ssh-ed25519 sshkey_per_device user@example2.com
     env::set_var("PER_DEVICE_SERVICEINFO", "false");
     Ok(())
 }
+
+#[tokio::test]
+async fn test_e2e_coap() -> Result<()> {
+    env::set_var("PER_DEVICE_SERVICEINFO", "false");
+    let mut ctx = TestContext::new().context("Error building test context")?;
+    let rendezvous_server = ctx
+        .start_test_server(
+            Binary::RendezvousServer,
+            |cfg| {
+                Ok(cfg.prepare_config_file(None, |cfg| {
+                    cfg.insert("coap", &true);
+                    Ok(())
+                })?)
+            },
+            |_| Ok(()),
+        )
+        .context("Error creating rendezvous server")?;
+    let serviceinfo_api_server = ctx
+        .start_test_server(
+            Binary::ServiceInfoApiServer,
+            |cfg| Ok(cfg.prepare_config_file(None, |_| Ok(()))?),
+            |_| Ok(()),
+        )
+        .context("Error creating serviceinfo API dev server")?;
+    let owner_onboarding_server = ctx
+        .start_test_server(
+            Binary::OwnerOnboardingServer,
+            |cfg| {
+                Ok(cfg.prepare_config_file(None, |cfg| {
+                    cfg.insert(
+                        "serviceinfo_api_server_port",
+                        &serviceinfo_api_server.server_port().unwrap(),
+                    );
+                    cfg.insert("coap", &true);
+                    cfg.insert("owner_transport", "coap");
+                    Ok(())
+                })?)
+            },
+            |cmd| {
+                cmd.env("ALLOW_NONINTEROPERABLE_KDF", &"1");
+                Ok(())
+            },
+        )
+        .context("Error creating owner server")?;
+    let mfg_server = ctx
+        .start_test_server(
+            Binary::ManufacturingServer,
+            |cfg| {
+                Ok(cfg.prepare_config_file(None, |cfg| {
+                    cfg.insert("diun_key_type", "FileSystem");
+                    cfg.insert("rendezvous_port", &rendezvous_server.server_port().unwrap());
+                    cfg.insert("rendezvous_protocol", "coapudp");
+                    cfg.insert("device_identification_format", "SerialNumber");
+                    cfg.insert("coap", &true);
+                    Ok(())
+                })?)
+            },
+            |_| Ok(()),
+        )
+        .context("Error creating manufacturing server")?;
+    ctx.wait_until_servers_ready()
+        .await
+        .context("Error waiting for servers to start")?;
+
+    // The servers listen for CoAP on the UDP port with the number of their HTTP port
+    let client_result = ctx
+        .run_client(
+            Binary::ManufacturingClient,
+            Some(&mfg_server),
+            |cfg| {
+                cfg.env(
+                    "MANUFACTURING_SERVER_URL",
+                    format!("coap://localhost:{}", mfg_server.server_port().unwrap()),
+                )
+                .env("DEVICE_CREDENTIAL_FILENAME", "devicecredential.dc")
+                .env("MANUFACTURING_INFO", "testdevice")
+                .env("DIUN_PUB_KEY_INSECURE", "true");
+                Ok(())
+            },
+            Duration::from_secs(10),
+        )
+        .context("Error running manufacturing client")?;
+    client_result
+        .expect_success()
+        .context("Manufacturing client failed")?;
+    let dc_path = client_result.client_path().join("devicecredential.dc");
+
+    // The admin endpoints are not served over CoAP, TO0 itself is
+    let client = reqwest::Client::new();
+    let res = client
+        .post(format!(
+            "http://localhost:{}/report-to-rendezvous", // DevSkim: ignore DS137138
+            owner_onboarding_server.server_port().unwrap()
+        ))
+        .send()
+        .await?;
+    L.l(format!("Status code report-to-rendezvous {}", res.status()));
+
+    let ssh_authorized_keys_path = ctx.testpath().join("authorized_keys");
+    let marker_file_path = ctx.testpath().join("marker");
+    let binary_file_path_prefix = ctx.testpath().join("binary_files");
+
+    std::fs::create_dir(&binary_file_path_prefix).context("Error creating binary_files dir")?;
+
+    let output = ctx
+        .run_client(
+            Binary::ClientLinuxapp,
+            None,
+            |cfg| {
+                cfg.env("DEVICE_CREDENTIAL", dc_path.to_str().unwrap())
+                    .env("SSH_KEY_PATH", &ssh_authorized_keys_path.to_str().unwrap())
+                    .env(
+                        "BINARYFILE_PATH_PREFIX",
+                        binary_file_path_prefix.to_str().unwrap(),
+                    )
+                    .env(
+                        "DEVICE_ONBOARDING_EXECUTED_MARKER_FILE_PATH",
+                        &marker_file_path.to_str().unwrap(),
+                    )
+                    .env("ALLOW_NONINTEROPERABLE_KDF", &"1");
+                Ok(())
+            },
+            Duration::from_secs(60),
+        )
+        .context("Error running client")?;
+    output.expect_success().context("client failed")?;
+    output.expect_stderr_line(&format!(
+        "coap://localhost:{}",
+        owner_onboarding_server.server_port().unwrap()
+    ))?;
+
+    pretty_assertions::assert_eq!(
+        fs::read_to_string(&marker_file_path).context("Error reading marker file")?,
+        "executed"
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_e2e_rsa_device_key() -> Result<()> {
+    let mut failed = Vec::new();
+
+    for (device_key_type, key_bits) in [
+        ("RSA2048RESTR", "2048"),
+        ("RSAPKCS", "2048"),
+        ("RSAPKCS", "3072"),
+    ] {
+        L.l(format!(
+            "Starting test case, device_key_type: {device_key_type}, key_bits: {key_bits}"
+        ));
+        if let Err(e) = test_e2e_rsa_device_key_impl(device_key_type, key_bits).await {
+            L.l(format!("Test FAILED: {:?}", e));
+            failed.push((device_key_type, key_bits, e));
+        }
+    }
+
+    if failed.is_empty() {
+        Ok(())
+    } else {
+        for failed_case in failed {
+            eprintln!("Failed test: {:?}", failed_case);
+        }
+        bail!("Some tests failed");
+    }
+}
+
+async fn test_e2e_rsa_device_key_impl(device_key_type: &str, key_bits: &str) -> Result<()> {
+    env::set_var("PER_DEVICE_SERVICEINFO", "false");
+    let mut ctx = TestContext::new().context("Error building test context")?;
+    let rendezvous_server = ctx
+        .start_test_server(
+            Binary::RendezvousServer,
+            |cfg| Ok(cfg.prepare_config_file(None, |_| Ok(()))?),
+            |_| Ok(()),
+        )
+        .context("Error creating rendezvous server")?;
+    let serviceinfo_api_server = ctx
+        .start_test_server(
+            Binary::ServiceInfoApiServer,
+            |cfg| Ok(cfg.prepare_config_file(None, |_| Ok(()))?),
+            |_| Ok(()),
+        )
+        .context("Error creating serviceinfo API dev server")?;
+    let owner_onboarding_server = ctx
+        .start_test_server(
+            Binary::OwnerOnboardingServer,
+            |cfg| {
+                Ok(cfg.prepare_config_file(None, |cfg| {
+                    cfg.insert(
+                        "serviceinfo_api_server_port",
+                        &serviceinfo_api_server.server_port().unwrap(),
+                    );
+                    Ok(())
+                })?)
+            },
+            |cmd| {
+                cmd.env("ALLOW_NONINTEROPERABLE_KDF", &"1");
+                Ok(())
+            },
+        )
+        .context("Error creating owner server")?;
+    let mfg_server = ctx
+        .start_test_server(
+            Binary::ManufacturingServer,
+            |cfg| {
+                Ok(cfg.prepare_config_file(None, |cfg| {
+                    cfg.insert("diun_key_type", "FileSystem");
+                    cfg.insert("diun_device_key_type", device_key_type);
+                    cfg.insert("rendezvous_port", &rendezvous_server.server_port().unwrap());
+                    cfg.insert("device_identification_format", "SerialNumber");
+                    Ok(())
+                })?)
+            },
+            |_| Ok(()),
+        )
+        .context("Error creating manufacturing server")?;
+    ctx.wait_until_servers_ready()
+        .await
+        .context("Error waiting for servers to start")?;
+
+    let client_result = ctx
+        .run_client(
+            Binary::ManufacturingClient,
+            Some(&mfg_server),
+            |cfg| {
+                cfg.env("DEVICE_CREDENTIAL_FILENAME", "devicecredential.dc")
+                    .env("MANUFACTURING_INFO", "testdevice")
+                    .env("DIUN_PUB_KEY_INSECURE", "true")
+                    .env("DI_RSA_KEY_BITS", key_bits);
+                Ok(())
+            },
+            Duration::from_secs(10),
+        )
+        .context("Error running manufacturing client")?;
+    client_result
+        .expect_success()
+        .context("Manufacturing client failed")?;
+    let dc_path = client_result.client_path().join("devicecredential.dc");
+
+    let client = reqwest::Client::new();
+    let res = client
+        .post(format!(
+            "http://localhost:{}/report-to-rendezvous", // DevSkim: ignore DS137138
+            owner_onboarding_server.server_port().unwrap()
+        ))
+        .send()
+        .await?;
+    L.l(format!("Status code report-to-rendezvous {}", res.status()));
+
+    let ssh_authorized_keys_path = ctx.testpath().join("authorized_keys");
+    let marker_file_path = ctx.testpath().join("marker");
+    let binary_file_path_prefix = ctx.testpath().join("binary_files");
+
+    std::fs::create_dir(&binary_file_path_prefix).context("Error creating binary_files dir")?;
+
+    // TO1 and TO2 both have the device prove itself with its RSA key
+    let output = ctx
+        .run_client(
+            Binary::ClientLinuxapp,
+            None,
+            |cfg| {
+                cfg.env("DEVICE_CREDENTIAL", dc_path.to_str().unwrap())
+                    .env("SSH_KEY_PATH", &ssh_authorized_keys_path.to_str().unwrap())
+                    .env(
+                        "BINARYFILE_PATH_PREFIX",
+                        binary_file_path_prefix.to_str().unwrap(),
+                    )
+                    .env(
+                        "DEVICE_ONBOARDING_EXECUTED_MARKER_FILE_PATH",
+                        &marker_file_path.to_str().unwrap(),
+                    )
+                    .env("ALLOW_NONINTEROPERABLE_KDF", &"1");
+                Ok(())
+            },
+            Duration::from_secs(60),
+        )
+        .context("Error running client")?;
+    output.expect_success().context("client failed")?;
+
+    pretty_assertions::assert_eq!(
+        fs::read_to_string(&marker_file_path).context("Error reading marker file")?,
+        "executed"
+    );
+
+    Ok(())
+}
@@ -4,7 +4,7 @@ use std::{fs, os::unix::prelude::PermissionsExt, path::Path, time::Duration};
 use common::{Binary, LogSide, TestContext};
 
 use anyhow::{bail, Context, Result};
-use fdo_data_formats::{devicecredential::FileDeviceCredential, types::Guid};
+use fdo_data_formats::{devicecredential::FileDeviceCredential, ProtocolVersion};
 
 const L: LogSide = LogSide::Test;
 
@@ -16,8 +16,12 @@ async fn test_to() -> Result<()> {
         for server_noninteroperable_kdf in [true, false] {
             L.l(format!("Starting test case, client_noninteroperable_kdf: {:?}, server_noninteroperable_kdf: {:?}", client_noninteroperable_kdf, server_noninteroperable_kdf));
             L.l("********************************************************============================================================");
-            if let Err(e) =
-                test_to_impl(client_noninteroperable_kdf, server_noninteroperable_kdf).await
+            if let Err(e) = test_to_impl(
+                client_noninteroperable_kdf,
+                server_noninteroperable_kdf,
+                ProtocolVersion::Version1_1,
+            )
+            .await
             {
                 L.l(format!("Test FAILED: {:?}", e));
                 failed.push(TestCase {
@@ -41,6 +45,11 @@ async fn test_to() -> Result<()> {
     }
 }
 
+#[tokio::test]
+async fn test_to_v10() -> Result<()> {
+    test_to_impl(false, false, ProtocolVersion::Version1_0).await
+}
+
 #[derive(Debug)]
 struct TestCase {
     #[allow(dead_code)]
@@ -54,6 +63,7 @@ struct TestCase {
 async fn test_to_impl(
     client_noninteroperable_kdf: bool,
     server_noninteroperable_kdf: bool,
+    protocol_version: ProtocolVersion,
 ) -> Result<()> {
     let mut ctx = TestContext::new().context("Error building test context")?;
 
@@ -129,6 +139,7 @@ async fn test_to_impl(
                     "--rendezvous-info={}",
                     rendezvous_info_path.to_str().unwrap()
                 ),
+                &format!("--protocol-version={}", protocol_version),
                 "testdevice",
                 ov_path.to_str().unwrap(),
                 dc_path.to_str().unwrap(),
@@ -173,9 +184,16 @@ async fn test_to_impl(
         })?;
     }
 
-    let device_guid = determine_device_credential_guid(&dc_path)
-        .context("Error determining device GUID")?
-        .to_string();
+    let device_credential =
+        read_device_credential(&dc_path).context("Error reading device credential")?;
+    if device_credential.protver != protocol_version {
+        bail!(
+            "Device credential has protocol version {}, expected {}",
+            device_credential.protver,
+            protocol_version
+        );
+    }
+    let device_guid = device_credential.guid.to_string();
     L.l(format!("Device GUID: {:?}", device_guid));
 
     let ov_to = ctx.testpath().join("ownership_vouchers").join(&device_guid);
@@ -290,9 +308,7 @@ ssh-ed25519 sshkey_default user@example2.com
     Ok(())
 }
 
-fn determine_device_credential_guid(path: &Path) -> Result<Guid> {
+fn read_device_credential(path: &Path) -> Result<FileDeviceCredential> {
     let dc_contents = fs::read(path).context("Error reading device credential")?;
-    let dc: FileDeviceCredential =
-        serde_cbor::from_slice(&dc_contents).context("Error deserializing device credential")?;
-    Ok(dc.guid)
+    serde_cbor::from_slice(&dc_contents).context("Error deserializing device credential")
 }
@@ -5,7 +5,7 @@ use std::path::Path;
 use anyhow::{Context, Result};
 
 use fdo_data_formats::{
-    ownershipvoucher::OwnershipVoucher, DeserializableMany, Error, ProtocolVersion,
+    ownershipvoucher::OwnershipVoucher, DeserializableMany, ProtocolVersion, Serializable,
 };
 
 fn test_single_voucher(_path: &Path, voucher: &[u8]) -> Result<()> {
@@ -21,6 +21,15 @@ fn test_single_voucher(_path: &Path, voucher: &[u8]) -> Result<()> {
         println!("Entry: {:?}", entry.context("Error parsing OV Entry")?);
     }
 
+    // The full verification must agree with the iterator
+    let report = voucher.verify(None, None, None, None);
+    for result in report.checks() {
+        println!("{}", result);
+    }
+    if !report.is_valid() || report.owner_public_key().is_none() {
+        anyhow::bail!("Voucher verification failed");
+    }
+
     Ok(())
 }
 
@@ -100,16 +109,18 @@ fn test_multiple_vouchers_pem() -> Result<()> {
     Ok(())
 }
 
-// We explicitly decided to drop support for the old format.
 #[test]
 fn test_voucher_v100() -> Result<()> {
-    let success = execute_for_each_voucher("vouchers/v100/", true, |success, _path, voucher| {
-        let voucher_result = OwnershipVoucher::from_pem(&voucher);
+    let success = execute_for_each_voucher("vouchers/v100/", true, |success, path, voucher| {
+        if test_single_voucher(&path, voucher).is_err() {
+            return Ok(false);
+        }
+        let parsed = OwnershipVoucher::from_pem(voucher)?;
+        // 1.0 vouchers must be written back in their original format
+        let original = pem::parse(voucher)?;
         Ok(success
-            && matches!(
-                voucher_result,
-                Err(Error::UnsupportedVersion(Some(ProtocolVersion::Version1_0)))
-            ))
+            && parsed.header().protocol_version() == ProtocolVersion::Version1_0
+            && parsed.serialize_data()? == original.contents())
     })
     .context("Error running tests")?;
 
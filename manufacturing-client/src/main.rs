@@ -535,12 +535,38 @@ enum KeyReference {
     },
 }
 
-fn semi_tpm_hmac_key_template(keytype: PublicKeyType) -> Result<tss_esapi::structures::Public> {
-    let hash_algo = match keytype {
-        PublicKeyType::SECP256R1 => HashingAlgorithm::Sha256,
-        PublicKeyType::SECP384R1 => HashingAlgorithm::Sha384,
+// RSA2048RESTR keys are always 2048 bits, RSAPKCS and RSAPSS keys can be 2048 or 3072 bits
+fn rsa_key_bits(keytype: PublicKeyType) -> Result<u32> {
+    match keytype {
+        PublicKeyType::Rsa2048RESTR => Ok(2048),
+        PublicKeyType::RsaPkcs | PublicKeyType::RsaPss => match env::var("DI_RSA_KEY_BITS") {
+            Ok(bits) => match bits.as_str() {
+                "2048" => Ok(2048),
+                "3072" => Ok(3072),
+                _ => bail!("Unsupported RSA key size {bits}, must be 2048 or 3072"),
+            },
+            Err(_) => Ok(3072),
+        },
+        _ => bail!("Key type {:?} is not an RSA key type", keytype),
+    }
+}
+
+fn device_key_hash_algo(keytype: PublicKeyType) -> Result<HashingAlgorithm> {
+    match keytype {
+        PublicKeyType::SECP256R1 => Ok(HashingAlgorithm::Sha256),
+        PublicKeyType::SECP384R1 => Ok(HashingAlgorithm::Sha384),
+        PublicKeyType::Rsa2048RESTR | PublicKeyType::RsaPkcs | PublicKeyType::RsaPss => {
+            match rsa_key_bits(keytype)? {
+                2048 => Ok(HashingAlgorithm::Sha256),
+                _ => Ok(HashingAlgorithm::Sha384),
+            }
+        }
         _ => bail!("Unsupported key type {:?}", keytype),
-    };
+    }
+}
+
+fn semi_tpm_hmac_key_template(keytype: PublicKeyType) -> Result<tss_esapi::structures::Public> {
+    let hash_algo = device_key_hash_algo(keytype)?;
     let primary_attributes = ObjectAttributesBuilder::new()
         .with_fixed_tpm(true)
         .with_fixed_parent(true)
@@ -567,12 +593,14 @@ fn semi_tpm_hmac_key_template(keytype: PublicKeyType) -> Result<tss_esapi::struc
 }
 
 fn semi_tpm_signing_key_template(key_type: PublicKeyType) -> Result<tss_esapi::structures::Public> {
+    // Only RSA2048RESTR is defined as a restricted key, all others need to sign arbitrary digests
+    let restricted = matches!(key_type, PublicKeyType::Rsa2048RESTR);
     let primary_attributes = ObjectAttributesBuilder::new()
         .with_fixed_tpm(true)
         .with_fixed_parent(true)
         .with_user_with_auth(true)
         .with_sensitive_data_origin(true)
-        .with_restricted(false)
+        .with_restricted(restricted)
         .with_sign_encrypt(true)
         .build()
         .context("Error creating object attributes")?;
@@ -606,6 +634,38 @@ fn semi_tpm_signing_key_template(key_type: PublicKeyType) -> Result<tss_esapi::s
                 ))
                 .with_ecc_unique_identifier(Default::default())
         }
+        PublicKeyType::Rsa2048RESTR | PublicKeyType::RsaPkcs | PublicKeyType::RsaPss => {
+            let key_bits = match rsa_key_bits(key_type)? {
+                2048 => tss_esapi::interface_types::key_bits::RsaKeyBits::Rsa2048,
+                _ => tss_esapi::interface_types::key_bits::RsaKeyBits::Rsa3072,
+            };
+            let hash_algo = device_key_hash_algo(key_type)?;
+            let scheme = match key_type {
+                PublicKeyType::RsaPss => tss_esapi::structures::RsaScheme::RsaPss(
+                    tss_esapi::structures::HashScheme::new(hash_algo),
+                ),
+                _ => tss_esapi::structures::RsaScheme::RsaSsa(
+                    tss_esapi::structures::HashScheme::new(hash_algo),
+                ),
+            };
+            builder
+                .with_public_algorithm(tss_esapi::interface_types::algorithm::PublicAlgorithm::Rsa)
+                .with_name_hashing_algorithm(
+                    tss_esapi::interface_types::algorithm::HashingAlgorithm::Sha256,
+                )
+                .with_rsa_parameters(
+                    tss_esapi::structures::PublicRsaParametersBuilder::new()
+                        .with_scheme(scheme)
+                        .with_key_bits(key_bits)
+                        .with_exponent(tss_esapi::structures::RsaExponent::default())
+                        .with_is_signing_key(true)
+                        .with_is_decryption_key(false)
+                        .with_restricted(restricted)
+                        .build()
+                        .context("Error creating RSA parameters")?,
+                )
+                .with_rsa_unique_identifier(Default::default())
+        }
         _ => bail!("Unsupported key type {:?}", key_type),
     }
     .build()
@@ -636,6 +696,18 @@ impl KeyReference {
                     hmac_key: hmac_key_buf.to_vec(),
                 })
             }
+            PublicKeyType::Rsa2048RESTR | PublicKeyType::RsaPkcs => {
+                let bits = rsa_key_bits(keytype)?;
+                let sign_key =
+                    PKey::from_rsa(Rsa::generate(bits).context("Error generating RSA key")?)
+                        .context("Error creating RSA key")?;
+                Ok(KeyReference::FileSystem {
+                    sign_key,
+                    hmac_key: hmac_key_buf.to_vec(),
+                })
+            }
+            // Plain keys do not record the signature scheme, so they always use PKCS#1 v1.5
+            PublicKeyType::RsaPss => bail!("RSA-PSS keys are only supported in the TPM"),
             _ => bail!("Key type not supported"),
         }
     }
@@ -1,7 +1,5 @@
-use std::collections::BTreeMap;
 use std::convert::{TryFrom, TryInto};
 use std::fs;
-use std::str::FromStr;
 use std::sync::Arc;
 
 use anyhow::{bail, Context, Error, Result};
@@ -9,12 +7,11 @@ use openssl::{
     pkey::{PKey, Private},
     x509::X509,
 };
-use serde_yaml::Value;
 use tokio::signal::unix::{signal, SignalKind};
 use warp::Filter;
 
 use fdo_data_formats::{
-    constants::{KeyStorageType, MfgStringType, PublicKeyType, RendezvousVariable},
+    constants::{KeyStorageType, MfgStringType, PublicKeyType},
     ownershipvoucher::OwnershipVoucher,
     publickey::{PublicKey, X5Chain},
     types::{Guid, RendezvousInfo},
@@ -23,7 +20,7 @@ use fdo_data_formats::{
 use fdo_store::Store;
 use fdo_util::servers::{
     configuration::manufacturing_server::{DiunSettings, ManufacturingServerSettings},
-    settings_for, yaml_to_cbor, OwnershipVoucherStoreMetadataKey,
+    load_rendezvous_info, settings_for, OwnershipVoucherStoreMetadataKey,
 };
 
 const PERFORMED_DIUN_SES_KEY: &str = "mfg_global_diun_performed";
@@ -115,29 +112,6 @@ impl TryFrom<DiunSettings> for DiunConfiguration {
     }
 }
 
-fn load_rendezvous_info(rvs: &[BTreeMap<String, Value>]) -> Result<RendezvousInfo> {
-    let mut info = Vec::new();
-    for val in rvs {
-        let mut entry = Vec::new();
-
-        for (key, val) in val.iter() {
-            let key = RendezvousVariable::from_str(key)
-                .with_context(|| format!("Error parsing rendezvous key '{key}'"))?;
-
-            let val = yaml_to_cbor(val)?;
-            let val = key
-                .value_from_human_to_machine(val)
-                .with_context(|| format!("Error parsing value for key '{key:?}'"))?;
-
-            entry.push((key, val));
-        }
-
-        info.push(entry);
-    }
-
-    RendezvousInfo::new(info).context("Error serializing rendezvous info")
-}
-
 const MAINTENANCE_INTERVAL: u64 = 60;
 
 async fn perform_maintenance(
@@ -176,6 +150,9 @@ async fn main() -> Result<()> {
 
     // Bind information
     let bind_addr = settings.bind.clone();
+    let coap_bind = settings.coap_bind.clone();
+    let metrics_bind = settings.metrics_bind.clone();
+    let tls_settings = settings.tls;
 
     // Initialize stores
     let session_store = settings
@@ -187,21 +164,26 @@ async fn main() -> Result<()> {
         .ownership_voucher_store_driver
         .initialize()
         .context("Error initializing ownership voucher store")?;
+    let ownership_voucher_store = fdo_http_wrapper::server::metrics::timed_store(
+        "ownership_vouchers",
+        ownership_voucher_store,
+    );
     let public_key_store = match settings.public_key_store_driver {
         None => None,
-        Some(driver) => Some(
+        Some(driver) => Some(fdo_http_wrapper::server::metrics::timed_store(
+            "public_keys",
             driver
                 .initialize()
                 .context("Error initializing public key store")?,
-        ),
+        )),
     };
 
     // Read keys and certificates
-    let device_cert_key = PKey::private_key_from_der(
-        &fs::read(settings.manufacturing.device_cert_ca_private_key)
-            .context("Error reading device CA private key")?,
-    )
-    .context("Error parsing device CA private key")?;
+    let device_cert_key = settings
+        .manufacturing
+        .device_cert_ca_private_key
+        .load()
+        .context("Error loading device CA private key")?;
     let device_cert_chain = X5Chain::new(
         X509::stack_from_pem(
             &fs::read(settings.manufacturing.device_cert_ca_chain)
@@ -218,11 +200,10 @@ async fn main() -> Result<()> {
 
     let manufacturer_key = match settings.manufacturing.manufacturer_private_key {
         None => None,
-        Some(path) => Some(
-            PKey::private_key_from_der(
-                &fs::read(path).context("Error reading manufacturer private key")?,
-            )
-            .context("Error parsing manufacturer private key")?,
+        Some(location) => Some(
+            location
+                .load()
+                .context("Error loading manufacturer private key")?,
         ),
     };
     let owner_cert = match settings.manufacturing.owner_cert_path {
@@ -304,33 +285,47 @@ async fn main() -> Result<()> {
         handlers::diun::provide_key,
     );
 
+    // The FDO protocol messages, the only routes also served over CoAP
+    let fdo_routes = handler_di_app_start
+        .or(handler_di_set_hmac)
+        // DIUN
+        .or(handler_diun_connect)
+        .or(handler_diun_request_key_parameters)
+        .or(handler_diun_provide_key);
+
     let routes = warp::post()
-        .and(
-            hello
-                .or(handler_ping)
-                // DI
-                .or(handler_di_app_start)
-                .or(handler_di_set_hmac)
-                // DIUN
-                .or(handler_diun_connect)
-                .or(handler_diun_request_key_parameters)
-                .or(handler_diun_provide_key),
-        )
+        .and(hello.or(handler_ping).or(fdo_routes.clone()))
+        .recover(fdo_http_wrapper::server::handle_rejection)
+        .with(warp::log("manufacturing-server"));
+    let coap_routes = fdo_routes
         .recover(fdo_http_wrapper::server::handle_rejection)
         .with(warp::log("manufacturing-server"));
 
     log::info!("Listening on {}", bind_addr);
-    let server = warp::serve(routes);
-
+    if let Some(metrics_bind) = metrics_bind {
+        let metrics_server = fdo_http_wrapper::server::metrics::serve_metrics(metrics_bind.into())
+            .context("Error binding metrics listener")?;
+        tokio::spawn(metrics_server);
+    }
+    if let Some(coap_bind) = coap_bind {
+        log::info!("Listening for CoAP on {}", coap_bind);
+        let coap_server = fdo_http_wrapper::server::coap::CoapServer::bind(
+            coap_bind.into(),
+            warp::service(coap_routes),
+        )
+        .await
+        .context("Error binding CoAP listener")?;
+        tokio::spawn(coap_server.run());
+    }
     let maintenance_runner =
         tokio::spawn(async move { perform_maintenance(user_data.clone()).await });
 
-    let server = server
-        .bind_with_graceful_shutdown(bind_addr, async {
-            signal(SignalKind::terminate()).unwrap().recv().await;
-            log::info!("Terminating");
-        })
-        .1;
+    let shutdown = async {
+        signal(SignalKind::terminate()).unwrap().recv().await;
+        log::info!("Terminating");
+    };
+    let (_, server) =
+        fdo_http_wrapper::server::serve(routes, bind_addr, tls_settings.map(Into::into), shutdown);
     let server = tokio::spawn(server);
 
     tokio::select!(
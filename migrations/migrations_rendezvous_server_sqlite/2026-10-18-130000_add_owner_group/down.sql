@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+
+DROP INDEX rendezvous_vouchers_owner_group;
+ALTER TABLE rendezvous_vouchers DROP COLUMN owner_group;
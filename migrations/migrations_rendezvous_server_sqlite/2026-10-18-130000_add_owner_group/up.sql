@@ -0,0 +1,4 @@
+-- Your SQL goes here
+
+ALTER TABLE rendezvous_vouchers ADD COLUMN owner_group varchar(255);
+CREATE INDEX rendezvous_vouchers_owner_group ON rendezvous_vouchers (owner_group);
@@ -0,0 +1,7 @@
+-- Your SQL goes here
+
+CREATE TABLE sessions (
+    id varchar(128) NOT NULL PRIMARY KEY,
+    contents bytea NOT NULL,
+    ttl bigint
+);
@@ -0,0 +1,5 @@
+CREATE TABLE sessions (
+    id varchar(128) NOT NULL PRIMARY KEY,
+    contents blob NOT NULL,
+    ttl bigint
+);
@@ -0,0 +1,552 @@
+use std::collections::HashSet;
+use std::convert::TryInto;
+use std::str::FromStr;
+
+use anyhow::{bail, Context, Result};
+use serde::Serialize;
+
+use fdo_data_formats::{ownershipvoucher::OwnershipVoucher, types::Guid};
+use fdo_store::MetadataKey;
+use fdo_util::servers::OwnershipVoucherStoreMetadataKey;
+
+use crate::OwnerServiceUDT;
+
+pub(crate) async fn admin_auth_handler(
+    user_data: OwnerServiceUDT,
+    auth_header: String,
+) -> Result<OwnerServiceUDT, warp::Rejection> {
+    match &user_data.admin_auth_token {
+        None => {
+            log::warn!("Admin API server disabled");
+            return Err(warp::reject::reject());
+        }
+        Some(token) => {
+            if token != &auth_header {
+                log::warn!("Request with invalid auth token");
+                return Err(warp::reject::reject());
+            }
+        }
+    }
+
+    Ok(user_data)
+}
+
+#[derive(Debug, Serialize)]
+struct AdminV0OwnershipVoucherState {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+
+    guid: String,
+    device_info: String,
+    to2_performed: Option<bool>,
+    to0_accept_owner_wait_seconds: Option<i64>,
+    owner_key_in_keyring: Option<bool>,
+}
+
+impl AdminV0OwnershipVoucherState {
+    /// The state of a voucher whose state could not be determined
+    fn error(ov: &OwnershipVoucher, error: anyhow::Error) -> Self {
+        AdminV0OwnershipVoucherState {
+            error: Some(format!("{error:#}")),
+
+            guid: ov.header().guid().to_string(),
+            device_info: ov.header().device_info().to_string(),
+            to2_performed: None,
+            to0_accept_owner_wait_seconds: None,
+            owner_key_in_keyring: None,
+        }
+    }
+}
+
+async fn ownership_voucher_state(
+    user_data: &OwnerServiceUDT,
+    ov: &OwnershipVoucher,
+) -> Result<AdminV0OwnershipVoucherState> {
+    let guid = ov.header().guid();
+    let to2_performed = user_data
+        .ownership_voucher_store
+        .load_metadata(
+            guid,
+            &MetadataKey::Local(OwnershipVoucherStoreMetadataKey::To2Performed),
+        )
+        .await?
+        .map(|val| val == b"true");
+    let to0_accept_owner_wait_seconds = match user_data
+        .ownership_voucher_store
+        .load_metadata(
+            guid,
+            &MetadataKey::Local(OwnershipVoucherStoreMetadataKey::To0AcceptOwnerWaitSeconds),
+        )
+        .await?
+    {
+        None => None,
+        Some(val) => {
+            // The store keeps the time at which the registration expires
+            let expires_at = i64::from_le_bytes(
+                val.as_slice()
+                    .try_into()
+                    .with_context(|| format!("Invalid TO0 metadata stored for {guid}"))?,
+            );
+            let now = time::OffsetDateTime::now_utc().unix_timestamp();
+            Some((expires_at - now).max(0))
+        }
+    };
+
+    let owner_key_in_keyring = user_data
+        .owner_keyring
+        .key_for_ov(ov)
+        .context("Error determining the owner key")?
+        .is_some();
+
+    Ok(AdminV0OwnershipVoucherState {
+        error: None,
+
+        guid: guid.to_string(),
+        device_info: ov.header().device_info().to_string(),
+        to2_performed,
+        to0_accept_owner_wait_seconds,
+        owner_key_in_keyring: Some(owner_key_in_keyring),
+    })
+}
+
+#[derive(Debug, Serialize)]
+struct AdminV0ListReply {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+
+    success: bool,
+
+    ownership_vouchers: Vec<AdminV0OwnershipVoucherState>,
+}
+
+pub(crate) async fn admin_v0_list_handler(
+    user_data: OwnerServiceUDT,
+) -> Result<warp::reply::Json, warp::Rejection> {
+    let list = async {
+        let mut ownership_vouchers = Vec::new();
+        for ov in user_data.ownership_voucher_store.load_all_data().await? {
+            // A single broken voucher should not hide all the others
+            let state = match ownership_voucher_state(&user_data, &ov).await {
+                Ok(state) => state,
+                Err(e) => {
+                    log::warn!(
+                        "Admin API: error determining state of {}: {:#}",
+                        ov.header().guid(),
+                        e
+                    );
+                    AdminV0OwnershipVoucherState::error(&ov, e)
+                }
+            };
+            ownership_vouchers.push(state);
+        }
+        Ok::<_, anyhow::Error>(ownership_vouchers)
+    };
+
+    Ok(match list.await {
+        Ok(ownership_vouchers) => warp::reply::json(&AdminV0ListReply {
+            error: None,
+            success: true,
+            ownership_vouchers,
+        }),
+        Err(e) => warp::reply::json(&AdminV0ListReply {
+            error: Some(format!("{e:#}")),
+            success: false,
+            ownership_vouchers: Vec::new(),
+        }),
+    })
+}
+
+#[derive(Debug, Serialize)]
+struct AdminV0UploadReply {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+
+    success: bool,
+
+    guids: Vec<String>,
+}
+
+fn parse_ownership_vouchers(contents: &[u8]) -> Result<Vec<OwnershipVoucher>> {
+    if contents.starts_with(b"-----") {
+        OwnershipVoucher::many_from_pem(contents).context("Error parsing PEM ownership vouchers")
+    } else {
+        Ok(vec![OwnershipVoucher::from_pem_or_raw(contents)
+            .context("Error parsing COSE ownership voucher")?])
+    }
+}
+
+pub(crate) async fn admin_v0_upload_handler(
+    user_data: OwnerServiceUDT,
+    contents: warp::hyper::body::Bytes,
+) -> Result<warp::reply::Json, warp::Rejection> {
+    // Everything is checked before the first voucher is stored, so that a
+    // rejected upload does not leave part of it behind. If storing fails
+    // anyway, the reply lists the vouchers that did get stored.
+    let mut guids = Vec::new();
+    let upload = async {
+        let ovs = parse_ownership_vouchers(&contents)?;
+        let mut seen = HashSet::new();
+        for ov in &ovs {
+            let guid = ov.header().guid();
+            if !seen.insert(guid.clone()) {
+                bail!("Ownership voucher for {guid} is included more than once");
+            }
+            if user_data
+                .ownership_voucher_store
+                .load_data(guid)
+                .await?
+                .is_some()
+            {
+                bail!("Ownership voucher for {guid} already exists");
+            }
+        }
+        for ov in ovs {
+            let guid = ov.header().guid().clone();
+            user_data
+                .ownership_voucher_store
+                .store_data(guid.clone(), ov)
+                .await
+                .with_context(|| format!("Error storing ownership voucher for {guid}"))?;
+            log::info!("Admin API: imported ownership voucher for {guid}");
+            guids.push(guid.to_string());
+        }
+        Ok::<_, anyhow::Error>(())
+    };
+
+    Ok(match upload.await {
+        Ok(()) => warp::reply::json(&AdminV0UploadReply {
+            error: None,
+            success: true,
+            guids,
+        }),
+        Err(e) => warp::reply::json(&AdminV0UploadReply {
+            error: Some(format!("{e:#}")),
+            success: false,
+            guids,
+        }),
+    })
+}
+
+async fn load_ownership_voucher(
+    user_data: &OwnerServiceUDT,
+    guid: &str,
+) -> Result<OwnershipVoucher> {
+    let guid = Guid::from_str(guid)?;
+    match user_data.ownership_voucher_store.load_data(&guid).await? {
+        Some(ov) => Ok(ov),
+        None => bail!("No ownership voucher found for {guid}"),
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct AdminV0GetReply {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+
+    success: bool,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    state: Option<AdminV0OwnershipVoucherState>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    ownership_voucher: Option<String>,
+}
+
+pub(crate) async fn admin_v0_get_handler(
+    user_data: OwnerServiceUDT,
+    guid: String,
+) -> Result<warp::reply::Json, warp::Rejection> {
+    let get = async {
+        let ov = load_ownership_voucher(&user_data, &guid).await?;
+        let state = ownership_voucher_state(&user_data, &ov).await?;
+        Ok::<_, anyhow::Error>((state, ov.to_pem()?))
+    };
+
+    Ok(match get.await {
+        Ok((state, ownership_voucher)) => warp::reply::json(&AdminV0GetReply {
+            error: None,
+            success: true,
+            state: Some(state),
+            ownership_voucher: Some(ownership_voucher),
+        }),
+        Err(e) => warp::reply::json(&AdminV0GetReply {
+            error: Some(format!("{e:#}")),
+            success: false,
+            state: None,
+            ownership_voucher: None,
+        }),
+    })
+}
+
+#[derive(Debug, Serialize)]
+struct AdminV0Reply {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+
+    success: bool,
+}
+
+impl From<Result<()>> for AdminV0Reply {
+    fn from(res: Result<()>) -> Self {
+        match res {
+            Ok(()) => AdminV0Reply {
+                error: None,
+                success: true,
+            },
+            Err(e) => AdminV0Reply {
+                error: Some(format!("{e:#}")),
+                success: false,
+            },
+        }
+    }
+}
+
+pub(crate) async fn admin_v0_delete_handler(
+    user_data: OwnerServiceUDT,
+    guid: String,
+) -> Result<warp::reply::Json, warp::Rejection> {
+    let delete = async {
+        let ov = load_ownership_voucher(&user_data, &guid).await?;
+        user_data
+            .ownership_voucher_store
+            .destroy_data(ov.header().guid())
+            .await?;
+        log::info!("Admin API: deleted ownership voucher for {guid}");
+        Ok::<_, anyhow::Error>(())
+    };
+
+    Ok(warp::reply::json(&AdminV0Reply::from(delete.await)))
+}
+
+pub(crate) async fn admin_v0_report_to_rendezvous_handler(
+    user_data: OwnerServiceUDT,
+    guid: String,
+) -> Result<warp::reply::Json, warp::Rejection> {
+    let report = async {
+        let ov = load_ownership_voucher(&user_data, &guid).await?;
+        let wait_seconds = crate::report_ov_to_rendezvous(
+            &ov,
+            &user_data.owner_addresses,
+            &user_data.owner_keyring,
+            user_data.ov_registration_period,
+            &user_data.trusted_device_keys,
+        )
+        .await
+        .context("Error reporting to rendezvous")?;
+        user_data
+            .ownership_voucher_store
+            .store_metadata(
+                ov.header().guid(),
+                &MetadataKey::Local(OwnershipVoucherStoreMetadataKey::To0AcceptOwnerWaitSeconds),
+                &time::Duration::new(wait_seconds.into(), 0),
+            )
+            .await?;
+        log::info!("Admin API: reported {guid} to rendezvous, registered for {wait_seconds}s");
+        Ok::<_, anyhow::Error>(())
+    };
+
+    Ok(warp::reply::json(&AdminV0Reply::from(report.await)))
+}
+
+#[cfg(test)]
+mod test {
+    use std::{path::Path, sync::Arc};
+
+    use fdo_data_formats::types::DEFAULT_MAX_SERVICE_INFO_LOOPS;
+    use fdo_http_wrapper::{
+        client::{JsonAuthentication, JsonClient},
+        server::SessionStore,
+    };
+    use fdo_store::StoreConfig;
+    use fdo_util::servers::configuration::owner_onboarding_server::{
+        DEFAULT_REGISTRATION_PERIOD, DEFAULT_RE_REGISTRATION_WINDOW,
+    };
+    use serde_json::Value;
+
+    use super::*;
+    use crate::{
+        keyring::{
+            test::{build_voucher, generate_key},
+            OwnerKey, OwnerKeyring,
+        },
+        OwnerServiceUD,
+    };
+
+    fn test_user_data(dir: &Path, owner_key: OwnerKey) -> OwnerServiceUDT {
+        let owner2_key = generate_key("Owner2");
+        Arc::new(OwnerServiceUD {
+            trusted_device_keys: None,
+            ownership_voucher_store: StoreConfig::Directory {
+                path: dir.join("ownership_vouchers"),
+            }
+            .initialize()
+            .unwrap(),
+            session_store: SessionStore::new(
+                StoreConfig::Directory {
+                    path: dir.join("sessions"),
+                }
+                .initialize()
+                .unwrap(),
+            ),
+            owner_keyring: OwnerKeyring::new(owner_key, Vec::new()),
+            owner2_key: owner2_key.private_key,
+            owner2_pub: owner2_key.public_key,
+            replacement_rendezvous_info: None,
+            replacement_ownership_voucher_store: None,
+            service_info_api_client: JsonClient::new(
+                "http://localhost:8089/device_info".to_string(),
+                JsonAuthentication::None,
+            )
+            .unwrap(),
+            owner_addresses: Vec::new(),
+            ov_registration_period: DEFAULT_REGISTRATION_PERIOD,
+            ov_re_registration_window: DEFAULT_RE_REGISTRATION_WINDOW,
+            window_check_enabled: false,
+            max_service_info_loops: DEFAULT_MAX_SERVICE_INFO_LOOPS,
+            admin_auth_token: Some("Bearer TestAdminToken".to_string()),
+        })
+    }
+
+    async fn reply_json(reply: warp::reply::Json) -> Value {
+        let body = warp::Reply::into_response(reply).into_body();
+        serde_json::from_slice(&warp::hyper::body::to_bytes(body).await.unwrap()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_admin_auth() {
+        let dir = tempfile::tempdir().unwrap();
+        let user_data = test_user_data(dir.path(), generate_key("Owner"));
+
+        assert!(
+            admin_auth_handler(user_data.clone(), "Bearer TestAdminToken".to_string())
+                .await
+                .is_ok()
+        );
+        assert!(
+            admin_auth_handler(user_data, "Bearer WrongToken".to_string())
+                .await
+                .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_admin_ownership_vouchers() {
+        let dir = tempfile::tempdir().unwrap();
+        let manufacturer_key = generate_key("Manufacturer");
+        let owner_key = generate_key("Owner");
+        let ov = build_voucher(&manufacturer_key, &owner_key);
+        let other_ov = build_voucher(&manufacturer_key, &generate_key("Other owner"));
+        let guid = ov.header().guid().to_string();
+        let user_data = test_user_data(dir.path(), owner_key);
+
+        let mut pem = ov.to_pem().unwrap();
+        pem.push_str(&other_ov.to_pem().unwrap());
+        let reply = reply_json(
+            admin_v0_upload_handler(user_data.clone(), pem.clone().into())
+                .await
+                .unwrap(),
+        )
+        .await;
+        assert_eq!(reply["success"], true, "{reply}");
+        assert_eq!(reply["guids"][0], guid);
+
+        // Existing vouchers are not overwritten
+        let reply = reply_json(
+            admin_v0_upload_handler(user_data.clone(), pem.into())
+                .await
+                .unwrap(),
+        )
+        .await;
+        assert_eq!(reply["success"], false);
+        assert!(reply["error"].as_str().unwrap().contains("already exists"));
+
+        // A rejected upload stores none of its vouchers
+        let new_ov = build_voucher(&manufacturer_key, &generate_key("New owner"));
+        let mut pem = new_ov.to_pem().unwrap();
+        pem.push_str(&ov.to_pem().unwrap());
+        let reply = reply_json(
+            admin_v0_upload_handler(user_data.clone(), pem.into())
+                .await
+                .unwrap(),
+        )
+        .await;
+        assert_eq!(reply["success"], false);
+        assert_eq!(reply["guids"], Value::Array(Vec::new()));
+        assert!(user_data
+            .ownership_voucher_store
+            .load_data(new_ov.header().guid())
+            .await
+            .unwrap()
+            .is_none());
+        let mut pem = new_ov.to_pem().unwrap();
+        pem.push_str(&new_ov.to_pem().unwrap());
+        let reply = reply_json(
+            admin_v0_upload_handler(user_data.clone(), pem.into())
+                .await
+                .unwrap(),
+        )
+        .await;
+        assert_eq!(reply["success"], false);
+        assert!(reply["error"]
+            .as_str()
+            .unwrap()
+            .contains("included more than once"));
+
+        // The wait seconds are reported relative to now
+        user_data
+            .ownership_voucher_store
+            .store_metadata(
+                ov.header().guid(),
+                &MetadataKey::Local(OwnershipVoucherStoreMetadataKey::To0AcceptOwnerWaitSeconds),
+                &time::Duration::new(100, 0),
+            )
+            .await
+            .unwrap();
+        let reply = reply_json(admin_v0_list_handler(user_data.clone()).await.unwrap()).await;
+        assert_eq!(reply["success"], true);
+        let vouchers = reply["ownership_vouchers"].as_array().unwrap();
+        assert_eq!(vouchers.len(), 2);
+        for state in vouchers {
+            if state["guid"] == guid {
+                let wait_seconds = state["to0_accept_owner_wait_seconds"].as_i64().unwrap();
+                assert!(wait_seconds > 0 && wait_seconds <= 100);
+                assert_eq!(state["owner_key_in_keyring"], true);
+            } else {
+                assert_eq!(state["to0_accept_owner_wait_seconds"], Value::Null);
+                assert_eq!(state["owner_key_in_keyring"], false);
+            }
+            assert_eq!(state["to2_performed"], Value::Null);
+        }
+
+        let reply = reply_json(
+            admin_v0_get_handler(user_data.clone(), guid.clone())
+                .await
+                .unwrap(),
+        )
+        .await;
+        assert_eq!(reply["success"], true);
+        assert_eq!(reply["state"]["guid"], guid);
+        assert_eq!(reply["ownership_voucher"], ov.to_pem().unwrap());
+
+        let reply = reply_json(
+            admin_v0_delete_handler(user_data.clone(), guid.clone())
+                .await
+                .unwrap(),
+        )
+        .await;
+        assert_eq!(reply["success"], true);
+        let reply = reply_json(
+            admin_v0_get_handler(user_data.clone(), guid.clone())
+                .await
+                .unwrap(),
+        )
+        .await;
+        assert_eq!(reply["success"], false);
+        let reply = reply_json(
+            admin_v0_get_handler(user_data, "invalid".to_string())
+                .await
+                .unwrap(),
+        )
+        .await;
+        assert_eq!(reply["success"], false);
+    }
+}
@@ -1,24 +1,36 @@
-use std::{collections::HashSet, str::FromStr};
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    str::FromStr,
+    time::Duration,
+};
 
 use fdo_data_formats::{
     constants::{DeviceSigType, ErrorCode, HeaderKeys},
     messages::Message,
+    ownershipvoucher::{OwnershipVoucher, OwnershipVoucherHeader},
     types::{
-        COSEHeaderMap, COSESign, CipherSuite, Guid, KeyDeriveSide, KeyExchange, Nonce,
-        RendezvousInfo, SigInfo, TO2ProveDevicePayload, TO2ProveOVHdrPayload,
-        TO2SetupDevicePayload,
+        COSEHeaderMap, COSESign, CborSimpleType, CborSimpleTypeExt, CipherSuite, Guid, HMac,
+        KeyDeriveSide, KeyExchange, Nonce, RendezvousInfo, ServiceInfo, SigInfo,
+        TO2ProveDevicePayload, TO2ProveOVHdrPayload, TO2SetupDevicePayload,
+        DEFAULT_SERVICE_INFO_MTU, MIN_SERVICE_INFO_MTU,
     },
+    Serializable,
 };
 use fdo_data_formats::{
-    constants::{FedoraIotServiceInfoModule, StandardServiceInfoModule},
+    constants::{FedoraIotServiceInfoModule, ServiceInfoModule, StandardServiceInfoModule},
     messages::{self, v11::to2::OwnerServiceInfo},
 };
 
 use fdo_http_wrapper::server::Error;
 use fdo_http_wrapper::server::RequestInformation;
+use fdo_http_wrapper::server::Session;
 use fdo_http_wrapper::EncryptionKeys;
 use fdo_store::MetadataKey;
-use fdo_util::servers::{OwnershipVoucherStoreMetadataKey, ServiceInfoApiReply};
+use fdo_util::servers::{
+    OwnershipVoucherStoreMetadataKey, ServiceInfoApiDevMod, ServiceInfoApiDeviceResults,
+    ServiceInfoApiDeviceUpload, ServiceInfoApiFile, ServiceInfoApiFileChunk, ServiceInfoApiReply,
+};
+use serde::{Deserialize, Serialize};
 
 pub(super) async fn hello_device(
     user_data: super::OwnerServiceUDT,
@@ -44,13 +56,69 @@ pub(super) async fn hello_device(
         }
         Some(dev) => dev,
     };
+    // Devices need to use the protocol version of their ownership voucher
+    if ownership_voucher.header().protocol_version() != request_info.protocol_version {
+        log::warn!(
+            "Device {} with an ownership voucher of protocol version {} used protocol version {}",
+            msg.guid(),
+            ownership_voucher.header().protocol_version(),
+            request_info.protocol_version
+        );
+        return Err(Error::new(
+            ErrorCode::InvalidMessageError,
+            messages::v11::to2::HelloDevice::message_type(),
+            "Protocol version mismatch",
+        )
+        .into());
+    }
+    let owner_key = match user_data
+        .owner_keyring
+        .key_for_ov(&ownership_voucher)
+        .map_err(Error::from_error::<messages::v11::to2::HelloDevice, _>)?
+    {
+        None => {
+            log::warn!(
+                "Device {} has an ownership voucher for an owner key not in the keyring",
+                msg.guid()
+            );
+            return Err(Error::new(
+                ErrorCode::ResourceNotFound,
+                messages::v11::to2::HelloDevice::message_type(),
+                "Device not found",
+            )
+            .into());
+        }
+        Some(owner_key) => owner_key,
+    };
+    let report = ownership_voucher.verify(
+        None,
+        user_data.trusted_device_keys.as_ref(),
+        Some(&owner_key.public_key),
+        None,
+    );
+    if let Some(failure) = report.failures().next() {
+        log::warn!(
+            "Ownership voucher for device {} is invalid: {}",
+            msg.guid(),
+            failure
+        );
+        return Err(Error::new(
+            ErrorCode::InvalidOwnershipVoucher,
+            messages::v11::to2::HelloDevice::message_type(),
+            "Invalid ownership voucher",
+        )
+        .into());
+    }
     session
         .insert("device_guid", msg.guid().to_string())
         .map_err(Error::from_error::<messages::v11::to2::HelloDevice, _>)?;
 
     // Check whether we support the specific siginfo
     match msg.a_signature_info().sig_type() {
-        DeviceSigType::StSECP256R1 | DeviceSigType::StSECP384R1 => {}
+        DeviceSigType::StSECP256R1
+        | DeviceSigType::StSECP384R1
+        | DeviceSigType::StRSA2048
+        | DeviceSigType::StRSA3072 => {}
         _ => {
             return Err(Error::new(
                 ErrorCode::MessageBodyError,
@@ -79,6 +147,7 @@ pub(super) async fn hello_device(
 
     // Now produce the result
     let res_payload = TO2ProveOVHdrPayload::new(
+        ownership_voucher.header().protocol_version(),
         ownership_voucher.header_raw(),
         ownership_voucher.num_entries(),
         ownership_voucher.header_hmac().clone(),
@@ -96,6 +165,9 @@ pub(super) async fn hello_device(
     session
         .insert("ciphersuite", msg.cipher_suite())
         .map_err(Error::from_error::<messages::v11::to2::HelloDevice, _>)?;
+    session
+        .insert("max_device_message_size", msg.max_device_message_size())
+        .map_err(Error::from_error::<messages::v11::to2::HelloDevice, _>)?;
     session
         .insert("a_key_exchange", a_key_exchange)
         .map_err(Error::from_error::<messages::v11::to2::HelloDevice, _>)?;
@@ -106,10 +178,10 @@ pub(super) async fn hello_device(
         .insert(HeaderKeys::CUPHNonce, &nonce6)
         .map_err(Error::from_error::<messages::v11::to2::HelloDevice, _>)?;
     res_header
-        .insert(HeaderKeys::CUPHOwnerPubKey, &user_data.owner_pubkey)
+        .insert(HeaderKeys::CUPHOwnerPubKey, &owner_key.public_key)
         .map_err(Error::from_error::<messages::v11::to2::HelloDevice, _>)?;
 
-    let res = COSESign::new(&res_payload, Some(res_header), &user_data.owner_key)
+    let res = COSESign::new(&res_payload, Some(res_header), &owner_key.private_key)
         .map_err(Error::from_error::<messages::v11::to2::HelloDevice, _>)?;
     let res = messages::v11::to2::ProveOVHdr::new(res);
 
@@ -278,7 +350,7 @@ pub(super) async fn prove_device(
     };
 
     let eat = token
-        .get_eat(dev_pubkey.as_ref())
+        .get_eat_with_pkey(dev_pubkey.as_ref())
         .map_err(Error::from_error::<messages::v11::to2::ProveDevice, _>)?;
 
     let eat_payload: TO2ProveDevicePayload = match eat
@@ -333,11 +405,53 @@ pub(super) async fn prove_device(
         new_keys,
     )?;
 
-    // Generate new ephemeral SetupDevicePayload
+    // Determine the replacement GUID and RendezvousInfo
+    let credential_reuse = user_data
+        .owner_keyring
+        .credential_reuse(&ownership_voucher, &user_data.owner2_pub)
+        .map_err(Error::from_error::<messages::v11::to2::ProveDevice, _>)?;
+    session
+        .insert("credential_reuse", credential_reuse)
+        .map_err(Error::from_error::<messages::v11::to2::ProveDevice, _>)?;
+    let (replacement_guid, replacement_rendezvous_info) = if credential_reuse {
+        // Credential Reuse: the device keeps its current credential
+        (
+            device_guid,
+            ownership_voucher.header().rendezvous_info().clone(),
+        )
+    } else if user_data.replacement_ownership_voucher_store.is_some() {
+        // Credential Resale: we keep the replacement voucher
+        let replacement_guid =
+            Guid::new().map_err(Error::from_error::<messages::v11::to2::ProveDevice, _>)?;
+        let replacement_rendezvous_info = match &user_data.replacement_rendezvous_info {
+            Some(rendezvous_info) => rendezvous_info.clone(),
+            None => ownership_voucher.header().rendezvous_info().clone(),
+        };
+        let replacement_header = ownership_voucher
+            .header()
+            .replacement(
+                replacement_guid.clone(),
+                replacement_rendezvous_info.clone(),
+                user_data.owner2_pub.clone(),
+            )
+            .and_then(|header| header.serialize_data())
+            .map_err(Error::from_error::<messages::v11::to2::ProveDevice, _>)?;
+        session
+            .insert("replacement_ov_header", replacement_header)
+            .map_err(Error::from_error::<messages::v11::to2::ProveDevice, _>)?;
+        (replacement_guid, replacement_rendezvous_info)
+    } else {
+        // Ephemeral Owner2 key: nobody will be able to use the new credential
+        (
+            Guid::new().map_err(Error::from_error::<messages::v11::to2::ProveDevice, _>)?,
+            RendezvousInfo::new(Vec::new())
+                .map_err(Error::from_error::<messages::v11::to2::ProveDevice, _>)?,
+        )
+    };
+
     let new_payload = TO2SetupDevicePayload::new(
-        RendezvousInfo::new(Vec::new())
-            .map_err(Error::from_error::<messages::v11::to2::ProveDevice, _>)?,
-        Guid::new().unwrap(),
+        replacement_rendezvous_info,
+        replacement_guid,
         nonce7,
         user_data.owner2_pub.clone(),
     );
@@ -356,8 +470,8 @@ pub(super) async fn prove_device(
 
 pub(super) async fn device_service_info_ready(
     _user_data: super::OwnerServiceUDT,
-    ses_with_store: RequestInformation,
-    _msg: messages::v11::to2::DeviceServiceInfoReady,
+    mut ses_with_store: RequestInformation,
+    msg: messages::v11::to2::DeviceServiceInfoReady,
 ) -> Result<
     (
         messages::v11::to2::OwnerServiceInfoReady,
@@ -378,13 +492,63 @@ pub(super) async fn device_service_info_ready(
         }
     };
 
+    if ses_with_store
+        .session
+        .get::<Vec<u8>>("replacement_ov_header")
+        .is_some()
+    {
+        match msg.replacement_hmac() {
+            Some(replacement_hmac) => ses_with_store
+                .session
+                .insert("replacement_hmac", replacement_hmac.clone())
+                .map_err(Error::from_error::<messages::v11::to2::DeviceServiceInfoReady, _>)?,
+            None => log::warn!(
+                "Device did not provide a replacement HMAC, not storing a replacement voucher"
+            ),
+        }
+    }
+
+    // Determine how large the ServiceInfo we send to the device may be
+    let mut owner_service_info_mtu = msg
+        .max_owner_service_info_size()
+        .unwrap_or(DEFAULT_SERVICE_INFO_MTU);
+    if let Some(max_device_message_size) =
+        ses_with_store.session.get::<u16>("max_device_message_size")
+    {
+        // Older implementations announced a bogus message size, so ignore anything unusably small
+        if max_device_message_size as u64 >= MIN_SERVICE_INFO_MTU {
+            owner_service_info_mtu =
+                std::cmp::min(owner_service_info_mtu, max_device_message_size as u64);
+        }
+    }
+    log::trace!(
+        "Using ServiceInfo MTU {} towards device",
+        owner_service_info_mtu
+    );
+    ses_with_store
+        .session
+        .insert("owner_service_info_mtu", owner_service_info_mtu)
+        .map_err(Error::from_error::<messages::v11::to2::DeviceServiceInfoReady, _>)?;
+
     Ok((
         messages::v11::to2::OwnerServiceInfoReady::new(None),
         ses_with_store,
     ))
 }
 
-const MAX_SERVICE_INFO_LOOPS: u32 = 1000;
+/// Records another ServiceInfo round in the session, returning its number,
+/// or None if the device has already used up all `max_loops` rounds
+fn count_service_info_loop(
+    session: &mut Session,
+    max_loops: u32,
+) -> Result<Option<u32>, serde_json::Error> {
+    let num_loops = session.get::<u32>("num_service_info_loops").unwrap_or(0);
+    if num_loops >= max_loops {
+        return Ok(None);
+    }
+    session.insert("num_service_info_loops", num_loops + 1)?;
+    Ok(Some(num_loops))
+}
 
 pub(super) async fn device_service_info(
     user_data: super::OwnerServiceUDT,
@@ -416,32 +580,26 @@ pub(super) async fn device_service_info(
     };
     let device_guid = Guid::from_str(&device_guid).unwrap();
 
-    let num_loops = match ses_with_store.session.get::<u32>("num_service_info_loops") {
-        Some(v) => v,
+    let num_loops = match count_service_info_loop(
+        &mut ses_with_store.session,
+        user_data.max_service_info_loops,
+    )
+    .map_err(Error::from_error::<messages::v11::to2::DeviceServiceInfo, _>)?
+    {
+        Some(num_loops) => num_loops,
         None => {
-            ses_with_store
-                .session
-                .insert("num_service_info_loops", 1)
-                .map_err(Error::from_error::<messages::v11::to2::DeviceServiceInfo, _>)?;
-            0
+            log::warn!(
+                "Device {:?} has attempted to perform too many ServiceInfo loops",
+                device_guid
+            );
+            return Err(Error::new(
+                ErrorCode::InvalidMessageError,
+                messages::v11::to2::DeviceServiceInfo::message_type(),
+                "Too many serviceinfo loops performed",
+            )
+            .into());
         }
     };
-    if num_loops > MAX_SERVICE_INFO_LOOPS {
-        log::warn!(
-            "Device {:?} has attempted to perform too many ServiceInfo loops",
-            device_guid
-        );
-        return Err(Error::new(
-            ErrorCode::InvalidMessageError,
-            messages::v11::to2::DeviceServiceInfo::message_type(),
-            "Too many serviceinfo loops performed",
-        )
-        .into());
-    }
-    ses_with_store
-        .session
-        .insert("num_service_info_loops", num_loops + 1)
-        .map_err(Error::from_error::<messages::v11::to2::DeviceServiceInfo, _>)?;
 
     log::trace!(
         "Device {:?} is now starting ServiceInfo loop {}",
@@ -475,27 +633,612 @@ pub(super) async fn device_service_info(
 
 async fn perform_service_info(
     user_data: super::OwnerServiceUDT,
-    _session: &mut fdo_http_wrapper::server::Session,
+    session: &mut fdo_http_wrapper::server::Session,
     device_guid: Guid,
     msg: messages::v11::to2::DeviceServiceInfo,
     loop_num: u32,
 ) -> Result<OwnerServiceInfo, anyhow::Error> {
-    if loop_num != 0 {
-        // Return DONE for now after the first loop.
+    let in_si = msg.service_info();
+
+    log::trace!("Received ServiceInfo loop {}: {:?}", loop_num, in_si);
+
+    let mut pending = match session
+        .get::<VecDeque<PendingServiceInfo>>("pending_owner_service_info")
+    {
+        Some(mut pending) => {
+            // Anything the device sends now is a response to our ServiceInfo, which is
+            // passed on in the round it arrives in, uploads included
+            // The results would be lost if they can't be forwarded, so fail the round instead
+            let mut upload = session.get("device_upload");
+            forward_device_results(
+                &user_data,
+                &device_guid,
+                in_si,
+                &mut upload,
+                !msg.is_more_service_info(),
+            )
+            .await?;
+            match upload {
+                Some(upload) => session.insert("device_upload", upload)?,
+                None => session.remove("device_upload"),
+            }
+            if let Some(status_reply) = sys_status_reply(&device_guid, in_si)? {
+                pending.extend(status_reply);
+            }
+            pending
+        }
+        None => {
+            // The device may need multiple messages to announce its modules
+            let mut device_si: ServiceInfo = session.get("device_service_info").unwrap_or_default();
+            device_si.extend(in_si.clone());
+            if msg.is_more_service_info() {
+                session.insert("device_service_info", device_si)?;
+                return Ok(messages::v11::to2::OwnerServiceInfo::new(
+                    false,
+                    false,
+                    ServiceInfo::new(),
+                ));
+            }
+            session.remove("device_service_info");
+
+            let pending =
+                build_owner_service_info(&user_data, device_guid.clone(), &device_si).await?;
+            log::trace!("Queueing ServiceInfo result: {:?}", pending);
+            pending
+        }
+    };
+
+    if msg.is_more_service_info() {
+        // The device has more to send, acknowledge and wait for the rest
+        session.insert("pending_owner_service_info", pending)?;
+        return Ok(messages::v11::to2::OwnerServiceInfo::new(
+            false,
+            false,
+            ServiceInfo::new(),
+        ));
+    }
+
+    if pending.is_empty() {
+        // Everything has been sent, and the device has nothing more for us
+        session.insert("pending_owner_service_info", pending)?;
         return Ok(messages::v11::to2::OwnerServiceInfo::new(
             false,
             true,
-            Default::default(),
+            ServiceInfo::new(),
         ));
     }
-    let in_si = msg.service_info();
 
-    log::trace!("Received ServiceInfo loop {}: {:?}", loop_num, in_si);
+    let mtu = session
+        .get("owner_service_info_mtu")
+        .unwrap_or(DEFAULT_SERVICE_INFO_MTU);
+    let out_si = next_owner_service_info(
+        &user_data.service_info_api_client,
+        &device_guid,
+        &mut pending,
+        mtu,
+    )
+    .await?;
+    let is_more = !pending.is_empty();
+    session.insert("pending_owner_service_info", pending)?;
+
+    log::trace!("Sending ServiceInfo result: {:?}", out_si);
+
+    Ok(messages::v11::to2::OwnerServiceInfo::new(
+        is_more, false, out_si,
+    ))
+}
+
+/// Owner ServiceInfo that is still to be sent to the device.
+///
+/// Files are only referenced, their contents are requested from the
+/// ServiceInfo API server a chunk at a time as the rounds need them.
+#[derive(Debug, Serialize, Deserialize)]
+enum PendingServiceInfo {
+    /// An entry, in the form of the ServiceInfo API `extra_commands`
+    Entry(ServiceInfoModule, String, serde_json::Value),
+    /// The contents of a file, of which the first `offset` bytes were sent
+    File {
+        module: ServiceInfoModule,
+        key: String,
+        file: String,
+        offset: u64,
+        length: u64,
+    },
+}
+
+impl PendingServiceInfo {
+    fn entry<M, T>(module: M, key: &str, value: &T) -> Result<Self, anyhow::Error>
+    where
+        M: Into<ServiceInfoModule>,
+        T: Serialize,
+    {
+        Ok(PendingServiceInfo::Entry(
+            module.into(),
+            key.to_string(),
+            serde_json::to_value(value)?,
+        ))
+    }
+
+    /// Converts an entry of the ServiceInfo API `extra_commands`, where a key
+    /// suffix of `|hex` marks binary values and `|file` marks file references
+    fn from_api(
+        module: ServiceInfoModule,
+        key: String,
+        value: serde_json::Value,
+    ) -> Result<Self, anyhow::Error> {
+        match key.strip_suffix("|file") {
+            Some(key) => {
+                let file: ServiceInfoApiFile = serde_json::from_value(value)
+                    .map_err(|e| anyhow::anyhow!("Invalid API response: bad file: {}", e))?;
+                Ok(PendingServiceInfo::File {
+                    module,
+                    key: key.to_string(),
+                    file: file.file,
+                    offset: 0,
+                    length: file.length,
+                })
+            }
+            None => Ok(PendingServiceInfo::Entry(module, key, value)),
+        }
+    }
+}
+
+/// Adds an entry in the form of the ServiceInfo API `extra_commands`
+fn add_api_entry(
+    out_si: &mut ServiceInfo,
+    module: &ServiceInfoModule,
+    key: &str,
+    value: &serde_json::Value,
+) -> Result<(), anyhow::Error> {
+    match key.strip_suffix("|hex") {
+        Some(key) => {
+            let value = hex::decode(
+                value
+                    .as_str()
+                    .ok_or_else(|| anyhow::anyhow!("Invalid API response: non-hex"))?,
+            )?;
+            out_si.add(module.clone(), key, &serde_bytes::ByteBuf::from(value))?;
+        }
+        None => out_si.add(module.clone(), key, value)?,
+    }
+    Ok(())
+}
+
+/// Takes the ServiceInfo for the next round off the queue, filling the
+/// message up to the MTU. File contents are requested from the ServiceInfo
+/// API server as far as they fit in this round.
+async fn next_owner_service_info(
+    client: &fdo_http_wrapper::client::JsonClient,
+    device_guid: &Guid,
+    pending: &mut VecDeque<PendingServiceInfo>,
+    mtu: u64,
+) -> Result<ServiceInfo, anyhow::Error> {
+    let budget = ServiceInfo::mtu_budget(mtu);
+    let mut out_si = ServiceInfo::new();
+    let mut size = 0;
+
+    while let Some(next) = pending.front_mut() {
+        match next {
+            PendingServiceInfo::Entry(module, key, value) => {
+                let mut entry = ServiceInfo::new();
+                add_api_entry(&mut entry, module, key, value)?;
+                let entry_size = entry.encoded_size()?;
+                if entry_size > budget {
+                    anyhow::bail!(
+                        "ServiceInfo entry {}:{} of {} bytes does not fit in the MTU budget of {} bytes",
+                        module,
+                        key,
+                        entry_size,
+                        budget
+                    );
+                }
+                if size + entry_size > budget {
+                    break;
+                }
+                size += entry_size;
+                out_si.extend(entry);
+            }
+            PendingServiceInfo::File {
+                module,
+                key,
+                file,
+                offset,
+                length,
+            } => {
+                let chunk_length = std::cmp::min(
+                    *length - *offset,
+                    ServiceInfo::max_bytes_value_len(
+                        module.clone(),
+                        key,
+                        budget.saturating_sub(size),
+                    )? as u64,
+                );
+                if chunk_length == 0 && *offset < *length {
+                    if out_si.is_empty() {
+                        anyhow::bail!("ServiceInfo MTU too small for {}:{}", module, key);
+                    }
+                    break;
+                }
+                if chunk_length > 0 {
+                    let chunk: ServiceInfoApiFileChunk = client
+                        .send_get([
+                            ("serviceinfo_api_version", "1"),
+                            ("device_guid", &device_guid.to_string()),
+                            ("file", file),
+                            ("offset", &offset.to_string()),
+                            ("length", &chunk_length.to_string()),
+                        ])
+                        .await?;
+                    let data = hex::decode(chunk.data_hex)?;
+                    if data.len() as u64 != chunk_length {
+                        anyhow::bail!(
+                            "Invalid API response: requested {} bytes of {}, got {}",
+                            chunk_length,
+                            file,
+                            data.len()
+                        );
+                    }
+                    out_si.add(module.clone(), key, &serde_bytes::ByteBuf::from(data))?;
+                    size = out_si.encoded_size()?;
+                    *offset += chunk_length;
+                }
+                if *offset < *length {
+                    // The rest goes out in the next round
+                    break;
+                }
+            }
+        }
+        pending.pop_front();
+    }
+
+    Ok(out_si)
+}
+
+/// Checks for fdo.sys:status_cb entries from the device, and returns the
+/// ServiceInfo to keep polling it if it reports a command is still running.
+fn sys_status_reply(
+    device_guid: &Guid,
+    in_si: &ServiceInfo,
+) -> Result<Option<Vec<PendingServiceInfo>>, anyhow::Error> {
+    for (module, key, value) in in_si.iter() {
+        if module != StandardServiceInfoModule::Sys.into() || key != "status_cb" {
+            continue;
+        }
+        let (completed, retcode, timeout): (bool, i64, u64) = serde_cbor::value::from_value(value)?;
+        if completed {
+            log::info!(
+                "Device {:?} finished fdo.sys command with return code {}",
+                device_guid,
+                retcode
+            );
+            continue;
+        }
+        log::trace!(
+            "Device {:?} fdo.sys command still running, checking back in {} seconds",
+            device_guid,
+            timeout
+        );
+        return Ok(Some(vec![
+            PendingServiceInfo::entry(StandardServiceInfoModule::Sys, "active", &true)?,
+            PendingServiceInfo::entry(
+                StandardServiceInfoModule::Sys,
+                "status_cb",
+                &(false, 0, timeout),
+            )?,
+        ]));
+    }
+    Ok(None)
+}
+
+/// A file being uploaded by the device with the fdo.upload module.
+///
+/// The data is passed on to the ServiceInfo API server in the round it
+/// arrives in, only this metadata is kept in the session between rounds.
+#[derive(Debug, Serialize, Deserialize)]
+struct UploadInProgress {
+    name: String,
+    length: Option<u64>,
+    received: u64,
+    sha_384_hex: Option<String>,
+    error: Option<String>,
+}
+
+impl UploadInProgress {
+    fn new(name: &str) -> Self {
+        UploadInProgress {
+            name: name.to_string(),
+            length: None,
+            received: 0,
+            sha_384_hex: None,
+            error: None,
+        }
+    }
+
+    fn finish(self, device_guid: &Guid) -> ServiceInfoApiDeviceUpload {
+        let error = if self.error.is_some() {
+            self.error
+        } else if self.length != Some(self.received) {
+            Some(format!(
+                "Received {} bytes, but length was {:?}",
+                self.received, self.length
+            ))
+        } else {
+            None
+        };
+
+        match &error {
+            Some(error) => log::warn!(
+                "Device {:?} failed to upload {}: {}",
+                device_guid,
+                self.name,
+                error
+            ),
+            None => log::info!(
+                "Device {:?} uploaded {} ({} bytes)",
+                device_guid,
+                self.name,
+                self.received
+            ),
+        }
+        ServiceInfoApiDeviceUpload {
+            name: self.name,
+            length: self.received,
+            sha_384_hex: self.sha_384_hex,
+            error,
+        }
+    }
+}
+
+/// Data of an upload received in a single round, to pass on to the
+/// ServiceInfo API server
+#[derive(Debug)]
+struct UploadChunk {
+    name: String,
+    offset: u64,
+    data: Vec<u8>,
+}
 
+// How often forwarding device results to the ServiceInfo API server is attempted
+const FORWARD_DEVICE_RESULTS_ATTEMPTS: u32 = 3;
+const FORWARD_DEVICE_RESULTS_RETRY_DELAY: Duration = Duration::from_millis(500);
+
+async fn forward_device_results(
+    user_data: &super::OwnerServiceUDT,
+    device_guid: &Guid,
+    in_si: &ServiceInfo,
+    upload: &mut Option<UploadInProgress>,
+    device_done: bool,
+) -> Result<(), anyhow::Error> {
+    let client = &user_data.service_info_api_client;
+    let (results, chunks) = build_device_results(device_guid, in_si, upload, device_done)?;
+
+    // The data goes first, so the uploads it belongs to can be completed
+    for chunk in chunks {
+        log::trace!(
+            "Forwarding {} bytes of upload {} at {}",
+            chunk.data.len(),
+            chunk.name,
+            chunk.offset
+        );
+        send_device_results(
+            client,
+            device_guid,
+            &[
+                ("upload", &chunk.name),
+                ("offset", &chunk.offset.to_string()),
+            ],
+            &ServiceInfoApiFileChunk {
+                data_hex: hex::encode(&chunk.data),
+            },
+        )
+        .await?;
+    }
+
+    if results.service_info.is_empty() && results.uploads.is_empty() {
+        return Ok(());
+    }
+    log::trace!("Forwarding ServiceInfo results: {:?}", results);
+    send_device_results(client, device_guid, &[], &results).await
+}
+
+async fn send_device_results<T>(
+    client: &fdo_http_wrapper::client::JsonClient,
+    device_guid: &Guid,
+    query: &[(&str, &str)],
+    body: &T,
+) -> Result<(), anyhow::Error>
+where
+    T: Serialize,
+{
+    let device_guid_str = device_guid.to_string();
+    let mut attempt = 1;
+    loop {
+        let res: Result<serde_json::Value, _> = client
+            .send_post(
+                [
+                    ("serviceinfo_api_version", "1"),
+                    ("device_guid", &device_guid_str),
+                ]
+                .iter()
+                .copied()
+                .chain(query.iter().copied()),
+                body,
+            )
+            .await;
+        match res {
+            Ok(_) => return Ok(()),
+            Err(e) if attempt < FORWARD_DEVICE_RESULTS_ATTEMPTS => {
+                log::warn!(
+                    "Error forwarding ServiceInfo results of device {:?} (attempt {}/{}): {:?}",
+                    device_guid,
+                    attempt,
+                    FORWARD_DEVICE_RESULTS_ATTEMPTS,
+                    e
+                );
+                tokio::time::sleep(FORWARD_DEVICE_RESULTS_RETRY_DELAY).await;
+                attempt += 1;
+            }
+            Err(e) => {
+                log::error!(
+                    "Giving up forwarding ServiceInfo results of device {:?}: {:?}",
+                    device_guid,
+                    e
+                );
+                return Err(e.into());
+            }
+        }
+    }
+}
+
+/// Converts the ServiceInfo the device sent in a round into the results to
+/// forward, and the data of the upload in progress. Uploads are completed
+/// when the device sends their digest or an error, or is done sending.
+fn build_device_results(
+    device_guid: &Guid,
+    in_si: &ServiceInfo,
+    upload: &mut Option<UploadInProgress>,
+    device_done: bool,
+) -> Result<(ServiceInfoApiDeviceResults, Vec<UploadChunk>), anyhow::Error> {
+    let mut results = ServiceInfoApiDeviceResults::default();
+    let mut chunks: Vec<UploadChunk> = Vec::new();
+    for (module, key, value) in in_si.iter() {
+        if module == StandardServiceInfoModule::Upload.into() {
+            if key == "name" {
+                let name = value
+                    .as_str()
+                    .ok_or_else(|| anyhow::anyhow!("Invalid fdo.upload:name"))?;
+                if let Some(previous) = upload.replace(UploadInProgress::new(name)) {
+                    results.uploads.push(previous.finish(device_guid));
+                }
+                continue;
+            }
+            let current = upload
+                .as_mut()
+                .ok_or_else(|| anyhow::anyhow!("Got fdo.upload:{} before name", key))?;
+            let invalid = || anyhow::anyhow!("Invalid fdo.upload:{}", key);
+            match key.as_str() {
+                "length" => current.length = Some(value.as_u64().ok_or_else(invalid)?),
+                "data" => {
+                    let data = value.as_bytes().ok_or_else(invalid)?;
+                    match chunks.last_mut() {
+                        Some(chunk)
+                            if chunk.name == current.name
+                                && chunk.offset + chunk.data.len() as u64 == current.received =>
+                        {
+                            chunk.data.extend_from_slice(data)
+                        }
+                        _ => chunks.push(UploadChunk {
+                            name: current.name.clone(),
+                            offset: current.received,
+                            data: data.to_vec(),
+                        }),
+                    }
+                    current.received += data.len() as u64;
+                }
+                "sha-384" => {
+                    current.sha_384_hex = Some(hex::encode(value.as_bytes().ok_or_else(invalid)?));
+                    results
+                        .uploads
+                        .push(upload.take().unwrap().finish(device_guid));
+                }
+                "error" => {
+                    current.error = Some(value.as_str().ok_or_else(invalid)?.to_string());
+                    results
+                        .uploads
+                        .push(upload.take().unwrap().finish(device_guid));
+                }
+                _ => log::warn!("Unsupported fdo.upload key {}", key),
+            }
+            continue;
+        }
+
+        match value {
+            // Binary values are sent hex-encoded, just like the API sends them to us
+            serde_cbor::Value::Bytes(value) => results.service_info.push((
+                module,
+                format!("{key}|hex"),
+                serde_json::Value::String(hex::encode(value)),
+            )),
+            value => results
+                .service_info
+                .push((module, key, serde_json::to_value(value)?)),
+        }
+    }
+
+    if device_done {
+        if let Some(upload) = upload.take() {
+            results.uploads.push(upload.finish(device_guid));
+        }
+    }
+
+    Ok((results, chunks))
+}
+
+// Standard devmod keys that are not passed on to the ServiceInfo API
+const DEVMOD_IGNORED_KEYS: &[&str] = &["active", "nummodules", "modules"];
+const DEVMOD_MANDATORY_KEYS: &[&str] = &["os", "arch", "version", "device", "sep", "bin"];
+const DEVMOD_OPTIONAL_KEYS: &[&str] = &["sn", "pathsep", "nl", "tmp", "dir", "progenv", "mudurl"];
+
+fn parse_devmod(
+    values: &HashMap<String, CborSimpleType>,
+) -> Result<ServiceInfoApiDevMod, anyhow::Error> {
+    for key in values.keys() {
+        if !DEVMOD_IGNORED_KEYS.contains(&key.as_str())
+            && !DEVMOD_MANDATORY_KEYS.contains(&key.as_str())
+            && !DEVMOD_OPTIONAL_KEYS.contains(&key.as_str())
+        {
+            log::warn!("Ignoring unknown devmod:{}", key);
+        }
+    }
+
+    let optional = |key: &str| -> Option<String> {
+        match values.get(key) {
+            None => None,
+            Some(CborSimpleType::Text(value)) => Some(value.clone()),
+            // The serial number is a bstr, but some devices send it as tstr
+            Some(CborSimpleType::Bytes(value)) if key == "sn" => {
+                Some(String::from_utf8_lossy(value).to_string())
+            }
+            Some(value) => {
+                log::warn!("Ignoring invalid value for devmod:{}: {:?}", key, value);
+                None
+            }
+        }
+    };
+    let mandatory = |key: &str| -> Result<String, anyhow::Error> {
+        optional(key).ok_or_else(|| anyhow::anyhow!("Missing mandatory devmod:{}", key))
+    };
+
+    Ok(ServiceInfoApiDevMod {
+        os: mandatory("os")?,
+        arch: mandatory("arch")?,
+        version: mandatory("version")?,
+        device: mandatory("device")?,
+        sep: mandatory("sep")?,
+        bin: mandatory("bin")?,
+        sn: optional("sn"),
+        pathsep: optional("pathsep"),
+        nl: optional("nl"),
+        tmp: optional("tmp"),
+        dir: optional("dir"),
+        progenv: optional("progenv"),
+        mudurl: optional("mudurl"),
+    })
+}
+
+async fn build_owner_service_info(
+    user_data: &super::OwnerServiceUDT,
+    device_guid: Guid,
+    in_si: &ServiceInfo,
+) -> Result<VecDeque<PendingServiceInfo>, anyhow::Error> {
     let mut module_list: Option<Vec<String>> = None;
+    let mut devmod_values: HashMap<String, CborSimpleType> = HashMap::new();
 
     for (module, var, value) in in_si.iter() {
-        if module == StandardServiceInfoModule::DevMod.into() && var == "modules" {
+        if module != StandardServiceInfoModule::DevMod.into() {
+            continue;
+        }
+        if var == "modules" {
             let mut rawmodlist: Vec<serde_cbor::Value> = serde_cbor::value::from_value(value)?;
             log::trace!("Received module list: {:?}", rawmodlist);
 
@@ -507,6 +1250,8 @@ async fn perform_service_info(
             log::trace!("Module list: {:?}", modlist);
 
             module_list = Some(modlist.into_iter().collect());
+        } else {
+            devmod_values.insert(var, value);
         }
     }
 
@@ -517,6 +1262,8 @@ async fn perform_service_info(
         }
         Some(l) => l,
     };
+    let devmod = parse_devmod(&devmod_values)?;
+    log::trace!("Device devmod: {:?}", devmod);
 
     let resp: ServiceInfoApiReply = user_data
         .service_info_api_client
@@ -524,63 +1271,61 @@ async fn perform_service_info(
             ("serviceinfo_api_version", "1"),
             ("device_guid", &device_guid.to_string()),
             ("modules", &module_list.join(",")),
+            ("devmod", &serde_json::to_string(&devmod)?),
         ])
         .await?;
 
     log::trace!("ServiceInfo API reply: {:?}", resp);
 
-    let mut out_si = fdo_data_formats::types::ServiceInfo::new();
+    let mut out_si = VecDeque::new();
 
     if let Some(initial_user) = resp.initial_user {
-        out_si.add(FedoraIotServiceInfoModule::SSHKey, "active", &true)?;
-        out_si.add(
+        out_si.push_back(PendingServiceInfo::entry(
+            FedoraIotServiceInfoModule::SSHKey,
+            "active",
+            &true,
+        )?);
+        out_si.push_back(PendingServiceInfo::entry(
             FedoraIotServiceInfoModule::SSHKey,
             "username",
             &initial_user.username,
-        )?;
+        )?);
         if initial_user.password.is_some() {
-            out_si.add(
+            out_si.push_back(PendingServiceInfo::entry(
                 FedoraIotServiceInfoModule::SSHKey,
                 "password",
                 &initial_user.password,
-            )?;
+            )?);
         }
         if initial_user.ssh_keys.is_some() {
-            out_si.add(
+            out_si.push_back(PendingServiceInfo::entry(
                 FedoraIotServiceInfoModule::SSHKey,
                 "sshkeys",
                 &(initial_user.ssh_keys.unwrap().join(";")),
-            )?;
+            )?);
         }
     }
 
     if let Some(extra_commands) = resp.extra_commands {
         for (module, key, value) in extra_commands {
-            if key.ends_with("|hex") {
-                let value = hex::decode(
-                    value
-                        .as_str()
-                        .ok_or_else(|| anyhow::anyhow!("Invalid API response: non-hex"))?,
-                )?;
-                let value = serde_bytes::ByteBuf::from(value);
-                let key = key.replace("|hex", "");
-                out_si.add(module, &key, &value)?;
-            } else {
-                out_si.add(module, &key, &value)?;
-            }
+            out_si.push_back(PendingServiceInfo::from_api(module, key, value)?);
         }
     }
 
     if let Some(reboot) = resp.reboot {
-        out_si.add(FedoraIotServiceInfoModule::Reboot, "active", &true)?;
-        out_si.add(FedoraIotServiceInfoModule::Reboot, "reboot", &reboot.reboot)?;
+        out_si.push_back(PendingServiceInfo::entry(
+            FedoraIotServiceInfoModule::Reboot,
+            "active",
+            &true,
+        )?);
+        out_si.push_back(PendingServiceInfo::entry(
+            FedoraIotServiceInfoModule::Reboot,
+            "reboot",
+            &reboot.reboot,
+        )?);
     }
 
-    log::trace!("Sending ServiceInfo result: {:?}", out_si);
-
-    Ok(messages::v11::to2::OwnerServiceInfo::new(
-        false, false, out_si,
-    ))
+    Ok(out_si)
 }
 
 pub(super) async fn done(
@@ -647,15 +1392,50 @@ pub(super) async fn done(
         }
     };
 
-    user_data
-        .ownership_voucher_store
-        .store_metadata(
+    // With Credential Reuse, the device can be onboarded again
+    let credential_reuse: bool = ses_with_store
+        .session
+        .get("credential_reuse")
+        .unwrap_or(false);
+    if !credential_reuse {
+        user_data
+            .ownership_voucher_store
+            .store_metadata(
+                &device_guid,
+                &MetadataKey::Local(OwnershipVoucherStoreMetadataKey::To2Performed),
+                &true,
+            )
+            .await
+            .map_err(Error::from_error::<messages::v11::to2::ProveDevice, _>)?;
+    }
+
+    let replacement_ov_header: Option<Vec<u8>> =
+        ses_with_store.session.get("replacement_ov_header");
+    let replacement_hmac: Option<HMac> = ses_with_store.session.get("replacement_hmac");
+    if let (Some(replacement_ov_header), Some(replacement_hmac)) =
+        (replacement_ov_header, replacement_hmac)
+    {
+        let replacement_guid = store_replacement_voucher(
+            &user_data,
             &device_guid,
-            &MetadataKey::Local(OwnershipVoucherStoreMetadataKey::To2Performed),
-            &true,
+            &replacement_ov_header,
+            replacement_hmac,
         )
         .await
-        .map_err(Error::from_error::<messages::v11::to2::ProveDevice, _>)?;
+        .map_err(|e| {
+            log::error!("Error storing replacement voucher: {:?}", e);
+            Error::new(
+                ErrorCode::InternalServerError,
+                messages::v11::to2::Done::message_type(),
+                "Error storing replacement voucher",
+            )
+        })?;
+        log::info!(
+            "Stored replacement voucher for device {:?}, new GUID: {}",
+            device_guid,
+            replacement_guid
+        );
+    }
 
     ses_with_store.session.remove("nonce7");
     ses_with_store.session.destroy();
@@ -663,6 +1443,39 @@ pub(super) async fn done(
     Ok((messages::v11::to2::Done2::new(nonce7), ses_with_store))
 }
 
+async fn store_replacement_voucher(
+    user_data: &super::OwnerServiceUDT,
+    device_guid: &Guid,
+    replacement_ov_header: &[u8],
+    replacement_hmac: HMac,
+) -> Result<Guid, anyhow::Error> {
+    let replacement_store = match &user_data.replacement_ownership_voucher_store {
+        Some(store) => store,
+        None => anyhow::bail!("No replacement ownership voucher store configured"),
+    };
+    let ownership_voucher = match user_data
+        .ownership_voucher_store
+        .load_data(device_guid)
+        .await?
+    {
+        Some(ov) => ov,
+        None => anyhow::bail!("Ownership voucher for device {} not found", device_guid),
+    };
+
+    let replacement_ov_header = OwnershipVoucherHeader::deserialize_data(replacement_ov_header)?;
+    let replacement_guid = replacement_ov_header.guid().clone();
+    let replacement = OwnershipVoucher::new(
+        replacement_ov_header,
+        replacement_hmac,
+        ownership_voucher.device_certificate_chain().cloned(),
+    )?;
+    replacement_store
+        .store_data(replacement_guid.clone(), replacement)
+        .await?;
+
+    Ok(replacement_guid)
+}
+
 #[derive(Debug)]
 #[allow(dead_code)]
 struct RtrFailure(anyhow::Error);
@@ -680,3 +1493,354 @@ pub(crate) async fn report_to_rendezvous_handler(
         .map_err(|e| warp::reject::custom(RtrFailure(e)))?;
     Ok(warp::reply::Response::new("ok".into()))
 }
+
+#[cfg(test)]
+mod test {
+    use std::{
+        collections::{HashMap, VecDeque},
+        sync::{
+            atomic::{AtomicU32, Ordering},
+            Arc,
+        },
+    };
+
+    use fdo_data_formats::{
+        constants::{HashType, StandardServiceInfoModule},
+        types::{CborSimpleType, CborSimpleTypeExt, Guid, Hash, ServiceInfo, MIN_SERVICE_INFO_MTU},
+    };
+    use fdo_http_wrapper::client::{JsonAuthentication, JsonClient};
+    use fdo_util::servers::{ServiceInfoApiDeviceResults, ServiceInfoApiFileChunk};
+    use warp::{http::StatusCode, Filter};
+
+    use super::{
+        build_device_results, count_service_info_loop, next_owner_service_info, parse_devmod,
+        send_device_results, PendingServiceInfo, Session, FORWARD_DEVICE_RESULTS_ATTEMPTS,
+    };
+
+    fn devmod_values(values: &[(&str, CborSimpleType)]) -> HashMap<String, CborSimpleType> {
+        values
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.clone()))
+            .collect()
+    }
+
+    fn mandatory_devmod_values() -> HashMap<String, CborSimpleType> {
+        devmod_values(&[
+            ("active", CborSimpleType::Bool(true)),
+            ("os", CborSimpleType::Text("Linux".to_string())),
+            ("arch", CborSimpleType::Text("x86_64".to_string())),
+            ("version", CborSimpleType::Text("Fedora 40".to_string())),
+            ("device", CborSimpleType::Text("testdevice".to_string())),
+            ("sep", CborSimpleType::Text(":".to_string())),
+            ("bin", CborSimpleType::Text("x86_64".to_string())),
+            ("nummodules", CborSimpleType::Integer(1)),
+        ])
+    }
+
+    #[test]
+    fn test_parse_devmod() {
+        let mut values = mandatory_devmod_values();
+        values.insert("sn".to_string(), CborSimpleType::Bytes(b"1234".to_vec()));
+        values.insert("tmp".to_string(), CborSimpleType::Text("/tmp".to_string()));
+
+        let devmod = parse_devmod(&values).unwrap();
+        assert_eq!(devmod.os, "Linux");
+        assert_eq!(devmod.bin, "x86_64");
+        assert_eq!(devmod.sn.as_deref(), Some("1234"));
+        assert_eq!(devmod.tmp.as_deref(), Some("/tmp"));
+        assert_eq!(devmod.dir, None);
+    }
+
+    #[test]
+    fn test_parse_devmod_skips_unknown_and_invalid_optional() {
+        let mut values = mandatory_devmod_values();
+        values.insert(
+            "vendor-extension".to_string(),
+            CborSimpleType::Text("value".to_string()),
+        );
+        values.insert("nl".to_string(), CborSimpleType::Integer(10));
+
+        let devmod = parse_devmod(&values).unwrap();
+        assert_eq!(devmod.device, "testdevice");
+        assert_eq!(devmod.nl, None);
+    }
+
+    #[test]
+    fn test_parse_devmod_missing_mandatory() {
+        let mut values = mandatory_devmod_values();
+        values.remove("arch");
+        let err = parse_devmod(&values).unwrap_err();
+        assert_eq!(err.to_string(), "Missing mandatory devmod:arch");
+
+        let mut values = mandatory_devmod_values();
+        values.insert("os".to_string(), CborSimpleType::Integer(1));
+        let err = parse_devmod(&values).unwrap_err();
+        assert_eq!(err.to_string(), "Missing mandatory devmod:os");
+    }
+
+    #[test]
+    fn test_service_info_loop_limit() {
+        let mut session = Session::new();
+        for expected in 0..3 {
+            assert_eq!(
+                count_service_info_loop(&mut session, 3).unwrap(),
+                Some(expected)
+            );
+        }
+        assert_eq!(count_service_info_loop(&mut session, 3).unwrap(), None);
+        // The limit is not reset by further attempts
+        assert_eq!(count_service_info_loop(&mut session, 3).unwrap(), None);
+        // A higher limit allows the device to continue
+        assert_eq!(count_service_info_loop(&mut session, 4).unwrap(), Some(3));
+    }
+
+    #[test]
+    fn test_build_device_results() {
+        let device_guid = Guid::new().unwrap();
+        let contents = b"uploaded file";
+        let mut si = ServiceInfo::new();
+        si.add(StandardServiceInfoModule::Sys, "active", &true)
+            .unwrap();
+        si.add(
+            StandardServiceInfoModule::Sys,
+            "stdout",
+            &serde_bytes::Bytes::new(b"output"),
+        )
+        .unwrap();
+        si.add(StandardServiceInfoModule::Upload, "name", &"test.txt")
+            .unwrap();
+        si.add(
+            StandardServiceInfoModule::Upload,
+            "length",
+            &(contents.len() as u64),
+        )
+        .unwrap();
+        for chunk in contents[..10].chunks(5) {
+            si.add(
+                StandardServiceInfoModule::Upload,
+                "data",
+                &serde_bytes::Bytes::new(chunk),
+            )
+            .unwrap();
+        }
+
+        let mut upload = None;
+        let (results, chunks) =
+            build_device_results(&device_guid, &si, &mut upload, false).unwrap();
+        assert_eq!(results.service_info.len(), 2);
+        assert_eq!(results.service_info[0].1, "active");
+        assert_eq!(results.service_info[0].2, serde_json::Value::Bool(true));
+        assert_eq!(results.service_info[1].1, "stdout|hex");
+        assert_eq!(
+            results.service_info[1].2,
+            serde_json::Value::String(hex::encode("output"))
+        );
+        assert!(results.uploads.is_empty());
+        // The data of a round is passed on at once
+        assert_eq!(chunks.len(), 1);
+        assert_eq!(chunks[0].name, "test.txt");
+        assert_eq!(chunks[0].offset, 0);
+        assert_eq!(chunks[0].data, &contents[..10]);
+
+        // The upload continues in the next round, with what was kept in the session
+        let mut upload = serde_json::from_value(serde_json::to_value(&upload).unwrap()).unwrap();
+        let mut si = ServiceInfo::new();
+        si.add(
+            StandardServiceInfoModule::Upload,
+            "data",
+            &serde_bytes::Bytes::new(&contents[10..]),
+        )
+        .unwrap();
+        let digest = Hash::from_data(HashType::Sha384, contents).unwrap();
+        si.add(
+            StandardServiceInfoModule::Upload,
+            "sha-384",
+            &serde_bytes::Bytes::new(digest.value()),
+        )
+        .unwrap();
+        si.add(StandardServiceInfoModule::Upload, "name", &"short.txt")
+            .unwrap();
+        si.add(StandardServiceInfoModule::Upload, "length", &100u64)
+            .unwrap();
+        si.add(StandardServiceInfoModule::Upload, "name", &"failed.txt")
+            .unwrap();
+        si.add(StandardServiceInfoModule::Upload, "error", &"No such file")
+            .unwrap();
+        si.add(StandardServiceInfoModule::Upload, "name", &"unfinished.txt")
+            .unwrap();
+
+        let (results, chunks) = build_device_results(&device_guid, &si, &mut upload, true).unwrap();
+        assert!(results.service_info.is_empty());
+        assert_eq!(chunks.len(), 1);
+        assert_eq!(chunks[0].offset, 10);
+        assert_eq!(chunks[0].data, &contents[10..]);
+        assert!(upload.is_none());
+
+        assert_eq!(results.uploads.len(), 4);
+        assert_eq!(results.uploads[0].name, "test.txt");
+        assert_eq!(results.uploads[0].length, contents.len() as u64);
+        assert_eq!(
+            results.uploads[0].sha_384_hex.as_deref(),
+            Some(hex::encode(digest.value()).as_str())
+        );
+        assert_eq!(results.uploads[0].error, None);
+        assert_eq!(results.uploads[1].name, "short.txt");
+        assert!(results.uploads[1].error.is_some());
+        assert_eq!(results.uploads[2].name, "failed.txt");
+        assert_eq!(results.uploads[2].error.as_deref(), Some("No such file"));
+        assert_eq!(results.uploads[3].name, "unfinished.txt");
+        assert!(results.uploads[3].error.is_some());
+    }
+
+    async fn results_server(failures: u32) -> (JsonClient, Arc<AtomicU32>) {
+        let requests = Arc::new(AtomicU32::new(0));
+        let filter = {
+            let requests = requests.clone();
+            warp::post().map(move || {
+                let status = if requests.fetch_add(1, Ordering::SeqCst) < failures {
+                    StatusCode::INTERNAL_SERVER_ERROR
+                } else {
+                    StatusCode::OK
+                };
+                warp::reply::with_status(warp::reply::json(&serde_json::json!({})), status)
+            })
+        };
+        let (addr, server) = warp::serve(filter).bind_ephemeral(([127, 0, 0, 1], 0));
+        tokio::spawn(server);
+
+        let client = JsonClient::new(
+            format!("http://{addr}/device_results"),
+            JsonAuthentication::None,
+        )
+        .unwrap();
+        (client, requests)
+    }
+
+    #[tokio::test]
+    async fn test_send_device_results_retries() {
+        let (client, requests) = results_server(FORWARD_DEVICE_RESULTS_ATTEMPTS - 1).await;
+        send_device_results(
+            &client,
+            &Guid::new().unwrap(),
+            &[],
+            &ServiceInfoApiDeviceResults::default(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            requests.load(Ordering::SeqCst),
+            FORWARD_DEVICE_RESULTS_ATTEMPTS
+        );
+    }
+
+    #[tokio::test]
+    async fn test_send_device_results_fails() {
+        let (client, requests) = results_server(u32::MAX).await;
+        assert!(send_device_results(
+            &client,
+            &Guid::new().unwrap(),
+            &[],
+            &ServiceInfoApiDeviceResults::default(),
+        )
+        .await
+        .is_err());
+        assert_eq!(
+            requests.load(Ordering::SeqCst),
+            FORWARD_DEVICE_RESULTS_ATTEMPTS
+        );
+    }
+
+    async fn file_server(contents: Vec<u8>) -> JsonClient {
+        let filter = warp::get()
+            .and(warp::query::<HashMap<String, String>>())
+            .map(move |query: HashMap<String, String>| {
+                assert_eq!(query["file"], "downloads/0");
+                let offset: usize = query["offset"].parse().unwrap();
+                let length: usize = query["length"].parse().unwrap();
+                warp::reply::json(&ServiceInfoApiFileChunk {
+                    data_hex: hex::encode(&contents[offset..offset + length]),
+                })
+            });
+        let (addr, server) = warp::serve(filter).bind_ephemeral(([127, 0, 0, 1], 0));
+        tokio::spawn(server);
+
+        JsonClient::new(
+            format!("http://{addr}/device_info"),
+            JsonAuthentication::None,
+        )
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_next_owner_service_info() {
+        let contents: Vec<u8> = (0..3000).map(|i| i as u8).collect();
+        let client = file_server(contents.clone()).await;
+        let device_guid = Guid::new().unwrap();
+
+        let mut pending: VecDeque<PendingServiceInfo> = vec![
+            PendingServiceInfo::entry(StandardServiceInfoModule::Download, "name", &"image")
+                .unwrap(),
+            PendingServiceInfo::from_api(
+                StandardServiceInfoModule::Download.into(),
+                "data|file".to_string(),
+                serde_json::json!({"file": "downloads/0", "length": contents.len()}),
+            )
+            .unwrap(),
+            PendingServiceInfo::from_api(
+                StandardServiceInfoModule::Download.into(),
+                "sha-384|hex".to_string(),
+                serde_json::json!("abcd"),
+            )
+            .unwrap(),
+        ]
+        .into();
+
+        let mut rounds = Vec::new();
+        while !pending.is_empty() {
+            let out_si =
+                next_owner_service_info(&client, &device_guid, &mut pending, MIN_SERVICE_INFO_MTU)
+                    .await
+                    .unwrap();
+            assert!(serde_cbor::to_vec(&out_si).unwrap().len() <= MIN_SERVICE_INFO_MTU as usize);
+            rounds.push(out_si);
+            // The queue is kept in the session between the rounds
+            pending = serde_json::from_value(serde_json::to_value(&pending).unwrap()).unwrap();
+        }
+        assert!(rounds.len() > contents.len() / MIN_SERVICE_INFO_MTU as usize);
+
+        let entries: Vec<_> = rounds.iter().flat_map(|si| si.iter()).collect();
+        assert_eq!(entries.first().unwrap().1, "name");
+        assert_eq!(entries.last().unwrap().1, "sha-384");
+        assert_eq!(
+            entries.last().unwrap().2.as_bytes().unwrap(),
+            &hex::decode("abcd").unwrap()
+        );
+        let mut received: Vec<u8> = Vec::new();
+        for (_, key, value) in &entries {
+            if key == "data" {
+                received.extend(value.as_bytes().unwrap());
+            }
+        }
+        assert_eq!(received, contents);
+    }
+
+    #[tokio::test]
+    async fn test_next_owner_service_info_too_large() {
+        let client = file_server(Vec::new()).await;
+        let mut pending: VecDeque<PendingServiceInfo> = vec![PendingServiceInfo::entry(
+            StandardServiceInfoModule::Download,
+            "name",
+            &"x".repeat(MIN_SERVICE_INFO_MTU as usize),
+        )
+        .unwrap()]
+        .into();
+        assert!(next_owner_service_info(
+            &client,
+            &Guid::new().unwrap(),
+            &mut pending,
+            MIN_SERVICE_INFO_MTU
+        )
+        .await
+        .is_err());
+    }
+}
@@ -0,0 +1,167 @@
+use std::convert::TryFrom;
+
+use anyhow::{bail, Context, Result};
+use openssl::{
+    pkey::{PKey, Private},
+    x509::X509,
+};
+
+use fdo_data_formats::{ownershipvoucher::OwnershipVoucher, publickey::PublicKey};
+use fdo_util::{private_key::PrivateKeyLocation, servers::configuration::AbsolutePathBuf};
+
+pub(crate) struct OwnerKey {
+    pub(crate) private_key: PKey<Private>,
+    pub(crate) public_key: PublicKey,
+}
+
+impl OwnerKey {
+    pub(crate) fn load(
+        private_key_path: &PrivateKeyLocation,
+        public_key_path: &AbsolutePathBuf,
+    ) -> Result<Self> {
+        let private_key = private_key_path
+            .load()
+            .with_context(|| format!("Error loading owner key from {private_key_path}"))?;
+        let contents = std::fs::read(public_key_path)
+            .with_context(|| format!("Error reading owner public key from {public_key_path}"))?;
+        let public_key = PublicKey::try_from(
+            X509::from_pem(&contents).context("Error parsing owner public key")?,
+        )
+        .context("Error converting owner public key to PK")?;
+        if !public_key.matches_pkey(&private_key)? {
+            bail!("Owner public key {public_key_path} does not match the owner private key {private_key_path}");
+        }
+        Ok(OwnerKey {
+            private_key,
+            public_key,
+        })
+    }
+
+    /// Whether the public key is the public part of this owner key
+    pub(crate) fn matches(&self, public_key: &PublicKey) -> Result<bool, fdo_data_formats::Error> {
+        public_key.matches_pkey(&self.private_key)
+    }
+}
+
+/// The owner keys that ownership vouchers may be extended to
+///
+/// The first key is the current owner key, the others are kept so that
+/// vouchers extended to them can still be onboarded after a key rotation.
+pub(crate) struct OwnerKeyring {
+    keys: Vec<OwnerKey>,
+}
+
+impl OwnerKeyring {
+    pub(crate) fn new(current: OwnerKey, additional: Vec<OwnerKey>) -> Self {
+        let mut keys = vec![current];
+        keys.extend(additional);
+        OwnerKeyring { keys }
+    }
+
+    pub(crate) fn keys(&self) -> impl Iterator<Item = &OwnerKey> {
+        self.keys.iter()
+    }
+
+    /// Returns the key the voucher's last entry was extended to, if it is in the keyring
+    pub(crate) fn key_for_ov(
+        &self,
+        ov: &OwnershipVoucher,
+    ) -> Result<Option<&OwnerKey>, fdo_data_formats::Error> {
+        let ov_owner_key = ov.owner_public_key_unverified()?;
+        for key in &self.keys {
+            if ov_owner_key.matches_pkey(key.public_key.pkey())? {
+                return Ok(Some(key));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Whether the device of the voucher keeps its credential (Credential Reuse)
+    ///
+    /// This is the case when the Owner2Key is the owner key that this voucher
+    /// is extended to, any other voucher gets Credential Resale.
+    pub(crate) fn credential_reuse(
+        &self,
+        ov: &OwnershipVoucher,
+        owner2_public_key: &PublicKey,
+    ) -> Result<bool, fdo_data_formats::Error> {
+        match self.key_for_ov(ov)? {
+            None => Ok(false),
+            Some(owner_key) => owner_key.matches(owner2_public_key),
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use fdo_data_formats::{
+        ownershipvoucher::OwnershipVoucher,
+        test_utils::{self, generate_public_key},
+    };
+
+    use super::{OwnerKey, OwnerKeyring};
+
+    pub(crate) fn generate_key(common_name: &str) -> OwnerKey {
+        let (private_key, public_key) = generate_public_key(common_name);
+        OwnerKey {
+            private_key,
+            public_key,
+        }
+    }
+
+    /// Builds a voucher extended from the manufacturer key to the owner key
+    pub(crate) fn build_voucher(
+        manufacturer_key: &OwnerKey,
+        owner_key: &OwnerKey,
+    ) -> OwnershipVoucher {
+        let mut ov = test_utils::build_voucher(&manufacturer_key.public_key, None);
+        ov.extend(&manufacturer_key.private_key, None, &owner_key.public_key)
+            .unwrap();
+        ov
+    }
+
+    #[test]
+    fn test_key_for_ov() {
+        let manufacturer_key = generate_key("Manufacturer");
+        let current = generate_key("Current owner");
+        let previous = generate_key("Previous owner");
+        let unknown = generate_key("Unknown owner");
+
+        let current_ov = build_voucher(&manufacturer_key, &current);
+        let previous_ov = build_voucher(&manufacturer_key, &previous);
+        let unknown_ov = build_voucher(&manufacturer_key, &unknown);
+
+        let keyring = OwnerKeyring::new(current, vec![previous]);
+
+        let key = keyring.key_for_ov(&current_ov).unwrap().unwrap();
+        assert!(std::ptr::eq(key, keyring.keys().next().unwrap()));
+        let key = keyring.key_for_ov(&previous_ov).unwrap().unwrap();
+        assert!(!std::ptr::eq(key, keyring.keys().next().unwrap()));
+        assert!(key
+            .matches(&previous_ov.owner_public_key_unverified().unwrap())
+            .unwrap());
+        assert!(keyring.key_for_ov(&unknown_ov).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_credential_reuse_per_voucher() {
+        let manufacturer_key = generate_key("Manufacturer");
+        let current = generate_key("Current owner");
+        let previous = generate_key("Previous owner");
+
+        let current_ov = build_voucher(&manufacturer_key, &current);
+        let previous_ov = build_voucher(&manufacturer_key, &previous);
+
+        // The Owner2Key is the current owner key
+        let owner2_public_key = current.public_key.clone();
+        let keyring = OwnerKeyring::new(current, vec![previous]);
+
+        assert!(keyring
+            .credential_reuse(&current_ov, &owner2_public_key)
+            .unwrap());
+        // Vouchers extended to an additional key get a replacement voucher
+        assert!(!keyring
+            .credential_reuse(&previous_ov, &owner2_public_key)
+            .unwrap());
+    }
+}
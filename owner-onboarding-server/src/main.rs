@@ -1,11 +1,10 @@
 use std::convert::{TryFrom, TryInto};
-use std::fs;
 use std::sync::Arc;
 
 use anyhow::{bail, Context, Result};
 use fdo_data_formats::constants::HashType;
 use fdo_data_formats::enhanced_types::RendezvousInterpreterSide;
-use fdo_data_formats::types::{COSESign, Hash, TO0Data, TO1DataPayload};
+use fdo_data_formats::types::{COSESign, Hash, RendezvousInfo, TO0Data, TO1DataPayload};
 use fdo_data_formats::{messages, ProtocolVersion, Serializable};
 use fdo_http_wrapper::client::RequestResult;
 use openssl::{
@@ -25,7 +24,7 @@ use fdo_data_formats::{
     enhanced_types::X5Bag,
     ownershipvoucher::OwnershipVoucher,
     publickey::PublicKey,
-    types::{Guid, TO2AddressEntry},
+    types::{Guid, TO2AddressEntry, DEFAULT_MAX_SERVICE_INFO_LOOPS},
 };
 
 use fdo_store::{Store, StoreConfig, StoreError};
@@ -33,12 +32,16 @@ use fdo_util::servers::{
     configuration::{
         owner_onboarding_server::OwnerOnboardingServerSettings,
         owner_onboarding_server::DEFAULT_REGISTRATION_PERIOD,
-        owner_onboarding_server::DEFAULT_RE_REGISTRATION_WINDOW, AbsolutePathBuf,
+        owner_onboarding_server::DEFAULT_RE_REGISTRATION_WINDOW,
     },
-    settings_for, OwnershipVoucherStoreMetadataKey,
+    load_rendezvous_info, settings_for, OwnershipVoucherStoreMetadataKey,
 };
 
+mod admin;
 mod handlers;
+mod keyring;
+
+use keyring::{OwnerKey, OwnerKeyring};
 
 pub(crate) struct OwnerServiceUD {
     // Trusted keys
@@ -56,13 +59,28 @@ pub(crate) struct OwnerServiceUD {
     session_store: Arc<fdo_http_wrapper::server::SessionStore>,
 
     // Our keys
-    owner_key: PKey<Private>,
-    owner_pubkey: PublicKey,
+    owner_keyring: OwnerKeyring,
 
-    // The new Owner2Key, randomly generated, but not stored
+    // The new Owner2Key, either loaded from the configuration or randomly
+    // generated (and not stored)
     owner2_key: PKey<Private>,
     owner2_pub: PublicKey,
 
+    // Credential Resale, for vouchers not extended to the Owner2Key:
+    // replacement rendezvous info (None to keep the
+    // current one) and the store for the replacement vouchers
+    replacement_rendezvous_info: Option<RendezvousInfo>,
+    replacement_ownership_voucher_store: Option<
+        Box<
+            dyn Store<
+                fdo_store::ReadWriteOpen,
+                Guid,
+                OwnershipVoucher,
+                OwnershipVoucherStoreMetadataKey,
+            >,
+        >,
+    >,
+
     // ServiceInfo API server configuration
     service_info_api_client: fdo_http_wrapper::client::JsonClient,
 
@@ -74,20 +92,21 @@ pub(crate) struct OwnerServiceUD {
     ov_re_registration_window: u32,
 
     window_check_enabled: bool,
-}
 
-pub(crate) type OwnerServiceUDT = Arc<OwnerServiceUD>;
+    // Number of ServiceInfo rounds a device may perform
+    max_service_info_loops: u32,
 
-fn load_private_key(path: &AbsolutePathBuf) -> Result<PKey<Private>> {
-    let contents = fs::read(path)?;
-    Ok(PKey::private_key_from_der(&contents)?)
+    // Admin API authentication, in the form of the Authorization header
+    admin_auth_token: Option<String>,
 }
 
+pub(crate) type OwnerServiceUDT = Arc<OwnerServiceUD>;
+
 async fn _handle_report_to_rendezvous(udt: &OwnerServiceUDT, ov: &OwnershipVoucher) -> Result<()> {
     match report_ov_to_rendezvous(
         ov,
         &udt.owner_addresses,
-        &udt.owner_key,
+        &udt.owner_keyring,
         udt.ov_registration_period,
         &udt.trusted_device_keys,
     )
@@ -116,6 +135,12 @@ async fn _handle_report_to_rendezvous(udt: &OwnerServiceUDT, ov: &OwnershipVouch
     }
 }
 
+/// Vouchers whose rendezvous info only has Bypass directives are onboarded
+/// without a rendezvous server, so there is nothing to register them with
+fn requires_to0(ov: &OwnershipVoucher) -> bool {
+    !ov.header().rendezvous_info().is_bypass_only()
+}
+
 async fn report_to_rendezvous(udt: OwnerServiceUDT) -> Result<()> {
     match udt.ownership_voucher_store.query_data().await {
         Ok(mut ft) => {
@@ -131,6 +156,8 @@ async fn report_to_rendezvous(udt: OwnerServiceUDT) -> Result<()> {
             );
             let ov_iter = ft.query().await?;
             if let Some(ovs) = ov_iter {
+                let ovs: Vec<OwnershipVoucher> = ovs.filter(requires_to0).collect();
+                fdo_http_wrapper::server::metrics::set_owner_vouchers_pending_to0(ovs.len());
                 for ov in ovs {
                     _handle_report_to_rendezvous(&udt, &ov).await?;
                 }
@@ -139,6 +166,8 @@ async fn report_to_rendezvous(udt: OwnerServiceUDT) -> Result<()> {
         Err(StoreError::MethodNotAvailable) => {
             match udt.ownership_voucher_store.query_ovs_db().await {
                 Ok(ovs) => {
+                    let ovs: Vec<OwnershipVoucher> = ovs.into_iter().filter(requires_to0).collect();
+                    fdo_http_wrapper::server::metrics::set_owner_vouchers_pending_to0(ovs.len());
                     for ov in ovs {
                         _handle_report_to_rendezvous(&udt, &ov).await?
                     }
@@ -166,11 +195,11 @@ async fn check_registration_window(udt: &OwnerServiceUDT) -> Result<()> {
         .ownership_voucher_store
         .query_ovs_db_to2_performed_to0_less_than(false, now_plus_window)
         .await?;
-    for ov in ovs {
+    for ov in ovs.into_iter().filter(requires_to0) {
         match report_ov_to_rendezvous(
             &ov,
             &udt.owner_addresses,
-            &udt.owner_key,
+            &udt.owner_keyring,
             udt.ov_registration_period,
             &udt.trusted_device_keys,
         )
@@ -209,36 +238,34 @@ async fn check_registration_window(udt: &OwnerServiceUDT) -> Result<()> {
 async fn report_ov_to_rendezvous(
     ov: &OwnershipVoucher,
     owner_addresses: &[TO2AddressEntry],
-    owner_key: &PKey<Private>,
+    owner_keyring: &OwnerKeyring,
     registration_period: u32,
     trusted_device_keys: &Option<X5Bag>,
 ) -> Result<u32> {
     let ov_header = ov.header();
-    if ov_header.protocol_version() != ProtocolVersion::Version1_1 {
-        bail!(
-            "Protocol version in OV ({}) not supported ({})",
-            ov_header.protocol_version(),
-            ProtocolVersion::Version1_1
-        );
-    }
 
-    match ov.device_certificate_chain() {
-        None => {
-            bail!("No device certificate chain found");
-        }
-        Some(device_cert_chain) => {
-            if let Some(trusted_device_keys) = trusted_device_keys {
-                device_cert_chain
-                    .verify_from_x5bag(trusted_device_keys)
-                    .context("Device certificate is not trusted")?
-            } else {
-                device_cert_chain
-                    .insecure_verify_without_root_verification()
-                    .context("Device certificate chain is malformed")?
-            };
-        }
+    let owner_key = match owner_keyring.key_for_ov(ov)? {
+        Some(owner_key) => owner_key,
+        None => bail!("Ownership voucher is extended to an owner key that is not in the keyring"),
     };
 
+    if ov.device_certificate_chain().is_none() {
+        bail!("No device certificate chain found");
+    }
+    let report = ov.verify(
+        None,
+        trusted_device_keys.as_ref(),
+        Some(&owner_key.public_key),
+        None,
+    );
+    if let Some(failure) = report.failures().next() {
+        bail!("Ownership voucher is invalid: {failure}");
+    }
+
+    if !requires_to0(ov) {
+        bail!("Ownership voucher uses Rendezvous Bypass, no TO0 needed");
+    }
+
     // Determine the RV IP
     let rv_info = ov_header
         .rendezvous_info()
@@ -264,8 +291,9 @@ async fn report_ov_to_rendezvous(
                 rv_url
             );
 
+            // 1.0 vouchers are registered with the 1.0 messages
             let mut rv_client =
-                fdo_http_wrapper::client::ServiceClient::new(ProtocolVersion::Version1_1, &rv_url);
+                fdo_http_wrapper::client::ServiceClient::new(ov_header.protocol_version(), &rv_url);
 
             // Send: Hello, Receive: HelloAck
             let hello_ack: RequestResult<messages::v11::to0::HelloAck> = rv_client
@@ -288,8 +316,8 @@ async fn report_ov_to_rendezvous(
                 Hash::from_data(HashType::Sha384, &to0d_vec).context("Error hashing to0d")?;
             let to0d = ByteBuf::from(to0d_vec);
             let to1d_payload = TO1DataPayload::new(Vec::from(owner_addresses), to0d_hash);
-            let to1d =
-                COSESign::new(&to1d_payload, None, owner_key).context("Error signing to1d")?;
+            let to1d = COSESign::new(&to1d_payload, None, &owner_key.private_key)
+                .context("Error signing to1d")?;
             // Send: OwnerSign, Receive: AcceptOwner
             let msg = messages::v11::to0::OwnerSign::new(to0d, to1d)
                 .context("Error creating OwnerSign message")?;
@@ -348,7 +376,7 @@ async fn perform_maintenance(udt: OwnerServiceUDT) -> std::result::Result<(), &'
     }
 }
 
-/// Generate an ephemeral owner2 key: we do not support reuse or resale protocols
+/// Generate an ephemeral owner2 key, used if no persistent owner2 key is configured
 fn generate_owner2_keys() -> Result<(PKey<Private>, PublicKey)> {
     let owner2_key_group =
         EcGroup::from_curve_name(Nid::X9_62_PRIME256V1).context("Error getting nist 256 group")?;
@@ -399,6 +427,9 @@ async fn main() -> Result<()> {
 
     // Bind information
     let bind_addr = settings.bind.clone();
+    let coap_bind = settings.coap_bind.clone();
+    let metrics_bind = settings.metrics_bind.clone();
+    let tls_settings = settings.tls;
 
     // Load trusted CA certs for device certificate chain verification
     let trusted_device_keys = settings
@@ -417,23 +448,22 @@ async fn main() -> Result<()> {
         .transpose()
         .context("Error loading trusted device keys")?;
 
-    // Our private key
-    let owner_key = load_private_key(&settings.owner_private_key_path).with_context(|| {
-        format!(
-            "Error loading owner key from {}",
-            &settings.owner_private_key_path
-        )
-    })?;
-    let owner_pubkey = {
-        let contents = std::fs::read(&settings.owner_public_key_path).with_context(|| {
-            format!(
-                "Error reading owner public key from {}",
-                &settings.owner_public_key_path
-            )
-        })?;
-        PublicKey::try_from(X509::from_pem(&contents).context("Error parsing owner public key")?)
-            .context("Error converting owner public key to PK")?
-    };
+    // Our private keys
+    let owner_key = OwnerKey::load(
+        &settings.owner_private_key_path,
+        &settings.owner_public_key_path,
+    )?;
+    let additional_owner_keys = settings
+        .additional_owner_keys
+        .iter()
+        .map(|key| OwnerKey::load(&key.private_key_path, &key.public_key_path))
+        .collect::<Result<Vec<_>>>()
+        .context("Error loading additional owner keys")?;
+    log::info!(
+        "Loaded owner keyring with {} additional keys",
+        additional_owner_keys.len()
+    );
+    let owner_keyring = OwnerKeyring::new(owner_key, additional_owner_keys);
 
     // Voucher registration times
     let ov_registration_period = match settings.ov_registration_period {
@@ -477,6 +507,10 @@ async fn main() -> Result<()> {
         .ownership_voucher_store_driver
         .initialize()
         .context("Error initializing ownership voucher datastore")?;
+    let ownership_voucher_store = fdo_http_wrapper::server::metrics::timed_store(
+        "ownership_vouchers",
+        ownership_voucher_store,
+    );
     let session_store = settings
         .session_store_driver
         .initialize()
@@ -493,9 +527,64 @@ async fn main() -> Result<()> {
 
     let session_store = fdo_http_wrapper::server::SessionStore::new(session_store);
 
-    // Generate a new Owner2
-    let (owner2_key, owner2_pub) =
-        generate_owner2_keys().context("Error generating new owner2 keys")?;
+    // Load or generate the Owner2 key
+    let (owner2_key, owner2_pub) = match (
+        &settings.owner2_private_key_path,
+        &settings.owner2_public_key_path,
+    ) {
+        (Some(private_key_path), Some(public_key_path)) => {
+            let owner2_key = private_key_path
+                .load()
+                .with_context(|| format!("Error loading owner2 key from {}", private_key_path))?;
+            let contents = std::fs::read(public_key_path).with_context(|| {
+                format!("Error reading owner2 public key from {}", public_key_path)
+            })?;
+            let owner2_pub = PublicKey::try_from(
+                X509::from_pem(&contents).context("Error parsing owner2 public key")?,
+            )
+            .context("Error converting owner2 public key to PK")?;
+            if !owner2_pub.matches_pkey(&owner2_key)? {
+                bail!("Owner2 public key does not match the owner2 private key");
+            }
+            (owner2_key, owner2_pub)
+        }
+        (None, None) => generate_owner2_keys().context("Error generating new owner2 keys")?,
+        _ => bail!("Both owner2_private_key_path and owner2_public_key_path must be set"),
+    };
+
+    // Credential Reuse and Resale: devices whose voucher is extended to the
+    // Owner2Key keep their credential, all others get a replacement
+    let mut credential_resale = false;
+    for owner_key in owner_keyring.keys() {
+        if !owner_key.matches(&owner2_pub)? {
+            credential_resale = true;
+        }
+    }
+    let replacement_rendezvous_info = settings
+        .replacement_rendezvous_info
+        .as_deref()
+        .map(load_rendezvous_info)
+        .transpose()
+        .context("Error loading replacement rendezvous info")?;
+    let replacement_ownership_voucher_store = settings
+        .replacement_ownership_voucher_store_driver
+        .as_ref()
+        .map(|driver| driver.initialize())
+        .transpose()
+        .context("Error initializing replacement ownership voucher datastore")?
+        .map(|store| {
+            fdo_http_wrapper::server::metrics::timed_store("replacement_ownership_vouchers", store)
+        });
+    if !credential_resale {
+        log::info!("Owner2 key is the owner key, performing Credential Reuse");
+    } else if settings.owner2_private_key_path.is_some() {
+        if replacement_ownership_voucher_store.is_none() {
+            bail!(
+                "Credential Resale requires replacement_ownership_voucher_store_driver to be set"
+            );
+        }
+        log::info!("Persistent owner2 key configured, performing Credential Resale for vouchers not extended to it");
+    }
 
     let mut owner_addresses: Vec<TO2AddressEntry> = Vec::new();
     for oa in settings.owner_addresses {
@@ -521,14 +610,17 @@ async fn main() -> Result<()> {
         // Trusted keys
         trusted_device_keys,
 
-        // Private owner key
-        owner_key,
-        owner_pubkey,
+        // Private owner keys
+        owner_keyring,
 
-        // Ephemeral owner2 key
+        // Owner2 key
         owner2_key,
         owner2_pub,
 
+        // Credential Resale
+        replacement_rendezvous_info,
+        replacement_ownership_voucher_store,
+
         // Service Info
         service_info_api_client,
 
@@ -540,6 +632,12 @@ async fn main() -> Result<()> {
         ov_re_registration_window,
 
         window_check_enabled,
+
+        max_service_info_loops: settings
+            .max_service_info_loops
+            .unwrap_or(DEFAULT_MAX_SERVICE_INFO_LOOPS),
+
+        admin_auth_token: settings.admin_auth_token.map(|s| format!("Bearer {s}")),
     });
 
     // Initialize handlers
@@ -584,6 +682,39 @@ async fn main() -> Result<()> {
         handlers::done,
     );
 
+    // TO2 for FDO 1.0 devices
+    let handler_to2_hello_device_v10 = fdo_http_wrapper::server::fdo_request_filter_v10(
+        user_data.clone(),
+        session_store.clone(),
+        handlers::hello_device,
+    );
+    let handler_to2_get_ov_next_entry_v10 = fdo_http_wrapper::server::fdo_request_filter_v10(
+        user_data.clone(),
+        session_store.clone(),
+        handlers::get_ov_next_entry,
+    );
+    let handler_to2_prove_device_v10 = fdo_http_wrapper::server::fdo_request_filter_v10(
+        user_data.clone(),
+        session_store.clone(),
+        handlers::prove_device,
+    );
+    let handler_to2_device_service_info_ready_v10 =
+        fdo_http_wrapper::server::fdo_request_filter_v10(
+            user_data.clone(),
+            session_store.clone(),
+            handlers::device_service_info_ready,
+        );
+    let handler_to2_device_service_info_v10 = fdo_http_wrapper::server::fdo_request_filter_v10(
+        user_data.clone(),
+        session_store.clone(),
+        handlers::device_service_info,
+    );
+    let handler_to2_done_v10 = fdo_http_wrapper::server::fdo_request_filter_v10(
+        user_data.clone(),
+        session_store.clone(),
+        handlers::done,
+    );
+
     let rtr_enabled = settings.report_to_rendezvous_endpoint_enabled;
     let ud = user_data.clone();
     let handler_report_to_rendezvous = warp::path("report-to-rendezvous")
@@ -592,34 +723,113 @@ async fn main() -> Result<()> {
         .untuple_one()
         .and_then(handlers::report_to_rendezvous_handler);
 
+    // Admin API
+    let ud = user_data.clone();
+    let admin_v0_auth = warp::any()
+        .map(move || ud.clone())
+        .and(warp::header::header("Authorization"))
+        .and_then(admin::admin_auth_handler);
+    let handler_admin_v0_list = warp::get()
+        .and(warp::path("admin"))
+        .and(warp::path("v0"))
+        .and(warp::path("ownership_vouchers"))
+        .and(warp::path::end())
+        .and(admin_v0_auth.clone())
+        .and_then(admin::admin_v0_list_handler);
+    let handler_admin_v0_upload = warp::post()
+        .and(warp::path("admin"))
+        .and(warp::path("v0"))
+        .and(warp::path("ownership_vouchers"))
+        .and(warp::path::end())
+        .and(admin_v0_auth.clone())
+        .and(warp::body::content_length_limit(1024 * 1024))
+        .and(warp::body::bytes())
+        .and_then(admin::admin_v0_upload_handler);
+    let handler_admin_v0_get = warp::get()
+        .and(warp::path("admin"))
+        .and(warp::path("v0"))
+        .and(warp::path("ownership_vouchers"))
+        .and(admin_v0_auth.clone())
+        .and(warp::path::param::<String>())
+        .and(warp::path::end())
+        .and_then(admin::admin_v0_get_handler);
+    let handler_admin_v0_delete = warp::delete()
+        .and(warp::path("admin"))
+        .and(warp::path("v0"))
+        .and(warp::path("ownership_vouchers"))
+        .and(admin_v0_auth.clone())
+        .and(warp::path::param::<String>())
+        .and(warp::path::end())
+        .and_then(admin::admin_v0_delete_handler);
+    let handler_admin_v0_report_to_rendezvous = warp::post()
+        .and(warp::path("admin"))
+        .and(warp::path("v0"))
+        .and(warp::path("ownership_vouchers"))
+        .and(admin_v0_auth)
+        .and(warp::path::param::<String>())
+        .and(warp::path("report-to-rendezvous"))
+        .and(warp::path::end())
+        .and_then(admin::admin_v0_report_to_rendezvous_handler);
+
+    // The FDO protocol messages, the only routes also served over CoAP
+    let fdo_routes = handler_to2_hello_device
+        .or(handler_to2_get_ov_next_entry)
+        .or(handler_to2_prove_device)
+        .or(handler_to2_device_service_info_ready)
+        .or(handler_to2_device_service_info)
+        .or(handler_to2_done)
+        // TO2 for FDO 1.0
+        .or(handler_to2_hello_device_v10)
+        .or(handler_to2_get_ov_next_entry_v10)
+        .or(handler_to2_prove_device_v10)
+        .or(handler_to2_device_service_info_ready_v10)
+        .or(handler_to2_device_service_info_v10)
+        .or(handler_to2_done_v10);
+
     let routes = warp::post()
         .and(
             hello
                 .or(handler_ping)
                 .or(handler_report_to_rendezvous)
-                // TO2
-                .or(handler_to2_hello_device)
-                .or(handler_to2_get_ov_next_entry)
-                .or(handler_to2_prove_device)
-                .or(handler_to2_device_service_info_ready)
-                .or(handler_to2_device_service_info)
-                .or(handler_to2_done),
+                .or(fdo_routes.clone()),
         )
+        // Admin API
+        .or(handler_admin_v0_list)
+        .or(handler_admin_v0_upload)
+        .or(handler_admin_v0_get)
+        .or(handler_admin_v0_delete)
+        .or(handler_admin_v0_report_to_rendezvous)
+        .recover(fdo_http_wrapper::server::handle_rejection)
+        .with(warp::log("owner-onboarding-service"));
+    let coap_routes = fdo_routes
         .recover(fdo_http_wrapper::server::handle_rejection)
         .with(warp::log("owner-onboarding-service"));
 
     log::info!("Listening on {}", bind_addr);
-    let server = warp::serve(routes);
-
+    if let Some(metrics_bind) = metrics_bind {
+        let metrics_server = fdo_http_wrapper::server::metrics::serve_metrics(metrics_bind.into())
+            .context("Error binding metrics listener")?;
+        tokio::spawn(metrics_server);
+    }
+    if let Some(coap_bind) = coap_bind {
+        log::info!("Listening for CoAP on {}", coap_bind);
+        let coap_server = fdo_http_wrapper::server::coap::CoapServer::bind(
+            coap_bind.into(),
+            warp::service(coap_routes),
+        )
+        .await
+        .context("Error binding CoAP listener")?;
+        tokio::spawn(coap_server.run());
+    }
     let maintenance_runner =
         tokio::spawn(async move { perform_maintenance(user_data.clone()).await });
 
-    let server = server
-        .bind_with_graceful_shutdown(bind_addr, async {
-            signal(SignalKind::terminate()).unwrap().recv().await;
-            log::info!("Terminating");
-        })
-        .1;
+    let shutdown = async {
+        signal(SignalKind::terminate()).unwrap().recv().await;
+        log::info!("Terminating");
+    };
+    let (_, server) =
+        fdo_http_wrapper::server::serve(routes, bind_addr, tls_settings.map(Into::into), shutdown);
     let server = tokio::spawn(server);
 
     tokio::select!(
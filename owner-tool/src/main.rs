@@ -7,6 +7,7 @@ use fdo_db::postgres::PostgresOwnerDB;
 use fdo_db::sqlite::SqliteOwnerDB;
 use fdo_db::DBStoreOwner;
 use fdo_db::{postgres::PostgresManufacturerDB, sqlite::SqliteManufacturerDB, DBStoreManufacturer};
+use fdo_util::private_key::PrivateKeyLocation;
 use openssl::{
     asn1::{Asn1Integer, Asn1Time},
     bn::BigNum,
@@ -18,6 +19,7 @@ use openssl::{
     sign::Signer,
     x509::{X509Builder, X509NameBuilder, X509NameRef, X509},
 };
+use serde::Serialize;
 use serde_yaml::Value;
 use std::fs::File;
 use tss_esapi::{structures::Public as TssPublic, traits::UnMarshall};
@@ -25,7 +27,8 @@ use tss_esapi::{structures::Public as TssPublic, traits::UnMarshall};
 use fdo_data_formats::{
     constants::{HashType, RendezvousVariable},
     devicecredential::FileDeviceCredential,
-    ownershipvoucher::{OwnershipVoucher, OwnershipVoucherHeader},
+    enhanced_types::X5Bag,
+    ownershipvoucher::{OwnershipVoucher, OwnershipVoucherHeader, VerificationReport},
     publickey::{PublicKey, X5Chain},
     types::{CborSimpleType, Guid, HMac, Hash, RendezvousInfo},
     ProtocolVersion, Serializable,
@@ -48,6 +51,8 @@ enum Commands {
     DumpDeviceCredential(DumpDeviceCredentialArguments),
     /// Extends an ownership voucher for a new owner
     ExtendOwnershipVoucher(ExtendOwnershipVoucherArguments),
+    /// Verifies an ownership voucher, and prints the result of each check
+    VerifyOwnershipVoucher(VerifyOwnershipVoucherArguments),
     /// Exports a single or all the ownership vouchers present in the Manufacturer DB
     ExportManufacturerVouchers(ExportManufacturerVouchersArguments),
     /// Imports into the Owner DB a single ownership voucher or all the ownership vouchers present at a given path
@@ -65,7 +70,7 @@ struct InitializeDeviceArguments {
     /// Path to the certificate for the manufacturer
     #[clap(long, action = ArgAction::Set)]
     manufacturer_cert: String,
-    /// Private key for the device certificate CA
+    /// Private key for the device certificate CA (path or pkcs11: URI)
     #[clap(long, action = ArgAction::Set)]
     device_cert_ca_private_key: String,
     /// Chain with CA certificates for device certificate
@@ -74,6 +79,26 @@ struct InitializeDeviceArguments {
     /// Path to a TOML file containing the rendezvous information
     #[clap(long, action = ArgAction::Set)]
     rendezvous_info: String,
+    /// FDO protocol version of the device credential and ownership voucher
+    #[clap(value_enum, long, default_value = "101", action = ArgAction::Set)]
+    protocol_version: DeviceProtocolVersion,
+}
+
+#[derive(Copy, Clone, ValueEnum)]
+enum DeviceProtocolVersion {
+    #[value(name = "100")]
+    Version1_0,
+    #[value(name = "101")]
+    Version1_1,
+}
+
+impl From<DeviceProtocolVersion> for ProtocolVersion {
+    fn from(version: DeviceProtocolVersion) -> Self {
+        match version {
+            DeviceProtocolVersion::Version1_0 => ProtocolVersion::Version1_0,
+            DeviceProtocolVersion::Version1_1 => ProtocolVersion::Version1_1,
+        }
+    }
 }
 
 #[derive(Copy, Clone, ValueEnum)]
@@ -82,6 +107,12 @@ enum OutputFormat {
     Cose,
 }
 
+#[derive(Copy, Clone, ValueEnum)]
+enum StructuredOutputFormat {
+    Json,
+    Yaml,
+}
+
 #[derive(Args)]
 struct DumpOwnershipVoucherArguments {
     /// Path to the ownership voucher
@@ -89,19 +120,31 @@ struct DumpOwnershipVoucherArguments {
     /// Output format
     #[clap(value_enum, long, required = false, action = ArgAction::Set)]
     outform: Option<OutputFormat>,
+    /// Machine-readable output format, instead of text
+    #[clap(
+        value_enum,
+        long,
+        required = false,
+        action = ArgAction::Set,
+        conflicts_with = "outform"
+    )]
+    output: Option<StructuredOutputFormat>,
 }
 
 #[derive(Args)]
 struct DumpDeviceCredentialArguments {
     /// Path to the device credential
     path: String,
+    /// Machine-readable output format, instead of text
+    #[clap(value_enum, long, required = false, action = ArgAction::Set)]
+    output: Option<StructuredOutputFormat>,
 }
 
 #[derive(Args)]
 struct ExtendOwnershipVoucherArguments {
     /// Path to the ownership voucher
     path: String,
-    /// Path to the current owner private key
+    /// Current owner private key (path or pkcs11: URI)
     #[clap(long, action = ArgAction::Set)]
     current_owner_private_key: String,
     /// Path to the new owner certificate
@@ -109,6 +152,24 @@ struct ExtendOwnershipVoucherArguments {
     new_owner_cert: String,
 }
 
+#[derive(Args)]
+struct VerifyOwnershipVoucherArguments {
+    /// Path to the ownership voucher
+    path: String,
+    /// Path to the trusted manufacturer certificates
+    #[clap(long, action = ArgAction::Set)]
+    trusted_manufacturer_keys: Option<String>,
+    /// Path to the CA certificates trusted to issue device certificates
+    #[clap(long, action = ArgAction::Set)]
+    device_ca_chain: Option<String>,
+    /// Path to the certificate of the owner the voucher should be extended to
+    #[clap(long, action = ArgAction::Set)]
+    expected_owner_cert: Option<String>,
+    /// Machine-readable output format, instead of text
+    #[clap(value_enum, long, required = false, action = ArgAction::Set)]
+    output: Option<StructuredOutputFormat>,
+}
+
 #[derive(Args)]
 struct ExportManufacturerVouchersArguments {
     /// Type of the Manufacturer DB holding the OVs
@@ -147,14 +208,14 @@ async fn main() -> Result<()> {
         Commands::DumpOwnershipVoucher(args) => dump_voucher(&args),
         Commands::DumpDeviceCredential(args) => dump_devcred(&args),
         Commands::ExtendOwnershipVoucher(args) => extend_voucher(&args),
+        Commands::VerifyOwnershipVoucher(args) => verify_voucher(&args),
         Commands::ExportManufacturerVouchers(args) => export_manufacturer_vouchers(&args),
         Commands::ImportOwnershipVouchers(args) => import_ownership_vouchers(&args),
     }
 }
 
-fn load_private_key(path: &str) -> Result<PKey<Private>, Error> {
-    let contents = fs::read(path)?;
-    Ok(PKey::private_key_from_der(&contents)?)
+fn load_private_key(location: &str) -> Result<PKey<Private>, Error> {
+    PrivateKeyLocation::from_str(location)?.load()
 }
 
 fn load_x509(path: &str) -> Result<X509, Error> {
@@ -167,6 +228,23 @@ fn load_x509s(path: &str) -> Result<Vec<X509>, Error> {
     Ok(X509::stack_from_pem(&contents)?)
 }
 
+fn load_x5bag(path: &str) -> Result<X5Bag, Error> {
+    Ok(X5Bag::with_certs(load_x509s(path)?)?)
+}
+
+fn write_structured_output<T: Serialize>(format: StructuredOutputFormat, value: &T) -> Result<()> {
+    let output = match format {
+        StructuredOutputFormat::Json => {
+            serde_json::to_string_pretty(value).context("Error serializing output to JSON")?
+        }
+        StructuredOutputFormat::Yaml => {
+            serde_yaml::to_string(value).context("Error serializing output to YAML")?
+        }
+    };
+    println!("{output}");
+    Ok(())
+}
+
 fn yaml_to_cbor(val: &Value) -> Result<CborSimpleType, Error> {
     Ok(match val {
         Value::Null => CborSimpleType::Null,
@@ -197,6 +275,36 @@ fn yaml_to_cbor(val: &Value) -> Result<CborSimpleType, Error> {
     })
 }
 
+fn cbor_to_yaml(val: &CborSimpleType) -> Result<Value, Error> {
+    Ok(match val {
+        CborSimpleType::Null => Value::Null,
+        CborSimpleType::Bool(b) => Value::Bool(*b),
+        CborSimpleType::Integer(nr) => {
+            if let Ok(nr) = u64::try_from(*nr) {
+                nr.into()
+            } else if let Ok(nr) = i64::try_from(*nr) {
+                nr.into()
+            } else {
+                bail!("Integer out of range: {nr}");
+            }
+        }
+        CborSimpleType::Float(nr) => (*nr).into(),
+        CborSimpleType::Text(str) => Value::String(str.clone()),
+        CborSimpleType::Bytes(bytes) => Value::String(hex::encode(bytes)),
+        CborSimpleType::Array(seq) => Value::Sequence(
+            seq.iter()
+                .map(cbor_to_yaml)
+                .collect::<Result<Vec<Value>>>()?,
+        ),
+        CborSimpleType::Map(map) => Value::Mapping(
+            map.iter()
+                .map(|(key, val)| Ok((cbor_to_yaml(key)?, cbor_to_yaml(val)?)))
+                .collect::<Result<serde_yaml::Mapping>>()?,
+        ),
+        _ => bail!("Unsupported CBOR value encountered"),
+    })
+}
+
 fn load_rendezvous_info(path: &str) -> Result<RendezvousInfo, Error> {
     let contents = fs::read(path)?;
     let mut info = Vec::new();
@@ -238,6 +346,29 @@ fn load_rendezvous_info(path: &str) -> Result<RendezvousInfo, Error> {
     RendezvousInfo::new(info).context("Error serializing rendezvous info")
 }
 
+/// Converts rendezvous info to the notation used by load_rendezvous_info
+fn rendezvous_info_to_yaml(info: &RendezvousInfo) -> Result<Vec<serde_yaml::Mapping>, Error> {
+    let mut out = Vec::new();
+
+    for directive in info.values() {
+        let mut entry = serde_yaml::Mapping::new();
+
+        for (key, val) in directive {
+            let val = CborSimpleType::deserialize_data(val)
+                .with_context(|| format!("Error parsing value for key '{key:?}'"))?;
+            let val = key
+                .value_from_machine_to_human(val)
+                .with_context(|| format!("Error converting value for key '{key:?}'"))?;
+
+            entry.insert(Value::String(key.name().to_string()), cbor_to_yaml(&val)?);
+        }
+
+        out.push(entry);
+    }
+
+    Ok(out)
+}
+
 fn build_device_cert<T: openssl::pkey::HasPublic>(
     subject_name: &X509NameRef,
     device_pubkey: &PKeyRef<T>,
@@ -393,7 +524,7 @@ fn initialize_device(args: &InitializeDeviceArguments) -> Result<(), Error> {
 
     // Construct Ownership Voucher Header
     let ov_header = OwnershipVoucherHeader::new(
-        ProtocolVersion::Version1_1,
+        args.protocol_version.into(),
         device_guid.clone(),
         rendezvous_info.clone(),
         args.device_id.clone(),
@@ -408,7 +539,7 @@ fn initialize_device(args: &InitializeDeviceArguments) -> Result<(), Error> {
     // Build device credential
     let devcred = FileDeviceCredential {
         active: true,
-        protver: ProtocolVersion::Version1_1,
+        protver: args.protocol_version.into(),
         device_info: args.device_id.clone(),
         guid: device_guid.clone(),
         rvinfo: rendezvous_info,
@@ -450,6 +581,161 @@ fn initialize_device(args: &InitializeDeviceArguments) -> Result<(), Error> {
     Ok(())
 }
 
+#[derive(Serialize)]
+struct HashOutput {
+    hash_type: String,
+    value: String,
+}
+
+impl From<&Hash> for HashOutput {
+    fn from(hash: &Hash) -> Self {
+        HashOutput {
+            hash_type: format!("{:?}", hash.get_type()),
+            value: hex::encode(hash.value()),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct CertificateOutput {
+    subject: String,
+    issuer: String,
+    serial_number: String,
+    not_before: String,
+    not_after: String,
+}
+
+fn x509_name_to_string(name: &X509NameRef) -> Result<String, Error> {
+    let mut parts = Vec::new();
+    for entry in name.entries() {
+        let field = entry.object().nid().short_name()?;
+        let value = entry.data().as_utf8()?;
+        parts.push(format!("{field}={value}"));
+    }
+    Ok(parts.join(", "))
+}
+
+impl TryFrom<&X509> for CertificateOutput {
+    type Error = Error;
+
+    fn try_from(cert: &X509) -> Result<Self, Error> {
+        Ok(CertificateOutput {
+            subject: x509_name_to_string(cert.subject_name())?,
+            issuer: x509_name_to_string(cert.issuer_name())?,
+            serial_number: cert.serial_number().to_bn()?.to_hex_str()?.to_string(),
+            not_before: cert.not_before().to_string(),
+            not_after: cert.not_after().to_string(),
+        })
+    }
+}
+
+fn certificate_chain_output(chain: &[X509]) -> Result<Vec<CertificateOutput>, Error> {
+    chain.iter().map(CertificateOutput::try_from).collect()
+}
+
+#[derive(Serialize)]
+struct PublicKeyOutput {
+    key_type: String,
+    pem: String,
+    certificate_chain: Option<Vec<CertificateOutput>>,
+}
+
+impl TryFrom<&PublicKey> for PublicKeyOutput {
+    type Error = Error;
+
+    fn try_from(key: &PublicKey) -> Result<Self, Error> {
+        Ok(PublicKeyOutput {
+            key_type: format!("{:?}", key.keytype()),
+            pem: String::from_utf8(key.pkey().public_key_to_pem()?)?,
+            certificate_chain: key
+                .chain()
+                .map(|chain| certificate_chain_output(chain.chain()))
+                .transpose()?,
+        })
+    }
+}
+
+#[derive(Serialize)]
+struct OwnershipVoucherHeaderOutput {
+    protocol_version: u16,
+    guid: String,
+    rendezvous_info: Vec<serde_yaml::Mapping>,
+    rendezvous_bypass_only: bool,
+    device_info: String,
+    manufacturer_public_key: PublicKeyOutput,
+    device_certificate_chain_hash: Option<HashOutput>,
+}
+
+#[derive(Serialize)]
+struct OwnershipVoucherEntryOutput {
+    previous_entry_hash: HashOutput,
+    header_info_hash: HashOutput,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    extra: Option<std::collections::BTreeMap<String, String>>,
+    public_key: PublicKeyOutput,
+}
+
+#[derive(Serialize)]
+struct OwnershipVoucherOutput {
+    header: OwnershipVoucherHeaderOutput,
+    header_hmac: HashOutput,
+    device_certificate_chain: Option<Vec<CertificateOutput>>,
+    entries: Vec<OwnershipVoucherEntryOutput>,
+}
+
+impl TryFrom<&OwnershipVoucher> for OwnershipVoucherOutput {
+    type Error = Error;
+
+    fn try_from(ov: &OwnershipVoucher) -> Result<Self, Error> {
+        let ov_header = ov.header();
+
+        let header = OwnershipVoucherHeaderOutput {
+            protocol_version: ov_header.protocol_version() as u16,
+            guid: ov_header.guid().to_string(),
+            rendezvous_info: rendezvous_info_to_yaml(ov_header.rendezvous_info())?,
+            rendezvous_bypass_only: ov_header.rendezvous_info().is_bypass_only(),
+            device_info: ov_header.device_info().to_string(),
+            manufacturer_public_key: PublicKeyOutput::try_from(
+                ov_header.manufacturer_public_key(),
+            )?,
+            device_certificate_chain_hash: ov_header
+                .device_certificate_chain_hash()
+                .map(HashOutput::from),
+        };
+
+        let mut entries = Vec::new();
+        for (pos, entry) in ov
+            .iter_entries()
+            .context("Error creating OV iterator")?
+            .enumerate()
+        {
+            let entry = entry.with_context(|| format!("Error parsing entry {pos}"))?;
+
+            entries.push(OwnershipVoucherEntryOutput {
+                previous_entry_hash: entry.hash_previous_entry().into(),
+                header_info_hash: entry.hash_header_info().into(),
+                extra: entry.extra().map(|extra| {
+                    extra
+                        .iter()
+                        .map(|(key, val)| (key.to_string(), hex::encode(val)))
+                        .collect()
+                }),
+                public_key: PublicKeyOutput::try_from(entry.public_key())?,
+            });
+        }
+
+        Ok(OwnershipVoucherOutput {
+            header,
+            header_hmac: ov.header_hmac().into(),
+            device_certificate_chain: ov
+                .device_certificate_chain()
+                .map(|chain| certificate_chain_output(chain.chain()))
+                .transpose()?,
+            entries,
+        })
+    }
+}
+
 fn dump_voucher(args: &DumpOwnershipVoucherArguments) -> Result<(), Error> {
     let ov = {
         let cts = fs::read(args.path.clone()).context("Error reading ownership voucher")?;
@@ -474,15 +760,12 @@ fn dump_voucher(args: &DumpOwnershipVoucherArguments) -> Result<(), Error> {
         return Ok(());
     }
 
-    let ov_header = ov.header();
-    if ov_header.protocol_version() != ProtocolVersion::Version1_1 {
-        bail!(
-            "Protocol version in OV ({}) not supported ({})",
-            ov_header.protocol_version(),
-            ProtocolVersion::Version1_1,
-        );
+    if let Some(format) = args.output {
+        return write_structured_output(format, &OwnershipVoucherOutput::try_from(&ov)?);
     }
 
+    let ov_header = ov.header();
+
     println!("Header:");
     println!("\tProtocol Version: {}", ov_header.protocol_version());
     println!("\tDevice GUID: {}", ov_header.guid());
@@ -490,6 +773,9 @@ fn dump_voucher(args: &DumpOwnershipVoucherArguments) -> Result<(), Error> {
     for rv_entry in ov_header.rendezvous_info().values() {
         println!("\t\t- {rv_entry:?}");
     }
+    if ov_header.rendezvous_info().is_bypass_only() {
+        println!("\t\t(Rendezvous Bypass: no TO0 or TO1 is performed)");
+    }
     println!("\tDevice Info: {:?}", ov_header.device_info());
     println!(
         "\tManufacturer public key: {}",
@@ -530,6 +816,27 @@ fn dump_voucher(args: &DumpOwnershipVoucherArguments) -> Result<(), Error> {
     Ok(())
 }
 
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum KeyStorageOutput {
+    Plain,
+    Tpm {
+        signing_public: String,
+        hmac_public: String,
+    },
+}
+
+#[derive(Serialize)]
+struct DeviceCredentialOutput {
+    active: bool,
+    protocol_version: u16,
+    device_info: String,
+    guid: String,
+    rendezvous_info: Vec<serde_yaml::Mapping>,
+    public_key_hash: HashOutput,
+    key_storage: KeyStorageOutput,
+}
+
 fn dump_devcred(args: &DumpDeviceCredentialArguments) -> Result<(), Error> {
     let dc = {
         let dc = fs::read(args.path.clone()).context("Error reading device credential")?;
@@ -537,11 +844,32 @@ fn dump_devcred(args: &DumpDeviceCredentialArguments) -> Result<(), Error> {
             .context("Error deserializing device credential")?
     };
 
-    if dc.protver != ProtocolVersion::Version1_1 {
-        bail!(
-            "Protocol version in OV ({}) not supported ({})",
-            dc.protver,
-            ProtocolVersion::Version1_1
+    if let Some(format) = args.output {
+        let key_storage = match &dc.key_storage {
+            fdo_data_formats::devicecredential::file::KeyStorage::Plain { .. } => {
+                KeyStorageOutput::Plain
+            }
+            fdo_data_formats::devicecredential::file::KeyStorage::Tpm {
+                signing_public,
+                hmac_public,
+                ..
+            } => KeyStorageOutput::Tpm {
+                signing_public: hex::encode(signing_public),
+                hmac_public: hex::encode(hmac_public),
+            },
+        };
+
+        return write_structured_output(
+            format,
+            &DeviceCredentialOutput {
+                active: dc.active,
+                protocol_version: dc.protver as u16,
+                device_info: dc.device_info.clone(),
+                guid: dc.guid.to_string(),
+                rendezvous_info: rendezvous_info_to_yaml(&dc.rvinfo)?,
+                public_key_hash: (&dc.pubkey_hash).into(),
+                key_storage,
+            },
         );
     }
 
@@ -584,13 +912,10 @@ fn extend_voucher(args: &ExtendOwnershipVoucherArguments) -> Result<(), Error> {
         OwnershipVoucher::from_pem_or_raw(&ov).context("Error deserializing ownership voucher")?
     };
 
-    let ov_header = ov.header();
-    if ov_header.protocol_version() != ProtocolVersion::Version1_1 {
-        bail!(
-            "Protocol version in OV ({}) not supported ({})",
-            ov_header.protocol_version(),
-            ProtocolVersion::Version1_1,
-        );
+    // Only extend vouchers that are valid to begin with
+    let report = ov.verify(None, None, None, None);
+    if let Some(failure) = report.failures().next() {
+        bail!("Ownership voucher is invalid: {failure}");
     }
 
     let current_owner_private_key = load_private_key(&args.current_owner_private_key)
@@ -626,6 +951,71 @@ fn extend_voucher(args: &ExtendOwnershipVoucherArguments) -> Result<(), Error> {
     Ok(())
 }
 
+#[derive(Serialize)]
+struct VerifyOwnershipVoucherOutput<'a> {
+    guid: String,
+    #[serde(flatten)]
+    report: &'a VerificationReport,
+}
+
+fn verify_voucher(args: &VerifyOwnershipVoucherArguments) -> Result<(), Error> {
+    let ov = {
+        let ov = fs::read(&args.path).context("Error reading ownership voucher")?;
+        OwnershipVoucher::from_pem_or_raw(&ov).context("Error deserializing ownership voucher")?
+    };
+
+    let trusted_manufacturer_keys = match &args.trusted_manufacturer_keys {
+        None => None,
+        Some(path) => Some(
+            load_x5bag(path)
+                .with_context(|| format!("Error loading trusted manufacturer keys at {path}"))?,
+        ),
+    };
+    let device_ca_bag = match &args.device_ca_chain {
+        None => None,
+        Some(path) => Some(
+            load_x5bag(path)
+                .with_context(|| format!("Error loading device CA certificates at {path}"))?,
+        ),
+    };
+    let expected_owner_key = match &args.expected_owner_cert {
+        None => None,
+        Some(path) => {
+            let cert = load_x509(path)
+                .with_context(|| format!("Error loading expected owner certificate at {path}"))?;
+            Some(PublicKey::try_from(cert).context("Error parsing expected owner public key")?)
+        }
+    };
+
+    let report = ov.verify(
+        trusted_manufacturer_keys.as_ref(),
+        device_ca_bag.as_ref(),
+        expected_owner_key.as_ref(),
+        None,
+    );
+
+    match args.output {
+        None => {
+            println!("Ownership voucher {}:", ov.header().guid());
+            for result in report.checks() {
+                println!("\t{result}");
+            }
+        }
+        Some(format) => write_structured_output(
+            format,
+            &VerifyOwnershipVoucherOutput {
+                guid: ov.header().guid().to_string(),
+                report: &report,
+            },
+        )?,
+    }
+
+    if !report.is_valid() {
+        bail!("Ownership voucher verification failed");
+    }
+    Ok(())
+}
+
 fn _write_ov_to_disk(db_ov: &ManufacturerOV, path: &Path) -> Result<()> {
     let new_path = path.join(&db_ov.guid);
     let file = File::create(new_path)?;
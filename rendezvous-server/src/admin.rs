@@ -0,0 +1,575 @@
+use std::convert::TryInto;
+use std::str::FromStr;
+
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+
+use fdo_data_formats::{
+    constants::HashType,
+    publickey::PublicKey,
+    types::{Guid, Hash, TO1DataPayload, TO2AddressEntry},
+    Serializable, StoredItem,
+};
+use fdo_store::MetadataKey;
+
+use crate::RendezvousUDT;
+
+pub(crate) async fn admin_auth_handler(
+    user_data: RendezvousUDT,
+    auth_header: String,
+) -> Result<RendezvousUDT, warp::Rejection> {
+    match &user_data.admin_auth_token {
+        None => {
+            log::warn!("Admin API server disabled");
+            return Err(warp::reject::reject());
+        }
+        Some(token) => {
+            if token != &auth_header {
+                log::warn!("Request with invalid auth token");
+                return Err(warp::reject::reject());
+            }
+        }
+    }
+
+    Ok(user_data)
+}
+
+#[derive(Debug, Serialize)]
+struct AdminV0OwnerAddress {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    ip: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    dns: Option<String>,
+    port: u16,
+    protocol: String,
+}
+
+impl From<&TO2AddressEntry> for AdminV0OwnerAddress {
+    fn from(entry: &TO2AddressEntry) -> Self {
+        AdminV0OwnerAddress {
+            ip: entry.ip().map(|ip| ip.to_string()),
+            dns: entry.dns().cloned(),
+            port: entry.port(),
+            protocol: format!("{:?}", entry.protocol()),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct AdminV0Registration {
+    guid: String,
+    // UNIX timestamp at which the registration expires
+    expires_at: Option<i64>,
+    // Not known for registrations stored by older versions
+    manufacturer_key_hash: Option<String>,
+    owner_key_hash: Option<String>,
+    // The owner policy the owner key falls under, if policies are configured
+    owner_policy: Option<String>,
+    owner_addresses: Vec<AdminV0OwnerAddress>,
+    to0d_hash: String,
+}
+
+fn public_key_hash(key: &Option<PublicKey>) -> Result<Option<String>> {
+    match key {
+        None => Ok(None),
+        Some(key) => {
+            let hash = Hash::from_data(HashType::Sha256, &key.serialize_data()?)?;
+            Ok(Some(format!("sha256:{}", hex::encode(hash.value()))))
+        }
+    }
+}
+
+fn public_key_matches(key: &Option<PublicKey>, hash: &Option<Hash>) -> Result<bool> {
+    match (key, hash) {
+        (_, None) => Ok(true),
+        (None, Some(_)) => Ok(false),
+        (Some(key), Some(hash)) => Ok(hash.compare_data(&key.serialize_data()?).is_ok()),
+    }
+}
+
+async fn load_registration(
+    user_data: &RendezvousUDT,
+    guid: &Guid,
+) -> Result<Option<(StoredItem, Option<i64>)>> {
+    let item = match user_data.store.load_data(guid).await? {
+        None => return Ok(None),
+        Some(item) => item,
+    };
+    let expires_at = match user_data
+        .store
+        .load_metadata(guid, &MetadataKey::Ttl)
+        .await?
+    {
+        None => None,
+        Some(val) => Some(i64::from_le_bytes(
+            val.as_slice()
+                .try_into()
+                .with_context(|| format!("Invalid TTL stored for {guid}"))?,
+        )),
+    };
+    Ok(Some((item, expires_at)))
+}
+
+fn registration_state(
+    user_data: &RendezvousUDT,
+    guid: &Guid,
+    item: &StoredItem,
+    expires_at: Option<i64>,
+) -> Result<AdminV0Registration> {
+    // The to1d signature was verified against the owner key during TO0
+    let to1d = item
+        .to1d
+        .get_payload_unverified::<TO1DataPayload>()
+        .context("Error decoding to1d")?;
+    let to1d = to1d.get_unverified_value();
+    let owner_policy = match (&user_data.owner_policies, &item.owner_public_key) {
+        (Some(policies), Some(owner_key)) => {
+            crate::policy::policy_for_owner(policies, owner_key).map(|policy| policy.name.clone())
+        }
+        _ => None,
+    };
+
+    Ok(AdminV0Registration {
+        guid: guid.to_string(),
+        expires_at,
+        manufacturer_key_hash: public_key_hash(&item.manufacturer_public_key)?,
+        owner_key_hash: public_key_hash(&item.owner_public_key)?,
+        owner_policy,
+        owner_addresses: to1d
+            .to2_addresses()
+            .iter()
+            .map(AdminV0OwnerAddress::from)
+            .collect(),
+        to0d_hash: to1d.to1d_to_to0d_hash().to_string(),
+    })
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct AdminV0ListQuery {
+    guid: Option<String>,
+    manufacturer_key_hash: Option<String>,
+    owner_key_hash: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct AdminV0ListReply {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+
+    success: bool,
+
+    registrations: Vec<AdminV0Registration>,
+}
+
+async fn list_registrations(
+    user_data: &RendezvousUDT,
+    query: AdminV0ListQuery,
+) -> Result<Vec<AdminV0Registration>> {
+    let guids = match &query.guid {
+        Some(guid) => vec![Guid::from_str(guid).context("Invalid GUID")?],
+        None => user_data.store.load_all_keys().await?,
+    };
+    let manufacturer_key_hash = query
+        .manufacturer_key_hash
+        .as_deref()
+        .map(Hash::from_str)
+        .transpose()
+        .context("Invalid manufacturer_key_hash")?;
+    let owner_key_hash = query
+        .owner_key_hash
+        .as_deref()
+        .map(Hash::from_str)
+        .transpose()
+        .context("Invalid owner_key_hash")?;
+
+    let mut registrations = Vec::new();
+    for guid in guids {
+        let (item, expires_at) = match load_registration(user_data, &guid).await? {
+            None => continue,
+            Some(registration) => registration,
+        };
+        if !public_key_matches(&item.manufacturer_public_key, &manufacturer_key_hash)?
+            || !public_key_matches(&item.owner_public_key, &owner_key_hash)?
+        {
+            continue;
+        }
+        registrations.push(registration_state(user_data, &guid, &item, expires_at)?);
+    }
+    Ok(registrations)
+}
+
+pub(crate) async fn admin_v0_list_handler(
+    user_data: RendezvousUDT,
+    query: AdminV0ListQuery,
+) -> Result<warp::reply::Json, warp::Rejection> {
+    Ok(match list_registrations(&user_data, query).await {
+        Ok(registrations) => warp::reply::json(&AdminV0ListReply {
+            error: None,
+            success: true,
+            registrations,
+        }),
+        Err(e) => warp::reply::json(&AdminV0ListReply {
+            error: Some(format!("{e:#}")),
+            success: false,
+            registrations: Vec::new(),
+        }),
+    })
+}
+
+#[derive(Debug, Serialize)]
+struct AdminV0GetReply {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+
+    success: bool,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    registration: Option<AdminV0Registration>,
+}
+
+pub(crate) async fn admin_v0_get_handler(
+    user_data: RendezvousUDT,
+    guid: String,
+) -> Result<warp::reply::Json, warp::Rejection> {
+    let get = async {
+        let guid = Guid::from_str(&guid)?;
+        match load_registration(&user_data, &guid).await? {
+            None => bail!("No registration found for {guid}"),
+            Some((item, expires_at)) => registration_state(&user_data, &guid, &item, expires_at),
+        }
+    };
+
+    Ok(match get.await {
+        Ok(registration) => warp::reply::json(&AdminV0GetReply {
+            error: None,
+            success: true,
+            registration: Some(registration),
+        }),
+        Err(e) => warp::reply::json(&AdminV0GetReply {
+            error: Some(format!("{e:#}")),
+            success: false,
+            registration: None,
+        }),
+    })
+}
+
+#[derive(Debug, Serialize)]
+struct AdminV0Reply {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+
+    success: bool,
+}
+
+impl From<Result<()>> for AdminV0Reply {
+    fn from(res: Result<()>) -> Self {
+        match res {
+            Ok(()) => AdminV0Reply {
+                error: None,
+                success: true,
+            },
+            Err(e) => AdminV0Reply {
+                error: Some(format!("{e:#}")),
+                success: false,
+            },
+        }
+    }
+}
+
+pub(crate) async fn admin_v0_delete_handler(
+    user_data: RendezvousUDT,
+    guid: String,
+) -> Result<warp::reply::Json, warp::Rejection> {
+    let delete = async {
+        let guid = Guid::from_str(&guid)?;
+        if user_data.store.load_data(&guid).await?.is_none() {
+            bail!("No registration found for {guid}");
+        }
+        user_data.store.destroy_data(&guid).await?;
+        log::info!("Admin API: deleted registration for {guid}");
+        Ok::<_, anyhow::Error>(())
+    };
+
+    Ok(warp::reply::json(&AdminV0Reply::from(delete.await)))
+}
+
+#[cfg(test)]
+mod test {
+    use std::{path::Path, sync::Arc};
+
+    use fdo_data_formats::{
+        constants::{HashType, ProtocolVersion, TransportProtocol},
+        publickey::PublicKey,
+        test_utils::generate_public_key,
+        types::{COSESign, Guid, Hash, TO1DataPayload, TO2AddressEntry},
+        StoredItem,
+    };
+    use fdo_store::{MetadataKey, ServerType, StoreConfig};
+    use openssl::pkey::{PKey, Private};
+    use serde_json::Value;
+
+    use super::{
+        admin_v0_delete_handler, admin_v0_get_handler, admin_v0_list_handler, public_key_hash,
+        public_key_matches, AdminV0ListQuery,
+    };
+    use crate::{RendezvousUD, RendezvousUDT};
+
+    fn test_user_data(store_config: StoreConfig, session_path: &Path) -> RendezvousUDT {
+        let session_store = StoreConfig::Directory {
+            path: session_path.to_path_buf(),
+        }
+        .initialize()
+        .unwrap();
+        Arc::new(RendezvousUD {
+            max_wait_seconds: 3600,
+            trusted_manufacturer_keys: None,
+            trusted_device_keys: None,
+            owner_policies: None,
+            store: store_config.initialize().unwrap(),
+            session_store: fdo_http_wrapper::server::SessionStore::new(session_store),
+            admin_auth_token: None,
+        })
+    }
+
+    /// Stores a registration like TO0 does, expiring in `ttl_secs`
+    async fn register(
+        user_data: &RendezvousUDT,
+        manufacturer_public_key: &PublicKey,
+        owner_key: &(PKey<Private>, PublicKey),
+        ttl_secs: i64,
+    ) -> Guid {
+        let guid = Guid::new().unwrap();
+        let (_, device_public_key) = generate_public_key("Device");
+        let to1d = TO1DataPayload::new(
+            vec![TO2AddressEntry::new(
+                None,
+                Some("owner.example.com".to_string()),
+                8080,
+                TransportProtocol::Http,
+            )],
+            Hash::from_data(HashType::Sha256, b"to0d").unwrap(),
+        );
+        let item = StoredItem {
+            public_key: device_public_key,
+            to1d: COSESign::new(&to1d, None, &owner_key.0).unwrap(),
+            manufacturer_public_key: Some(manufacturer_public_key.clone()),
+            owner_public_key: Some(owner_key.1.clone()),
+            protocol_version: Some(ProtocolVersion::Version1_1),
+        };
+        user_data
+            .store
+            .store_data(guid.clone(), item)
+            .await
+            .unwrap();
+        user_data
+            .store
+            .store_metadata(&guid, &MetadataKey::Ttl, &time::Duration::new(ttl_secs, 0))
+            .await
+            .unwrap();
+        guid
+    }
+
+    async fn reply_json(reply: warp::reply::Json) -> Value {
+        let body = warp::hyper::body::to_bytes(warp::Reply::into_response(reply).into_body())
+            .await
+            .unwrap();
+        serde_json::from_slice(&body).unwrap()
+    }
+
+    async fn list(user_data: &RendezvousUDT, query: AdminV0ListQuery) -> Value {
+        reply_json(
+            admin_v0_list_handler(user_data.clone(), query)
+                .await
+                .unwrap(),
+        )
+        .await
+    }
+
+    fn listed_guids(reply: &Value) -> Vec<String> {
+        assert_eq!(reply["success"], true, "{}", reply);
+        reply["registrations"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|registration| registration["guid"].as_str().unwrap().to_string())
+            .collect()
+    }
+
+    fn query(
+        guid: Option<&Guid>,
+        manufacturer_key_hash: Option<String>,
+        owner_key_hash: Option<String>,
+    ) -> AdminV0ListQuery {
+        AdminV0ListQuery {
+            guid: guid.map(|guid| guid.to_string()),
+            manufacturer_key_hash,
+            owner_key_hash,
+        }
+    }
+
+    async fn test_admin_handlers(store_config: StoreConfig) {
+        let session_dir = tempfile::tempdir().unwrap();
+        let user_data = test_user_data(store_config, session_dir.path());
+
+        let (_, manufacturer1) = generate_public_key("Manufacturer 1");
+        let (_, manufacturer2) = generate_public_key("Manufacturer 2");
+        let owner1 = generate_public_key("Owner 1");
+        let owner2 = generate_public_key("Owner 2");
+        let registration1 = register(&user_data, &manufacturer1, &owner1, 600).await;
+        let registration2 = register(&user_data, &manufacturer2, &owner2, 600).await;
+        let expired = register(&user_data, &manufacturer1, &owner1, -600).await;
+
+        // Expired registrations are not listed
+        let guids = listed_guids(&list(&user_data, query(None, None, None)).await);
+        assert!(guids.contains(&registration1.to_string()));
+        assert!(guids.contains(&registration2.to_string()));
+        assert!(!guids.contains(&expired.to_string()));
+
+        let manufacturer1_hash = public_key_hash(&Some(manufacturer1)).unwrap();
+        let owner2_hash = public_key_hash(&Some(owner2.1.clone())).unwrap();
+        assert_eq!(
+            listed_guids(&list(&user_data, query(None, manufacturer1_hash.clone(), None)).await),
+            vec![registration1.to_string()]
+        );
+        assert_eq!(
+            listed_guids(&list(&user_data, query(None, None, owner2_hash.clone())).await),
+            vec![registration2.to_string()]
+        );
+        assert_eq!(
+            listed_guids(
+                &list(
+                    &user_data,
+                    query(Some(&registration1), manufacturer1_hash.clone(), None)
+                )
+                .await
+            ),
+            vec![registration1.to_string()]
+        );
+        assert!(listed_guids(
+            &list(
+                &user_data,
+                query(Some(&registration2), manufacturer1_hash, None)
+            )
+            .await
+        )
+        .is_empty());
+
+        let reply = list(
+            &user_data,
+            query(None, None, Some("sha256:nothex".to_string())),
+        )
+        .await;
+        assert_eq!(reply["success"], false);
+        assert!(reply["error"]
+            .as_str()
+            .unwrap()
+            .starts_with("Invalid owner_key_hash"));
+        let reply = list(
+            &user_data,
+            AdminV0ListQuery {
+                guid: Some("not-a-guid".to_string()),
+                manufacturer_key_hash: None,
+                owner_key_hash: None,
+            },
+        )
+        .await;
+        assert_eq!(reply["success"], false);
+
+        let reply = reply_json(
+            admin_v0_get_handler(user_data.clone(), registration2.to_string())
+                .await
+                .unwrap(),
+        )
+        .await;
+        assert_eq!(reply["success"], true, "{}", reply);
+        let registration = &reply["registration"];
+        assert_eq!(registration["guid"], registration2.to_string());
+        assert!(registration["expires_at"].as_i64().unwrap() > 0);
+        assert_eq!(registration["owner_key_hash"], owner2_hash.unwrap());
+        assert_eq!(
+            registration["owner_addresses"][0]["dns"],
+            "owner.example.com"
+        );
+        assert_eq!(registration["owner_addresses"][0]["port"], 8080);
+        assert_eq!(
+            registration["to0d_hash"],
+            Hash::from_data(HashType::Sha256, b"to0d")
+                .unwrap()
+                .to_string()
+        );
+
+        let reply = reply_json(
+            admin_v0_get_handler(user_data.clone(), Guid::new().unwrap().to_string())
+                .await
+                .unwrap(),
+        )
+        .await;
+        assert_eq!(reply["success"], false);
+        assert!(reply.get("registration").is_none());
+
+        for guid in [&registration1, &registration2] {
+            let reply = reply_json(
+                admin_v0_delete_handler(user_data.clone(), guid.to_string())
+                    .await
+                    .unwrap(),
+            )
+            .await;
+            assert_eq!(reply["success"], true, "{}", reply);
+        }
+        let reply = reply_json(
+            admin_v0_delete_handler(user_data.clone(), registration1.to_string())
+                .await
+                .unwrap(),
+        )
+        .await;
+        assert_eq!(reply["success"], false);
+        let guids = listed_guids(&list(&user_data, query(None, None, None)).await);
+        assert!(!guids.contains(&registration1.to_string()));
+        assert!(!guids.contains(&registration2.to_string()));
+
+        // Expired registrations are removed by the store maintenance
+        user_data.store.perform_maintenance().await.unwrap();
+        assert!(user_data.store.load_data(&expired).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_admin_handlers_directory() {
+        let dir = tempfile::tempdir().unwrap();
+        test_admin_handlers(StoreConfig::Directory {
+            path: dir.path().to_path_buf(),
+        })
+        .await;
+    }
+
+    #[tokio::test]
+    async fn test_admin_handlers_sqlite() {
+        let url = std::env::var("SQLITE_RENDEZVOUS_DATABASE_URL")
+            .expect("SQLITE_RENDEZVOUS_DATABASE_URL must be set");
+        test_admin_handlers(StoreConfig::Sqlite {
+            server: ServerType::Rendezvous,
+            url,
+        })
+        .await;
+    }
+
+    #[test]
+    fn test_public_key_matches() {
+        let (_, key) = generate_public_key("Owner");
+        let (_, other_key) = generate_public_key("Other owner");
+        let hash = |key: &PublicKey| {
+            public_key_hash(&Some(key.clone()))
+                .unwrap()
+                .unwrap()
+                .parse::<Hash>()
+                .unwrap()
+        };
+
+        // No filter matches any key, including unknown ones
+        assert!(public_key_matches(&Some(key.clone()), &None).unwrap());
+        assert!(public_key_matches(&None, &None).unwrap());
+
+        assert!(public_key_matches(&Some(key.clone()), &Some(hash(&key))).unwrap());
+        assert!(!public_key_matches(&Some(key.clone()), &Some(hash(&other_key))).unwrap());
+        assert!(!public_key_matches(&None, &Some(hash(&key))).unwrap());
+    }
+}
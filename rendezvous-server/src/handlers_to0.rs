@@ -1,9 +1,8 @@
-use std::convert::TryInto;
-
 use fdo_data_formats::messages;
 use fdo_data_formats::{
     constants::ErrorCode,
     messages::Message,
+    ownershipvoucher::OwnershipVoucherCheck,
     types::{Nonce, TO1DataPayload},
     StoredItem,
 };
@@ -69,60 +68,74 @@ pub(super) async fn ownersign(
         .into());
     }
 
-    // Now check the OV first public key: is it one we trust?
-    let manufacturer_pubkey = to0d
-        .ownership_voucher()
-        .header()
-        .manufacturer_public_key()
-        .clone();
-    log::trace!(
-        "Checking whether manufacturer key {:?} is trusted",
-        manufacturer_pubkey
-    );
-    if let Some(trusted_manufacturer_keys) = &user_data.trusted_manufacturer_keys {
-        if !trusted_manufacturer_keys.contains_publickey(&manufacturer_pubkey) {
-            return Err(Error::new(
-                ErrorCode::InvalidOwnershipVoucher,
-                messages::v11::to0::OwnerSign::message_type(),
-                "Ownership voucher manufacturer not trusted",
-            )
-            .into());
-        }
+    // The OV needs to be registered over the messages of its own protocol version
+    let ov_protocol_version = to0d.ownership_voucher().header().protocol_version();
+    if ov_protocol_version != ses_with_store.protocol_version {
+        log::error!(
+            "Ownership voucher of protocol version {} registered with protocol version {}",
+            ov_protocol_version,
+            ses_with_store.protocol_version
+        );
+        return Err(Error::new(
+            ErrorCode::InvalidOwnershipVoucher,
+            messages::v11::to0::OwnerSign::message_type(),
+            "Ownership voucher protocol version mismatch",
+        )
+        .into());
     }
 
-    // Now, get the final owner key
-    let ov_iter = to0d
-        .ownership_voucher()
-        .iter_entries()
-        .map_err(Error::from_error::<messages::v11::to0::OwnerSign, _>)?;
-    let owner = match ov_iter.last() {
+    // Now verify the OV itself: the manufacturer, the device certificate chain and the entries
+    let report = to0d.ownership_voucher().verify(
+        user_data.trusted_manufacturer_keys.as_ref(),
+        user_data.trusted_device_keys.as_ref(),
+        None,
+        None,
+    );
+    if let Some(failure) = report.failures().next() {
+        log::error!("Ownership voucher verification failed: {}", failure);
+        let error_string = match failure.check {
+            OwnershipVoucherCheck::ManufacturerKey => "Ownership voucher manufacturer not trusted",
+            OwnershipVoucherCheck::DeviceCertificateChainHash
+            | OwnershipVoucherCheck::DeviceCertificateChain
+            | OwnershipVoucherCheck::DeviceCertificateChainTrusted => {
+                "Device certificate not trusted"
+            }
+            _ => "Invalid OV",
+        };
+        return Err(Error::new(
+            ErrorCode::InvalidOwnershipVoucher,
+            messages::v11::to0::OwnerSign::message_type(),
+            error_string,
+        )
+        .into());
+    }
+    if to0d.ownership_voucher().num_entries() == 0 {
+        log::error!("No OV entries encountered");
+        return Err(Error::new(
+            ErrorCode::InvalidOwnershipVoucher,
+            messages::v11::to0::OwnerSign::message_type(),
+            "Invalid OV",
+        )
+        .into());
+    }
+    let owner_public_key = match report.owner_public_key() {
+        Some(owner_public_key) => owner_public_key,
         None => {
-            log::error!("No OV entries encountered");
             return Err(Error::new(
                 ErrorCode::InvalidOwnershipVoucher,
                 messages::v11::to0::OwnerSign::message_type(),
                 "Invalid OV",
             )
-            .into());
-        }
-        Some(Err(e)) => {
-            log::error!("Invalid OV entry encountered: {:?}", e);
-            return Err(Error::new(
-                ErrorCode::InvalidOwnershipVoucher,
-                messages::v11::to0::OwnerSign::message_type(),
-                "Invalid OV",
-            )
-            .into());
+            .into())
         }
-        Some(Ok(owner)) => owner,
     };
 
     // Verify the signature on to1d
     log::trace!(
         "Checking whether to1d payload is signed by owner public key {:?}",
-        owner.public_key(),
+        owner_public_key,
     );
-    let to1d_payload: TO1DataPayload = match msg.to1d().get_payload(owner.public_key().pkey()) {
+    let to1d_payload: TO1DataPayload = match msg.to1d().get_payload(owner_public_key.pkey()) {
         Err(e) => {
             log::error!("Error verifying to1d: {:?}", e);
             return Err(Error::new(
@@ -145,8 +158,8 @@ pub(super) async fn ownersign(
         .map_err(Error::from_error::<messages::v11::to0::OwnerSign, _>)?;
 
     // Okay, wow! We can now trust the to1d payload, and the other data!
-    // First, verify the device certificate chain
-    let device_cert_chain = match to0d.ownership_voucher().device_certificate_chain() {
+    // The device certificate chain was verified with the OV, but is required here
+    let device_pubkey = match report.device_public_key() {
         None => {
             return Err(Error::new(
                 ErrorCode::InvalidOwnershipVoucher,
@@ -155,57 +168,97 @@ pub(super) async fn ownersign(
             )
             .into());
         }
-        Some(v) => v,
+        Some(v) => v.clone(),
     };
 
-    let verify_device_pubkey = if let Some(trusted_device_keys) = &user_data.trusted_device_keys {
-        device_cert_chain.verify_from_x5bag(trusted_device_keys)
-    } else {
-        device_cert_chain.insecure_verify_without_root_verification()
-    };
+    let device_guid = to0d.ownership_voucher().header().guid().clone();
 
-    let device_pubkey = match verify_device_pubkey {
-        Err(cert_chain_err) => {
-            log::debug!("Error verifying device certificate: {:?}", cert_chain_err);
-            return Err(Error::new(
-                ErrorCode::InvalidOwnershipVoucher,
-                messages::v11::to0::OwnerSign::message_type(),
-                "Device certificate not trusted",
-            )
-            .into());
-        }
-        Ok(v) => v
-            .clone()
-            .try_into()
-            .map_err(Error::from_error::<messages::v11::to0::OwnerSign, _>)?,
+    // Check the owner against the owner policies
+    let owner_policy = match &user_data.owner_policies {
+        None => None,
+        Some(policies) => match super::policy::policy_for_owner(policies, owner_public_key) {
+            None => {
+                log::info!(
+                    "Rejecting registration for device with GUID {:?}: owner not allowed",
+                    device_guid
+                );
+                return Err(Error::new(
+                    ErrorCode::InvalidOwnershipVoucher,
+                    messages::v11::to0::OwnerSign::message_type(),
+                    "Ownership voucher owner not trusted",
+                )
+                .into());
+            }
+            Some(policy) => Some(policy),
+        },
     };
-
     // Now compute the new wait_seconds and stuff to store
+    let max_wait_seconds = owner_policy
+        .and_then(|policy| policy.max_wait_seconds)
+        .unwrap_or(user_data.max_wait_seconds);
     let mut wait_seconds = to0d.wait_seconds();
-    if wait_seconds > user_data.max_wait_seconds {
-        wait_seconds = user_data.max_wait_seconds;
+    if wait_seconds > max_wait_seconds {
+        wait_seconds = max_wait_seconds;
     }
     let wait_seconds = wait_seconds;
-    let device_guid = to0d.ownership_voucher().header().guid().clone();
 
     // Actually store the data here
     let ttl = time::Duration::new(wait_seconds as i64, 0);
     log::info!(
-        "Storing TO1D for device with GUID {:?} for {:?}",
+        "Storing TO1D for device with GUID {:?} for {:?} (owner policy: {})",
         device_guid,
-        ttl
+        ttl,
+        owner_policy.map_or("none", |policy| policy.name.as_str())
     );
-    user_data
-        .store
-        .store_data(
-            device_guid.clone(),
-            StoredItem {
-                public_key: device_pubkey,
-                to1d: msg.to1d().clone(),
-            },
-        )
-        .await
-        .map_err(Error::from_error::<messages::v11::to0::OwnerSign, _>)?;
+    let item = StoredItem {
+        public_key: device_pubkey,
+        to1d: msg.to1d().clone(),
+        manufacturer_public_key: Some(
+            to0d.ownership_voucher()
+                .header()
+                .manufacturer_public_key()
+                .clone(),
+        ),
+        owner_public_key: Some(owner_public_key.clone()),
+        protocol_version: Some(ov_protocol_version),
+    };
+    match owner_policy {
+        None => user_data
+            .store
+            .store_data(device_guid.clone(), item)
+            .await
+            .map_err(Error::from_error::<messages::v11::to0::OwnerSign, _>)?,
+        Some(policy) => {
+            // Registrations are grouped per policy, so the quota check and
+            // the insert happen atomically in the store
+            let stored = user_data
+                .store
+                .store_data_in_group(
+                    device_guid.clone(),
+                    item,
+                    &policy.name,
+                    policy.max_registrations,
+                )
+                .await
+                .map_err(Error::from_error::<messages::v11::to0::OwnerSign, _>)?;
+            if !stored {
+                log::info!(
+                    "Rejecting registration for device with GUID {:?}: owner policy {} is at its quota of {} registrations",
+                    device_guid,
+                    policy.name,
+                    policy.max_registrations.unwrap_or_default()
+                );
+                fdo_http_wrapper::server::metrics::record_registration_over_quota(&policy.name);
+                // The specification has no error code for this, the owner retries later
+                return Err(Error::new(
+                    ErrorCode::InternalServerError,
+                    messages::v11::to0::OwnerSign::message_type(),
+                    "Owner registration quota exceeded",
+                )
+                .into());
+            }
+        }
+    }
 
     user_data
         .store
@@ -17,7 +17,10 @@ pub(super) async fn hello_rv(
     // Check the signature info
     let a_sig_info = msg.a_signature_info();
     match a_sig_info.sig_type() {
-        DeviceSigType::StSECP256R1 | DeviceSigType::StSECP384R1 => {}
+        DeviceSigType::StSECP256R1
+        | DeviceSigType::StSECP384R1
+        | DeviceSigType::StRSA2048
+        | DeviceSigType::StRSA3072 => {}
         _ => {
             return Err(Error::new(
                 ErrorCode::InvalidMessageError,
@@ -44,6 +47,18 @@ pub(super) async fn hello_rv(
         .await
         .map_err(Error::from_error::<messages::v11::to1::HelloRV, _>)?;
     match dev_to1d {
+        // Devices need to use the protocol version of their ownership voucher
+        Some(item)
+            if matches!(item.protocol_version,
+                Some(version) if version != ses_with_store.protocol_version) =>
+        {
+            return Err(Error::new(
+                ErrorCode::InvalidMessageError,
+                messages::v11::to1::HelloRV::message_type(),
+                "Protocol version mismatch",
+            )
+            .into())
+        }
         Some(_) => {}
         None => {
             return Err(Error::new(
@@ -131,14 +146,17 @@ pub(super) async fn prove_to_rv(
     };
 
     // Check if token is signed
-    let device_eat = msg.token().get_eat(dev_pkey.pkey()).map_err(|e| {
-        log::debug!("Error parsing EAToken: {:?}", e);
-        Error::new(
-            ErrorCode::InvalidMessageError,
-            messages::v11::to1::ProveToRV::message_type(),
-            "Token invalid",
-        )
-    })?;
+    let device_eat = msg
+        .token()
+        .get_eat_with_pkey(dev_pkey.pkey())
+        .map_err(|e| {
+            log::debug!("Error parsing EAToken: {:?}", e);
+            Error::new(
+                ErrorCode::InvalidMessageError,
+                messages::v11::to1::ProveToRV::message_type(),
+                "Token invalid",
+            )
+        })?;
 
     let signed_nonce: &Nonce = device_eat.nonce();
 
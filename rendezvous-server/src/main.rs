@@ -9,8 +9,10 @@ use fdo_data_formats::{enhanced_types::X5Bag, types::Guid, ProtocolVersion, Stor
 use fdo_store::Store;
 use fdo_util::servers::{configuration::rendezvous_server::RendezvousServerSettings, settings_for};
 
+mod admin;
 mod handlers_to0;
 mod handlers_to1;
+mod policy;
 
 #[derive(Debug, Clone, Copy)]
 #[non_exhaustive]
@@ -26,10 +28,15 @@ struct RendezvousUD {
     max_wait_seconds: u32,
     trusted_manufacturer_keys: Option<X5Bag>,
     trusted_device_keys: Option<X5Bag>,
+    // If set, only owners matching one of these may register
+    owner_policies: Option<Vec<policy::OwnerPolicy>>,
 
     store: Box<dyn Store<fdo_store::ReadWriteOpen, Guid, StoredItem, RendezvousStoreMetadataKey>>,
 
     session_store: Arc<fdo_http_wrapper::server::SessionStore>,
+
+    // Admin API authentication, in the form of the Authorization header
+    admin_auth_token: Option<String>,
 }
 
 type RendezvousUDT = Arc<RendezvousUD>;
@@ -52,6 +59,13 @@ async fn perform_maintenance(udt: RendezvousUDT) {
         if let Err(e) = store_res {
             log::warn!("Error during store maintenance: {:?}", e);
         }
+        match udt.store.count_data().await {
+            Ok(count) => fdo_http_wrapper::server::metrics::set_store_entries(
+                "rendezvous_registrations",
+                count,
+            ),
+            Err(e) => log::debug!("Unable to count the rendezvous registrations: {:?}", e),
+        }
         if let Err(e) = ses_res {
             log::warn!("Error during session store maintenance: {:?}", e);
         }
@@ -75,12 +89,16 @@ async fn main() -> Result<()> {
 
     // Bind information
     let bind_addr = settings.bind.clone();
+    let coap_bind = settings.coap_bind.clone();
+    let metrics_bind = settings.metrics_bind.clone();
+    let tls_settings = settings.tls;
 
     // Initialize stores
     let store = settings
         .storage_driver
         .initialize()
         .context("Error initializing store")?;
+    let store = fdo_http_wrapper::server::metrics::timed_store("rendezvous_registrations", store);
     let session_store = settings
         .session_store_driver
         .initialize()
@@ -120,14 +138,29 @@ async fn main() -> Result<()> {
         .transpose()
         .context("Error loading trusted device keys")?;
 
+    // Load the owner policies
+    let owner_policies = settings
+        .owner_policies
+        .map(|policies| {
+            policies
+                .into_iter()
+                .map(policy::OwnerPolicy::load)
+                .collect::<Result<Vec<_>>>()
+        })
+        .transpose()
+        .context("Error loading owner policies")?;
+
     // Initialize handler stores
     let user_data = Arc::new(RendezvousUD {
         max_wait_seconds,
         store,
         trusted_manufacturer_keys,
         trusted_device_keys,
+        owner_policies,
 
         session_store: session_store.clone(),
+
+        admin_auth_token: settings.admin_auth_token.map(|s| format!("Bearer {s}")),
     });
 
     // Install handlers
@@ -162,32 +195,108 @@ async fn main() -> Result<()> {
         handlers_to1::prove_to_rv,
     );
 
+    // TO0 and TO1 for FDO 1.0 owners and devices
+    let handler_to0_hello_v10 = fdo_http_wrapper::server::fdo_request_filter_v10(
+        user_data.clone(),
+        session_store.clone(),
+        handlers_to0::hello,
+    );
+    let handler_to0_ownersign_v10 = fdo_http_wrapper::server::fdo_request_filter_v10(
+        user_data.clone(),
+        session_store.clone(),
+        handlers_to0::ownersign,
+    );
+    let handler_to1_hello_rv_v10 = fdo_http_wrapper::server::fdo_request_filter_v10(
+        user_data.clone(),
+        session_store.clone(),
+        handlers_to1::hello_rv,
+    );
+    let handler_to1_prove_to_rv_v10 = fdo_http_wrapper::server::fdo_request_filter_v10(
+        user_data.clone(),
+        session_store.clone(),
+        handlers_to1::prove_to_rv,
+    );
+
+    // Admin API
+    let ud = user_data.clone();
+    let admin_v0_auth = warp::any()
+        .map(move || ud.clone())
+        .and(warp::header::header("Authorization"))
+        .and_then(admin::admin_auth_handler);
+    let handler_admin_v0_list = warp::get()
+        .and(warp::path("admin"))
+        .and(warp::path("v0"))
+        .and(warp::path("registrations"))
+        .and(warp::path::end())
+        .and(admin_v0_auth.clone())
+        .and(warp::query::<admin::AdminV0ListQuery>())
+        .and_then(admin::admin_v0_list_handler);
+    let handler_admin_v0_get = warp::get()
+        .and(warp::path("admin"))
+        .and(warp::path("v0"))
+        .and(warp::path("registrations"))
+        .and(admin_v0_auth.clone())
+        .and(warp::path::param::<String>())
+        .and(warp::path::end())
+        .and_then(admin::admin_v0_get_handler);
+    let handler_admin_v0_delete = warp::delete()
+        .and(warp::path("admin"))
+        .and(warp::path("v0"))
+        .and(warp::path("registrations"))
+        .and(admin_v0_auth)
+        .and(warp::path::param::<String>())
+        .and(warp::path::end())
+        .and_then(admin::admin_v0_delete_handler);
+
+    // The FDO protocol messages, the only routes also served over CoAP
+    let fdo_routes = handler_to0_hello
+        .or(handler_to0_ownersign)
+        // TO1
+        .or(handler_to1_hello_rv)
+        .or(handler_to1_prove_to_rv)
+        // FDO 1.0
+        .or(handler_to0_hello_v10)
+        .or(handler_to0_ownersign_v10)
+        .or(handler_to1_hello_rv_v10)
+        .or(handler_to1_prove_to_rv_v10);
+
     let routes = warp::post()
-        .and(
-            hello
-                .or(handler_ping)
-                // TO0
-                .or(handler_to0_hello)
-                .or(handler_to0_ownersign)
-                // TO1
-                .or(handler_to1_hello_rv)
-                .or(handler_to1_prove_to_rv),
-        )
+        .and(hello.or(handler_ping).or(fdo_routes.clone()))
+        // Admin API
+        .or(handler_admin_v0_list)
+        .or(handler_admin_v0_get)
+        .or(handler_admin_v0_delete)
+        .recover(fdo_http_wrapper::server::handle_rejection)
+        .with(warp::log("rendezvous-server"));
+    let coap_routes = fdo_routes
         .recover(fdo_http_wrapper::server::handle_rejection)
         .with(warp::log("rendezvous-server"));
 
     log::info!("Listening on {}", bind_addr);
-    let server = warp::serve(routes);
-
+    if let Some(metrics_bind) = metrics_bind {
+        let metrics_server = fdo_http_wrapper::server::metrics::serve_metrics(metrics_bind.into())
+            .context("Error binding metrics listener")?;
+        tokio::spawn(metrics_server);
+    }
+    if let Some(coap_bind) = coap_bind {
+        log::info!("Listening for CoAP on {}", coap_bind);
+        let coap_server = fdo_http_wrapper::server::coap::CoapServer::bind(
+            coap_bind.into(),
+            warp::service(coap_routes),
+        )
+        .await
+        .context("Error binding CoAP listener")?;
+        tokio::spawn(coap_server.run());
+    }
     let maintenance_runner =
         tokio::spawn(async move { perform_maintenance(user_data.clone()).await });
 
-    let server = server
-        .bind_with_graceful_shutdown(bind_addr, async {
-            signal(SignalKind::terminate()).unwrap().recv().await;
-            log::info!("Terminating");
-        })
-        .1;
+    let shutdown = async {
+        signal(SignalKind::terminate()).unwrap().recv().await;
+        log::info!("Terminating");
+    };
+    let (_, server) =
+        fdo_http_wrapper::server::serve(routes, bind_addr, tls_settings.map(Into::into), shutdown);
     let server = tokio::spawn(server);
 
     tokio::select!(
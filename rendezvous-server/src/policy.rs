@@ -0,0 +1,47 @@
+use anyhow::{Context, Result};
+use openssl::x509::X509;
+
+use fdo_data_formats::{enhanced_types::X5Bag, publickey::PublicKey};
+use fdo_util::servers::configuration::rendezvous_server::OwnerPolicySettings;
+
+/// The limits applied to the owners whose key is in `owner_keys`
+pub(crate) struct OwnerPolicy {
+    pub(crate) name: String,
+    owner_keys: X5Bag,
+    pub(crate) max_wait_seconds: Option<u32>,
+    pub(crate) max_registrations: Option<u64>,
+}
+
+impl OwnerPolicy {
+    pub(crate) fn load(settings: OwnerPolicySettings) -> Result<Self> {
+        let contents = std::fs::read(&settings.owner_keys_path).with_context(|| {
+            format!(
+                "Error reading owner keys for policy {} at {}",
+                settings.name, settings.owner_keys_path
+            )
+        })?;
+        let certs = X509::stack_from_pem(&contents)
+            .with_context(|| format!("Error parsing owner keys for policy {}", settings.name))?;
+        let owner_keys = X5Bag::with_certs(certs)
+            .with_context(|| format!("Error building owner keys for policy {}", settings.name))?;
+
+        Ok(OwnerPolicy {
+            name: settings.name,
+            owner_keys,
+            max_wait_seconds: settings.max_wait_seconds,
+            max_registrations: settings.max_registrations,
+        })
+    }
+
+    pub(crate) fn applies_to(&self, owner_key: &PublicKey) -> bool {
+        self.owner_keys.contains_publickey(owner_key)
+    }
+}
+
+/// Returns the policy for this owner key, the first matching one wins
+pub(crate) fn policy_for_owner<'a>(
+    policies: &'a [OwnerPolicy],
+    owner_key: &PublicKey,
+) -> Option<&'a OwnerPolicy> {
+    policies.iter().find(|policy| policy.applies_to(owner_key))
+}
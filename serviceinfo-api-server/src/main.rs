@@ -1,16 +1,23 @@
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
 use fdo_data_formats::{
-    constants::{FedoraIotServiceInfoModule, HashType, ServiceInfoModule},
-    types::{Guid, Hash},
+    constants::{FedoraIotServiceInfoModule, ServiceInfoModule, StandardServiceInfoModule},
+    types::Guid,
 };
 use fdo_store::Store;
 use fdo_util::servers::{
     configuration::serviceinfo_api_server::{ServiceInfoApiServerSettings, ServiceInfoSettings},
-    settings_for, settings_per_device, ServiceInfoApiReply, ServiceInfoApiReplyInitialUser,
-    ServiceInfoApiReplyReboot,
+    settings_for, settings_per_device, ServiceInfoApiDevMod, ServiceInfoApiDeviceResults,
+    ServiceInfoApiDeviceUpload, ServiceInfoApiFile, ServiceInfoApiFileChunk, ServiceInfoApiReply,
+    ServiceInfoApiReplyInitialUser, ServiceInfoApiReplyReboot,
 };
+use openssl::hash::{Hasher, MessageDigest};
 use serde::{Deserialize, Serialize};
-use std::{collections::HashSet, str::FromStr};
+use std::{
+    collections::HashSet,
+    io::{Read, Seek, SeekFrom, Write},
+    path::PathBuf,
+    str::FromStr,
+};
 use tokio::signal::unix::{signal, SignalKind};
 use warp::Filter;
 
@@ -19,6 +26,191 @@ use warp::Filter;
 struct ServiceInfoFailure(anyhow::Error);
 impl warp::reject::Reject for ServiceInfoFailure {}
 
+// Size of the blocks files are read in to compute their digest
+const DIGEST_BLOCK_SIZE: usize = 64 * 1024;
+// Largest file chunk handed out at once, the Owner Onboarding Server only
+// requests as much as fits in the ServiceInfo message it is building
+const MAX_FILE_CHUNK_SIZE: u64 = 64 * 1024;
+
+/// A file to send to a device. The ServiceInfo reply only references it, the
+/// Owner Onboarding Server requests the contents a chunk at a time while it
+/// sends them to the device, so it is never held in memory as a whole.
+struct SourceFile<'a> {
+    source_path: &'a str,
+    length: u64,
+}
+
+impl<'a> SourceFile<'a> {
+    fn open(source_path: &'a str) -> Result<Self> {
+        let metadata = std::fs::metadata(source_path)
+            .with_context(|| format!("Failed to open file {source_path}"))?;
+        Ok(SourceFile {
+            source_path,
+            length: metadata.len(),
+        })
+    }
+
+    /// Returns the hex-encoded SHA-384 digest of the contents
+    fn digest_hex(&self) -> Result<String> {
+        let mut file = std::fs::File::open(self.source_path)
+            .with_context(|| format!("Failed to open file {}", self.source_path))?;
+        let mut hasher = Hasher::new(MessageDigest::sha384())?;
+        let mut block = vec![0; DIGEST_BLOCK_SIZE];
+        let mut read = 0;
+        loop {
+            let len = file
+                .read(&mut block)
+                .with_context(|| format!("Failed to read file {}", self.source_path))?;
+            if len == 0 {
+                break;
+            }
+            read += len as u64;
+            hasher.update(&block[..len])?;
+        }
+        if read != self.length {
+            bail!("File {} changed while it was read", self.source_path);
+        }
+        Ok(hex::encode(hasher.finish()?))
+    }
+
+    /// Reads `length` bytes of the contents, starting at `offset`
+    fn read_chunk(&self, offset: u64, length: u64) -> Result<Vec<u8>> {
+        if length > MAX_FILE_CHUNK_SIZE {
+            bail!("Requested chunk of {} bytes is too large", length);
+        }
+        match offset.checked_add(length) {
+            Some(end) if end <= self.length => {}
+            _ => bail!(
+                "Requested chunk at {} of {} bytes is out of range of file {} ({} bytes)",
+                offset,
+                length,
+                self.source_path,
+                self.length
+            ),
+        }
+        let mut file = std::fs::File::open(self.source_path)
+            .with_context(|| format!("Failed to open file {}", self.source_path))?;
+        file.seek(SeekFrom::Start(offset))
+            .with_context(|| format!("Failed to read file {}", self.source_path))?;
+        let mut chunk = vec![0; length as usize];
+        file.read_exact(&mut chunk)
+            .with_context(|| format!("Failed to read file {}", self.source_path))?;
+        Ok(chunk)
+    }
+
+    /// The reference to this file to put in a ServiceInfo reply
+    fn reference(&self, file: String) -> ServiceInfoApiFile {
+        ServiceInfoApiFile {
+            file,
+            length: self.length,
+        }
+    }
+}
+
+/// The directory the files uploaded by devices are written to, per device
+/// GUID. The chunks are written as they arrive, to a partial file that is
+/// moved into place once the upload is complete and verified.
+struct DeviceUploads {
+    path: PathBuf,
+    names: Vec<String>,
+}
+
+impl DeviceUploads {
+    fn file_path(&self, device_guid: &Guid, name: &str, partial: bool) -> Result<PathBuf> {
+        if !self.names.iter().any(|requested| requested == name) {
+            bail!("Upload {} was not requested", name);
+        }
+        // Keep the device path readable, but contained in a single file name
+        let mut file_name = name.replace('%', "%25").replace('/', "%2F");
+        if partial {
+            file_name.push_str(".partial");
+        }
+        Ok(self.path.join(device_guid.to_string()).join(file_name))
+    }
+
+    fn write_chunk(&self, device_guid: &Guid, name: &str, offset: u64, data: &[u8]) -> Result<()> {
+        let path = self.file_path(device_guid, name, true)?;
+        if offset == 0 {
+            std::fs::create_dir_all(path.parent().unwrap())
+                .with_context(|| format!("Failed to create directory for {}", path.display()))?;
+        }
+        let mut file = std::fs::OpenOptions::new()
+            .write(true)
+            .create(offset == 0)
+            .open(&path)
+            .with_context(|| format!("Failed to open {}", path.display()))?;
+        let received = file.metadata()?.len();
+        if received < offset {
+            bail!(
+                "Chunk of upload {} at {} leaves a gap, {} bytes were received",
+                name,
+                offset,
+                received
+            );
+        }
+        // A retried chunk replaces what was written of it before
+        file.set_len(offset)?;
+        file.seek(SeekFrom::Start(offset))?;
+        file.write_all(data)
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+        Ok(())
+    }
+
+    /// Verifies a finished upload and moves it into place. Failed uploads are
+    /// removed, and an error is returned if the contents do not match.
+    fn finish(&self, device_guid: &Guid, upload: &ServiceInfoApiDeviceUpload) -> Result<()> {
+        let partial_path = self.file_path(device_guid, &upload.name, true)?;
+        if upload.error.is_some() {
+            match std::fs::remove_file(&partial_path) {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                    log::warn!("Failed to remove {}: {}", partial_path.display(), e)
+                }
+                _ => {}
+            }
+            return Ok(());
+        }
+        if upload.length == 0 {
+            // No chunks were sent for an empty file
+            self.write_chunk(device_guid, &upload.name, 0, &[])?;
+        }
+        let path = self.file_path(device_guid, &upload.name, false)?;
+        if !partial_path.exists() && path.exists() {
+            // The results were sent again, after the upload was moved into place
+            return self.verify(&path, upload);
+        }
+        if let Err(e) = self.verify(&partial_path, upload) {
+            let _ = std::fs::remove_file(&partial_path);
+            return Err(e);
+        }
+        std::fs::rename(&partial_path, &path)
+            .with_context(|| format!("Failed to move upload into place at {}", path.display()))
+    }
+
+    fn verify(&self, path: &std::path::Path, upload: &ServiceInfoApiDeviceUpload) -> Result<()> {
+        let path = path.to_string_lossy();
+        let contents = SourceFile::open(&path)?;
+        if contents.length != upload.length {
+            bail!(
+                "Received {} bytes, but the device sent {}",
+                contents.length,
+                upload.length
+            );
+        }
+        if let Some(sha_384_hex) = &upload.sha_384_hex {
+            if &contents.digest_hex()? != sha_384_hex {
+                bail!("Invalid digest");
+            }
+        }
+        Ok(())
+    }
+}
+
+fn check_source_file(source_path: &str) -> Result<()> {
+    std::fs::File::open(source_path)
+        .with_context(|| format!("Failed to open file {source_path}"))?;
+    Ok(())
+}
+
 #[derive(Debug)]
 struct ServiceInfoConfiguration {
     settings: ServiceInfoSettings,
@@ -45,15 +237,7 @@ impl ServiceInfoConfiguration {
                     None
                 };
 
-                let contents = std::fs::read(&file.source_path)
-                    .with_context(|| format!("Failed to read file {}", file.source_path))?;
-                file.hash_hex = hex::encode(
-                    Hash::from_data(HashType::Sha384, &contents)
-                        .with_context(|| format!("Failed to hash file {}", file.source_path))?
-                        .value_bytes(),
-                );
-                file.contents_len = contents.len();
-                file.contents_hex = hex::encode(&contents);
+                check_source_file(&file.source_path)?;
 
                 new_files.push(file);
             }
@@ -63,8 +247,51 @@ impl ServiceInfoConfiguration {
             None
         };
 
+        if let Some(downloads) = &settings.downloads {
+            for download in downloads {
+                check_source_file(&download.source_path)?;
+            }
+        }
+
+        // Check the fdo.sys operations are valid
+        if let Some(operations) = &settings.sys {
+            for operation in operations {
+                match (
+                    &operation.filedesc,
+                    &operation.source_path,
+                    &operation.exec,
+                    &operation.exec_cb,
+                ) {
+                    (Some(_), Some(source_path), None, None) => check_source_file(source_path)?,
+                    (None, None, Some(_), None) | (None, None, None, Some(_)) => {}
+                    _ => anyhow::bail!(
+                        "Invalid fdo.sys operation {:?}: exactly one of filedesc (with source_path), exec or exec_cb is required",
+                        operation
+                    ),
+                }
+            }
+        }
+
         Ok(ServiceInfoConfiguration { settings })
     }
+
+    /// Returns the source path of a file referenced in a ServiceInfo reply
+    fn source_path(&self, file: &str) -> Option<&str> {
+        let (kind, index) = file.split_once('/')?;
+        let index: usize = index.parse().ok()?;
+        match kind {
+            "files" => Some(&self.settings.files.as_ref()?.get(index)?.source_path),
+            "downloads" => Some(&self.settings.downloads.as_ref()?.get(index)?.source_path),
+            "sys" => self
+                .settings
+                .sys
+                .as_ref()?
+                .get(index)?
+                .source_path
+                .as_deref(),
+            _ => None,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -84,6 +311,22 @@ struct ServiceInfoApiServerUD {
     device_specific_store: Box<
         dyn Store<fdo_store::ReadWriteOpen, Guid, ServiceInfoStoreData, ServiceInfoMetadataKey>,
     >,
+    device_results_store: Option<
+        Box<
+            dyn Store<fdo_store::ReadWriteOpen, Guid, ServiceInfoStoreData, ServiceInfoMetadataKey>,
+        >,
+    >,
+    device_uploads_store: Option<
+        Box<
+            dyn Store<
+                fdo_store::ReadWriteOpen,
+                Guid,
+                Vec<ServiceInfoApiDeviceUpload>,
+                ServiceInfoMetadataKey,
+            >,
+        >,
+    >,
+    device_uploads: Option<DeviceUploads>,
 
     // Auth Info
     service_info_auth_token: Option<String>,
@@ -184,6 +427,104 @@ async fn admin_v0_handler(
     }
 }
 
+#[derive(Debug, Serialize)]
+struct AdminV0DeviceResultsReply {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+
+    success: bool,
+
+    service_info: ServiceInfoStoreData,
+}
+
+async fn admin_v0_device_results_handler(
+    user_data: ServiceInfoApiServerUDT,
+    device_guid: String,
+) -> Result<warp::reply::Json, warp::Rejection> {
+    let reply = |res: Result<ServiceInfoStoreData>| match res {
+        Ok(service_info) => warp::reply::json(&AdminV0DeviceResultsReply {
+            error: None,
+            success: true,
+            service_info,
+        }),
+        Err(e) => warp::reply::json(&AdminV0DeviceResultsReply {
+            error: Some(e.to_string()),
+            success: false,
+            service_info: Vec::new(),
+        }),
+    };
+
+    let store = match &user_data.device_results_store {
+        Some(store) => store,
+        None => {
+            return Ok(reply(Err(anyhow::anyhow!(
+                "No device results store configured"
+            ))))
+        }
+    };
+    let device_guid = match Guid::from_str(&device_guid) {
+        Ok(guid) => guid,
+        Err(e) => return Ok(reply(Err(e.into()))),
+    };
+
+    Ok(reply(
+        store
+            .load_data(&device_guid)
+            .await
+            .map(Option::unwrap_or_default)
+            .map_err(anyhow::Error::from),
+    ))
+}
+
+#[derive(Debug, Serialize)]
+struct AdminV0DeviceUploadsReply {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+
+    success: bool,
+
+    uploads: Vec<ServiceInfoApiDeviceUpload>,
+}
+
+async fn admin_v0_device_uploads_handler(
+    user_data: ServiceInfoApiServerUDT,
+    device_guid: String,
+) -> Result<warp::reply::Json, warp::Rejection> {
+    let reply = |res: Result<Vec<ServiceInfoApiDeviceUpload>>| match res {
+        Ok(uploads) => warp::reply::json(&AdminV0DeviceUploadsReply {
+            error: None,
+            success: true,
+            uploads,
+        }),
+        Err(e) => warp::reply::json(&AdminV0DeviceUploadsReply {
+            error: Some(e.to_string()),
+            success: false,
+            uploads: Vec::new(),
+        }),
+    };
+
+    let store = match &user_data.device_uploads_store {
+        Some(store) => store,
+        None => {
+            return Ok(reply(Err(anyhow::anyhow!(
+                "No device uploads store configured"
+            ))))
+        }
+    };
+    let device_guid = match Guid::from_str(&device_guid) {
+        Ok(guid) => guid,
+        Err(e) => return Ok(reply(Err(e.into()))),
+    };
+
+    Ok(reply(
+        store
+            .load_data(&device_guid)
+            .await
+            .map(Option::unwrap_or_default)
+            .map_err(anyhow::Error::from),
+    ))
+}
+
 async fn serviceinfo_auth_handler(
     user_data: ServiceInfoApiServerUDT,
     auth_header: String,
@@ -221,6 +562,15 @@ async fn serviceinfo_handler(
         query_info.device_guid,
         query_info.modules
     );
+    if let Some(devmod) = &query_info.devmod {
+        log::info!(
+            "Device {:?} is a {} running {} ({})",
+            query_info.device_guid,
+            devmod.device,
+            devmod.version,
+            devmod.arch
+        );
+    }
 
     let mut reply: ServiceInfoApiReplyBuilder = Default::default();
 
@@ -261,12 +611,17 @@ async fn serviceinfo_handler(
         .contains(&FedoraIotServiceInfoModule::BinaryFile.into())
     {
         if let Some(files) = &user_data.service_info_configuration.settings.files {
-            for file in files {
+            for (index, file) in files.iter().enumerate() {
+                let contents = SourceFile::open(&file.source_path)
+                    .map_err(|e| warp::reject::custom(ServiceInfoFailure(e)))?;
+                let hash_hex = contents
+                    .digest_hex()
+                    .map_err(|e| warp::reject::custom(ServiceInfoFailure(e)))?;
                 reply.add_extra(FedoraIotServiceInfoModule::BinaryFile, "name", &file.path);
                 reply.add_extra(
                     FedoraIotServiceInfoModule::BinaryFile,
                     "length",
-                    &file.contents_len,
+                    &contents.length,
                 );
                 if let Some(parsed_permissions) = &file.parsed_permissions {
                     reply.add_extra(
@@ -277,13 +632,44 @@ async fn serviceinfo_handler(
                 }
                 reply.add_extra(
                     FedoraIotServiceInfoModule::BinaryFile,
-                    "data001|hex",
-                    &file.contents_hex,
+                    "data|file",
+                    &contents.reference(format!("files/{index}")),
                 );
                 reply.add_extra(
                     FedoraIotServiceInfoModule::BinaryFile,
                     "sha-384|hex",
-                    &file.hash_hex,
+                    &hash_hex,
+                );
+            }
+        }
+    }
+
+    if query_info
+        .modules
+        .contains(&StandardServiceInfoModule::Download.into())
+    {
+        if let Some(downloads) = &user_data.service_info_configuration.settings.downloads {
+            for (index, download) in downloads.iter().enumerate() {
+                let contents = SourceFile::open(&download.source_path)
+                    .map_err(|e| warp::reject::custom(ServiceInfoFailure(e)))?;
+                let hash_hex = contents
+                    .digest_hex()
+                    .map_err(|e| warp::reject::custom(ServiceInfoFailure(e)))?;
+                reply.add_extra(
+                    StandardServiceInfoModule::Download,
+                    "length",
+                    &contents.length,
+                );
+                reply.add_extra(
+                    StandardServiceInfoModule::Download,
+                    "sha-384|hex",
+                    &hash_hex,
+                );
+                reply.add_extra(StandardServiceInfoModule::Download, "name", &download.name);
+                reply.add_extra(
+                    StandardServiceInfoModule::Download,
+                    "data|file",
+                    &contents.reference(format!("downloads/{index}")),
                 );
             }
         }
@@ -360,6 +746,44 @@ async fn serviceinfo_handler(
         }
     }
 
+    if query_info
+        .modules
+        .contains(&StandardServiceInfoModule::Sys.into())
+    {
+        if let Some(operations) = &user_data.service_info_configuration.settings.sys {
+            for (index, operation) in operations.iter().enumerate() {
+                if let (Some(filedesc), Some(source_path)) =
+                    (&operation.filedesc, &operation.source_path)
+                {
+                    let contents = SourceFile::open(source_path)
+                        .map_err(|e| warp::reject::custom(ServiceInfoFailure(e)))?;
+                    reply.add_extra(StandardServiceInfoModule::Sys, "filedesc", filedesc);
+                    reply.add_extra(
+                        StandardServiceInfoModule::Sys,
+                        "write|file",
+                        &contents.reference(format!("sys/{index}")),
+                    );
+                } else if let Some(exec) = &operation.exec {
+                    reply.add_extra(StandardServiceInfoModule::Sys, "exec", exec);
+                } else if let Some(exec_cb) = &operation.exec_cb {
+                    reply.add_extra(StandardServiceInfoModule::Sys, "exec_cb", exec_cb);
+                }
+            }
+        }
+    }
+
+    if query_info
+        .modules
+        .contains(&StandardServiceInfoModule::Upload.into())
+    {
+        if let Some(uploads) = &user_data.service_info_configuration.settings.uploads {
+            reply.add_extra(StandardServiceInfoModule::Upload, "need_sha", &true);
+            for name in uploads {
+                reply.add_extra(StandardServiceInfoModule::Upload, "name", name);
+            }
+        }
+    }
+
     if query_info
         .modules
         .contains(&FedoraIotServiceInfoModule::Reboot.into())
@@ -391,6 +815,163 @@ async fn serviceinfo_handler(
     Ok(warp::reply::json(&reply.reply))
 }
 
+async fn file_chunk_handler(
+    user_data: ServiceInfoApiServerUDT,
+    query_info: FileChunkQueryInfo,
+) -> Result<warp::reply::Json, warp::Rejection> {
+    if query_info.api_version != 1 {
+        log::warn!(
+            "Unsupported API version {} requested",
+            query_info.api_version
+        );
+        return Err(warp::reject::reject());
+    }
+    log::trace!(
+        "File chunk request for device {:?}: {} at {} of {} bytes",
+        query_info.device_guid,
+        query_info.file,
+        query_info.offset,
+        query_info.length
+    );
+
+    let source_path = match user_data
+        .service_info_configuration
+        .source_path(&query_info.file)
+    {
+        Some(source_path) => source_path,
+        None => {
+            log::warn!("Request for unknown file {}", query_info.file);
+            return Err(warp::reject::not_found());
+        }
+    };
+    let chunk = SourceFile::open(source_path)
+        .and_then(|contents| contents.read_chunk(query_info.offset, query_info.length))
+        .map_err(|e| warp::reject::custom(ServiceInfoFailure(e)))?;
+
+    Ok(warp::reply::json(&ServiceInfoApiFileChunk {
+        data_hex: hex::encode(chunk),
+    }))
+}
+
+async fn upload_chunk_handler(
+    user_data: ServiceInfoApiServerUDT,
+    query_info: UploadChunkQueryInfo,
+    chunk: ServiceInfoApiFileChunk,
+) -> Result<warp::reply::Json, warp::Rejection> {
+    if query_info.api_version != 1 {
+        log::warn!(
+            "Unsupported API version {} requested",
+            query_info.api_version
+        );
+        return Err(warp::reject::reject());
+    }
+    let device_uploads = match &user_data.device_uploads {
+        Some(device_uploads) => device_uploads,
+        None => {
+            log::warn!("Received upload while no uploads are configured");
+            return Err(warp::reject::not_found());
+        }
+    };
+    let data = hex::decode(chunk.data_hex)
+        .map_err(|e| warp::reject::custom(ServiceInfoFailure(e.into())))?;
+    log::trace!(
+        "Device {:?} sent {} bytes of {} at {}",
+        query_info.device_guid,
+        data.len(),
+        query_info.upload,
+        query_info.offset
+    );
+    device_uploads
+        .write_chunk(
+            &query_info.device_guid,
+            &query_info.upload,
+            query_info.offset,
+            &data,
+        )
+        .map_err(|e| warp::reject::custom(ServiceInfoFailure(e)))?;
+
+    Ok(warp::reply::json(&serde_json::json!({})))
+}
+
+async fn device_results_handler(
+    user_data: ServiceInfoApiServerUDT,
+    query_info: DeviceResultsQueryInfo,
+    results: ServiceInfoApiDeviceResults,
+) -> Result<warp::reply::Json, warp::Rejection> {
+    if query_info.api_version != 1 {
+        log::warn!(
+            "Unsupported API version {} requested",
+            query_info.api_version
+        );
+        return Err(warp::reject::reject());
+    }
+    for (module, key, value) in &results.service_info {
+        log::info!(
+            "ServiceInfo result for device {:?}: {}:{} = {}",
+            query_info.device_guid,
+            module,
+            key,
+            value
+        );
+    }
+
+    let mut uploads = results.uploads;
+    for upload in &mut uploads {
+        // Failed uploads are cleaned up, the others verified and moved into place
+        let finished = match &user_data.device_uploads {
+            Some(device_uploads) => device_uploads.finish(&query_info.device_guid, upload),
+            None => Err(anyhow::anyhow!("No uploads are configured")),
+        };
+        if let (Err(e), None) = (finished, &upload.error) {
+            upload.error = Some(e.to_string());
+        }
+        match &upload.error {
+            None => log::info!(
+                "Device {:?} uploaded {} ({} bytes)",
+                query_info.device_guid,
+                upload.name,
+                upload.length
+            ),
+            Some(error) => log::warn!(
+                "Device {:?} failed to upload {}: {}",
+                query_info.device_guid,
+                upload.name,
+                error
+            ),
+        }
+    }
+
+    if let Some(store) = &user_data.device_results_store {
+        let mut stored = store
+            .load_data(&query_info.device_guid)
+            .await
+            .map_err(|e| warp::reject::custom(ServiceInfoFailure(e.into())))?
+            .unwrap_or_default();
+        stored.extend(results.service_info);
+        store
+            .store_data(query_info.device_guid.clone(), stored)
+            .await
+            .map_err(|e| warp::reject::custom(ServiceInfoFailure(e.into())))?;
+    }
+
+    if let (Some(store), false) = (&user_data.device_uploads_store, uploads.is_empty()) {
+        let mut stored = store
+            .load_data(&query_info.device_guid)
+            .await
+            .map_err(|e| warp::reject::custom(ServiceInfoFailure(e.into())))?
+            .unwrap_or_default();
+        // Newer uploads replace earlier ones with the same name
+        stored.retain(|existing| !uploads.iter().any(|upload| upload.name == existing.name));
+        stored.extend(uploads);
+        store
+            .store_data(query_info.device_guid, stored)
+            .await
+            .map_err(|e| warp::reject::custom(ServiceInfoFailure(e.into())))?;
+    }
+
+    Ok(warp::reply::json(&serde_json::json!({})))
+}
+
 fn deserialize_from_str<'de, D>(deserializer: D) -> Result<fdo_data_formats::types::Guid, D::Error>
 where
     D: serde::de::Deserializer<'de>,
@@ -411,6 +992,16 @@ where
         .collect())
 }
 
+fn deserialize_devmod<'de, D>(deserializer: D) -> Result<Option<ServiceInfoApiDevMod>, D::Error>
+where
+    D: serde::de::Deserializer<'de>,
+{
+    let s = String::deserialize(deserializer)?;
+    serde_json::from_str(&s)
+        .map(Some)
+        .map_err(serde::de::Error::custom)
+}
+
 #[derive(Debug, Deserialize)]
 struct QueryInfo {
     #[serde(rename = "serviceinfo_api_version")]
@@ -419,6 +1010,37 @@ struct QueryInfo {
     device_guid: fdo_data_formats::types::Guid,
     #[serde(deserialize_with = "deserialize_from_comma_separated_strings")]
     modules: HashSet<ServiceInfoModule>,
+    #[serde(default, deserialize_with = "deserialize_devmod")]
+    devmod: Option<ServiceInfoApiDevMod>,
+}
+
+#[derive(Debug, Deserialize)]
+struct FileChunkQueryInfo {
+    #[serde(rename = "serviceinfo_api_version")]
+    api_version: u32,
+    #[serde(deserialize_with = "deserialize_from_str")]
+    device_guid: fdo_data_formats::types::Guid,
+    file: String,
+    offset: u64,
+    length: u64,
+}
+
+#[derive(Debug, Deserialize)]
+struct UploadChunkQueryInfo {
+    #[serde(rename = "serviceinfo_api_version")]
+    api_version: u32,
+    #[serde(deserialize_with = "deserialize_from_str")]
+    device_guid: fdo_data_formats::types::Guid,
+    upload: String,
+    offset: u64,
+}
+
+#[derive(Debug, Deserialize)]
+struct DeviceResultsQueryInfo {
+    #[serde(rename = "serviceinfo_api_version")]
+    api_version: u32,
+    #[serde(deserialize_with = "deserialize_from_str")]
+    device_guid: fdo_data_formats::types::Guid,
 }
 
 #[tokio::main]
@@ -432,6 +1054,8 @@ async fn main() -> Result<()> {
 
     // Bind information
     let bind_addr = settings.bind.clone();
+    let tls_settings = settings.tls;
+    let metrics_bind = settings.metrics_bind.clone();
 
     // ServiceInfo settings
     let service_info_configuration = ServiceInfoConfiguration::from_settings(settings.service_info)
@@ -441,11 +1065,51 @@ async fn main() -> Result<()> {
         .device_specific_store_driver
         .initialize()
         .context("Error initializing device-specific store")?;
+    let device_specific_store =
+        fdo_http_wrapper::server::metrics::timed_store("device_specific", device_specific_store);
+    let device_results_store = match settings.device_results_store_driver {
+        Some(driver) => Some(fdo_http_wrapper::server::metrics::timed_store(
+            "device_results",
+            driver
+                .initialize()
+                .context("Error initializing device results store")?,
+        )),
+        None => None,
+    };
+    let device_uploads_store = match settings.device_uploads_store_driver {
+        Some(driver) => Some(fdo_http_wrapper::server::metrics::timed_store(
+            "device_uploads",
+            driver
+                .initialize()
+                .context("Error initializing device uploads store")?,
+        )),
+        None => None,
+    };
+
+    let device_uploads = match (
+        &service_info_configuration.settings.uploads,
+        settings.device_uploads_path,
+    ) {
+        (None, _) => None,
+        (Some(_), None) => bail!("Uploads are configured, but device_uploads_path is not set"),
+        (Some(names), Some(path)) => {
+            std::fs::create_dir_all(&path).with_context(|| {
+                format!("Error creating device uploads directory {}", path.display())
+            })?;
+            Some(DeviceUploads {
+                path,
+                names: names.clone(),
+            })
+        }
+    };
 
     let user_data = std::sync::Arc::new(ServiceInfoApiServerUD {
         service_info_configuration,
 
         device_specific_store,
+        device_results_store,
+        device_uploads_store,
+        device_uploads,
 
         service_info_auth_token: settings
             .service_info_auth_token
@@ -453,7 +1117,12 @@ async fn main() -> Result<()> {
         admin_auth_token: settings.admin_auth_token.map(|s| format!("Bearer {s}")),
     });
     let ud_si = user_data.clone();
+    let ud_file_chunk = user_data.clone();
+    let ud_results = user_data.clone();
+    let ud_upload_chunk = user_data.clone();
     let ud_admin = user_data.clone();
+    let ud_admin_results = user_data.clone();
+    let ud_admin_uploads = user_data.clone();
 
     let serviceinfo = warp::path("device_info")
         .map(move || ud_si.clone())
@@ -462,6 +1131,14 @@ async fn main() -> Result<()> {
         .and(warp::query::query::<QueryInfo>())
         .and_then(serviceinfo_handler);
 
+    let file_chunk = warp::get()
+        .and(warp::path("device_info"))
+        .map(move || ud_file_chunk.clone())
+        .and(warp::header::header("Authorization"))
+        .and_then(serviceinfo_auth_handler)
+        .and(warp::query::query::<FileChunkQueryInfo>())
+        .and_then(file_chunk_handler);
+
     let admin_v0 = warp::post()
         .and(warp::path("admin"))
         .and(warp::path("v0"))
@@ -471,23 +1148,218 @@ async fn main() -> Result<()> {
         .and(warp::body::json())
         .and_then(admin_v0_handler);
 
+    let upload_chunk = warp::post()
+        .and(warp::path("device_info"))
+        .map(move || ud_upload_chunk.clone())
+        .and(warp::header::header("Authorization"))
+        .and_then(serviceinfo_auth_handler)
+        .and(warp::query::query::<UploadChunkQueryInfo>())
+        .and(warp::body::json())
+        .and_then(upload_chunk_handler);
+
+    let device_results = warp::post()
+        .and(warp::path("device_info"))
+        .map(move || ud_results.clone())
+        .and(warp::header::header("Authorization"))
+        .and_then(serviceinfo_auth_handler)
+        .and(warp::query::query::<DeviceResultsQueryInfo>())
+        .and(warp::body::json())
+        .and_then(device_results_handler);
+
+    let admin_v0_device_results = warp::get()
+        .and(warp::path("admin"))
+        .and(warp::path("v0"))
+        .and(warp::path("device_results"))
+        .map(move || ud_admin_results.clone())
+        .and(warp::header::header("Authorization"))
+        .and_then(admin_auth_handler)
+        .and(warp::path::param::<String>())
+        .and(warp::path::end())
+        .and_then(admin_v0_device_results_handler);
+
+    let admin_v0_device_uploads = warp::get()
+        .and(warp::path("admin"))
+        .and(warp::path("v0"))
+        .and(warp::path("device_uploads"))
+        .map(move || ud_admin_uploads.clone())
+        .and(warp::header::header("Authorization"))
+        .and_then(admin_auth_handler)
+        .and(warp::path::param::<String>())
+        .and(warp::path::end())
+        .and_then(admin_v0_device_uploads_handler);
+
     let handler_ping = fdo_http_wrapper::server::ping_handler();
 
-    let routes = warp::get()
-        .and(serviceinfo)
+    let routes = file_chunk
+        .or(warp::get().and(serviceinfo))
+        .or(upload_chunk)
+        .or(device_results)
         .or(admin_v0)
+        .or(admin_v0_device_results)
+        .or(admin_v0_device_uploads)
         .or(handler_ping)
         .with(warp::log("serviceinfo-api-server"));
 
     log::info!("Listening on {}", bind_addr);
-    let server = warp::serve(routes);
-    let server = server
-        .bind_with_graceful_shutdown(bind_addr, async {
-            signal(SignalKind::terminate()).unwrap().recv().await;
-            log::info!("Terminating");
-        })
-        .1;
-    tokio::join!(server);
+    if let Some(metrics_bind) = metrics_bind {
+        let metrics_server = fdo_http_wrapper::server::metrics::serve_metrics(metrics_bind.into())
+            .context("Error binding metrics listener")?;
+        tokio::spawn(metrics_server);
+    }
+    let shutdown = async {
+        signal(SignalKind::terminate()).unwrap().recv().await;
+        log::info!("Terminating");
+    };
+    let (_, server) =
+        fdo_http_wrapper::server::serve(routes, bind_addr, tls_settings.map(Into::into), shutdown);
+    let server = tokio::spawn(server);
+    let _ = tokio::join!(server);
 
     Ok(())
 }
+
+#[cfg(test)]
+mod test {
+    use fdo_data_formats::types::Guid;
+    use fdo_util::servers::ServiceInfoApiDeviceUpload;
+
+    use super::{DeviceUploads, ServiceInfoConfiguration, SourceFile, MAX_FILE_CHUNK_SIZE};
+
+    #[test]
+    fn test_source_file_chunks() {
+        let path = std::env::temp_dir().join(format!("fdo-test-source-{}", std::process::id()));
+        let contents: Vec<u8> = (0..100_000).map(|i| i as u8).collect();
+        std::fs::write(&path, &contents).unwrap();
+
+        let source = SourceFile::open(path.to_str().unwrap()).unwrap();
+        assert_eq!(source.length, contents.len() as u64);
+        assert_eq!(
+            source.digest_hex().unwrap(),
+            hex::encode(openssl::sha::sha384(&contents))
+        );
+
+        let reference = source.reference("files/0".to_string());
+        assert_eq!(reference.file, "files/0");
+        assert_eq!(reference.length, contents.len() as u64);
+
+        assert_eq!(source.read_chunk(0, 1000).unwrap(), &contents[..1000]);
+        assert_eq!(
+            source.read_chunk(99_000, 1000).unwrap(),
+            &contents[99_000..]
+        );
+        assert!(source.read_chunk(0, 0).unwrap().is_empty());
+
+        // Chunks out of range or too large are refused
+        assert!(source.read_chunk(99_001, 1000).is_err());
+        assert!(source.read_chunk(u64::MAX, 1).is_err());
+        assert!(source.read_chunk(0, MAX_FILE_CHUNK_SIZE + 1).is_err());
+
+        // A file that changes after it was announced is not sent
+        std::fs::write(&path, b"changed").unwrap();
+        assert!(source.digest_hex().is_err());
+        assert!(source.read_chunk(0, 1000).is_err());
+
+        std::fs::remove_file(&path).unwrap();
+        assert!(SourceFile::open(path.to_str().unwrap()).is_err());
+    }
+
+    #[test]
+    fn test_source_path() {
+        let configuration = ServiceInfoConfiguration {
+            settings: serde_json::from_value(serde_json::json!({
+                "files": [{"path": "/etc/hosts", "source_path": "/srv/hosts"}],
+                "downloads": [{"name": "firmware.img", "source_path": "/srv/firmware.img"}],
+                "sys": [
+                    {"exec": ["/bin/true"]},
+                    {"filedesc": "setup.sh", "source_path": "/srv/setup.sh"},
+                ],
+            }))
+            .unwrap(),
+        };
+
+        assert_eq!(configuration.source_path("files/0"), Some("/srv/hosts"));
+        assert_eq!(
+            configuration.source_path("downloads/0"),
+            Some("/srv/firmware.img")
+        );
+        assert_eq!(configuration.source_path("sys/1"), Some("/srv/setup.sh"));
+
+        // Only configured files can be read
+        assert_eq!(configuration.source_path("sys/0"), None);
+        assert_eq!(configuration.source_path("files/1"), None);
+        assert_eq!(configuration.source_path("files/-1"), None);
+        assert_eq!(configuration.source_path("/srv/hosts"), None);
+        assert_eq!(configuration.source_path("commands/0"), None);
+    }
+
+    #[test]
+    fn test_device_uploads() {
+        let path = std::env::temp_dir().join(format!("fdo-test-uploads-{}", std::process::id()));
+        let uploads = DeviceUploads {
+            path: path.clone(),
+            names: vec!["/var/log/messages".to_string(), "/empty".to_string()],
+        };
+        let device_guid = Guid::new().unwrap();
+        let contents = b"uploaded file";
+        let upload = |name: &str, length: usize, digest: &[u8]| ServiceInfoApiDeviceUpload {
+            name: name.to_string(),
+            length: length as u64,
+            sha_384_hex: Some(hex::encode(openssl::sha::sha384(digest))),
+            error: None,
+        };
+
+        // Only requested uploads are accepted
+        assert!(uploads
+            .write_chunk(&device_guid, "/etc/shadow", 0, contents)
+            .is_err());
+
+        uploads
+            .write_chunk(&device_guid, "/var/log/messages", 0, &contents[..5])
+            .unwrap();
+        // A chunk that is sent again replaces the earlier one
+        uploads
+            .write_chunk(&device_guid, "/var/log/messages", 0, &contents[..5])
+            .unwrap();
+        assert!(uploads
+            .write_chunk(&device_guid, "/var/log/messages", 6, &contents[6..])
+            .is_err());
+        uploads
+            .write_chunk(&device_guid, "/var/log/messages", 5, &contents[5..])
+            .unwrap();
+
+        let finished = upload("/var/log/messages", contents.len(), contents);
+        uploads.finish(&device_guid, &finished).unwrap();
+        let stored = path
+            .join(device_guid.to_string())
+            .join("%2Fvar%2Flog%2Fmessages");
+        assert_eq!(std::fs::read(&stored).unwrap(), contents);
+        // Finishing again, when the results are forwarded again, is fine
+        uploads.finish(&device_guid, &finished).unwrap();
+
+        // Uploads that do not match what the device sent are removed
+        uploads
+            .write_chunk(&device_guid, "/var/log/messages", 0, b"corrupted fil")
+            .unwrap();
+        assert!(uploads.finish(&device_guid, &finished).is_err());
+        uploads
+            .write_chunk(&device_guid, "/var/log/messages", 0, &contents[..5])
+            .unwrap();
+        assert!(uploads.finish(&device_guid, &finished).is_err());
+        assert!(!uploads
+            .file_path(&device_guid, "/var/log/messages", true)
+            .unwrap()
+            .exists());
+
+        // Empty files have no chunks
+        uploads
+            .finish(&device_guid, &upload("/empty", 0, b""))
+            .unwrap();
+        assert!(
+            std::fs::read(path.join(device_guid.to_string()).join("%2Fempty"))
+                .unwrap()
+                .is_empty()
+        );
+
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+}
@@ -4,6 +4,7 @@ use std::convert::TryInto;
 use std::fs::{self, File};
 use std::marker::PhantomData;
 use std::path::{Path, PathBuf};
+use std::sync::Mutex;
 use std::time::{Duration, SystemTime};
 
 use async_trait::async_trait;
@@ -47,6 +48,7 @@ where
         phantom_v: PhantomData,
 
         directory: canonicalized_directory,
+        group_lock: Mutex::new(()),
     }))
 }
 
@@ -56,6 +58,8 @@ struct DirectoryStore<K, V> {
     phantom_v: PhantomData<V>,
 
     directory: PathBuf,
+    // Serializes the group checks in store_data_in_group
+    group_lock: Mutex<()>,
 }
 
 impl<K, V> DirectoryStore<K, V>
@@ -65,8 +69,95 @@ where
     fn get_path(&self, key: &K) -> PathBuf {
         self.directory.join(key.to_string().replace('/', "_slash_"))
     }
+
+    fn write_data(&self, key: &K, value: &V, owner_group: Option<&str>) -> Result<(), StoreError>
+    where
+        V: Serializable,
+    {
+        let finalpath = self.get_path(key);
+        let mut path = finalpath.clone();
+        path.set_file_name(format!(
+            ".{}.tmp",
+            finalpath.file_name().unwrap().to_str().unwrap()
+        ));
+        log::trace!(
+            "Attempting to store data to {} (temporary at {})",
+            finalpath.display(),
+            path.display()
+        );
+
+        let file = File::create(&path).map_err(|e| {
+            StoreError::Unspecified(format!("Error creating file {}: {:?}", path.display(), e))
+        })?;
+        value.serialize_to_writer(&file).map_err(|e| {
+            StoreError::Unspecified(format!("Error writing file {}: {:?}", path.display(), e))
+        })?;
+        if let Some(owner_group) = owner_group {
+            file.set_xattr(format_xattr(OWNER_GROUP_KEY), owner_group.as_bytes())
+                .map_err(|e| {
+                    StoreError::Unspecified(format!(
+                        "Error creating xattr on {}: {:?}",
+                        path.display(),
+                        e
+                    ))
+                })?;
+        }
+
+        fs::rename(&path, &finalpath).map_err(|e| {
+            StoreError::Unspecified(format!(
+                "Error moving temporary file {} to {}: {:?}",
+                path.display(),
+                finalpath.display(),
+                e
+            ))
+        })
+    }
+
+    /// Counts the unexpired entries of `owner_group`, except for `key`
+    fn count_group<MKT: MetadataLocalKey>(
+        &self,
+        key: &K,
+        owner_group: &str,
+    ) -> Result<u64, StoreError> {
+        let except = self.get_path(key);
+        let dir_entries = fs::read_dir(&self.directory).map_err(|e| {
+            StoreError::Unspecified(format!(
+                "Unable to list directory {}: {:?}",
+                &self.directory.display(),
+                e
+            ))
+        })?;
+        let mut count = 0;
+        for entry in dir_entries.flatten() {
+            match entry.file_type() {
+                Ok(v) if v.is_file() => {}
+                _ => continue,
+            }
+            let path = entry.path();
+            // Skip the temporary files from store_data
+            if path == except || entry.file_name().to_string_lossy().starts_with('.') {
+                continue;
+            }
+            match xattr::get(&path, format_xattr(OWNER_GROUP_KEY)) {
+                Ok(Some(group)) if group == owner_group.as_bytes() => {}
+                _ => continue,
+            }
+            if let Ok(Some(ttl)) =
+                xattr::get(&path, format_xattr(crate::MetadataKey::<MKT>::Ttl.to_key()))
+            {
+                if SystemTime::now() > ttl_from_disk(&ttl)? {
+                    continue;
+                }
+            }
+            count += 1;
+        }
+        Ok(count)
+    }
 }
 
+// The xattr holding the group of store_data_in_group
+const OWNER_GROUP_KEY: &str = "owner_group";
+
 // TODO(runcom): fix this to use time::Duration and time
 fn ttl_from_disk(ttl: &[u8]) -> Result<SystemTime, StoreError> {
     if ttl.len() != 8 {
@@ -236,6 +327,137 @@ where
         })?))
     }
 
+    async fn load_all_data(&self) -> Result<Vec<V>, StoreError> {
+        let dir_entries = fs::read_dir(&self.directory).map_err(|e| {
+            StoreError::Unspecified(format!(
+                "Unable to list directory {}: {:?}",
+                &self.directory.display(),
+                e
+            ))
+        })?;
+        let mut values = Vec::new();
+        for entry in dir_entries {
+            let entry = entry
+                .map_err(|e| StoreError::Unspecified(format!("Unable to process entry: {e:?}")))?;
+            let path = entry.path();
+            match entry.file_type() {
+                Ok(v) if v.is_file() => {}
+                _ => continue,
+            }
+            // Skip the temporary files from store_data
+            if entry.file_name().to_string_lossy().starts_with('.') {
+                continue;
+            }
+            let file = match File::open(&path) {
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(StoreError::Unspecified(format!("Error opening file: {e}"))),
+                Ok(f) => f,
+            };
+            if let Ok(Some(ttl)) =
+                file.get_xattr(format_xattr(crate::MetadataKey::<MKT>::Ttl.to_key()))
+            {
+                if SystemTime::now() > ttl_from_disk(&ttl)? {
+                    continue;
+                }
+            }
+            match V::deserialize_from_reader(&file) {
+                Ok(v) => values.push(v),
+                Err(e) => log::warn!("Error deserializing data {}: {:?}", path.display(), e),
+            }
+        }
+        Ok(values)
+    }
+
+    async fn load_all_keys(&self) -> Result<Vec<K>, StoreError> {
+        let dir_entries = fs::read_dir(&self.directory).map_err(|e| {
+            StoreError::Unspecified(format!(
+                "Unable to list directory {}: {:?}",
+                &self.directory.display(),
+                e
+            ))
+        })?;
+        let mut keys = Vec::new();
+        for entry in dir_entries.flatten() {
+            match entry.file_type() {
+                Ok(v) if v.is_file() => {}
+                _ => continue,
+            }
+            let file_name = entry.file_name().to_string_lossy().to_string();
+            // Skip the temporary files from store_data
+            if file_name.starts_with('.') {
+                continue;
+            }
+            if let Ok(Some(ttl)) = xattr::get(
+                entry.path(),
+                format_xattr(crate::MetadataKey::<MKT>::Ttl.to_key()),
+            ) {
+                if SystemTime::now() > ttl_from_disk(&ttl)? {
+                    continue;
+                }
+            }
+            match K::from_str(&file_name.replace("_slash_", "/")) {
+                Ok(key) => keys.push(key),
+                Err(_) => log::warn!("Skipping file with invalid key name {file_name}"),
+            }
+        }
+        Ok(keys)
+    }
+
+    async fn count_data(&self) -> Result<u64, StoreError> {
+        let dir_entries = fs::read_dir(&self.directory).map_err(|e| {
+            StoreError::Unspecified(format!(
+                "Unable to list directory {}: {:?}",
+                &self.directory.display(),
+                e
+            ))
+        })?;
+        let mut count = 0;
+        for entry in dir_entries.flatten() {
+            match entry.file_type() {
+                Ok(v) if v.is_file() => {}
+                _ => continue,
+            }
+            // Skip the temporary files from store_data
+            if entry.file_name().to_string_lossy().starts_with('.') {
+                continue;
+            }
+            if let Ok(Some(ttl)) = xattr::get(
+                entry.path(),
+                format_xattr(crate::MetadataKey::<MKT>::Ttl.to_key()),
+            ) {
+                if SystemTime::now() > ttl_from_disk(&ttl)? {
+                    continue;
+                }
+            }
+            count += 1;
+        }
+        Ok(count)
+    }
+
+    async fn load_metadata(
+        &self,
+        key: &K,
+        metadata_key: &crate::MetadataKey<MKT>,
+    ) -> Result<Option<Vec<u8>>, StoreError> {
+        let path = self.get_path(key);
+        log::trace!("Attempting to load metadata from {}", path.display());
+
+        let file = match File::open(&path) {
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(StoreError::Unspecified(format!("Error opening file: {e}"))),
+            Ok(f) => f,
+        };
+
+        file.get_xattr(format_xattr(metadata_key.to_key()))
+            .map_err(|e| {
+                StoreError::Unspecified(format!(
+                    "Error reading xattr on {}: {:?}",
+                    path.display(),
+                    e
+                ))
+            })
+    }
+
     async fn store_metadata(
         &self,
         key: &K,
@@ -311,33 +533,24 @@ where
     }
 
     async fn store_data(&self, key: K, value: V) -> Result<(), StoreError> {
-        let finalpath = self.get_path(&key);
-        let mut path = finalpath.clone();
-        path.set_file_name(format!(
-            ".{}.tmp",
-            finalpath.file_name().unwrap().to_str().unwrap()
-        ));
-        log::trace!(
-            "Attempting to store data to {} (temporary at {})",
-            finalpath.display(),
-            path.display()
-        );
-
-        let file = File::create(&path).map_err(|e| {
-            StoreError::Unspecified(format!("Error creating file {}: {:?}", path.display(), e))
-        })?;
-        value.serialize_to_writer(&file).map_err(|e| {
-            StoreError::Unspecified(format!("Error writing file {}: {:?}", path.display(), e))
-        })?;
+        self.write_data(&key, &value, None)
+    }
 
-        fs::rename(&path, &finalpath).map_err(|e| {
-            StoreError::Unspecified(format!(
-                "Error moving temporary file {} to {}: {:?}",
-                path.display(),
-                finalpath.display(),
-                e
-            ))
-        })
+    async fn store_data_in_group(
+        &self,
+        key: K,
+        value: V,
+        owner_group: &str,
+        max_entries: Option<u64>,
+    ) -> Result<bool, StoreError> {
+        let _guard = self.group_lock.lock().unwrap();
+        if let Some(max_entries) = max_entries {
+            if self.count_group::<MKT>(&key, owner_group)? >= max_entries {
+                return Ok(false);
+            }
+        }
+        self.write_data(&key, &value, Some(owner_group))?;
+        Ok(true)
     }
 
     async fn destroy_data(&self, key: &K) -> Result<(), StoreError> {
@@ -403,3 +616,72 @@ where
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::format_xattr;
+    use crate::{MetadataKey, MetadataLocalKey, ReadWriteOpen, Store};
+
+    enum TestMetadataKey {}
+
+    impl MetadataLocalKey for TestMetadataKey {
+        fn to_key(&self) -> &'static str {
+            match *self {}
+        }
+    }
+
+    #[tokio::test]
+    async fn test_store_data_in_group() {
+        let dir = tempfile::tempdir().unwrap();
+        let store: Box<dyn Store<ReadWriteOpen, String, String, TestMetadataKey>> =
+            super::initialize(dir.path()).unwrap();
+
+        for key in ["a", "b"] {
+            assert!(store
+                .store_data_in_group(key.to_string(), key.to_string(), "group", Some(2))
+                .await
+                .unwrap());
+        }
+        assert!(!store
+            .store_data_in_group("c".to_string(), "c".to_string(), "group", Some(2))
+            .await
+            .unwrap());
+        assert_eq!(store.load_data(&"c".to_string()).await.unwrap(), None);
+
+        // Replacing a value of the group does not count it twice
+        assert!(store
+            .store_data_in_group("a".to_string(), "new".to_string(), "group", Some(2))
+            .await
+            .unwrap());
+        assert_eq!(
+            store.load_data(&"a".to_string()).await.unwrap(),
+            Some("new".to_string())
+        );
+
+        // Other groups and unlimited groups are not affected
+        assert!(store
+            .store_data_in_group("c".to_string(), "c".to_string(), "other", Some(1))
+            .await
+            .unwrap());
+        assert!(store
+            .store_data_in_group("d".to_string(), "d".to_string(), "group", None)
+            .await
+            .unwrap());
+        assert!(!store
+            .store_data_in_group("e".to_string(), "e".to_string(), "group", Some(3))
+            .await
+            .unwrap());
+
+        // Expired values no longer count
+        xattr::set(
+            dir.path().join("b"),
+            format_xattr(MetadataKey::<TestMetadataKey>::Ttl.to_key()),
+            &1_u64.to_le_bytes(),
+        )
+        .unwrap();
+        assert!(store
+            .store_data_in_group("e".to_string(), "e".to_string(), "group", Some(3))
+            .await
+            .unwrap());
+    }
+}
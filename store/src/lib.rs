@@ -88,8 +88,29 @@ impl<T: MetadataLocalKey> MetadataKey<T> {
     }
 }
 
+/// The metadata stored along with the ownership vouchers of the owner
+/// onboarding and manufacturing servers
+#[non_exhaustive]
+pub enum OwnershipVoucherStoreMetadataKey {
+    To2Performed,
+    To0AcceptOwnerWaitSeconds,
+}
+
+impl MetadataLocalKey for OwnershipVoucherStoreMetadataKey {
+    fn to_key(&self) -> &'static str {
+        match self {
+            OwnershipVoucherStoreMetadataKey::To2Performed => "fdo.to2_performed",
+            OwnershipVoucherStoreMetadataKey::To0AcceptOwnerWaitSeconds => {
+                "fdo.to0_accept_owner_wait_seconds"
+            }
+        }
+    }
+}
+
 type FilterQueryResult<V> = Option<ValueIter<V>>;
 
+type MetadataResult = Result<Option<Vec<u8>>, StoreError>;
+
 pub trait FilterType<V, MKT>: Send + Sync
 where
     V: Clone,
@@ -154,6 +175,43 @@ pub trait Store<OT: StoreOpenMode, K, V, MKT: MetadataLocalKey>: Send + Sync {
         Self: 'async_trait,
         OT: Readable;
 
+    fn load_all_data<'life0, 'async_trait>(
+        &'life0 self,
+    ) -> Pin<Box<dyn Future<Output = Result<Vec<V>, StoreError>> + 'async_trait + Send>>
+    where
+        'life0: 'async_trait,
+        Self: 'async_trait,
+        OT: Readable;
+
+    fn load_all_keys<'life0, 'async_trait>(
+        &'life0 self,
+    ) -> Pin<Box<dyn Future<Output = Result<Vec<K>, StoreError>> + 'async_trait + Send>>
+    where
+        'life0: 'async_trait,
+        Self: 'async_trait,
+        OT: Readable;
+
+    fn count_data<'life0, 'async_trait>(
+        &'life0 self,
+    ) -> Pin<Box<dyn Future<Output = Result<u64, StoreError>> + 'async_trait + Send>>
+    where
+        'life0: 'async_trait,
+        Self: 'async_trait,
+        OT: Readable;
+
+    /// Returns the metadata value in the format produced by [`MetadataValue::to_stored`]
+    fn load_metadata<'life0, 'life1, 'life2, 'async_trait>(
+        &'life0 self,
+        key: &'life1 K,
+        metadata_key: &'life2 MetadataKey<MKT>,
+    ) -> Pin<Box<dyn Future<Output = MetadataResult> + 'async_trait + Send>>
+    where
+        'life0: 'async_trait,
+        'life1: 'async_trait,
+        'life2: 'async_trait,
+        Self: 'async_trait,
+        OT: Readable;
+
     fn store_metadata<'life0, 'life1, 'life2, 'life3, 'async_trait>(
         &'life0 self,
         key: &'life1 K,
@@ -218,6 +276,22 @@ pub trait Store<OT: StoreOpenMode, K, V, MKT: MetadataLocalKey>: Send + Sync {
         Self: 'async_trait,
         OT: Writable;
 
+    /// Stores the value tagged with `owner_group`, unless `max_entries` other
+    /// unexpired values of that group are stored already. The check and the
+    /// store are atomic. Returns whether the value was stored.
+    fn store_data_in_group<'life0, 'life1, 'async_trait>(
+        &'life0 self,
+        key: K,
+        value: V,
+        owner_group: &'life1 str,
+        max_entries: Option<u64>,
+    ) -> Pin<Box<dyn Future<Output = Result<bool, StoreError>> + 'async_trait + Send>>
+    where
+        'life0: 'async_trait,
+        'life1: 'async_trait,
+        Self: 'async_trait,
+        OT: Writable;
+
     fn destroy_data<'life0, 'life1, 'async_trait>(
         &'life0 self,
         key: &'life1 K,
@@ -245,6 +319,7 @@ pub enum ServerType {
     Manufacturer,
     Owner,
     Rendezvous,
+    Session,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -10,7 +10,9 @@ use std::marker::PhantomData;
 use crate::ServerType;
 use crate::Store;
 use crate::StoreError;
-use crate::{FilterType, MetadataLocalKey, MetadataValue, ValueIter};
+use crate::{
+    FilterType, MetadataLocalKey, MetadataValue, OwnershipVoucherStoreMetadataKey, ValueIter,
+};
 use fdo_data_formats::Serializable;
 
 pub(super) fn initialize<OT, K, V, MKT>(
@@ -39,6 +41,11 @@ where
             phantom_v: PhantomData,
             connection_pool: fdo_db::sqlite::SqliteRendezvousDB::get_conn_pool(url),
         })),
+        ServerType::Session => Ok(Box::new(SqliteSessionStore {
+            phantom_k: PhantomData,
+            phantom_v: PhantomData,
+            connection_pool: fdo_db::sqlite::SqliteSessionDB::get_conn_pool(url),
+        })),
     }
 }
 
@@ -98,6 +105,69 @@ where
         })?))
     }
 
+    async fn load_all_data(&self) -> Result<Vec<V>, StoreError> {
+        let conn = &mut self
+            .connection_pool
+            .get()
+            .map_err(|e| StoreError::Database(format!("Error connecting to DB {e:?}")))?;
+        let db_ovs = fdo_db::sqlite::SqliteManufacturerDB::get_all_ovs(conn)
+            .map_err(|e| StoreError::Database(format!("Error selecting all OVs: {e:?}")))?;
+        db_ovs
+            .iter()
+            .map(|db_ov| {
+                V::deserialize_data(&db_ov.contents).map_err(|e| {
+                    StoreError::Unspecified(format!("Error deserializing value: {e:?}"))
+                })
+            })
+            .collect()
+    }
+
+    async fn load_all_keys(&self) -> Result<Vec<K>, StoreError> {
+        let conn = &mut self
+            .connection_pool
+            .get()
+            .map_err(|e| StoreError::Database(format!("Error connecting to DB {e:?}")))?;
+        let db_ovs = fdo_db::sqlite::SqliteManufacturerDB::get_all_ovs(conn)
+            .map_err(|e| StoreError::Database(format!("Error selecting all OVs: {e:?}")))?;
+        db_ovs
+            .iter()
+            .map(|db_ov| {
+                K::from_str(&db_ov.guid).map_err(|_| {
+                    StoreError::Unspecified(format!("Invalid key {} in the DB", db_ov.guid))
+                })
+            })
+            .collect()
+    }
+
+    async fn count_data(&self) -> Result<u64, StoreError> {
+        let conn = &mut self
+            .connection_pool
+            .get()
+            .map_err(|e| StoreError::Database(format!("Error connecting to DB {e:?}")))?;
+        let count = fdo_db::sqlite::SqliteManufacturerDB::count_ovs(conn)
+            .map_err(|e| StoreError::Database(format!("Error counting OVs: {e:?}")))?;
+        Ok(count as u64)
+    }
+
+    async fn load_metadata(
+        &self,
+        key: &K,
+        _metadata_key: &crate::MetadataKey<MKT>,
+    ) -> Result<Option<Vec<u8>>, StoreError> {
+        let conn = &mut self
+            .connection_pool
+            .get()
+            .map_err(|e| StoreError::Database(format!("Error connecting to DB {e:?}")))?;
+        let ov_db =
+            fdo_db::sqlite::SqliteManufacturerDB::get_ov(&key.to_string(), conn).map_err(|e| {
+                StoreError::Database(format!(
+                    "Error selecting OV with guid {}: {e:?}",
+                    key.to_string()
+                ))
+            })?;
+        Ok(ov_db.ttl.map(|ttl| i64::to_le_bytes(ttl).into()))
+    }
+
     async fn store_metadata(
         &self,
         key: &K,
@@ -174,6 +244,16 @@ where
         })
     }
 
+    async fn store_data_in_group(
+        &self,
+        _key: K,
+        _value: V,
+        _owner_group: &str,
+        _max_entries: Option<u64>,
+    ) -> Result<bool, StoreError> {
+        Err(StoreError::MethodNotAvailable)
+    }
+
     async fn destroy_data(&self, key: &K) -> Result<(), StoreError> {
         let conn = &mut self
             .connection_pool
@@ -248,13 +328,107 @@ where
             .connection_pool
             .get()
             .expect("Couldn't establish a connection");
-        let ov_db = fdo_db::sqlite::SqliteOwnerDB::get_ov(&key.to_string(), conn)
-            .expect("Error selecting OV");
+        let ov_db = match fdo_db::sqlite::SqliteOwnerDB::get_ov(&key.to_string(), conn) {
+            Ok(ov_db) => ov_db,
+            Err(e) => match e.downcast_ref::<diesel::result::Error>() {
+                Some(diesel::result::Error::NotFound) => return Ok(None),
+                _ => {
+                    return Err(StoreError::Database(format!(
+                        "Error selecting OV with guid {}: {e:?}",
+                        key.to_string()
+                    )))
+                }
+            },
+        };
         Ok(Some(V::deserialize_data(&ov_db.contents).map_err(|e| {
             StoreError::Unspecified(format!("Error deserializing value: {e:?}"))
         })?))
     }
 
+    async fn load_all_data(&self) -> Result<Vec<V>, StoreError> {
+        let conn = &mut self
+            .connection_pool
+            .get()
+            .map_err(|e| StoreError::Database(format!("Error connecting to DB {e:?}")))?;
+        let db_ovs = fdo_db::sqlite::SqliteOwnerDB::get_all_ovs(conn)
+            .map_err(|e| StoreError::Database(format!("Error selecting all OVs: {e:?}")))?;
+        db_ovs
+            .iter()
+            .map(|db_ov| {
+                V::deserialize_data(&db_ov.contents).map_err(|e| {
+                    StoreError::Unspecified(format!("Error deserializing value: {e:?}"))
+                })
+            })
+            .collect()
+    }
+
+    async fn load_all_keys(&self) -> Result<Vec<K>, StoreError> {
+        let conn = &mut self
+            .connection_pool
+            .get()
+            .map_err(|e| StoreError::Database(format!("Error connecting to DB {e:?}")))?;
+        let db_ovs = fdo_db::sqlite::SqliteOwnerDB::get_all_ovs(conn)
+            .map_err(|e| StoreError::Database(format!("Error selecting all OVs: {e:?}")))?;
+        db_ovs
+            .iter()
+            .map(|db_ov| {
+                K::from_str(&db_ov.guid).map_err(|_| {
+                    StoreError::Unspecified(format!("Invalid key {} in the DB", db_ov.guid))
+                })
+            })
+            .collect()
+    }
+
+    async fn count_data(&self) -> Result<u64, StoreError> {
+        let conn = &mut self
+            .connection_pool
+            .get()
+            .map_err(|e| StoreError::Database(format!("Error connecting to DB {e:?}")))?;
+        let count = fdo_db::sqlite::SqliteOwnerDB::count_ovs(conn)
+            .map_err(|e| StoreError::Database(format!("Error counting OVs: {e:?}")))?;
+        Ok(count as u64)
+    }
+
+    async fn load_metadata(
+        &self,
+        key: &K,
+        metadata_key: &crate::MetadataKey<MKT>,
+    ) -> Result<Option<Vec<u8>>, StoreError> {
+        let conn = &mut self
+            .connection_pool
+            .get()
+            .map_err(|e| StoreError::Database(format!("Error connecting to DB {e:?}")))?;
+        let ov_db = match fdo_db::sqlite::SqliteOwnerDB::get_ov(&key.to_string(), conn) {
+            Ok(ov_db) => ov_db,
+            Err(e) => match e.downcast_ref::<diesel::result::Error>() {
+                Some(diesel::result::Error::NotFound) => return Ok(None),
+                _ => {
+                    return Err(StoreError::Database(format!(
+                        "Error selecting OV with guid {}: {e:?}",
+                        key.to_string()
+                    )))
+                }
+            },
+        };
+        // Return the values in the same format MetadataValue::to_stored uses
+        match metadata_key.to_key() {
+            name if name == OwnershipVoucherStoreMetadataKey::To2Performed.to_key() => Ok(ov_db
+                .to2_performed
+                .map(|val| val.to_string().as_bytes().to_vec())),
+            name if name
+                == OwnershipVoucherStoreMetadataKey::To0AcceptOwnerWaitSeconds.to_key() =>
+            {
+                Ok(ov_db
+                    .to0_accept_owner_wait_seconds
+                    .map(|val| i64::to_le_bytes(val).into()))
+            }
+            _ => Err(StoreError::Unspecified(format!(
+                "Unable to handle metadata key {}",
+                metadata_key.to_key()
+            ))),
+        }
+    }
+
     async fn store_metadata(
         &self,
         key: &K,
@@ -266,7 +440,7 @@ where
             .get()
             .expect("Couldn't establish a connection");
         match metadata_key.to_key() {
-            "fdo.to2_performed" => {
+            name if name == OwnershipVoucherStoreMetadataKey::To2Performed.to_key() => {
                 let val = metadata_value
                     .to_text()
                     .parse::<bool>()
@@ -279,7 +453,9 @@ where
                         ))
                     })
             }
-            "fdo.to0_accept_owner_wait_seconds" => {
+            name if name
+                == OwnershipVoucherStoreMetadataKey::To0AcceptOwnerWaitSeconds.to_key() =>
+            {
                 let val = metadata_value
                     .to_text()
                     .parse::<i64>()
@@ -400,6 +576,16 @@ where
         })
     }
 
+    async fn store_data_in_group(
+        &self,
+        _key: K,
+        _value: V,
+        _owner_group: &str,
+        _max_entries: Option<u64>,
+    ) -> Result<bool, StoreError> {
+        Err(StoreError::MethodNotAvailable)
+    }
+
     async fn destroy_data(&self, key: &K) -> Result<(), StoreError> {
         let conn = &mut self
             .connection_pool
@@ -470,13 +656,76 @@ where
             .connection_pool
             .get()
             .expect("Couldn't establish a connection");
-        let ov_db = fdo_db::sqlite::SqliteRendezvousDB::get_ov(&key.to_string(), conn)
-            .expect("Error selecting OV");
+        let ov_db = match fdo_db::sqlite::SqliteRendezvousDB::get_ov(&key.to_string(), conn) {
+            Ok(ov_db) => ov_db,
+            Err(e) => match e.downcast_ref::<diesel::result::Error>() {
+                Some(diesel::result::Error::NotFound) => return Ok(None),
+                _ => {
+                    return Err(StoreError::Database(format!(
+                        "Error selecting OV with guid {}: {e:?}",
+                        key.to_string()
+                    )))
+                }
+            },
+        };
         Ok(Some(V::deserialize_data(&ov_db.contents).map_err(|e| {
             StoreError::Unspecified(format!("Error deserializing value: {e:?}"))
         })?))
     }
 
+    async fn load_all_data(&self) -> Result<Vec<V>, StoreError> {
+        Err(StoreError::MethodNotAvailable)
+    }
+
+    async fn load_all_keys(&self) -> Result<Vec<K>, StoreError> {
+        let conn = &mut self
+            .connection_pool
+            .get()
+            .map_err(|e| StoreError::Database(format!("Error connecting to DB {e:?}")))?;
+        let db_ovs = fdo_db::sqlite::SqliteRendezvousDB::get_all_ovs(conn)
+            .map_err(|e| StoreError::Database(format!("Error selecting all OVs: {e:?}")))?;
+        // Expired registrations are only removed during maintenance
+        let now = time::OffsetDateTime::now_utc().unix_timestamp();
+        db_ovs
+            .iter()
+            .filter(|db_ov| !matches!(db_ov.ttl, Some(ttl) if ttl <= now))
+            .map(|db_ov| {
+                K::from_str(&db_ov.guid).map_err(|_| {
+                    StoreError::Unspecified(format!("Invalid key {} in the DB", db_ov.guid))
+                })
+            })
+            .collect()
+    }
+
+    async fn count_data(&self) -> Result<u64, StoreError> {
+        let conn = &mut self
+            .connection_pool
+            .get()
+            .map_err(|e| StoreError::Database(format!("Error connecting to DB {e:?}")))?;
+        let count = fdo_db::sqlite::SqliteRendezvousDB::count_ovs(conn)
+            .map_err(|e| StoreError::Database(format!("Error counting OVs: {e:?}")))?;
+        Ok(count as u64)
+    }
+
+    async fn load_metadata(
+        &self,
+        key: &K,
+        _metadata_key: &crate::MetadataKey<MKT>,
+    ) -> Result<Option<Vec<u8>>, StoreError> {
+        let conn = &mut self
+            .connection_pool
+            .get()
+            .map_err(|e| StoreError::Database(format!("Error connecting to DB {e:?}")))?;
+        let ov_db =
+            fdo_db::sqlite::SqliteRendezvousDB::get_ov(&key.to_string(), conn).map_err(|e| {
+                StoreError::Database(format!(
+                    "Error selecting OV with guid {}: {e:?}",
+                    key.to_string()
+                ))
+            })?;
+        Ok(ov_db.ttl.map(|ttl| i64::to_le_bytes(ttl).into()))
+    }
+
     async fn store_metadata(
         &self,
         key: &K,
@@ -554,6 +803,38 @@ where
             })
     }
 
+    async fn store_data_in_group(
+        &self,
+        key: K,
+        value: V,
+        owner_group: &str,
+        max_entries: Option<u64>,
+    ) -> Result<bool, StoreError> {
+        let conn = &mut self
+            .connection_pool
+            .get()
+            .map_err(|e| StoreError::Database(format!("Error connecting to DB {e:?}")))?;
+        let raw = V::serialize_data(&value)
+            .map_err(|e| StoreError::Unspecified(format!("Error serializing data: {e:?}")))?;
+        let stored = StoredItem::deserialize_data(&raw)
+            .map_err(|e| StoreError::Unspecified(format!("Error converting StoredItem: {e:?}")))?;
+        let now = time::OffsetDateTime::now_utc().unix_timestamp();
+        fdo_db::sqlite::SqliteRendezvousDB::insert_ov_in_group(
+            &stored,
+            &key.to_string(),
+            owner_group,
+            max_entries,
+            now,
+            conn,
+        )
+        .map_err(|e| {
+            StoreError::Database(format!(
+                "Error inserting StoredItem with guid {} in group {owner_group}: {e:?}",
+                key.to_string()
+            ))
+        })
+    }
+
     async fn destroy_data(&self, key: &K) -> Result<(), StoreError> {
         let conn = &mut self
             .connection_pool
@@ -578,3 +859,188 @@ where
         })
     }
 }
+
+struct SqliteSessionStore<K, V> {
+    phantom_k: PhantomData<K>,
+    phantom_v: PhantomData<V>,
+
+    connection_pool: Pool<ConnectionManager<SqliteConnection>>,
+}
+
+#[async_trait]
+impl<OT, K, V, MKT> Store<OT, K, V, MKT> for SqliteSessionStore<K, V>
+where
+    OT: crate::StoreOpenMode,
+    K: std::str::FromStr + std::string::ToString + Send + Sync + 'static,
+    V: Serializable + Send + Sync + Clone + 'static,
+    MKT: crate::MetadataLocalKey + 'static,
+{
+    async fn load_data(&self, key: &K) -> Result<Option<V>, StoreError> {
+        let conn = &mut self
+            .connection_pool
+            .get()
+            .map_err(|e| StoreError::Database(format!("Error connecting to DB {e:?}")))?;
+        let session_db = match fdo_db::sqlite::SqliteSessionDB::get_session(&key.to_string(), conn)
+        {
+            Ok(session_db) => session_db,
+            Err(e) => match e.downcast_ref::<diesel::result::Error>() {
+                Some(diesel::result::Error::NotFound) => return Ok(None),
+                _ => {
+                    return Err(StoreError::Database(format!(
+                        "Error selecting session {}: {e:?}",
+                        key.to_string()
+                    )))
+                }
+            },
+        };
+        if let Some(ttl) = session_db.ttl {
+            if ttl <= time::OffsetDateTime::now_utc().unix_timestamp() {
+                log::trace!("Session has expired, ignoring it");
+                return Ok(None);
+            }
+        }
+        Ok(Some(V::deserialize_data(&session_db.contents).map_err(
+            |e| StoreError::Unspecified(format!("Error deserializing value: {e:?}")),
+        )?))
+    }
+
+    async fn load_all_data(&self) -> Result<Vec<V>, StoreError> {
+        Err(StoreError::MethodNotAvailable)
+    }
+
+    async fn load_all_keys(&self) -> Result<Vec<K>, StoreError> {
+        Err(StoreError::MethodNotAvailable)
+    }
+
+    async fn count_data(&self) -> Result<u64, StoreError> {
+        let conn = &mut self
+            .connection_pool
+            .get()
+            .map_err(|e| StoreError::Database(format!("Error connecting to DB {e:?}")))?;
+        let count = fdo_db::sqlite::SqliteSessionDB::count_sessions(conn)
+            .map_err(|e| StoreError::Database(format!("Error counting sessions: {e:?}")))?;
+        Ok(count as u64)
+    }
+
+    async fn load_metadata(
+        &self,
+        key: &K,
+        _metadata_key: &crate::MetadataKey<MKT>,
+    ) -> Result<Option<Vec<u8>>, StoreError> {
+        let conn = &mut self
+            .connection_pool
+            .get()
+            .map_err(|e| StoreError::Database(format!("Error connecting to DB {e:?}")))?;
+        let session_db = fdo_db::sqlite::SqliteSessionDB::get_session(&key.to_string(), conn)
+            .map_err(|e| {
+                StoreError::Database(format!(
+                    "Error selecting session {}: {e:?}",
+                    key.to_string()
+                ))
+            })?;
+        Ok(session_db.ttl.map(|ttl| i64::to_le_bytes(ttl).into()))
+    }
+
+    async fn store_metadata(
+        &self,
+        key: &K,
+        _metadata_key: &crate::MetadataKey<MKT>,
+        metadata_value: &dyn MetadataValue,
+    ) -> Result<(), StoreError> {
+        let conn = &mut self
+            .connection_pool
+            .get()
+            .map_err(|e| StoreError::Database(format!("Error connecting to DB {e:?}")))?;
+        let val = metadata_value.to_text().parse::<i64>().map_err(|e| {
+            StoreError::Unspecified(format!("Unable to convert session ttl: {e:?}"))
+        })?;
+        fdo_db::sqlite::SqliteSessionDB::update_session_ttl(&key.to_string(), Some(val), conn)
+            .map_err(|e| {
+                StoreError::Database(format!(
+                    "Unable to update session {} with ttl {val}: {e:?}",
+                    key.to_string()
+                ))
+            })
+    }
+
+    async fn destroy_metadata(
+        &self,
+        key: &K,
+        _metadata_key: &crate::MetadataKey<MKT>,
+    ) -> Result<(), StoreError> {
+        let conn = &mut self
+            .connection_pool
+            .get()
+            .map_err(|e| StoreError::Database(format!("Error connecting to DB {e:?}")))?;
+        fdo_db::sqlite::SqliteSessionDB::update_session_ttl(&key.to_string(), None, conn).map_err(
+            |e| {
+                StoreError::Database(format!(
+                    "Unable to set 'None' ttl on session {}: {e:?}",
+                    key.to_string()
+                ))
+            },
+        )
+    }
+
+    async fn query_data(&self) -> crate::QueryResult<V, MKT> {
+        Err(StoreError::MethodNotAvailable)
+    }
+
+    async fn query_ovs_db(&self) -> Result<Vec<OwnershipVoucher>, StoreError> {
+        Err(StoreError::MethodNotAvailable)
+    }
+
+    async fn query_ovs_db_to2_performed_to0_less_than(
+        &self,
+        _to2: bool,
+        _to0_max: i64,
+    ) -> Result<Vec<OwnershipVoucher>, StoreError> {
+        Err(StoreError::MethodNotAvailable)
+    }
+
+    async fn store_data(&self, key: K, value: V) -> Result<(), StoreError> {
+        let conn = &mut self
+            .connection_pool
+            .get()
+            .map_err(|e| StoreError::Database(format!("Error connecting to DB {e:?}")))?;
+        let raw = V::serialize_data(&value)
+            .map_err(|e| StoreError::Unspecified(format!("Error serializing session: {e:?}")))?;
+        fdo_db::sqlite::SqliteSessionDB::insert_session(&key.to_string(), raw, conn).map_err(|e| {
+            StoreError::Database(format!(
+                "Error inserting session {}: {e:?}",
+                key.to_string()
+            ))
+        })
+    }
+
+    async fn store_data_in_group(
+        &self,
+        _key: K,
+        _value: V,
+        _owner_group: &str,
+        _max_entries: Option<u64>,
+    ) -> Result<bool, StoreError> {
+        Err(StoreError::MethodNotAvailable)
+    }
+
+    async fn destroy_data(&self, key: &K) -> Result<(), StoreError> {
+        let conn = &mut self
+            .connection_pool
+            .get()
+            .map_err(|e| StoreError::Database(format!("Error connecting to DB {e:?}")))?;
+        fdo_db::sqlite::SqliteSessionDB::delete_session(&key.to_string(), conn).map_err(|e| {
+            StoreError::Database(format!("Error deleting session {}: {e:?}", key.to_string()))
+        })
+    }
+
+    async fn perform_maintenance(&self) -> Result<(), StoreError> {
+        let conn = &mut self
+            .connection_pool
+            .get()
+            .map_err(|e| StoreError::Database(format!("Error connecting to DB {e:?}")))?;
+        let now = time::OffsetDateTime::now_utc().unix_timestamp();
+        fdo_db::sqlite::SqliteSessionDB::delete_session_ttl_le(now, conn).map_err(|e| {
+            StoreError::Database(format!("Error deleting sessions with ttl <= {now}: {e:?}"))
+        })
+    }
+}
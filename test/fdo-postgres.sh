@@ -67,6 +67,7 @@ mkdir -p initdb
 cp migrations/migrations_manufacturing_server_postgres/2023-10-03-152801_create_db/up.sql initdb/manufacturing.sql
 cp migrations/migrations_owner_onboarding_server_postgres/2023-10-03-152801_create_db/up.sql initdb/owner-onboarding.sql
 cp migrations/migrations_rendezvous_server_postgres/2023-10-03-152801_create_db/up.sql initdb/rendezvous.sql
+cp migrations/migrations_rendezvous_server_postgres/2026-10-18-130000_add_owner_group/up.sql initdb/rendezvous_owner_group.sql
 
 greenprint "🔧 Starting postgres"
 sudo podman run -d \
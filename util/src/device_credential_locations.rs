@@ -4,14 +4,17 @@ use std::path::Path;
 
 use anyhow::{anyhow, Context, Result};
 
-use fdo_data_formats::{devicecredential::FileDeviceCredential, DeviceCredential, Serializable};
+use fdo_data_formats::{
+    devicecredential::FileDeviceCredential, ownershipvoucher::OwnershipVoucherHeader,
+    DeviceCredential, Serializable,
+};
 
 pub fn find() -> Option<Result<Box<dyn UsableDeviceCredentialLocation>>> {
     let device_credential_locations: &[Box<dyn DeviceCredentialLocation>] = &[
         Box::new(FileSystemPath {
             path: "/sys/firmware/qemu_fw_cfg/by_name/opt/device_onboarding/devicecredential/raw"
                 .to_string(),
-            deactivation_method: DeactivationMethod::None,
+            deactivation_method: DeactivationMethod::ReadOnly,
         }),
         Box::new(FileSystemPathEnv {
             env_var: "DEVICE_CREDENTIAL".to_string(),
@@ -38,6 +41,8 @@ enum DeactivationMethod {
     None,
     Delete,
     Deactivate,
+    // The location can not be written to, so credentials can't be replaced
+    ReadOnly,
 }
 
 pub trait DeviceCredentialLocation: std::fmt::Debug {
@@ -47,6 +52,14 @@ pub trait DeviceCredentialLocation: std::fmt::Debug {
 pub trait UsableDeviceCredentialLocation: DeviceCredentialLocation {
     fn read(&self) -> Result<Box<dyn DeviceCredential>>;
     fn deactivate(&self) -> Result<()>;
+    /// Whether the credential can be rewritten by `replace`
+    fn can_replace(&self) -> bool;
+    /// Rewrites the credential to match the replacement Ownership Voucher
+    /// header received during TO2 (Credential Resale).
+    ///
+    /// This is done instead of `deactivate`: the replaced credential stays
+    /// active, so the device can be onboarded by the next owner.
+    fn replace(&self, replacement_header: &OwnershipVoucherHeader) -> Result<()>;
 }
 
 #[derive(Debug, Clone)]
@@ -77,12 +90,28 @@ impl UsableDeviceCredentialLocation for FileSystemPath {
 
     fn deactivate(&self) -> Result<()> {
         match self.deactivation_method {
-            DeactivationMethod::None => Ok(()),
+            DeactivationMethod::None | DeactivationMethod::ReadOnly => Ok(()),
             DeactivationMethod::Delete => fs::remove_file(&self.path)
                 .with_context(|| format!("Error deleting file at {}", &self.path)),
             DeactivationMethod::Deactivate => self.perform_deactivation(),
         }
     }
+
+    fn can_replace(&self) -> bool {
+        !matches!(self.deactivation_method, DeactivationMethod::ReadOnly)
+    }
+
+    fn replace(&self, replacement_header: &OwnershipVoucherHeader) -> Result<()> {
+        match self.deactivation_method {
+            DeactivationMethod::ReadOnly => Err(anyhow!(
+                "Device credential at {} is read-only, can not store replacement",
+                &self.path
+            )),
+            DeactivationMethod::None
+            | DeactivationMethod::Delete
+            | DeactivationMethod::Deactivate => self.perform_replacement(replacement_header),
+        }
+    }
 }
 
 impl FileSystemPath {
@@ -100,6 +129,26 @@ impl FileSystemPath {
             .context("Error writing out new device credential for deactivation")
     }
 
+    fn perform_replacement(&self, replacement_header: &OwnershipVoucherHeader) -> Result<()> {
+        let contents = fs::read(&self.path)
+            .with_context(|| format!("Error reading (device credential) file at {}", &self.path))?;
+        let mut fdc = FileDeviceCredential::deserialize_data(&contents)
+            .with_context(|| format!("Error parsing device credential from {}", &self.path))?;
+
+        fdc.active = true;
+        fdc.protver = replacement_header.protocol_version();
+        fdc.guid = replacement_header.guid().clone();
+        fdc.rvinfo = replacement_header.rendezvous_info().clone();
+        fdc.pubkey_hash = replacement_header
+            .manufacturer_public_key_hash(fdc.pubkey_hash.get_type())
+            .context("Error computing replacement public key hash")?;
+        let new_dc_contents = fdc
+            .serialize_data()
+            .context("Error serializing replacement device credential")?;
+        self.write(new_dc_contents)
+            .context("Error writing out replacement device credential")
+    }
+
     fn write(&self, new_contents: Vec<u8>) -> Result<()> {
         fs::write(&self.path, new_contents)
             .with_context(|| format!("Error writing to file at {}", &self.path))
@@ -141,3 +190,103 @@ impl DeviceCredentialLocation for FileSystemPathEnv {
         .resolve()
     }
 }
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+    use std::path::{Path, PathBuf};
+
+    use fdo_data_formats::{
+        constants::HashType,
+        devicecredential::{file::KeyStorage, FileDeviceCredential},
+        ownershipvoucher::OwnershipVoucherHeader,
+        test_utils::{build_voucher, generate_public_key},
+        types::{Guid, Hash, RendezvousInfo},
+        ProtocolVersion, Serializable,
+    };
+
+    use super::{DeactivationMethod, FileSystemPath, UsableDeviceCredentialLocation};
+
+    fn credential_path(name: &str) -> PathBuf {
+        let path =
+            std::env::temp_dir().join(format!("fdo-devcred-test-{}-{}", std::process::id(), name));
+        let credential = FileDeviceCredential {
+            active: true,
+            protver: ProtocolVersion::Version1_1,
+            device_info: "testdevice".to_string(),
+            guid: Guid::new().unwrap(),
+            rvinfo: RendezvousInfo::new(Vec::new()).unwrap(),
+            pubkey_hash: Hash::from_data(HashType::Sha256, b"manufacturer").unwrap(),
+            key_storage: KeyStorage::Plain {
+                hmac_secret: vec![1; 32],
+                private_key: Vec::new(),
+            },
+        };
+        fs::write(&path, credential.serialize_data().unwrap()).unwrap();
+        path
+    }
+
+    fn location(path: &Path, deactivation_method: DeactivationMethod) -> FileSystemPath {
+        FileSystemPath {
+            path: path.to_str().unwrap().to_string(),
+            deactivation_method,
+        }
+    }
+
+    fn read_credential(path: &Path) -> FileDeviceCredential {
+        FileDeviceCredential::deserialize_data(&fs::read(path).unwrap()).unwrap()
+    }
+
+    fn replacement_header() -> OwnershipVoucherHeader {
+        let (_, manufacturer_public_key) = generate_public_key("Owner2");
+        build_voucher(&manufacturer_public_key, None)
+            .header()
+            .clone()
+    }
+
+    /// Replaces the credential, like a resale onboarding does, and checks that
+    /// the replaced credential survives and stays active
+    fn replace_credential(name: &str, deactivation_method: DeactivationMethod) {
+        let path = credential_path(name);
+        let header = replacement_header();
+        let devcredloc = location(&path, deactivation_method);
+
+        assert!(devcredloc.can_replace());
+        devcredloc.replace(&header).unwrap();
+
+        let credential = read_credential(&path);
+        assert_eq!(&credential.guid, header.guid());
+        assert!(credential.active);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_replace_deactivate() {
+        replace_credential("deactivate", DeactivationMethod::Deactivate);
+    }
+
+    #[test]
+    fn test_replace_delete() {
+        replace_credential("delete", DeactivationMethod::Delete);
+    }
+
+    #[test]
+    fn test_replace_none() {
+        replace_credential("none", DeactivationMethod::None);
+    }
+
+    #[test]
+    fn test_replace_read_only() {
+        let path = credential_path("read-only");
+        let original_guid = read_credential(&path).guid;
+        let devcredloc = location(&path, DeactivationMethod::ReadOnly);
+
+        assert!(!devcredloc.can_replace());
+        assert!(devcredloc.replace(&replacement_header()).is_err());
+
+        let credential = read_credential(&path);
+        assert_eq!(credential.guid, original_guid);
+        assert!(credential.active);
+        fs::remove_file(path).unwrap();
+    }
+}
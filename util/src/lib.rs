@@ -1,6 +1,7 @@
 pub mod device_credential_locations;
 pub mod device_identification;
 pub mod passwd_shadow;
+pub mod private_key;
 pub mod servers;
 pub mod system_info;
 
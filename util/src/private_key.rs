@@ -0,0 +1,305 @@
+//! Loading of private signing keys, either from DER files on disk or from
+//! PKCS#11 tokens (HSMs) identified by a `pkcs11:` URI (RFC 7512).
+//!
+//! PKCS#11 keys are loaded through the OpenSSL store API, so the signing
+//! operations are performed by the token via the OpenSSL PKCS#11 provider
+//! (or engine on OpenSSL 1.1), which needs to be configured in the OpenSSL
+//! configuration file. The resulting key is a regular `PKey<Private>`, so it
+//! can be used for COSE signatures, ownership voucher extension and
+//! certificate issuance alike.
+
+use std::{ffi::CString, fmt, fs, path::PathBuf, ptr, str::FromStr};
+
+use anyhow::{bail, Context, Result};
+use foreign_types::ForeignType;
+use openssl::{
+    error::ErrorStack,
+    pkey::{PKey, Private},
+};
+use serde::{Deserialize, Serialize};
+
+const PKCS11_URI_SCHEME: &str = "pkcs11:";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PrivateKeyLocation {
+    /// A DER-encoded private key file
+    File(PathBuf),
+    /// A key on a PKCS#11 token, as a `pkcs11:` URI
+    Pkcs11(String),
+}
+
+impl PrivateKeyLocation {
+    pub fn load(&self) -> Result<PKey<Private>> {
+        match self {
+            PrivateKeyLocation::File(path) => {
+                let contents = fs::read(path)
+                    .with_context(|| format!("Error reading private key from {path:?}"))?;
+                PKey::private_key_from_der(&contents)
+                    .with_context(|| format!("Error parsing private key from {path:?}"))
+            }
+            PrivateKeyLocation::Pkcs11(uri) => {
+                load_from_store(uri).with_context(|| format!("Error loading private key {self}"))
+            }
+        }
+    }
+
+    pub fn is_pkcs11(&self) -> bool {
+        matches!(self, PrivateKeyLocation::Pkcs11(_))
+    }
+}
+
+impl FromStr for PrivateKeyLocation {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        if s.is_empty() {
+            bail!("private key location is empty");
+        }
+        if s.starts_with(PKCS11_URI_SCHEME) {
+            Ok(PrivateKeyLocation::Pkcs11(s.to_string()))
+        } else {
+            Ok(PrivateKeyLocation::File(PathBuf::from(s)))
+        }
+    }
+}
+
+impl fmt::Display for PrivateKeyLocation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PrivateKeyLocation::File(path) => fmt::Debug::fmt(path, f),
+            // The query attributes may contain the PIN, so leave them out
+            PrivateKeyLocation::Pkcs11(uri) => f.write_str(uri.split('?').next().unwrap()),
+        }
+    }
+}
+
+impl Serialize for PrivateKeyLocation {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        match self {
+            PrivateKeyLocation::File(path) => serializer.serialize_str(&path.to_string_lossy()),
+            PrivateKeyLocation::Pkcs11(uri) => serializer.serialize_str(uri),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for PrivateKeyLocation {
+    fn deserialize<D>(deserializer: D) -> Result<PrivateKeyLocation, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        let location = PrivateKeyLocation::from_str(&s).map_err(serde::de::Error::custom)?;
+        if let PrivateKeyLocation::File(path) = &location {
+            if !path.is_absolute() {
+                return Err(serde::de::Error::custom(format!(
+                    "path {s} is not absolute"
+                )));
+            }
+        }
+        Ok(location)
+    }
+}
+
+#[allow(non_camel_case_types)]
+mod ffi {
+    use std::os::raw::{c_char, c_int, c_void};
+
+    pub enum OSSL_STORE_CTX {}
+    pub enum OSSL_STORE_INFO {}
+
+    pub type OSSL_STORE_post_process_info_fn =
+        unsafe extern "C" fn(*mut OSSL_STORE_INFO, *mut c_void) -> *mut OSSL_STORE_INFO;
+
+    extern "C" {
+        pub fn OSSL_STORE_open(
+            uri: *const c_char,
+            ui_method: *const c_void,
+            ui_data: *mut c_void,
+            post_process: Option<OSSL_STORE_post_process_info_fn>,
+            post_process_data: *mut c_void,
+        ) -> *mut OSSL_STORE_CTX;
+        pub fn OSSL_STORE_load(ctx: *mut OSSL_STORE_CTX) -> *mut OSSL_STORE_INFO;
+        pub fn OSSL_STORE_eof(ctx: *mut OSSL_STORE_CTX) -> c_int;
+        pub fn OSSL_STORE_error(ctx: *mut OSSL_STORE_CTX) -> c_int;
+        pub fn OSSL_STORE_close(ctx: *mut OSSL_STORE_CTX) -> c_int;
+        pub fn OSSL_STORE_INFO_get1_PKEY(
+            info: *const OSSL_STORE_INFO,
+        ) -> *mut openssl_sys::EVP_PKEY;
+        pub fn OSSL_STORE_INFO_free(info: *mut OSSL_STORE_INFO);
+    }
+}
+
+fn load_from_store(uri: &str) -> Result<PKey<Private>> {
+    openssl_sys::init();
+
+    let c_uri = CString::new(uri).context("Invalid URI")?;
+
+    unsafe {
+        let ctx = ffi::OSSL_STORE_open(
+            c_uri.as_ptr(),
+            ptr::null(),
+            ptr::null_mut(),
+            None,
+            ptr::null_mut(),
+        );
+        if ctx.is_null() {
+            return Err(ErrorStack::get()).context("Error opening key store");
+        }
+
+        let mut pkey: *mut openssl_sys::EVP_PKEY = ptr::null_mut();
+        while pkey.is_null() && ffi::OSSL_STORE_eof(ctx) == 0 {
+            let info = ffi::OSSL_STORE_load(ctx);
+            if info.is_null() {
+                if ffi::OSSL_STORE_error(ctx) != 0 {
+                    break;
+                }
+                continue;
+            }
+            // Returns NULL for anything that is not a private key
+            pkey = ffi::OSSL_STORE_INFO_get1_PKEY(info);
+            ffi::OSSL_STORE_INFO_free(info);
+        }
+        ffi::OSSL_STORE_close(ctx);
+
+        if pkey.is_null() {
+            return Err(ErrorStack::get()).context("No private key found");
+        }
+        // Clear any errors from objects we skipped over
+        let _ = ErrorStack::get();
+        Ok(PKey::from_ptr(pkey))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{path::Path, process::Command};
+
+    use openssl::{
+        ec::{EcGroup, EcKey},
+        hash::MessageDigest,
+        nid::Nid,
+        pkey::PKey,
+        provider::Provider,
+        sign::{Signer, Verifier},
+    };
+
+    use super::PrivateKeyLocation;
+
+    const SOFTHSM_MODULES: &[&str] = &[
+        "/usr/lib64/pkcs11/libsofthsm2.so",
+        "/usr/lib64/softhsm/libsofthsm.so",
+        "/usr/lib/softhsm/libsofthsm2.so",
+        "/usr/lib/x86_64-linux-gnu/softhsm/libsofthsm2.so",
+    ];
+
+    fn softhsm_util(args: &[&str]) {
+        let output = Command::new("softhsm2-util").args(args).output().unwrap();
+        assert!(
+            output.status.success(),
+            "softhsm2-util {args:?} failed: {}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+
+    #[test]
+    fn test_parse_locations() {
+        let loc: PrivateKeyLocation = serde_yaml::from_str("/etc/fdo/keys/owner_key.der").unwrap();
+        assert_eq!(
+            loc,
+            PrivateKeyLocation::File("/etc/fdo/keys/owner_key.der".into())
+        );
+        assert!(!loc.is_pkcs11());
+
+        let loc: PrivateKeyLocation =
+            serde_yaml::from_str("\"pkcs11:token=fdo;object=owner?pin-value=1234\"").unwrap();
+        assert!(loc.is_pkcs11());
+        assert_eq!(loc.to_string(), "pkcs11:token=fdo;object=owner");
+
+        assert!(serde_yaml::from_str::<PrivateKeyLocation>("keys/owner_key.der").is_err());
+    }
+
+    #[test]
+    fn test_load_softhsm_key() {
+        let module = match SOFTHSM_MODULES.iter().find(|path| Path::new(path).exists()) {
+            Some(module) => module,
+            None => {
+                eprintln!("SoftHSM module not found, skipping");
+                return;
+            }
+        };
+        if Command::new("softhsm2-util")
+            .arg("--version")
+            .output()
+            .is_err()
+        {
+            eprintln!("softhsm2-util not found, skipping");
+            return;
+        }
+
+        let dir = tempfile::tempdir().unwrap();
+        let token_dir = dir.path().join("tokens");
+        std::fs::create_dir(&token_dir).unwrap();
+        let config = dir.path().join("softhsm2.conf");
+        std::fs::write(
+            &config,
+            format!("directories.tokendir = {}\n", token_dir.display()),
+        )
+        .unwrap();
+        std::env::set_var("SOFTHSM2_CONF", &config);
+        std::env::set_var("PKCS11_PROVIDER_MODULE", module);
+
+        let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1).unwrap();
+        let key = PKey::from_ec_key(EcKey::generate(&group).unwrap()).unwrap();
+        let key_path = dir.path().join("key.pem");
+        std::fs::write(&key_path, key.private_key_to_pem_pkcs8().unwrap()).unwrap();
+
+        softhsm_util(&[
+            "--init-token",
+            "--free",
+            "--label",
+            "fdo-test",
+            "--pin",
+            "1234",
+            "--so-pin",
+            "123456",
+        ]);
+        softhsm_util(&[
+            "--import",
+            key_path.to_str().unwrap(),
+            "--token",
+            "fdo-test",
+            "--label",
+            "owner",
+            "--id",
+            "01",
+            "--pin",
+            "1234",
+        ]);
+
+        // Keep the default provider available for everything else
+        let _provider = match Provider::try_load(None, "pkcs11", true) {
+            Ok(provider) => provider,
+            Err(e) => {
+                eprintln!("OpenSSL pkcs11 provider not available ({e}), skipping");
+                return;
+            }
+        };
+
+        let loc: PrivateKeyLocation =
+            "pkcs11:token=fdo-test;object=owner;type=private?pin-value=1234"
+                .parse()
+                .unwrap();
+        let hsm_key = loc.load().unwrap();
+
+        // The token signs, the public half of the imported key verifies
+        let mut signer = Signer::new(MessageDigest::sha256(), &hsm_key).unwrap();
+        signer.update(b"fdo").unwrap();
+        let signature = signer.sign_to_vec().unwrap();
+        let mut verifier = Verifier::new(MessageDigest::sha256(), &key).unwrap();
+        verifier.update(b"fdo").unwrap();
+        assert!(verifier.verify(&signature).unwrap());
+    }
+}
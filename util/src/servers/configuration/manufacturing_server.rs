@@ -4,7 +4,8 @@ use fdo_data_formats::constants::{KeyStorageType, MfgStringType, PublicKeyType};
 use fdo_store::StoreConfig;
 use serde::{Deserialize, Serialize};
 
-use super::{AbsolutePathBuf, Bind};
+use super::{AbsolutePathBuf, Bind, TlsSettings};
+use crate::private_key::PrivateKeyLocation;
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ManufacturingServerSettings {
@@ -23,6 +24,17 @@ pub struct ManufacturingServerSettings {
     // Bind information
     pub bind: Bind,
 
+    #[serde(default)]
+    pub tls: Option<TlsSettings>,
+
+    // CoAP listener, serving the same messages over UDP, disabled if not set
+    #[serde(default)]
+    pub coap_bind: Option<Bind>,
+
+    // Prometheus metrics listener, metrics are not served if not set
+    #[serde(default)]
+    pub metrics_bind: Option<Bind>,
+
     pub protocols: ProtocolSetting,
 
     pub rendezvous_info: Vec<BTreeMap<String, serde_yaml::Value>>,
@@ -33,11 +45,11 @@ pub struct ManufacturingServerSettings {
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ManufacturingSettings {
     pub manufacturer_cert_path: AbsolutePathBuf,
-    pub device_cert_ca_private_key: AbsolutePathBuf,
+    pub device_cert_ca_private_key: PrivateKeyLocation,
     pub device_cert_ca_chain: AbsolutePathBuf,
 
     pub owner_cert_path: Option<AbsolutePathBuf>,
-    pub manufacturer_private_key: Option<AbsolutePathBuf>,
+    pub manufacturer_private_key: Option<PrivateKeyLocation>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -76,6 +88,9 @@ impl From<MfgStringTypeString> for MfgStringType {
 pub enum PublicKeyTypeString {
     SECP256R1,
     SECP384R1,
+    RSA2048RESTR,
+    RSAPKCS,
+    RSAPSS,
 }
 
 impl From<PublicKeyTypeString> for PublicKeyType {
@@ -83,6 +98,9 @@ impl From<PublicKeyTypeString> for PublicKeyType {
         match key_type {
             PublicKeyTypeString::SECP256R1 => PublicKeyType::SECP256R1,
             PublicKeyTypeString::SECP384R1 => PublicKeyType::SECP384R1,
+            PublicKeyTypeString::RSA2048RESTR => PublicKeyType::Rsa2048RESTR,
+            PublicKeyTypeString::RSAPKCS => PublicKeyType::RsaPkcs,
+            PublicKeyTypeString::RSAPSS => PublicKeyType::RsaPss,
         }
     }
 }
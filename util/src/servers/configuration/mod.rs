@@ -64,6 +64,28 @@ impl From<Bind> for SocketAddr {
     }
 }
 
+/// TLS configuration for a server listener, which uses plain HTTP if not set
+///
+/// If `client_ca_path` is set, clients are required to present a certificate
+/// signed by one of the CAs in that file (mutual TLS).
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TlsSettings {
+    pub cert_chain_path: AbsolutePathBuf,
+    pub private_key_path: AbsolutePathBuf,
+    #[serde(default)]
+    pub client_ca_path: Option<AbsolutePathBuf>,
+}
+
+impl From<TlsSettings> for fdo_http_wrapper::server::TlsConfig {
+    fn from(settings: TlsSettings) -> Self {
+        fdo_http_wrapper::server::TlsConfig {
+            cert_chain_path: settings.cert_chain_path.0,
+            private_key_path: settings.private_key_path.0,
+            client_ca_path: settings.client_ca_path.map(|path| path.0),
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct AbsolutePathBuf(PathBuf);
 
@@ -1,8 +1,11 @@
+use std::collections::BTreeMap;
+
 use fdo_data_formats::types::RemoteConnection;
 use fdo_store::StoreConfig;
 use serde::{Deserialize, Serialize};
 
-use super::{AbsolutePathBuf, Bind};
+use super::{AbsolutePathBuf, Bind, TlsSettings};
+use crate::private_key::PrivateKeyLocation;
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct OwnerOnboardingServerSettings {
@@ -17,13 +20,27 @@ pub struct OwnerOnboardingServerSettings {
     // Trusted keys
     pub trusted_device_keys_path: Option<AbsolutePathBuf>,
 
-    // Our private owner key
-    pub owner_private_key_path: AbsolutePathBuf,
+    // Our private owner key, a path or a pkcs11: URI
+    pub owner_private_key_path: PrivateKeyLocation,
     pub owner_public_key_path: AbsolutePathBuf,
+    // Other owner keys (e.g. before a rotation) that vouchers may be extended to
+    #[serde(default)]
+    pub additional_owner_keys: Vec<OwnerKeySettings>,
 
     // Bind information
     pub bind: Bind,
 
+    #[serde(default)]
+    pub tls: Option<TlsSettings>,
+
+    // CoAP listener, serving the same messages over UDP, disabled if not set
+    #[serde(default)]
+    pub coap_bind: Option<Bind>,
+
+    // Prometheus metrics listener, metrics are not served if not set
+    #[serde(default)]
+    pub metrics_bind: Option<Bind>,
+
     // Service Info API Server
     pub service_info_api_url: String,
     #[serde(with = "serde_yaml::with::singleton_map")]
@@ -33,8 +50,33 @@ pub struct OwnerOnboardingServerSettings {
 
     pub report_to_rendezvous_endpoint_enabled: bool,
 
+    // Admin API, disabled if not set
+    #[serde(default)]
+    pub admin_auth_token: Option<String>,
+
     pub ov_registration_period: Option<u32>,
     pub ov_re_registration_window: Option<u32>,
+
+    // Number of ServiceInfo rounds a device may perform, defaults to
+    // DEFAULT_MAX_SERVICE_INFO_LOOPS
+    #[serde(default)]
+    pub max_service_info_loops: Option<u32>,
+
+    // Credential Reuse/Resale: persistent Owner2 key
+    pub owner2_private_key_path: Option<PrivateKeyLocation>,
+    pub owner2_public_key_path: Option<AbsolutePathBuf>,
+
+    // Credential Resale: rendezvous info for the replacement vouchers
+    pub replacement_rendezvous_info: Option<Vec<BTreeMap<String, serde_yaml::Value>>>,
+    // Credential Resale: where replacement vouchers are stored
+    #[serde(default, with = "serde_yaml::with::singleton_map")]
+    pub replacement_ownership_voucher_store_driver: Option<StoreConfig>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct OwnerKeySettings {
+    pub private_key_path: PrivateKeyLocation,
+    pub public_key_path: AbsolutePathBuf,
 }
 
 // 10 minutes
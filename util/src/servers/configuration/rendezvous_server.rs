@@ -1,7 +1,7 @@
 use fdo_store::StoreConfig;
 use serde::{Deserialize, Serialize};
 
-use super::{AbsolutePathBuf, Bind};
+use super::{AbsolutePathBuf, Bind, TlsSettings};
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct RendezvousServerSettings {
@@ -22,6 +22,38 @@ pub struct RendezvousServerSettings {
     // Other info
     pub max_wait_seconds: Option<u32>,
 
+    // Owners allowed to register, any owner may register if not set
+    #[serde(default)]
+    pub owner_policies: Option<Vec<OwnerPolicySettings>>,
+
     // Bind information
     pub bind: Bind,
+
+    #[serde(default)]
+    pub tls: Option<TlsSettings>,
+
+    // CoAP listener, serving the same messages over UDP, disabled if not set
+    #[serde(default)]
+    pub coap_bind: Option<Bind>,
+
+    // Prometheus metrics listener, metrics are not served if not set
+    #[serde(default)]
+    pub metrics_bind: Option<Bind>,
+
+    // Admin API, disabled if not set
+    #[serde(default)]
+    pub admin_auth_token: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct OwnerPolicySettings {
+    pub name: String,
+    // PEM certificates with the owner public keys this policy applies to
+    pub owner_keys_path: AbsolutePathBuf,
+    // Overrides the global max_wait_seconds for these owners
+    #[serde(default)]
+    pub max_wait_seconds: Option<u32>,
+    // Maximum number of devices registered by these owners at a time
+    #[serde(default)]
+    pub max_registrations: Option<u64>,
 }
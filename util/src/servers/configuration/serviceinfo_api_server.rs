@@ -1,21 +1,35 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, path::PathBuf};
 
 use fdo_data_formats::constants::ServiceInfoModule;
 use fdo_store::StoreConfig;
 use serde::{Deserialize, Serialize};
 
-use super::Bind;
+use super::{Bind, TlsSettings};
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ServiceInfoApiServerSettings {
     pub service_info: ServiceInfoSettings,
     pub bind: Bind,
 
+    #[serde(default)]
+    pub tls: Option<TlsSettings>,
+
+    // Prometheus metrics listener, metrics are not served if not set
+    #[serde(default)]
+    pub metrics_bind: Option<Bind>,
+
     pub service_info_auth_token: Option<String>,
     pub admin_auth_token: Option<String>,
 
     #[serde(with = "serde_yaml::with::singleton_map")]
     pub device_specific_store_driver: StoreConfig,
+    #[serde(default, with = "serde_yaml::with::singleton_map")]
+    pub device_results_store_driver: Option<StoreConfig>,
+    #[serde(default, with = "serde_yaml::with::singleton_map")]
+    pub device_uploads_store_driver: Option<StoreConfig>,
+    // Directory the files uploaded by devices are written to
+    #[serde(default)]
+    pub device_uploads_path: Option<PathBuf>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -25,10 +39,16 @@ pub struct ServiceInfoSettings {
 
     pub files: Option<Vec<ServiceInfoFile>>,
 
+    pub downloads: Option<Vec<ServiceInfoDownload>>,
+
+    pub uploads: Option<Vec<String>>,
+
     pub commands: Option<Vec<ServiceInfoCommand>>,
 
     pub diskencryption_clevis: Option<Vec<ServiceInfoDiskEncryptionClevis>>,
 
+    pub sys: Option<Vec<ServiceInfoSysOperation>>,
+
     pub additional_serviceinfo: Option<HashMap<ServiceInfoModule, Vec<(String, String)>>>,
 
     pub after_onboarding_reboot: Option<bool>,
@@ -53,12 +73,12 @@ pub struct ServiceInfoFile {
     pub permissions: Option<String>,
     #[serde(skip)]
     pub parsed_permissions: Option<u32>,
-    #[serde(skip)]
-    pub contents_len: usize,
-    #[serde(skip)]
-    pub contents_hex: String,
-    #[serde(skip)]
-    pub hash_hex: String,
+    pub source_path: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ServiceInfoDownload {
+    pub name: String,
     pub source_path: String,
 }
 
@@ -74,6 +94,19 @@ pub struct ServiceInfoCommand {
     pub return_stderr: bool,
 }
 
+/// A single fdo.sys operation, performed on the device in the configured order.
+///
+/// Exactly one of `filedesc` (together with `source_path`), `exec` or
+/// `exec_cb` must be set.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct ServiceInfoSysOperation {
+    pub filedesc: Option<String>,
+    pub source_path: Option<String>,
+    pub exec: Option<Vec<String>>,
+    pub exec_cb: Option<Vec<String>>,
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct ServiceInfoInitialUser {
     pub username: String,
@@ -1,38 +1,24 @@
 use anyhow::{bail, Context, Result};
 use config::Config;
-use fdo_data_formats::constants::ServiceInfoModule;
+use fdo_data_formats::constants::{RendezvousVariable, ServiceInfoModule};
+use fdo_data_formats::types::RendezvousInfo;
 use fdo_store::StoreConfig;
 use glob::glob;
 use serde::{Deserialize, Serialize};
 use serde_cbor::Value as CborValue;
 use serde_yaml::Value;
+use std::collections::BTreeMap;
 use std::env;
 use std::path::Path;
 use std::result::Result::Ok;
+use std::str::FromStr;
 
 pub mod configuration;
 use crate::servers::configuration::serviceinfo_api_server::{
     ServiceInfoApiServerSettings, ServiceInfoSettings,
 };
 
-// TODO(runcom): find a better home for this as it's shared between
-// owner-onboarding-server and manufacturing-server...
-#[non_exhaustive]
-pub enum OwnershipVoucherStoreMetadataKey {
-    To2Performed,
-    To0AcceptOwnerWaitSeconds,
-}
-
-impl fdo_store::MetadataLocalKey for OwnershipVoucherStoreMetadataKey {
-    fn to_key(&self) -> &'static str {
-        match self {
-            OwnershipVoucherStoreMetadataKey::To2Performed => "fdo.to2_performed",
-            OwnershipVoucherStoreMetadataKey::To0AcceptOwnerWaitSeconds => {
-                "fdo.to0_accept_owner_wait_seconds"
-            }
-        }
-    }
-}
+pub use fdo_store::OwnershipVoucherStoreMetadataKey;
 
 pub fn settings_for(component: &str) -> Result<config::Config> {
     // the last added source (if available) will be the one being used
@@ -156,6 +142,29 @@ pub fn yaml_to_cbor(val: &Value) -> Result<CborValue> {
     })
 }
 
+pub fn load_rendezvous_info(rvs: &[BTreeMap<String, Value>]) -> Result<RendezvousInfo> {
+    let mut info = Vec::new();
+    for val in rvs {
+        let mut entry = Vec::new();
+
+        for (key, val) in val.iter() {
+            let key = RendezvousVariable::from_str(key)
+                .with_context(|| format!("Error parsing rendezvous key '{key}'"))?;
+
+            let val = yaml_to_cbor(val)?;
+            let val = key
+                .value_from_human_to_machine(val)
+                .with_context(|| format!("Error parsing value for key '{key:?}'"))?;
+
+            entry.push((key, val));
+        }
+
+        info.push(entry);
+    }
+
+    RendezvousInfo::new(info).context("Error serializing rendezvous info")
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ServiceInfoApiReplyInitialUser {
     pub username: String,
@@ -177,3 +186,69 @@ pub struct ServiceInfoApiReply {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub reboot: Option<ServiceInfoApiReplyReboot>,
 }
+
+/// A file referenced from the `extra_commands` of a ServiceInfo API reply, with
+/// a `|file` suffix on its key. Its contents are requested from the API server
+/// a chunk at a time, while they are sent to the device.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ServiceInfoApiFile {
+    pub file: String,
+    pub length: u64,
+}
+
+/// A chunk of the contents of a `ServiceInfoApiFile`, or of a file uploaded by
+/// a device.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ServiceInfoApiFileChunk {
+    pub data_hex: String,
+}
+
+/// The devmod values reported by a device, as passed to the ServiceInfo API
+/// server by the Owner Onboarding Server.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ServiceInfoApiDevMod {
+    pub os: String,
+    pub arch: String,
+    pub version: String,
+    pub device: String,
+    pub sep: String,
+    pub bin: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sn: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pathsep: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub nl: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tmp: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dir: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub progenv: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mudurl: Option<String>,
+}
+
+/// ServiceInfo entries returned by a device after it processed the owner's
+/// ServiceInfo, as forwarded by the Owner Onboarding Server.
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct ServiceInfoApiDeviceResults {
+    pub service_info: Vec<(ServiceInfoModule, String, serde_json::Value)>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub uploads: Vec<ServiceInfoApiDeviceUpload>,
+}
+
+/// A file uploaded by a device with the fdo.upload module.
+///
+/// The contents are not part of this, they are passed on in chunks while the
+/// device uploads them. This completes the upload, with the number of bytes
+/// received and the digest reported by the device.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ServiceInfoApiDeviceUpload {
+    pub name: String,
+    pub length: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sha_384_hex: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}